@@ -1,6 +1,10 @@
-use crate::lsp::semantic_token::semantic_token_type_index;
+use crate::lsp::{
+    semantic_token::{lexical_token_modifiers, semantic_token_type_index},
+    span::Span,
+    trivia::{Comment, collect_comments},
+};
 use candid_parser::{
-    syntax::{IDLMergedProg, IDLProg},
+    syntax::{Dec, IDLMergedProg, IDLProg},
     token::{LexicalError, Token, Tokenizer, TriviaMap},
 };
 use lalrpop_util::ParseError;
@@ -13,6 +17,28 @@ pub enum CandidError {
     Lexer(LexicalError),
 }
 
+impl CandidError {
+    /// Source span the error points at, if it carries one.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            CandidError::Lexer(err) => Some(err.span.clone()),
+            CandidError::Parser(candid_parser::Error::Parse(err)) => Some(match err {
+                ParseError::InvalidToken { location }
+                | ParseError::UnrecognizedEof { location, .. } => *location..*location,
+                ParseError::UnrecognizedToken {
+                    token: (start, _, end),
+                    ..
+                }
+                | ParseError::ExtraToken {
+                    token: (start, _, end),
+                } => *start..*end,
+                ParseError::User { error } => error.span.clone(),
+            }),
+            CandidError::Parser(_) => None,
+        }
+    }
+}
+
 impl From<LexicalError> for CandidError {
     fn from(err: LexicalError) -> Self {
         CandidError::Lexer(err)
@@ -20,11 +46,12 @@ impl From<LexicalError> for CandidError {
 }
 
 /// Incomplete semantic-token entry paired with source offsets produced during lexing.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ImCompleteSemanticToken {
     pub start: usize,
     pub length: usize,
     pub token_type: usize,
+    pub token_modifiers: u32,
 }
 
 /// Aggregated parse output containing the AST, errors, and semantic-token data.
@@ -33,16 +60,53 @@ pub struct ParserResult {
     pub ast: Option<IDLMergedProg>,
     pub parse_errors: Vec<CandidError>,
     pub semantic_tokens: Vec<ImCompleteSemanticToken>,
+    /// Comments from the parser's trivia, for formatting.
+    pub comments: Vec<Comment>,
+    /// Byte offsets just past every `;` that is not nested inside braces or parentheses.
+    pub declaration_ends: Vec<usize>,
+}
+
+/// Unmerged parse output, keeping the raw `IDLProg`.
+#[derive(Debug)]
+pub struct ProgramParseResult {
+    pub program: Option<IDLProg>,
+    pub parse_errors: Vec<CandidError>,
+    pub semantic_tokens: Vec<ImCompleteSemanticToken>,
+    pub comments: Vec<Comment>,
+    pub declaration_ends: Vec<usize>,
 }
 
 /// Tokenize and parse a Candid source string, returning any partial data collected along the way.
 pub fn parse(src: &str) -> ParserResult {
+    let ProgramParseResult {
+        program,
+        parse_errors,
+        semantic_tokens,
+        comments,
+        declaration_ends,
+    } = parse_program(src);
+    ParserResult {
+        ast: program.map(IDLMergedProg::new),
+        parse_errors,
+        semantic_tokens,
+        comments,
+        declaration_ends,
+    }
+}
+
+/// Like [`parse`], but keeps the raw `IDLProg` so callers can combine it with other programs.
+pub fn parse_program(src: &str) -> ProgramParseResult {
     let trivia = TriviaMap::default();
     let mut tokenizer = RecordingTokenizer::new(src, trivia.clone());
-    let (ast, parser_errors) = IDLProg::parse_lossy_from_tokens(Some(&trivia), &mut tokenizer);
-    let ast = ast.map(IDLMergedProg::new);
-
-    let (semantic_tokens, lexer_errors) = tokenizer.into_parts();
+    let (program, parser_errors) = IDLProg::parse_lossy_from_tokens(Some(&trivia), &mut tokenizer);
+
+    let comments = collect_comments(
+        src,
+        &trivia,
+        &tokenizer.line_comments,
+        &tokenizer.block_comment_starts,
+    );
+    let (semantic_tokens, lexer_errors, declaration_ends) = tokenizer.into_parts();
     let mut parse_errors: Vec<CandidError> = lexer_errors
         .iter()
         .cloned()
@@ -59,30 +123,96 @@ pub fn parse(src: &str) -> ParserResult {
         parse_errors.push(CandidError::Parser(candid_parser::Error::Parse(err)));
     }
 
-    ParserResult {
-        ast,
+    ProgramParseResult {
+        program,
         parse_errors,
         semantic_tokens,
+        comments,
+        declaration_ends,
+    }
+}
+
+/// Copy a declaration, which the parser's syntax tree does not derive `Clone` for.
+pub(crate) fn clone_dec(dec: &Dec) -> Dec {
+    match dec {
+        Dec::TypD(binding) => Dec::TypD(binding.clone()),
+        Dec::ImportType { path, span } => Dec::ImportType {
+            path: path.clone(),
+            span: span.clone(),
+        },
+        Dec::ImportServ { path, span } => Dec::ImportServ {
+            path: path.clone(),
+            span: span.clone(),
+        },
+    }
+}
+
+/// Token stream of a source string without parsing, for documents that are not Candid programs.
+#[derive(Debug)]
+pub struct LexResult {
+    /// Tokens other than comments, with their start and end offsets.
+    pub tokens: Vec<(usize, Token, usize)>,
+    pub lexer_errors: Vec<LexicalError>,
+    pub semantic_tokens: Vec<ImCompleteSemanticToken>,
+}
+
+/// Tokenize `src`, dropping comments but recording them as semantic tokens.
+pub fn lex(src: &str) -> LexResult {
+    let mut tokenizer = RecordingTokenizer::new(src, TriviaMap::default());
+    let tokens = tokenizer
+        .by_ref()
+        .filter_map(Result::ok)
+        .filter(|(_, token, _)| !matches!(token, Token::LineComment | Token::StartComment))
+        .collect();
+    let (semantic_tokens, lexer_errors, _) = tokenizer.into_parts();
+    LexResult {
+        tokens,
+        lexer_errors,
+        semantic_tokens,
     }
 }
 
 struct RecordingTokenizer<'src> {
+    src: &'src str,
     inner: Tokenizer<'src>,
     semantic_tokens: Vec<ImCompleteSemanticToken>,
     lexer_errors: Vec<LexicalError>,
+    depth: usize,
+    declaration_ends: Vec<usize>,
+    line_comments: Vec<Span>,
+    /// Offset of the `/*` opening every block comment.
+    block_comment_starts: Vec<usize>,
 }
 
 impl<'src> RecordingTokenizer<'src> {
     fn new(src: &'src str, trivia: TriviaMap) -> Self {
         Self {
+            src,
             inner: Tokenizer::new_with_trivia(src, trivia),
             semantic_tokens: Vec::new(),
             lexer_errors: Vec::new(),
+            depth: 0,
+            declaration_ends: Vec::new(),
+            line_comments: Vec::new(),
+            block_comment_starts: Vec::new(),
         }
     }
 
-    fn into_parts(self) -> (Vec<ImCompleteSemanticToken>, Vec<LexicalError>) {
-        (self.semantic_tokens, self.lexer_errors)
+    fn into_parts(self) -> (Vec<ImCompleteSemanticToken>, Vec<LexicalError>, Vec<usize>) {
+        (
+            self.semantic_tokens,
+            self.lexer_errors,
+            self.declaration_ends,
+        )
+    }
+
+    fn track_nesting(&mut self, token: &Token, end: usize) {
+        match token {
+            Token::LBrace | Token::LParen => self.depth += 1,
+            Token::RBrace | Token::RParen => self.depth = self.depth.saturating_sub(1),
+            Token::Semi if self.depth == 0 => self.declaration_ends.push(end),
+            _ => {}
+        }
     }
 }
 
@@ -95,11 +225,19 @@ impl<'src> Iterator for RecordingTokenizer<'src> {
         match &token {
             Ok((start, token, end)) => {
                 let token_type = semantic_token_type_index(token);
+                let text = self.src.get(*start..*end).unwrap_or_default();
                 self.semantic_tokens.push(ImCompleteSemanticToken {
                     start: *start,
                     length: end - start,
                     token_type,
+                    token_modifiers: lexical_token_modifiers(token, text),
                 });
+                self.track_nesting(token, *end);
+                match token {
+                    Token::LineComment => self.line_comments.push(*start..*end),
+                    Token::StartComment => self.block_comment_starts.push(*start),
+                    _ => {}
+                }
             }
             Err(err) => self.lexer_errors.push(err.clone()),
         }
@@ -1,21 +1,25 @@
 use crate::{
-    candid_lang::{CandidError, ImCompleteSemanticToken, ParserResult, parse},
+    candid_lang::ImCompleteSemanticToken,
     lsp::{
+        bindings::{BindingLanguage, generate_bindings},
         completion::CompletionDocumentCache,
-        config::{CompletionEngineMode, ServerConfig, ServiceSnippetStyle},
+        config::{CompletionEngineMode, DiagnosticsConfig, ServerConfig, ServiceSnippetStyle},
+        diagnostics::{DIAGNOSTIC_IDENTIFIER, document_result_id, tag_result_id},
+        example_value::{EXAMPLE_VALUE_KIND, ExampleOptions},
         navigation::lookup_identifier,
         position::{offset_to_position, position_to_offset, span_to_range},
-        semantic_analyze::{Semantic, analyze_program},
+        project_config::ProjectConfigs,
+        semantic_analyze::Semantic,
         tasks::{DocumentTaskKind, DocumentTaskState, DocumentTaskToken},
+        trivia::Comment,
+        type_relation::{Relation, TypeRelation, TypeRelationParams, type_relation},
+        value_check::TypeEnv,
+        value_document::ValueTarget,
+        workspace::{DocumentKind, uri_to_path},
     },
 };
-use candid_parser::{
-    candid::{Error as CandidCoreError, error::Label as CandidLabel},
-    syntax::IDLMergedProg,
-    token::{LexicalError, Token},
-};
+use candid_parser::syntax::IDLMergedProg;
 use dashmap::DashMap;
-use lalrpop_util::ParseError;
 use rapidhash::fast::RandomState;
 use ropey::Rope;
 use serde_json::Value;
@@ -23,7 +27,12 @@ use std::{
     borrow::Cow,
     error::Error as StdError,
     fmt::Write,
-    sync::{Arc, Mutex, RwLock},
+    path::Path,
+    sync::{
+        Arc, Mutex, OnceLock, RwLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
 };
 use tower_lsp_server::{
     Client, LanguageServer,
@@ -31,34 +40,74 @@ use tower_lsp_server::{
     ls_types::{notification::Notification, *},
 };
 
+mod analysis;
+pub mod bindings;
+pub mod code_action;
+pub mod code_lens;
+pub mod commands;
 pub mod completion;
 pub mod config;
+pub mod diagnostic_rules;
+pub mod diagnostics;
+pub mod error_span;
+pub mod example_value;
 pub mod format;
 pub mod hover;
+pub mod incremental;
 pub mod markdown;
+pub mod message_codec;
+pub mod missing_docs;
+pub mod naming;
 pub mod navigation;
+pub mod normal_value;
+pub mod parse_diagnostics;
 pub mod position;
+pub mod project_config;
 pub mod semantic_analyze;
 pub mod semantic_token;
 pub mod span;
+pub mod suppression;
 pub mod symbol_table;
 pub mod tasks;
+pub mod test_suite;
+pub mod text_diff;
+pub mod trivia;
 pub mod type_display;
 pub mod type_docs;
-
+pub mod type_relation;
+pub mod usage_hierarchy;
+pub mod value;
+pub mod value_check;
+pub mod value_document;
+pub mod workspace;
+
+use analysis::AnalysisJob;
+use code_action::code_action as code_action_handler;
+use code_lens::code_lens as code_lens_handler;
+use commands::{COMMANDS, execute_command as execute_command_handler};
 use completion::completion as completion_handler;
-use format::format as format_handler;
+use diagnostics::{document_diagnostic, workspace_diagnostic as workspace_diagnostic_handler};
+use format::{
+    FormatOptions, ON_TYPE_TRIGGERS, format as format_handler, format_on_type, format_range,
+};
 use hover::hover;
-use semantic_token::LEGEND_TYPES;
+use incremental::{EditLog, PreviousProgram, ProgramChunks, TextChange};
+use semantic_token::{LEGEND_MODIFIERS, LEGEND_TYPES, semantic_tokens_edits};
+use usage_hierarchy::{UsageIndex, incoming_usages, outgoing_usages, prepare_usage_hierarchy};
 
 #[derive(Debug)]
 pub struct CandidLanguageServer {
     pub client: Client,
     pub documents: DashMap<String, DocumentSnapshot, RandomState>,
-    pub analysis_map: DashMap<String, AnalysisSnapshot, RandomState>,
+    pub analysis_map: Arc<DashMap<String, AnalysisSnapshot, RandomState>>,
     pub task_states: DashMap<String, Arc<DocumentTaskState>, RandomState>,
     config: RwLock<ServerConfig>,
+    project_configs: RwLock<ProjectConfigs>,
     hover_offset_cache: Mutex<HoverOffsetCache>,
+    workspace_folders: RwLock<Vec<Uri>>,
+    pull_diagnostics: AtomicBool,
+    watch_config_files: AtomicBool,
+    semantic_tokens_generation: Arc<AtomicU64>,
 }
 
 impl LanguageServer for CandidLanguageServer {
@@ -66,6 +115,25 @@ impl LanguageServer for CandidLanguageServer {
         if let Some(options) = params.initialization_options {
             self.apply_settings_value(options);
         }
+        let pull_diagnostics = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .is_some_and(|caps| caps.diagnostic.is_some());
+        self.pull_diagnostics
+            .store(pull_diagnostics, Ordering::Relaxed);
+        let watch_config_files = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|caps| caps.did_change_watched_files.as_ref())
+            .and_then(|caps| caps.dynamic_registration)
+            .unwrap_or(false);
+        self.watch_config_files
+            .store(watch_config_files, Ordering::Relaxed);
+        if let Some(folders) = params.workspace_folders {
+            self.set_workspace_folders(folders.into_iter().map(|folder| folder.uri).collect());
+        }
         Ok(InitializeResult {
             server_info: None,
             capabilities: ServerCapabilities {
@@ -100,17 +168,49 @@ impl LanguageServer for CandidLanguageServer {
                             },
                             legend: SemanticTokensLegend {
                                 token_types: LEGEND_TYPES.to_vec(),
-                                token_modifiers: vec![],
+                                token_modifiers: LEGEND_MODIFIERS.to_vec(),
                             },
                             range: Some(true),
-                            full: Some(SemanticTokensFullOptions::Bool(true)),
+                            full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
                         },
                     ),
                 ),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
                 definition_provider: Some(OneOf::Left(true)),
                 references_provider: Some(OneOf::Left(true)),
+                call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
                 document_formatting_provider: Some(OneOf::Left(true)),
+                document_range_formatting_provider: Some(OneOf::Left(true)),
+                document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+                    first_trigger_character: ON_TYPE_TRIGGERS[0].to_string(),
+                    more_trigger_character: Some(
+                        ON_TYPE_TRIGGERS[1..]
+                            .iter()
+                            .map(|ch| ch.to_string())
+                            .collect(),
+                    ),
+                }),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![CodeActionKind::QUICKFIX, EXAMPLE_VALUE_KIND]),
+                        ..CodeActionOptions::default()
+                    },
+                )),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(false),
+                }),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: COMMANDS.iter().map(ToString::to_string).collect(),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
+                diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
+                    DiagnosticOptions {
+                        identifier: Some(DIAGNOSTIC_IDENTIFIER.to_string()),
+                        inter_file_dependencies: false,
+                        workspace_diagnostics: true,
+                        work_done_progress_options: WorkDoneProgressOptions::default(),
+                    },
+                )),
                 ..ServerCapabilities::default()
             },
             #[cfg(feature = "proposed")]
@@ -123,6 +223,9 @@ impl LanguageServer for CandidLanguageServer {
             .log_message(MessageType::INFO, "initialized")
             .await;
         let _ = self.refresh_configuration().await;
+        if self.watch_config_files.load(Ordering::Relaxed) {
+            self.watch_project_config_files().await;
+        }
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -137,6 +240,7 @@ impl LanguageServer for CandidLanguageServer {
         let version_label = Self::version_tag(Some(version));
         self.log_info_event("did_open", format!("uri={} {}", uri_label, version_label))
             .await;
+        self.attach_project_config(&uri).await;
         let text = text_document.text;
         let rope = Rope::from_str(&text);
         self.on_change(TextDocumentItem {
@@ -144,6 +248,8 @@ impl LanguageServer for CandidLanguageServer {
             rope,
             text: Cow::Owned(text),
             version: Some(version),
+            edits: EditLog::new(Some(version)),
+            debounce: false,
         })
         .await
     }
@@ -168,11 +274,17 @@ impl LanguageServer for CandidLanguageServer {
             ),
         )
         .await;
-        let (mut rope, current_version) = if let Some(doc) = self.documents.get(&uri_key) {
-            (doc.rope().clone(), doc.version())
+        let (mut rope, current_version, mut edits) = if let Some(doc) = self.documents.get(&uri_key)
+        {
+            (doc.rope().clone(), doc.version(), doc.edits.clone())
         } else {
-            (Rope::default(), None)
+            (Rope::default(), None, EditLog::default())
         };
+        edits.prune(
+            self.analysis_map
+                .get(&uri_key)
+                .and_then(|snapshot| snapshot.version()),
+        );
 
         for change in content_changes {
             let TextDocumentContentChangeEvent { range, text, .. } = change;
@@ -180,6 +292,7 @@ impl LanguageServer for CandidLanguageServer {
             match range {
                 None => {
                     rope = Rope::from_str(&text);
+                    edits = EditLog::new(version);
                 }
                 Some(range) => {
                     let mut start_offset = self
@@ -197,6 +310,15 @@ impl LanguageServer for CandidLanguageServer {
                         continue;
                     }
 
+                    let start = rope.char_to_byte(start_offset);
+                    edits.record(
+                        version,
+                        TextChange {
+                            start,
+                            old_end: rope.char_to_byte(end_offset),
+                            new_end: start + text.len(),
+                        },
+                    );
                     rope.remove(start_offset..end_offset);
                     rope.insert(start_offset, &text);
                 }
@@ -210,6 +332,8 @@ impl LanguageServer for CandidLanguageServer {
             rope,
             text: Cow::Owned(text),
             version,
+            edits,
+            debounce: true,
         })
         .await
     }
@@ -229,10 +353,18 @@ impl LanguageServer for CandidLanguageServer {
                 rope: Rope::from_str(&text),
                 text: Cow::Owned(text),
                 version: None,
+                edits: EditLog::new(None),
+                debounce: false,
             };
             self.on_change(item).await;
             _ = self.client.semantic_tokens_refresh().await;
         }
+        if DocumentKind::of(&uri) == DocumentKind::Interface {
+            self.reanalyze_value_documents().await;
+            if self.pull_diagnostics_enabled() {
+                _ = self.client.workspace_diagnostic_refresh().await;
+            }
+        }
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
@@ -240,15 +372,75 @@ impl LanguageServer for CandidLanguageServer {
         let uri_label = uri.to_string();
         self.log_info_event("did_close", format!("uri={}", uri_label))
             .await;
-        self.task_states.remove(&uri_label);
+        if let Some((_, state)) = self.task_states.remove(&uri_label) {
+            state.token(DocumentTaskKind::Analysis).cancel();
+        }
+        // Workspace diagnostics read the file from disk again once the buffer is gone.
+        self.documents.remove(&uri_label);
+        self.analysis_map.remove(&uri_label);
+        if let Ok(mut cache) = self.hover_offset_cache.lock() {
+            cache.invalidate_uri(&uri_label);
+        }
+        self.project_configs
+            .write()
+            .unwrap_or_else(|poison| poison.into_inner())
+            .attach(&uri_label, None);
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let config_changed = params.changes.iter().any(|change| {
+            uri_to_path(&change.uri)
+                .as_deref()
+                .and_then(Path::file_name)
+                .is_some_and(|name| project_config::is_config_file_name(&name.to_string_lossy()))
+        });
+        self.log_info_event(
+            "did_change_watched_files",
+            format!(
+                "changes={} config_changed={config_changed}",
+                params.changes.len()
+            ),
+        )
+        .await;
+        if config_changed {
+            self.reload_project_configs().await;
+        }
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        let DidChangeWorkspaceFoldersParams { event } = params;
+        self.log_info_event(
+            "did_change_workspace_folders",
+            format!(
+                "added={} removed={}",
+                event.added.len(),
+                event.removed.len()
+            ),
+        )
+        .await;
+        let mut folders = self.workspace_folders();
+        folders.retain(|uri| !event.removed.iter().any(|folder| &folder.uri == uri));
+        for folder in event.added {
+            if !folders.contains(&folder.uri) {
+                folders.push(folder.uri);
+            }
+        }
+        self.set_workspace_folders(folders);
     }
 
     async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
         self.log_info_event("did_change_configuration", "".to_string())
             .await;
+        let previous_diagnostics = self.editor_diagnostics_config();
         if !self.refresh_configuration().await {
             self.apply_settings_value(params.settings);
         }
+        if self.editor_diagnostics_config() != previous_diagnostics {
+            self.reanalyze_open_documents().await;
+            if self.pull_diagnostics_enabled() {
+                _ = self.client.workspace_diagnostic_refresh().await;
+            }
+        }
     }
 
     async fn goto_definition(
@@ -274,10 +466,9 @@ impl LanguageServer for CandidLanguageServer {
             let uri = params.text_document_position_params.text_document.uri;
             let uri_key = uri.to_string();
             let analysis = self.analysis_map.get(&uri_key)?;
-            let document = self.documents.get(&uri_key)?;
             let semantic = analysis.semantic()?;
-            let rope = document.rope();
-            let version = document.version();
+            let rope = analysis.rope();
+            let version = analysis.version();
             let position = params.text_document_position_params.position;
             let offset = self.cached_position_to_offset(&uri_key, position, rope, version)?;
 
@@ -346,10 +537,9 @@ impl LanguageServer for CandidLanguageServer {
             let uri = params.text_document_position.text_document.uri;
             let uri_key = uri.to_string();
             let analysis = self.analysis_map.get(&uri_key)?;
-            let document = self.documents.get(&uri_key)?;
             let semantic = analysis.semantic()?;
-            let rope = document.rope();
-            let version = document.version();
+            let rope = analysis.rope();
+            let version = analysis.version();
             let offset = self.cached_position_to_offset(&uri_key, position, rope, version)?;
 
             let info = lookup_identifier(semantic, offset)?;
@@ -512,6 +702,259 @@ impl LanguageServer for CandidLanguageServer {
         result
     }
 
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let uri_label = params.text_document.uri.to_string();
+        self.log_info_event(
+            "range_formatting",
+            format!(
+                "uri={} start_line={} end_line={}",
+                uri_label, params.range.start.line, params.range.end.line
+            ),
+        )
+        .await;
+        let result = format_range(self, params).await;
+        match &result {
+            Ok(edits) => {
+                self.log_info_event(
+                    "range_formatting_result",
+                    format!(
+                        "uri={} edits={}",
+                        uri_label,
+                        edits.as_ref().map_or(0, Vec::len)
+                    ),
+                )
+                .await;
+            }
+            Err(err) => {
+                self.log_warn_event(
+                    "range_formatting_error",
+                    format!("uri={} error={err}", uri_label),
+                )
+                .await;
+            }
+        }
+        result
+    }
+
+    async fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let uri_label = params.text_document_position.text_document.uri.to_string();
+        let position = params.text_document_position.position;
+        self.log_info_event(
+            "on_type_formatting",
+            format!(
+                "uri={} line={} character={} ch={}",
+                uri_label, position.line, position.character, params.ch
+            ),
+        )
+        .await;
+        let result = format_on_type(self, params).await;
+        if let Ok(edits) = &result {
+            self.log_info_event(
+                "on_type_formatting_result",
+                format!(
+                    "uri={} edits={}",
+                    uri_label,
+                    edits.as_ref().map_or(0, Vec::len)
+                ),
+            )
+            .await;
+        }
+        result
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri_label = params.text_document.uri.to_string();
+        self.log_info_event(
+            "code_action",
+            format!(
+                "uri={} diagnostics={}",
+                uri_label,
+                params.context.diagnostics.len()
+            ),
+        )
+        .await;
+        let result = code_action_handler(self, params).await;
+        match &result {
+            Ok(actions) => {
+                self.log_info_event(
+                    "code_action_result",
+                    format!(
+                        "uri={} actions={}",
+                        uri_label,
+                        actions.as_ref().map_or(0, Vec::len)
+                    ),
+                )
+                .await;
+            }
+            Err(err) => {
+                self.log_warn_event(
+                    "code_action_error",
+                    format!("uri={} error={err}", uri_label),
+                )
+                .await;
+            }
+        }
+        result
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        let uri_label = params.text_document.uri.to_string();
+        self.log_info_event("code_lens", format!("uri={}", uri_label))
+            .await;
+        let result = code_lens_handler(self, params).await;
+        match &result {
+            Ok(lenses) => {
+                self.log_info_event(
+                    "code_lens_result",
+                    format!(
+                        "uri={} lenses={}",
+                        uri_label,
+                        lenses.as_ref().map_or(0, Vec::len)
+                    ),
+                )
+                .await;
+            }
+            Err(err) => {
+                self.log_warn_event("code_lens_error", format!("uri={} error={err}", uri_label))
+                    .await;
+            }
+        }
+        result
+    }
+
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> Result<Option<Vec<CallHierarchyItem>>> {
+        let uri_label = params
+            .text_document_position_params
+            .text_document
+            .uri
+            .to_string();
+        self.log_info_event("prepare_call_hierarchy", format!("uri={}", uri_label))
+            .await;
+        let result = prepare_usage_hierarchy(self, params).await;
+        if let Ok(items) = &result {
+            self.log_info_event(
+                "prepare_call_hierarchy_result",
+                format!("uri={} found={}", uri_label, items.is_some()),
+            )
+            .await;
+        }
+        result
+    }
+
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        let label = format!("uri={} item={}", params.item.uri.as_str(), params.item.name);
+        self.log_info_event("incoming_calls", label.clone()).await;
+        let result = incoming_usages(self, params).await;
+        if let Ok(calls) = &result {
+            self.log_info_event(
+                "incoming_calls_result",
+                format!("{} users={}", label, calls.as_ref().map_or(0, Vec::len)),
+            )
+            .await;
+        }
+        result
+    }
+
+    async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        let label = format!("uri={} item={}", params.item.uri.as_str(), params.item.name);
+        self.log_info_event("outgoing_calls", label.clone()).await;
+        let result = outgoing_usages(self, params).await;
+        if let Ok(calls) = &result {
+            self.log_info_event(
+                "outgoing_calls_result",
+                format!("{} uses={}", label, calls.as_ref().map_or(0, Vec::len)),
+            )
+            .await;
+        }
+        result
+    }
+
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        let command = params.command.clone();
+        self.log_info_event("execute_command", format!("command={}", command))
+            .await;
+        let result = execute_command_handler(self, params).await;
+        if let Err(err) = &result {
+            self.log_warn_event(
+                "execute_command_error",
+                format!("command={} error={err}", command),
+            )
+            .await;
+        }
+        result
+    }
+
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult> {
+        let uri_label = params.text_document.uri.to_string();
+        self.log_info_event("diagnostic", format!("uri={}", uri_label))
+            .await;
+        let result = document_diagnostic(self, params).await;
+        let outcome = match &result {
+            Ok(DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report))) => {
+                format!(
+                    "full items={}",
+                    report.full_document_diagnostic_report.items.len()
+                )
+            }
+            Ok(DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Unchanged(_))) => {
+                "unchanged".to_string()
+            }
+            Ok(DocumentDiagnosticReportResult::Partial(_)) => "partial".to_string(),
+            Err(err) => format!("error={err}"),
+        };
+        self.log_info_event(
+            "diagnostic_result",
+            format!("uri={} {}", uri_label, outcome),
+        )
+        .await;
+        result
+    }
+
+    async fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> Result<WorkspaceDiagnosticReportResult> {
+        self.log_info_event(
+            "workspace_diagnostic",
+            format!("previous={}", params.previous_result_ids.len()),
+        )
+        .await;
+        let result = workspace_diagnostic_handler(self, params).await;
+        match &result {
+            Ok(WorkspaceDiagnosticReportResult::Report(report)) => {
+                self.log_info_event(
+                    "workspace_diagnostic_result",
+                    format!("documents={}", report.items.len()),
+                )
+                .await;
+            }
+            Ok(WorkspaceDiagnosticReportResult::Partial(_)) => {}
+            Err(err) => {
+                self.log_warn_event("workspace_diagnostic_error", format!("error={err}"))
+                    .await;
+            }
+        }
+        result
+    }
+
     async fn semantic_tokens_full(
         &self,
         params: SemanticTokensParams,
@@ -520,7 +963,7 @@ impl LanguageServer for CandidLanguageServer {
         let uri_label = uri.to_string();
         self.log_info_event("semantic_tokens_full", format!("uri={}", uri_label))
             .await;
-        let result = self.semantic_tokens_for_range(&uri_label, None);
+        let result = self.full_semantic_tokens(&uri_label);
         self.log_info_event(
             "semantic_tokens_full_result",
             format!(
@@ -533,6 +976,37 @@ impl LanguageServer for CandidLanguageServer {
         Ok(result.map(SemanticTokensResult::Tokens))
     }
 
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> Result<Option<SemanticTokensFullDeltaResult>> {
+        let uri_label = params.text_document.uri.to_string();
+        self.log_info_event(
+            "semantic_tokens_full_delta",
+            format!("uri={} previous={}", uri_label, params.previous_result_id),
+        )
+        .await;
+        let result = self.semantic_tokens_delta(&uri_label, &params.previous_result_id);
+        let outcome = match &result {
+            Some(SemanticTokensFullDeltaResult::TokensDelta(delta)) => {
+                format!("edits={}", delta.edits.len())
+            }
+            Some(SemanticTokensFullDeltaResult::Tokens(tokens)) => {
+                format!("tokens={}", tokens.data.len())
+            }
+            Some(SemanticTokensFullDeltaResult::PartialTokensDelta { edits }) => {
+                format!("edits={}", edits.len())
+            }
+            None => "none".to_string(),
+        };
+        self.log_info_event(
+            "semantic_tokens_full_delta_result",
+            format!("uri={} {}", uri_label, outcome),
+        )
+        .await;
+        Ok(result)
+    }
+
     async fn semantic_tokens_range(
         &self,
         params: SemanticTokensRangeParams,
@@ -567,17 +1041,26 @@ struct TextDocumentItem<'a> {
     rope: Rope,
     text: Cow<'a, str>,
     version: Option<i32>,
+    /// Ranged edits since the last published analysis, so it can be spliced instead of redone.
+    edits: EditLog,
+    /// Whether analysis waits for typing to settle before running in the background.
+    debounce: bool,
 }
 
 #[derive(Debug)]
 pub struct DocumentSnapshot {
     rope: Rope,
     version: Option<i32>,
+    edits: EditLog,
 }
 
 impl DocumentSnapshot {
-    fn new(rope: Rope, version: Option<i32>) -> Self {
-        Self { rope, version }
+    fn new(rope: Rope, version: Option<i32>, edits: EditLog) -> Self {
+        Self {
+            rope,
+            version,
+            edits,
+        }
     }
 
     fn rope(&self) -> &Rope {
@@ -591,33 +1074,150 @@ impl DocumentSnapshot {
 
 #[derive(Debug)]
 pub struct AnalysisSnapshot {
+    rope: Rope,
     ast: Option<IDLMergedProg>,
     semantic: Option<Semantic>,
     completion_cache: Option<CompletionDocumentCache>,
     semantic_tokens: Vec<ImCompleteSemanticToken>,
     parse_errors: usize,
+    diagnostics: Vec<Diagnostic>,
+    diagnostic_result_id: String,
+    semantic_tokens_result_id: String,
+    encoded_semantic_tokens: OnceLock<Arc<[SemanticToken]>>,
+    previous_semantic_tokens: Option<(String, Arc<[SemanticToken]>)>,
+    usage_index: OnceLock<UsageIndex>,
+    type_env: Option<TypeEnv>,
+    value_target: Option<ValueTarget>,
+    rust_bindings: OnceLock<Option<String>>,
+    comments: Vec<Comment>,
+    chunks: Option<ProgramChunks>,
     version: Option<i32>,
 }
 
 impl AnalysisSnapshot {
+    #[allow(clippy::too_many_arguments)]
     fn new(
+        rope: Rope,
         ast: Option<IDLMergedProg>,
         semantic: Option<Semantic>,
         completion_cache: Option<CompletionDocumentCache>,
         semantic_tokens: Vec<ImCompleteSemanticToken>,
         parse_errors: usize,
+        diagnostics: Vec<Diagnostic>,
         version: Option<i32>,
     ) -> Self {
+        let diagnostic_result_id = document_result_id(version, &diagnostics);
         Self {
+            rope,
             ast,
             semantic,
             completion_cache,
             semantic_tokens,
             parse_errors,
+            diagnostics,
+            diagnostic_result_id,
+            semantic_tokens_result_id: String::new(),
+            encoded_semantic_tokens: OnceLock::new(),
+            previous_semantic_tokens: None,
+            usage_index: OnceLock::new(),
+            type_env: None,
+            value_target: None,
+            rust_bindings: OnceLock::new(),
+            comments: Vec::new(),
+            chunks: None,
             version,
         }
     }
 
+    /// Attach the semantic-token result id and the last encoded tokens sent for this document.
+    fn with_semantic_tokens_history(
+        mut self,
+        result_id: String,
+        previous: Option<(String, Arc<[SemanticToken]>)>,
+    ) -> Self {
+        self.semantic_tokens_result_id = result_id;
+        self.previous_semantic_tokens = previous;
+        self
+    }
+
+    /// Tie the diagnostic result id to the configuration the diagnostics were produced with.
+    fn with_diagnostics_config(mut self, config: &DiagnosticsConfig) -> Self {
+        self.diagnostic_result_id =
+            tag_result_id(std::mem::take(&mut self.diagnostic_result_id), config);
+        self
+    }
+
+    /// Derive the diagnostic result id from the diagnostics themselves, for documents whose
+    /// diagnostics can change without a new version, such as `.args` files checked against an
+    /// interface on disk.
+    fn with_content_result_id(mut self) -> Self {
+        self.diagnostic_result_id = document_result_id(None, &self.diagnostics);
+        self
+    }
+
+    /// Keep the type environment of the program and its imports for value and type requests.
+    fn with_type_env(mut self, type_env: Option<TypeEnv>) -> Self {
+        self.type_env = type_env;
+        self
+    }
+
+    /// Keep the method a value document was checked against, for completion.
+    fn with_value_target(mut self, target: Option<ValueTarget>) -> Self {
+        self.value_target = target;
+        self
+    }
+
+    /// Keep the comments of the parse so formatting can attach them to the printed items.
+    fn with_comments(mut self, comments: Vec<Comment>) -> Self {
+        self.comments = comments;
+        self
+    }
+
+    /// Keep where the declarations end, so the next edit can re-parse only the ones it touches.
+    fn with_chunks(mut self, chunks: Option<ProgramChunks>) -> Self {
+        self.chunks = chunks;
+        self
+    }
+
+    /// The parts of this analysis an edit can be spliced into.
+    fn program(&self) -> Option<PreviousProgram<'_>> {
+        Some(PreviousProgram {
+            ast: self.ast.as_ref()?,
+            semantic: self.semantic.as_ref(),
+            comments: &self.comments,
+            chunks: self.chunks.as_ref()?,
+        })
+    }
+
+    /// Full token array in LSP encoding, computed on first use.
+    fn encoded_semantic_tokens(&self) -> Arc<[SemanticToken]> {
+        let data = self.encoded_semantic_tokens.get_or_init(|| {
+            Arc::from(build_semantic_tokens(&self.semantic_tokens, &self.rope, None).data)
+        });
+        Arc::clone(data)
+    }
+
+    /// Encoded tokens already sent to the client, if any request has produced them.
+    fn sent_semantic_tokens(&self) -> Option<(String, Arc<[SemanticToken]>)> {
+        self.encoded_semantic_tokens
+            .get()
+            .map(|data| (self.semantic_tokens_result_id.clone(), Arc::clone(data)))
+            .or_else(|| self.previous_semantic_tokens.clone())
+    }
+
+    /// Whether the encoded tokens differ from the last ones sent for this document, so the
+    /// client has to be asked to request them again.
+    fn semantic_tokens_changed(&self) -> bool {
+        self.previous_semantic_tokens
+            .as_ref()
+            .is_some_and(|(_, sent)| sent.as_ref() != self.encoded_semantic_tokens().as_ref())
+    }
+
+    /// Text the snapshot was analysed from; spans in the AST and `Semantic` refer to it.
+    fn rope(&self) -> &Rope {
+        &self.rope
+    }
+
     fn ast(&self) -> Option<&IDLMergedProg> {
         self.ast.as_ref()
     }
@@ -626,6 +1226,28 @@ impl AnalysisSnapshot {
         self.semantic.as_ref()
     }
 
+    /// Enclosing declaration of every reference in the semantic model, computed on first use.
+    fn usage_index(&self) -> Option<&UsageIndex> {
+        let semantic = self.semantic.as_ref()?;
+        Some(self.usage_index.get_or_init(|| UsageIndex::build(semantic)))
+    }
+
+    fn type_env(&self) -> Option<&TypeEnv> {
+        self.type_env.as_ref()
+    }
+
+    fn value_target(&self) -> Option<&ValueTarget> {
+        self.value_target.as_ref()
+    }
+
+    /// Rust bindings of the program, generated on first use; `None` when it does not check.
+    fn rust_bindings(&self) -> Option<&str> {
+        let (ast, env) = (self.ast.as_ref()?, self.type_env.as_ref()?);
+        self.rust_bindings
+            .get_or_init(|| generate_bindings(ast, env, BindingLanguage::Rust).ok())
+            .as_deref()
+    }
+
     fn completion_cache(&self) -> Option<&CompletionDocumentCache> {
         self.completion_cache.as_ref()
     }
@@ -634,11 +1256,22 @@ impl AnalysisSnapshot {
         &self.semantic_tokens
     }
 
+    fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
     fn has_parse_errors(&self) -> bool {
         self.parse_errors > 0
     }
 
-    #[allow(dead_code)]
+    fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    fn diagnostic_result_id(&self) -> &str {
+        &self.diagnostic_result_id
+    }
+
     fn version(&self) -> Option<i32> {
         self.version
     }
@@ -652,51 +1285,229 @@ impl CandidLanguageServer {
         Self {
             client,
             documents: DashMap::with_hasher(hasher),
-            analysis_map: DashMap::with_hasher(hasher),
+            analysis_map: Arc::new(DashMap::with_hasher(hasher)),
             task_states: DashMap::with_hasher(hasher),
             config: RwLock::new(ServerConfig::default()),
+            project_configs: RwLock::new(ProjectConfigs::default()),
             hover_offset_cache: Mutex::new(HoverOffsetCache::new(64)),
+            workspace_folders: RwLock::new(Vec::new()),
+            pull_diagnostics: AtomicBool::new(false),
+            watch_config_files: AtomicBool::new(false),
+            semantic_tokens_generation: Arc::new(AtomicU64::new(0)),
         }
     }
 
-    pub fn service_snippet_style(&self) -> ServiceSnippetStyle {
+    /// Answer the `candid/isSubtype` request.
+    pub async fn is_subtype(&self, params: TypeRelationParams) -> Result<TypeRelation> {
+        self.log_info_event(
+            "is_subtype",
+            format!(
+                "uri={} a={} b={}",
+                params.uri.as_str(),
+                params.type_a,
+                params.type_b
+            ),
+        )
+        .await;
+        type_relation(self, params, Relation::Subtype)
+    }
+
+    /// Answer the `candid/typeEquals` request.
+    pub async fn type_equals(&self, params: TypeRelationParams) -> Result<TypeRelation> {
+        self.log_info_event(
+            "type_equals",
+            format!(
+                "uri={} a={} b={}",
+                params.uri.as_str(),
+                params.type_a,
+                params.type_b
+            ),
+        )
+        .await;
+        type_relation(self, params, Relation::Equal)
+    }
+
+    pub fn workspace_folders(&self) -> Vec<Uri> {
         let guard = self
-            .config
+            .workspace_folders
             .read()
             .unwrap_or_else(|poison| poison.into_inner());
-        guard.service_snippet_style()
+        guard.clone()
     }
 
-    pub fn completion_mode(&self, rope: &Rope) -> CompletionEngineMode {
-        let guard = self
-            .config
-            .read()
+    fn set_workspace_folders(&self, folders: Vec<Uri>) {
+        let mut guard = self
+            .workspace_folders
+            .write()
             .unwrap_or_else(|poison| poison.into_inner());
-        guard.completion_mode_for(rope)
+        *guard = folders;
     }
 
-    pub fn format_enabled(&self) -> bool {
+    /// Whether the client pulls diagnostics itself, making `publishDiagnostics` redundant.
+    fn pull_diagnostics_enabled(&self) -> bool {
+        self.pull_diagnostics.load(Ordering::Relaxed)
+    }
+
+    /// Read the settings for the document `uri`: its project configuration merged under the
+    /// editor settings, or the editor settings alone.
+    fn with_config<T>(&self, uri: &str, read: impl FnOnce(&ServerConfig) -> T) -> T {
+        let projects = self
+            .project_configs
+            .read()
+            .unwrap_or_else(|poison| poison.into_inner());
+        if let Some(config) = projects.config_for(uri) {
+            return read(config);
+        }
+        drop(projects);
         let guard = self
             .config
             .read()
             .unwrap_or_else(|poison| poison.into_inner());
-        guard.format_enabled()
+        read(&guard)
+    }
+
+    pub fn service_snippet_style(&self, uri: &str) -> ServiceSnippetStyle {
+        self.with_config(uri, ServerConfig::service_snippet_style)
+    }
+
+    pub fn completion_mode(&self, uri: &str, rope: &Rope) -> CompletionEngineMode {
+        self.with_config(uri, |config| config.completion_mode_for(rope))
+    }
+
+    pub fn format_enabled(&self, uri: &str) -> bool {
+        self.with_config(uri, ServerConfig::format_enabled)
+    }
+
+    pub fn format_options(&self, uri: &str, client: &FormattingOptions) -> FormatOptions {
+        self.with_config(uri, |config| config.format_options(client))
+    }
+
+    pub fn diagnostics_config(&self, uri: &str) -> DiagnosticsConfig {
+        self.with_config(uri, |config| config.diagnostics().clone())
+    }
+
+    pub fn analysis_debounce(&self, uri: &str) -> Duration {
+        self.with_config(uri, ServerConfig::analysis_debounce)
+    }
+
+    pub fn hover_rust_bindings(&self, uri: &str) -> bool {
+        self.with_config(uri, ServerConfig::hover_rust_bindings)
+    }
+
+    pub fn hover_expand_depth(&self, uri: &str) -> usize {
+        self.with_config(uri, ServerConfig::hover_expand_depth)
+    }
+
+    pub fn example_options(&self, uri: &str) -> ExampleOptions {
+        self.with_config(uri, ServerConfig::example_options)
     }
 
-    pub fn format_indent_width(&self) -> Option<usize> {
+    /// Diagnostic settings from the editor alone, which override every project file.
+    fn editor_diagnostics_config(&self) -> DiagnosticsConfig {
         let guard = self
             .config
             .read()
             .unwrap_or_else(|poison| poison.into_inner());
-        guard.format_indent_width()
+        guard.diagnostics().clone()
     }
 
-    pub fn format_blank_lines(&self) -> Option<usize> {
-        let guard = self
-            .config
+    /// Diagnostic settings for the file at `path`, which need not be open.
+    pub async fn diagnostics_config_for_path(&self, path: &Path) -> DiagnosticsConfig {
+        let Some(config_path) = project_config::discover(path) else {
+            return self.editor_diagnostics_config();
+        };
+        self.ensure_project_config(&config_path).await;
+        let config = self
+            .project_configs
             .read()
+            .unwrap_or_else(|poison| poison.into_inner())
+            .config_at(&config_path)
+            .map(|config| config.diagnostics().clone());
+        config.unwrap_or_else(|| self.editor_diagnostics_config())
+    }
+
+    /// Load the configuration file at `path` unless it already is, reporting invalid files.
+    async fn ensure_project_config(&self, path: &Path) {
+        let result = {
+            let mut projects = self
+                .project_configs
+                .write()
+                .unwrap_or_else(|poison| poison.into_inner());
+            if projects.is_loaded(path) {
+                return;
+            }
+            projects.load(path)
+        };
+        match result {
+            Ok(()) => {
+                self.log_info_event("project_config", format!("loaded {}", path.display()))
+                    .await
+            }
+            Err(err) => {
+                self.log_warn_event("project_config_error", err.to_string())
+                    .await
+            }
+        }
+    }
+
+    /// Let the nearest project configuration file govern the document `uri`.
+    async fn attach_project_config(&self, uri: &Uri) {
+        let config_path = uri_to_path(uri).and_then(|path| project_config::discover(&path));
+        if let Some(config_path) = &config_path {
+            self.ensure_project_config(config_path).await;
+        }
+        let mut projects = self
+            .project_configs
+            .write()
             .unwrap_or_else(|poison| poison.into_inner());
-        guard.format_blank_lines()
+        projects.attach(uri.as_str(), config_path);
+    }
+
+    /// Ask the client to report changes to files that can hold project settings.
+    async fn watch_project_config_files(&self) {
+        let watchers = std::iter::once(project_config::CONFIG_FILE_NAME)
+            .chain(project_config::PROJECT_FILE_NAMES)
+            .map(|name| FileSystemWatcher {
+                glob_pattern: GlobPattern::String(format!("**/{name}")),
+                kind: None,
+            })
+            .collect();
+        let registration = Registration {
+            id: "candid-ls-project-config".to_string(),
+            method: notification::DidChangeWatchedFiles::METHOD.to_string(),
+            register_options: serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
+                watchers,
+            })
+            .ok(),
+        };
+        if let Err(err) = self.client.register_capability(vec![registration]).await {
+            self.log_warn_event(
+                "project_config_error",
+                format!("failed to watch configuration files: {err}"),
+            )
+            .await;
+        }
+    }
+
+    /// Reload project configuration after a configuration file changed on disk, re-attach open
+    /// documents, since a new file may now be nearer, and re-check them.
+    async fn reload_project_configs(&self) {
+        self.project_configs
+            .write()
+            .unwrap_or_else(|poison| poison.into_inner())
+            .clear_files();
+        let open = self
+            .documents
+            .iter()
+            .filter_map(|entry| entry.key().parse::<Uri>().ok())
+            .collect::<Vec<_>>();
+        for uri in open {
+            self.attach_project_config(&uri).await;
+        }
+        self.reanalyze_open_documents().await;
+        if self.pull_diagnostics_enabled() {
+            _ = self.client.workspace_diagnostic_refresh().await;
+        }
     }
 
     pub fn task_token(&self, uri: &str, kind: DocumentTaskKind) -> DocumentTaskToken {
@@ -746,6 +1557,10 @@ impl CandidLanguageServer {
         if value.is_null() {
             return;
         }
+        self.project_configs
+            .write()
+            .unwrap_or_else(|poison| poison.into_inner())
+            .apply_editor_settings(value.clone());
         let mut guard = self
             .config
             .write()
@@ -760,7 +1575,7 @@ impl CandidLanguageServer {
         }
     }
 
-    fn event_message(event: &str, details: &str) -> String {
+    pub(crate) fn event_message(event: &str, details: &str) -> String {
         if details.is_empty() {
             format!("[{event}]")
         } else {
@@ -790,6 +1605,8 @@ impl CandidLanguageServer {
             rope,
             text,
             version,
+            edits,
+            debounce,
         } = params;
         let uri_key = uri.to_string();
         let version_label = Self::version_tag(version);
@@ -801,149 +1618,71 @@ impl CandidLanguageServer {
 
         self.documents.insert(
             uri_key.clone(),
-            DocumentSnapshot::new(rope.clone(), version),
+            DocumentSnapshot::new(rope.clone(), version, edits.clone()),
         );
         if let Ok(mut cache) = self.hover_offset_cache.lock() {
             cache.invalidate_uri(&uri_key);
         }
 
-        let ParserResult {
-            ast,
-            parse_errors,
-            semantic_tokens,
-        } = parse(&text);
-        let parse_error_count = parse_errors.len();
-        self.log_info_event(
-            "parse",
-            format!("uri={} parse_errors={}", uri_key, parse_error_count),
-        )
-        .await;
+        let token = self.task_token(&uri_key, DocumentTaskKind::Analysis);
+        let job = AnalysisJob {
+            uri,
+            rope,
+            text: text.into_owned(),
+            version,
+            edits,
+            debounce: if debounce {
+                self.analysis_debounce(&uri_key)
+            } else {
+                Duration::ZERO
+            },
+            pull_diagnostics: self.pull_diagnostics_enabled(),
+            diagnostics_config: self.diagnostics_config(&uri_key),
+            client: self.client.clone(),
+            analysis_map: Arc::clone(&self.analysis_map),
+            semantic_tokens_generation: Arc::clone(&self.semantic_tokens_generation),
+        };
+        if debounce {
+            tokio::spawn(job.run(token));
+        } else {
+            job.run(token).await;
+        }
+    }
 
-        let mut diagnostics = Vec::with_capacity(parse_errors.len());
-        for item in parse_errors {
-            let diag = match item {
-                CandidError::Parser(err) => match err {
-                    candid_parser::Error::Parse(parse_err) => {
-                        Some(parse_error_to_diagnostic(parse_err, &rope))
-                    }
-                    candid_parser::Error::Custom(err) => {
-                        let mut message = String::from("custom parser error: ");
-                        message.push_str(&format_error_chain(err.as_ref()));
-                        Some(Diagnostic {
-                            range: Range::default(),
-                            severity: Some(DiagnosticSeverity::ERROR),
-                            source: Some("parser".to_string()),
-                            message,
-                            related_information: None,
-                            ..Default::default()
-                        })
-                    }
-                    candid_parser::Error::CandidError(err) => {
-                        Some(candid_error_to_diagnostic(&err, &rope))
-                    }
-                },
-                CandidError::Lexer(err) => {
-                    let start_position = offset_to_position(err.span.start, &rope);
-                    let end_position = offset_to_position(err.span.end, &rope);
-                    match (start_position, end_position) {
-                        (Some(start), Some(end)) => Some(Diagnostic {
-                            range: Range::new(start, end),
-                            severity: Some(DiagnosticSeverity::ERROR),
-                            source: Some("lexer".to_string()),
-                            message: err.to_string(),
-                            related_information: None,
-                            ..Default::default()
-                        }),
-                        _ => None,
-                    }
-                }
-            };
+    /// Re-run analysis for every open document, e.g. after the diagnostic rules changed.
+    async fn reanalyze_open_documents(&self) {
+        self.reanalyze_documents(|_| true).await;
+    }
 
-            if let Some(mut diag) = diag {
-                diag.message = clean_diagnostic_message(diag.message);
-                diagnostics.push(diag);
-            }
-        }
+    /// Re-check open `.args` documents, whose target interface may have changed on disk.
+    async fn reanalyze_value_documents(&self) {
+        self.reanalyze_documents(|uri| DocumentKind::of(uri) == DocumentKind::Values)
+            .await;
+    }
 
-        let analysis_snapshot = if let Some(ast) = ast {
-            match analyze_program(&ast, &rope) {
-                Ok(semantic) => {
-                    let completion_cache =
-                        CompletionDocumentCache::build(Some(&ast), Some(&semantic), version);
-                    self.log_info_event("semantic", format!("uri={} status=ok", uri_key))
-                        .await;
-                    Some(AnalysisSnapshot::new(
-                        Some(ast),
-                        Some(semantic),
-                        completion_cache,
-                        semantic_tokens,
-                        parse_error_count,
-                        version,
-                    ))
-                }
-                Err(err) => {
-                    let completion_cache =
-                        CompletionDocumentCache::build(Some(&ast), None, version);
-                    let span = err.span();
-                    let start_position = offset_to_position(span.start, &rope);
-                    let end_position = offset_to_position(span.end, &rope);
-                    if let (Some(start), Some(end)) = (start_position, end_position) {
-                        let diag = Diagnostic::new_simple(
-                            Range::new(start, end),
-                            clean_diagnostic_message(format!("{err}")),
-                        );
-                        diagnostics.push(diag);
-                    } else {
-                        let diag = Diagnostic {
-                            range: Range::default(),
-                            severity: Some(DiagnosticSeverity::ERROR),
-                            source: Some("semantic".to_string()),
-                            message: clean_diagnostic_message(format!("{err}")),
-                            related_information: None,
-                            ..Default::default()
-                        };
-                        diagnostics.push(diag);
-                    }
-                    self.log_warn_event(
-                        "semantic",
-                        format!("uri={} status=error error={err}", uri_key),
-                    )
-                    .await;
-                    Some(AnalysisSnapshot::new(
-                        Some(ast),
-                        None,
-                        completion_cache,
-                        semantic_tokens,
-                        parse_error_count,
-                        version,
-                    ))
-                }
+    async fn reanalyze_documents(&self, include: impl Fn(&Uri) -> bool) {
+        let open = self
+            .documents
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.rope().clone(), entry.version()))
+            .collect::<Vec<_>>();
+        for (uri_key, rope, version) in open {
+            let Ok(uri) = uri_key.parse::<Uri>() else {
+                continue;
+            };
+            if !include(&uri) {
+                continue;
             }
-        } else {
-            self.log_info_event("semantic", format!("uri={} status=no-ast", uri_key))
-                .await;
-            let completion_cache = CompletionDocumentCache::build(None, None, version);
-            Some(AnalysisSnapshot::new(
-                None,
-                None,
-                completion_cache,
-                semantic_tokens,
-                parse_error_count,
+            let item = TextDocumentItem {
+                uri,
+                text: Cow::Owned(rope.to_string()),
+                rope,
                 version,
-            ))
-        };
-
-        if let Some(snapshot) = analysis_snapshot {
-            self.analysis_map.insert(uri_key.clone(), snapshot);
-        } else {
-            self.analysis_map.remove(&uri_key);
+                edits: EditLog::new(version),
+                debounce: false,
+            };
+            self.on_change(item).await;
         }
-
-        self.client
-            .publish_diagnostics(uri.clone(), diagnostics, version)
-            .await;
-        self.log_info_event("diagnostics", format!("uri={}", uri_key))
-            .await;
     }
 
     fn cached_position_to_offset(
@@ -970,12 +1709,51 @@ impl CandidLanguageServer {
         Some(offset)
     }
 
+    /// Full token array for `uri`, encoded once per snapshot and tagged with its result id.
+    fn full_semantic_tokens(&self, uri: &str) -> Option<SemanticTokens> {
+        let analysis = self.analysis_map.get(uri)?;
+        let data = analysis.encoded_semantic_tokens();
+        Some(SemanticTokens {
+            result_id: Some(analysis.semantic_tokens_result_id.clone()),
+            data: data.to_vec(),
+        })
+    }
+
+    fn semantic_tokens_delta(
+        &self,
+        uri: &str,
+        previous_result_id: &str,
+    ) -> Option<SemanticTokensFullDeltaResult> {
+        let analysis = self.analysis_map.get(uri)?;
+        let data = analysis.encoded_semantic_tokens();
+        let result_id = analysis.semantic_tokens_result_id.clone();
+        if previous_result_id == result_id {
+            return Some(SemanticTokensFullDeltaResult::TokensDelta(
+                SemanticTokensDelta {
+                    result_id: Some(result_id),
+                    edits: Vec::new(),
+                },
+            ));
+        }
+        match &analysis.previous_semantic_tokens {
+            Some((previous_id, previous)) if previous_id == previous_result_id => Some(
+                SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+                    result_id: Some(result_id),
+                    edits: semantic_tokens_edits(previous, &data),
+                }),
+            ),
+            _ => Some(SemanticTokensFullDeltaResult::Tokens(SemanticTokens {
+                result_id: Some(result_id),
+                data: data.to_vec(),
+            })),
+        }
+    }
+
     fn semantic_tokens_for_range(&self, uri: &str, range: Option<Range>) -> Option<SemanticTokens> {
         let analysis = self.analysis_map.get(uri)?;
-        let doc = self.documents.get(uri)?;
         Some(build_semantic_tokens(
             analysis.semantic_tokens(),
-            doc.rope(),
+            analysis.rope(),
             range,
         ))
     }
@@ -1046,27 +1824,6 @@ impl HoverCacheEntry {
     }
 }
 
-fn range_single_char(offset: usize, rope: &Rope) -> Range {
-    if let Some(start) = offset_to_position(offset, rope) {
-        let end_offset = offset.saturating_add(1);
-        let end = offset_to_position(end_offset, rope)
-            .unwrap_or_else(|| Position::new(start.line, start.character + 1));
-        Range::new(start, end)
-    } else {
-        Range::default()
-    }
-}
-
-fn range_offsets(start_offset: usize, end_offset: usize, rope: &Rope) -> Range {
-    match (
-        offset_to_position(start_offset, rope),
-        offset_to_position(end_offset, rope),
-    ) {
-        (Some(start), Some(end)) => Range::new(start, end),
-        _ => Range::default(),
-    }
-}
-
 fn build_semantic_tokens(
     tokens: &[ImCompleteSemanticToken],
     rope: &Rope,
@@ -1104,13 +1861,14 @@ fn build_semantic_tokens(
         };
         let length = u32::try_from(token.length).unwrap_or(u32::MAX);
         let token_type = u32::try_from(token.token_type).unwrap_or(0);
+        let token_modifiers_bitset = token.token_modifiers;
 
         data.push(SemanticToken {
             delta_line,
             delta_start,
             length,
             token_type,
-            token_modifiers_bitset: 0,
+            token_modifiers_bitset,
         });
 
         prev_line = position.line;
@@ -1157,130 +1915,3 @@ fn clean_diagnostic_message(mut message: String) -> String {
 
     message
 }
-
-fn candid_error_to_diagnostic(err: &CandidCoreError, rope: &Rope) -> Diagnostic {
-    use CandidCoreError::{Binread, Custom, Reserve, Subtype};
-
-    match err {
-        Binread(labels) => {
-            let mut message = err.to_string();
-            if let Some(label) = labels.first() {
-                let range = binread_label_range(label, rope).unwrap_or_default();
-                let extras = labels
-                    .iter()
-                    .skip(1)
-                    .filter_map(binread_label_message)
-                    .collect::<Vec<_>>();
-
-                if !extras.is_empty() {
-                    message.push_str("\nAdditional details:");
-                    for extra in extras {
-                        message.push_str("\n  - ");
-                        message.push_str(&extra);
-                    }
-                }
-                message = clean_diagnostic_message(message);
-
-                Diagnostic {
-                    range,
-                    severity: Some(DiagnosticSeverity::ERROR),
-                    source: Some("candid".to_string()),
-                    message,
-                    related_information: None,
-                    ..Default::default()
-                }
-            } else {
-                message = clean_diagnostic_message(message);
-                Diagnostic {
-                    message,
-                    ..candid_error_with_fallback(err)
-                }
-            }
-        }
-        Subtype(_) | Custom(_) | Reserve(_) => candid_error_with_fallback(err),
-    }
-}
-
-fn candid_error_with_fallback(err: &CandidCoreError) -> Diagnostic {
-    Diagnostic {
-        range: Range::default(),
-        severity: Some(DiagnosticSeverity::ERROR),
-        source: Some("candid".to_string()),
-        message: format_error_chain(err),
-        related_information: None,
-        ..Default::default()
-    }
-}
-
-fn binread_label_range(label: &CandidLabel, rope: &Rope) -> Option<Range> {
-    let debug = format!("{label:?}");
-    let pos_part = debug.split("pos: ").nth(1)?.split(',').next()?;
-    let pos = pos_part.trim().parse::<usize>().ok()?;
-    let start_offset = pos / 2;
-    offset_to_position(start_offset, rope).map(|start| {
-        let end = Position::new(start.line, start.character + 1);
-        Range::new(start, end)
-    })
-}
-
-fn binread_label_message(label: &CandidLabel) -> Option<String> {
-    let debug = format!("{label:?}");
-    let rest = debug.split("message: \"").nth(1)?;
-    let msg = rest.split('\"').next()?.replace("\\\"", "\"");
-    Some(msg)
-}
-
-pub fn parse_error_to_diagnostic(
-    err: ParseError<usize, Token, LexicalError>,
-    rope: &Rope,
-) -> Diagnostic {
-    match err {
-        ParseError::InvalidToken { location } => Diagnostic {
-            range: range_single_char(location, rope),
-            severity: Some(DiagnosticSeverity::ERROR),
-            source: Some("parser".to_string()),
-            message: clean_diagnostic_message("invalid token".to_string()),
-            ..Default::default()
-        },
-        ParseError::UnrecognizedEof { location, expected } => Diagnostic {
-            range: range_single_char(location, rope),
-            severity: Some(DiagnosticSeverity::ERROR),
-            source: Some("parser".to_string()),
-            message: clean_diagnostic_message(format!(
-                "unexpected end of file, expected one of: {}",
-                expected.join(", ")
-            )),
-            ..Default::default()
-        },
-        ParseError::UnrecognizedToken { token, expected } => {
-            let (start, _tok, end) = token;
-            Diagnostic {
-                range: range_offsets(start, end, rope),
-                severity: Some(DiagnosticSeverity::ERROR),
-                source: Some("parser".to_string()),
-                message: clean_diagnostic_message(format!(
-                    "unexpected token, expected one of: {}",
-                    expected.join(", ")
-                )),
-                ..Default::default()
-            }
-        }
-        ParseError::ExtraToken { token } => {
-            let (start, _tok, end) = token;
-            Diagnostic {
-                range: range_offsets(start, end, rope),
-                severity: Some(DiagnosticSeverity::ERROR),
-                source: Some("parser".to_string()),
-                message: clean_diagnostic_message("extra token".to_string()),
-                ..Default::default()
-            }
-        }
-        ParseError::User { error } => Diagnostic {
-            range: Range::default(),
-            severity: Some(DiagnosticSeverity::ERROR),
-            source: Some("parser".to_string()),
-            message: clean_diagnostic_message(error.to_string()),
-            ..Default::default()
-        },
-    }
-}
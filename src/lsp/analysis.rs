@@ -0,0 +1,373 @@
+use crate::{
+    candid_lang::{ImCompleteSemanticToken, ParserResult, parse},
+    lsp::{
+        AnalysisSnapshot, CandidLanguageServer,
+        completion::CompletionDocumentCache,
+        config::DiagnosticsConfig,
+        diagnostics::{lint_diagnostics, parse_error_diagnostics, semantic_error_diagnostic},
+        incremental::{EditLog, ProgramChunks, SplicedProgram, splice},
+        semantic_analyze::{Semantic, analyze_program},
+        semantic_token::apply_semantic_tokens,
+        suppression::Suppressions,
+        tasks::DocumentTaskToken,
+        test_suite::{parse_test_suite, test_suite_diagnostics, test_suite_semantic_tokens},
+        trivia::Comment,
+        value_check::TypeEnv,
+        value_document::analyze_value_document,
+        workspace::{DocumentKind, uri_to_path},
+    },
+};
+use candid_parser::syntax::IDLMergedProg;
+use dashmap::DashMap;
+use rapidhash::fast::RandomState;
+use ropey::Rope;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tower_lsp_server::{
+    Client,
+    ls_types::{Diagnostic, MessageType, Uri},
+};
+
+/// Everything derived from one version of a Candid interface or test suite.
+pub(crate) struct DocumentAnalysis {
+    pub(crate) ast: Option<IDLMergedProg>,
+    pub(crate) semantic: Option<Semantic>,
+    /// Why semantic analysis failed, when the document parsed.
+    pub(crate) semantic_error: Option<String>,
+    pub(crate) semantic_tokens: Vec<ImCompleteSemanticToken>,
+    pub(crate) comments: Vec<Comment>,
+    /// Where the declarations end, for splicing the next edit.
+    pub(crate) chunks: Option<ProgramChunks>,
+    pub(crate) parse_errors: usize,
+    pub(crate) type_env: Option<TypeEnv>,
+    /// Number of assertions, for test suites.
+    pub(crate) suite_assertions: Option<usize>,
+    /// Diagnostics left after suppressions and the configured rule levels.
+    pub(crate) diagnostics: Vec<Diagnostic>,
+}
+
+/// Parse, analyse, lint and check `text`, then apply suppressions and `config`.
+///
+/// A `spliced` program, assembled from the previous analysis of an interface, replaces the full
+/// parse and, when it carries one, the semantic analysis. Both published and pulled diagnostics
+/// come from here, so the two cannot disagree.
+pub(crate) fn analyze_document(
+    uri: &Uri,
+    text: &str,
+    rope: &Rope,
+    config: &DiagnosticsConfig,
+    spliced: Option<SplicedProgram>,
+) -> DocumentAnalysis {
+    // Only the definitions before the first assertion of a test suite are a program.
+    let suite = (DocumentKind::of(uri) == DocumentKind::TestSuite).then(|| parse_test_suite(text));
+    let defs = suite.as_ref().map_or(text, |suite| &text[..suite.defs_end]);
+    let (parsed, spliced_semantic) = match spliced {
+        Some(SplicedProgram { parsed, semantic }) => (parsed, semantic),
+        None => (parse(defs), None),
+    };
+    let chunks = suite
+        .is_none()
+        .then(|| ProgramChunks::new(&parsed, defs))
+        .flatten();
+    let ParserResult {
+        ast,
+        parse_errors,
+        mut semantic_tokens,
+        comments,
+        ..
+    } = parsed;
+    let parse_error_count = parse_errors.len();
+    let mut diagnostics = parse_error_diagnostics(parse_errors, uri, rope);
+
+    let mut semantic_error = None;
+    let semantic = ast.as_ref().and_then(|ast| {
+        match spliced_semantic.unwrap_or_else(|| analyze_program(ast, rope)) {
+            Ok(semantic) => {
+                apply_semantic_tokens(&mut semantic_tokens, &semantic);
+                diagnostics.extend(lint_diagnostics(&semantic, rope, config));
+                Some(semantic)
+            }
+            Err(err) => {
+                diagnostics.push(semantic_error_diagnostic(&err, rope));
+                semantic_error = Some(err.to_string());
+                None
+            }
+        }
+    });
+    let type_env = ast
+        .as_ref()
+        .map(|ast| TypeEnv::new(ast, uri_to_path(uri).as_deref()));
+    if let Some(suite) = &suite {
+        let empty = TypeEnv::default();
+        let env = type_env.as_ref().unwrap_or(&empty);
+        diagnostics.extend(test_suite_diagnostics(suite, env, rope));
+        semantic_tokens.extend(test_suite_semantic_tokens(text, suite));
+    }
+    let suppressions = Suppressions::collect(text, &semantic_tokens, rope);
+    let diagnostics = config.apply(suppressions.apply(diagnostics, &config.rules));
+    DocumentAnalysis {
+        ast,
+        semantic,
+        semantic_error,
+        semantic_tokens,
+        comments,
+        chunks,
+        parse_errors: parse_error_count,
+        type_env,
+        suite_assertions: suite.map(|suite| suite.assertions.len()),
+        diagnostics,
+    }
+}
+
+/// Parse and analysis work for one document version, detached from the request handlers.
+///
+/// Jobs only hold shared handles, so they can be spawned onto the runtime and superseded by a
+/// newer version through their `DocumentTaskToken`.
+pub(crate) struct AnalysisJob {
+    pub(crate) uri: Uri,
+    pub(crate) rope: Rope,
+    pub(crate) text: String,
+    pub(crate) version: Option<i32>,
+    /// Edits since the previous analysis, which decide what of it can be spliced.
+    pub(crate) edits: EditLog,
+    pub(crate) debounce: Duration,
+    pub(crate) pull_diagnostics: bool,
+    pub(crate) diagnostics_config: DiagnosticsConfig,
+    pub(crate) client: Client,
+    pub(crate) analysis_map: Arc<DashMap<String, AnalysisSnapshot, RandomState>>,
+    pub(crate) semantic_tokens_generation: Arc<AtomicU64>,
+}
+
+impl AnalysisJob {
+    /// Run the job, giving up as soon as a newer version of the document has been scheduled.
+    pub(crate) async fn run(self, token: DocumentTaskToken) {
+        let uri_key = self.uri.to_string();
+        if !self.debounce.is_zero() {
+            tokio::time::sleep(self.debounce).await;
+        }
+        if token.yield_and_check().await.is_err() {
+            self.log_info("analysis", format!("uri={} status=superseded", uri_key))
+                .await;
+            return;
+        }
+
+        if DocumentKind::of(&self.uri) == DocumentKind::Values {
+            self.run_values(&token).await;
+            return;
+        }
+
+        let AnalysisJob {
+            uri,
+            rope,
+            text,
+            version,
+            ..
+        } = &self;
+        let spliced = self.splice_previous();
+        let reused = match &spliced {
+            Some(SplicedProgram {
+                semantic: Some(_), ..
+            }) => "ast+semantic",
+            Some(_) => "ast",
+            None => "none",
+        };
+        let DocumentAnalysis {
+            ast,
+            semantic,
+            semantic_error,
+            semantic_tokens,
+            comments,
+            chunks,
+            parse_errors,
+            type_env,
+            suite_assertions,
+            diagnostics,
+        } = analyze_document(uri, text, rope, &self.diagnostics_config, spliced);
+        self.log_info(
+            "parse",
+            format!(
+                "uri={} parse_errors={} reused={}",
+                uri_key, parse_errors, reused
+            ),
+        )
+        .await;
+        match (&semantic, &semantic_error) {
+            (Some(_), _) => {
+                self.log_info("semantic", format!("uri={} status=ok", uri_key))
+                    .await
+            }
+            (None, Some(err)) => {
+                self.log_warn(
+                    "semantic",
+                    format!("uri={} status=error error={err}", uri_key),
+                )
+                .await
+            }
+            (None, None) => {
+                self.log_info("semantic", format!("uri={} status=no-ast", uri_key))
+                    .await
+            }
+        }
+        if let Some(assertions) = suite_assertions {
+            self.log_info(
+                "suite",
+                format!("uri={} assertions={}", uri_key, assertions),
+            )
+            .await;
+        }
+        if token.yield_and_check().await.is_err() {
+            return;
+        }
+
+        let version = *version;
+        let completion_cache =
+            CompletionDocumentCache::build(ast.as_ref(), semantic.as_ref(), version);
+        let snapshot = AnalysisSnapshot::new(
+            rope.clone(),
+            ast,
+            semantic,
+            completion_cache,
+            semantic_tokens,
+            parse_errors,
+            diagnostics.clone(),
+            version,
+        )
+        .with_type_env(type_env)
+        .with_comments(comments)
+        .with_chunks(chunks);
+
+        self.publish(&token, snapshot, diagnostics).await;
+    }
+
+    /// Splice this version into the published analysis of the interface, re-parsing only the
+    /// declarations edited since.
+    fn splice_previous(&self) -> Option<SplicedProgram> {
+        if DocumentKind::of(&self.uri) != DocumentKind::Interface {
+            return None;
+        }
+        let previous = self.analysis_map.get(&self.uri.to_string())?;
+        let change = self.edits.since(previous.version())?;
+        splice(previous.program()?, &self.text, &self.rope, change)
+    }
+
+    /// Analyze a `.args` value document: parse it and check it against its target method.
+    async fn run_values(&self, token: &DocumentTaskToken) {
+        let uri_key = self.uri.to_string();
+        let analysis = analyze_value_document(&self.uri, &self.text, &self.rope);
+        self.log_info(
+            "values",
+            format!(
+                "uri={} parse_errors={} method={}",
+                uri_key,
+                analysis.parse_errors,
+                analysis
+                    .target
+                    .as_ref()
+                    .map_or("-", |target| target.method.as_str())
+            ),
+        )
+        .await;
+        let suppressions = Suppressions::collect(&self.text, &analysis.semantic_tokens, &self.rope);
+        let config = &self.diagnostics_config;
+        let diagnostics = config.apply(suppressions.apply(analysis.diagnostics, &config.rules));
+        let snapshot = AnalysisSnapshot::new(
+            self.rope.clone(),
+            None,
+            None,
+            None,
+            analysis.semantic_tokens,
+            analysis.parse_errors,
+            diagnostics.clone(),
+            self.version,
+        )
+        .with_content_result_id()
+        .with_value_target(analysis.target);
+        self.publish(token, snapshot, diagnostics).await;
+    }
+
+    /// Whether `token` still belongs to the latest job for the document; logs when it does not.
+    async fn still_active(&self, token: &DocumentTaskToken) -> bool {
+        if token.ensure_active().is_ok() {
+            return true;
+        }
+        self.log_info("analysis", format!("uri={} status=superseded", self.uri))
+            .await;
+        false
+    }
+
+    /// Store `snapshot` and publish its diagnostics unless the job has been superseded.
+    async fn publish(
+        &self,
+        token: &DocumentTaskToken,
+        snapshot: AnalysisSnapshot,
+        diagnostics: Vec<Diagnostic>,
+    ) {
+        let uri_key = self.uri.to_string();
+        if !self.still_active(token).await {
+            return;
+        }
+        let (previous_tokens, diagnostics_changed) = match self.analysis_map.get(&uri_key) {
+            Some(previous) => (
+                previous.sent_semantic_tokens(),
+                previous.diagnostics() != diagnostics.as_slice(),
+            ),
+            None => (None, true),
+        };
+        let generation = self
+            .semantic_tokens_generation
+            .fetch_add(1, Ordering::Relaxed);
+        let snapshot = snapshot
+            .with_semantic_tokens_history((generation + 1).to_string(), previous_tokens)
+            .with_diagnostics_config(&self.diagnostics_config);
+        // Clients request tokens and diagnostics themselves after an edit; only results that
+        // changed after such a request was answered need a refresh.
+        let tokens_changed = snapshot.semantic_tokens_changed();
+        self.analysis_map.insert(uri_key.clone(), snapshot);
+
+        if tokens_changed && (self.pull_diagnostics || !self.debounce.is_zero()) {
+            _ = self.client.semantic_tokens_refresh().await;
+        }
+        if self.pull_diagnostics {
+            if diagnostics_changed {
+                _ = self.client.workspace_diagnostic_refresh().await;
+            }
+            self.log_info(
+                "diagnostics",
+                format!(
+                    "uri={} mode=pull refreshed={}",
+                    uri_key, diagnostics_changed
+                ),
+            )
+            .await;
+            return;
+        }
+        // A newer edit may have stored its own snapshot while the refresh was awaited.
+        if !self.still_active(token).await {
+            return;
+        }
+        self.client
+            .publish_diagnostics(self.uri.clone(), diagnostics, self.version)
+            .await;
+        self.log_info("diagnostics", format!("uri={}", uri_key))
+            .await;
+    }
+
+    async fn log_info(&self, event: &str, details: String) {
+        let message = CandidLanguageServer::event_message(event, &details);
+        #[cfg(feature = "tracing")]
+        tracing::info!("{message}");
+        let _ = self.client.log_message(MessageType::INFO, message).await;
+    }
+
+    async fn log_warn(&self, event: &str, details: String) {
+        let message = CandidLanguageServer::event_message(event, &details);
+        #[cfg(feature = "tracing")]
+        tracing::warn!("{message}");
+        let _ = self.client.log_message(MessageType::WARNING, message).await;
+    }
+}
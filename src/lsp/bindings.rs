@@ -0,0 +1,252 @@
+use crate::lsp::value_check::TypeEnv;
+use candid_parser::{
+    bindings::{javascript, motoko, rust, typescript},
+    configs::Configs,
+    syntax::IDLMergedProg,
+};
+use serde::Serialize;
+use std::{ops::Range, str::FromStr};
+use tower_lsp_server::ls_types::{Position, Range as LspRange, Uri};
+
+/// A target language of `candid_parser`'s binding generators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BindingLanguage {
+    Rust,
+    Motoko,
+    TypeScript,
+    JavaScript,
+}
+
+impl BindingLanguage {
+    pub const ALL: [BindingLanguage; 4] = [
+        BindingLanguage::Rust,
+        BindingLanguage::Motoko,
+        BindingLanguage::TypeScript,
+        BindingLanguage::JavaScript,
+    ];
+
+    /// Name used in command arguments, also the LSP language id of the generated document.
+    pub fn id(self) -> &'static str {
+        match self {
+            BindingLanguage::Rust => "rust",
+            BindingLanguage::Motoko => "motoko",
+            BindingLanguage::TypeScript => "typescript",
+            BindingLanguage::JavaScript => "javascript",
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            BindingLanguage::Rust => "Rust",
+            BindingLanguage::Motoko => "Motoko",
+            BindingLanguage::TypeScript => "TypeScript",
+            BindingLanguage::JavaScript => "JavaScript",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            BindingLanguage::Rust => "rs",
+            BindingLanguage::Motoko => "mo",
+            BindingLanguage::TypeScript => "d.ts",
+            BindingLanguage::JavaScript => "js",
+        }
+    }
+}
+
+impl FromStr for BindingLanguage {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "rust" | "rs" => Ok(BindingLanguage::Rust),
+            "motoko" | "mo" => Ok(BindingLanguage::Motoko),
+            "typescript" | "ts" => Ok(BindingLanguage::TypeScript),
+            "javascript" | "js" => Ok(BindingLanguage::JavaScript),
+            other => Err(format!("Invalid binding language: {other}")),
+        }
+    }
+}
+
+/// What a generated item was produced from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindingTarget {
+    /// A `type` definition, by name.
+    Type(String),
+    /// The service (actor) of the file.
+    Service,
+}
+
+/// Run the generator for `language` over `ast`, type-checked together with its imports in `env`.
+pub fn generate_bindings(
+    ast: &IDLMergedProg,
+    env: &TypeEnv,
+    language: BindingLanguage,
+) -> Result<String, String> {
+    let checked = env.checked()?;
+    let (env, actor) = (&checked.env, &checked.actor);
+    Ok(match language {
+        BindingLanguage::Rust => {
+            let configs = Configs::from_str("").map_err(|err| err.to_string())?;
+            let config = rust::Config::new(configs);
+            rust::compile(&config, env, actor, ast, rust::ExternalConfig::default()).0
+        }
+        BindingLanguage::Motoko => motoko::compile(env, actor, ast),
+        BindingLanguage::TypeScript => typescript::compile(env, actor, ast),
+        BindingLanguage::JavaScript => javascript::compile(env, actor),
+    })
+}
+
+/// Generated bindings, returned by `candid.showBindings` for the client to open read-only.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BindingsDocument {
+    /// Virtual `candid-bindings:` URI naming the source file and target language.
+    pub uri: String,
+    pub language_id: String,
+    pub content: String,
+    /// Lines of the item generated for the requested type or service, when one was given.
+    pub selection: Option<LspRange>,
+}
+
+/// Generate the `language` bindings of the document at `uri`.
+pub fn bindings_document(
+    uri: &Uri,
+    ast: &IDLMergedProg,
+    env: &TypeEnv,
+    language: BindingLanguage,
+    target: Option<&BindingTarget>,
+) -> Result<BindingsDocument, String> {
+    let content = generate_bindings(ast, env, language)?;
+    let selection = target
+        .and_then(|target| binding_item_lines(&content, language, target))
+        .map(|lines| {
+            LspRange::new(
+                Position::new(lines.start as u32, 0),
+                Position::new(lines.end as u32, 0),
+            )
+        });
+    Ok(BindingsDocument {
+        uri: bindings_uri(uri, language),
+        language_id: language.id().to_string(),
+        content,
+        selection,
+    })
+}
+
+/// `candid-bindings:<path>.<ext>` for the bindings of `uri` in `language`.
+pub fn bindings_uri(uri: &Uri, language: BindingLanguage) -> String {
+    let text = uri.as_str();
+    let path = text.split_once(':').map_or(text, |(_, path)| path);
+    format!("candid-bindings:{path}.{}", language.extension())
+}
+
+/// Line range of the item generated for `target`, including the attributes and doc comments
+/// above it.
+pub fn binding_item_lines(
+    source: &str,
+    language: BindingLanguage,
+    target: &BindingTarget,
+) -> Option<Range<usize>> {
+    let lines: Vec<&str> = source.lines().collect();
+    let wanted = match target {
+        BindingTarget::Type(name) => normalize_name(name),
+        BindingTarget::Service => normalize_name(match language {
+            BindingLanguage::Rust => "Service",
+            BindingLanguage::Motoko => "Self",
+            BindingLanguage::TypeScript => "_SERVICE",
+            BindingLanguage::JavaScript => return None,
+        }),
+    };
+    let header = lines.iter().position(|line| {
+        item_name(line, language).is_some_and(|name| normalize_name(name) == wanted)
+    })?;
+    let mut start = header;
+    while start > 0 {
+        let previous = lines[start - 1].trim_start();
+        if previous.starts_with("#[")
+            || previous.starts_with("//")
+            || previous.starts_with("/**")
+            || previous.starts_with('*')
+        {
+            start -= 1;
+        } else {
+            break;
+        }
+    }
+    let mut end = item_end(&lines, header);
+    // Rust services are a struct followed by the `impl` block holding their methods.
+    if let Some(name) = item_name(lines[header], language)
+        && let Some(next) = lines.get(end)
+        && next.trim_start().starts_with(&format!("impl {name} "))
+    {
+        end = item_end(&lines, end);
+    }
+    Some(start..end)
+}
+
+/// One past the last line of the item starting at `header`, found by balancing delimiters.
+fn item_end(lines: &[&str], header: usize) -> usize {
+    let mut depth = 0i32;
+    for (idx, line) in lines.iter().enumerate().skip(header) {
+        for ch in line.chars() {
+            match ch {
+                '{' | '(' | '[' => depth += 1,
+                '}' | ')' | ']' => depth -= 1,
+                _ => {}
+            }
+        }
+        let trimmed = line.trim_end();
+        if depth <= 0 && (trimmed.ends_with(';') || trimmed.ends_with('}')) {
+            return idx + 1;
+        }
+    }
+    lines.len()
+}
+
+/// Source of the item generated for `target`.
+pub fn binding_item(
+    source: &str,
+    language: BindingLanguage,
+    target: &BindingTarget,
+) -> Option<String> {
+    let lines = binding_item_lines(source, language, target)?;
+    let item: Vec<&str> = source.lines().skip(lines.start).take(lines.len()).collect();
+    Some(item.join("\n"))
+}
+
+/// Name declared by a generated line, if it starts a type-level item.
+fn item_name(line: &str, language: BindingLanguage) -> Option<&str> {
+    let trimmed = line.trim_start();
+    let prefixes: &[&str] = match language {
+        BindingLanguage::Rust => &[
+            "pub struct ",
+            "pub enum ",
+            "pub type ",
+            "struct ",
+            "enum ",
+            "type ",
+        ],
+        BindingLanguage::Motoko => &["public type ", "type "],
+        BindingLanguage::TypeScript => {
+            &["export type ", "export interface ", "type ", "interface "]
+        }
+        BindingLanguage::JavaScript => &["export const ", "const "],
+    };
+    let rest = prefixes
+        .iter()
+        .find_map(|prefix| trimmed.strip_prefix(prefix))?;
+    let end = rest
+        .find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+        .unwrap_or(rest.len());
+    (end > 0).then(|| &rest[..end])
+}
+
+/// Generators rename types to each language's conventions, so compare names ignoring case and
+/// underscores.
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|ch| *ch != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
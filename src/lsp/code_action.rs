@@ -0,0 +1,110 @@
+use crate::lsp::{
+    CandidLanguageServer,
+    example_value::{EXAMPLE_VALUE_KIND, example_actions},
+    missing_docs::missing_doc_actions,
+    naming::naming_actions,
+    position::position_to_offset,
+    suppression::suppression_actions,
+};
+use std::collections::HashMap;
+use tower_lsp_server::jsonrpc::Result;
+use tower_lsp_server::ls_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams, CodeActionResponse,
+    Diagnostic, TextEdit, Uri, WorkspaceEdit,
+};
+
+/// Answer `textDocument/codeAction` with quick fixes for the diagnostics in the request, and
+/// example values for the declaration under the cursor.
+pub async fn code_action(
+    server: &CandidLanguageServer,
+    params: CodeActionParams,
+) -> Result<Option<CodeActionResponse>> {
+    let wants = |kind: &CodeActionKind| {
+        params
+            .context
+            .only
+            .as_ref()
+            .is_none_or(|only| only.iter().any(|o| kind.as_str().starts_with(o.as_str())))
+    };
+    let quick_fixes = wants(&CodeActionKind::QUICKFIX);
+    let examples = wants(&EXAMPLE_VALUE_KIND);
+    if !quick_fixes && !examples {
+        return Ok(None);
+    }
+    let uri = params.text_document.uri;
+    let Some(doc) = server.documents.get(uri.as_str()) else {
+        return Ok(None);
+    };
+    let naming = server.diagnostics_config(uri.as_str()).naming;
+    let analysis = server
+        .analysis_map
+        .get(uri.as_str())
+        .filter(|snapshot| snapshot.version() == doc.version());
+
+    let mut actions = Vec::new();
+    if examples
+        && let Some(snapshot) = &analysis
+        && let (Some(ast), Some(env)) = (snapshot.ast(), snapshot.type_env())
+        && env.checked().is_ok()
+        && let Some(offset) = position_to_offset(params.range.start, snapshot.rope())
+    {
+        actions.extend(example_actions(&uri, ast, env, offset));
+    }
+    let diagnostics = if quick_fixes {
+        params.context.diagnostics.as_slice()
+    } else {
+        &[]
+    };
+    for diagnostic in diagnostics {
+        if let Some(snapshot) = &analysis
+            && let Some(semantic) = snapshot.semantic()
+        {
+            actions.extend(naming_actions(
+                &uri,
+                diagnostic,
+                semantic,
+                snapshot.rope(),
+                &naming,
+            ));
+            actions.extend(missing_doc_actions(
+                &uri,
+                diagnostic,
+                semantic,
+                snapshot.rope(),
+            ));
+        }
+        actions.extend(suppression_actions(&uri, diagnostic, doc.rope()));
+    }
+    Ok((!actions.is_empty()).then_some(actions))
+}
+
+/// A quick fix resolving `diagnostic` with a single edit to `uri`.
+pub fn quick_fix(
+    title: String,
+    uri: &Uri,
+    diagnostic: &Diagnostic,
+    edit: TextEdit,
+) -> CodeActionOrCommand {
+    quick_fix_with_edits(title, uri, diagnostic, vec![edit], false)
+}
+
+/// A quick fix resolving `diagnostic` with several edits to `uri`.
+pub fn quick_fix_with_edits(
+    title: String,
+    uri: &Uri,
+    diagnostic: &Diagnostic,
+    edits: Vec<TextEdit>,
+    preferred: bool,
+) -> CodeActionOrCommand {
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title,
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..Default::default()
+        }),
+        is_preferred: preferred.then_some(true),
+        ..Default::default()
+    })
+}
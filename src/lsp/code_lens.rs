@@ -0,0 +1,151 @@
+use crate::lsp::{
+    CandidLanguageServer,
+    bindings::BindingLanguage,
+    commands::SHOW_BINDINGS,
+    position::{offset_to_position, span_to_range},
+    semantic_analyze::Semantic,
+    usage_hierarchy::{UsageIndex, UsageNode, dependent_methods, method_name},
+    workspace::DocumentKind,
+};
+use candid_parser::syntax::{Dec, IDLMergedProg};
+use ropey::Rope;
+use serde_json::{Value, json};
+use tower_lsp_server::{
+    jsonrpc::Result,
+    ls_types::{CodeLens, CodeLensParams, Command, Location, Range, Uri},
+};
+
+/// Client command that opens the references peek view.
+const SHOW_REFERENCES: &str = "editor.action.showReferences";
+
+/// Answer `textDocument/codeLens`.
+pub async fn code_lens(
+    server: &CandidLanguageServer,
+    params: CodeLensParams,
+) -> Result<Option<Vec<CodeLens>>> {
+    let uri = params.text_document.uri;
+    if DocumentKind::of(&uri) != DocumentKind::Interface {
+        return Ok(None);
+    }
+    let Some(snapshot) = server.analysis_map.get(uri.as_str()) else {
+        return Ok(None);
+    };
+    let Some(ast) = snapshot.ast() else {
+        return Ok(None);
+    };
+    let mut lenses = match (snapshot.semantic(), snapshot.usage_index()) {
+        (Some(semantic), Some(index)) => usage_lenses(&uri, semantic, index, snapshot.rope()),
+        _ => Vec::new(),
+    };
+    lenses.extend(binding_lenses(&uri, ast, snapshot.rope()));
+    Ok(Some(lenses))
+}
+
+/// A lens above every type definition counting its references and naming the service methods
+/// that depend on it, and one above every service method showing its mode.
+pub fn usage_lenses(
+    uri: &Uri,
+    semantic: &Semantic,
+    index: &UsageIndex,
+    rope: &Rope,
+) -> Vec<CodeLens> {
+    let mut lenses = Vec::new();
+    for (symbol_id, name) in semantic.symbol_ident_names.iter_enumerated() {
+        let (Some(_), Some(span)) = (name, semantic.table.symbol_id_to_span.get(symbol_id)) else {
+            continue;
+        };
+        let (Some(range), Some(position)) = (
+            span_to_range(span, rope),
+            semantic
+                .symbol_ident_spans
+                .get(symbol_id)
+                .cloned()
+                .flatten()
+                .and_then(|ident| offset_to_position(ident.start, rope)),
+        ) else {
+            continue;
+        };
+        let locations: Vec<Location> = semantic
+            .table
+            .symbol_id_to_references
+            .get(&symbol_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|reference_id| semantic.table.reference_id_to_reference.get(*reference_id))
+            .filter_map(|reference| span_to_range(&reference.span, rope))
+            .map(|range| Location::new(uri.clone(), range))
+            .collect();
+        let mut title = match locations.len() {
+            1 => "1 reference".to_string(),
+            count => format!("{count} references"),
+        };
+        let methods: Vec<String> = dependent_methods(semantic, index, UsageNode::Type(symbol_id))
+            .into_iter()
+            .filter_map(|method_id| method_name(semantic, rope, method_id))
+            .collect();
+        if !methods.is_empty() {
+            title.push_str(&format!(" · used by {}", methods.join(", ")));
+        }
+        lenses.push(CodeLens {
+            range: Range::new(range.start, range.start),
+            command: Some(Command {
+                title,
+                command: SHOW_REFERENCES.to_string(),
+                arguments: Some(vec![json!(uri.as_str()), json!(position), json!(locations)]),
+            }),
+            data: None,
+        });
+    }
+    for metadata in semantic.service_methods.iter() {
+        let (Some(signature), Some(position)) = (
+            &metadata.signature,
+            offset_to_position(metadata.span.start, rope),
+        ) else {
+            continue;
+        };
+        lenses.push(CodeLens {
+            range: Range::new(position, position),
+            command: Some(Command {
+                title: signature.mode_name().to_string(),
+                command: String::new(),
+                arguments: None,
+            }),
+            data: None,
+        });
+    }
+    lenses
+}
+
+/// One lens per binding language above every type definition and the service.
+pub fn binding_lenses(uri: &Uri, ast: &IDLMergedProg, rope: &Rope) -> Vec<CodeLens> {
+    let mut lenses = Vec::new();
+    let mut push = |start: usize, target: Value| {
+        let Some(position) = offset_to_position(start, rope) else {
+            return;
+        };
+        for language in BindingLanguage::ALL {
+            let mut argument = json!({ "uri": uri.as_str(), "language": language.id() });
+            if let (Some(argument), Some(target)) = (argument.as_object_mut(), target.as_object()) {
+                argument.extend(target.clone());
+            }
+            lenses.push(CodeLens {
+                range: Range::new(position, position),
+                command: Some(Command {
+                    title: language.title().to_string(),
+                    command: SHOW_BINDINGS.to_string(),
+                    arguments: Some(vec![argument]),
+                }),
+                data: None,
+            });
+        }
+    };
+    for dec in ast.decs().iter() {
+        if let Dec::TypD(binding) = dec {
+            push(binding.span.start, json!({ "type": binding.id }));
+        }
+    }
+    if let Some(actor) = ast.resolve_actor().ok().flatten() {
+        push(actor.span.start, json!({ "service": true }));
+    }
+    lenses
+}
@@ -0,0 +1,300 @@
+use crate::lsp::{
+    CandidLanguageServer,
+    bindings::{BindingLanguage, BindingTarget, bindings_document},
+    example_value::{ExampleTarget, example_for},
+    message_codec::{
+        MessageError, MessageFormat, MethodTypes, decode_method_message, encode_method_message,
+        parse_message_bytes,
+    },
+    position::span_to_range,
+    semantic_analyze::ParamRole,
+    type_display::{render_expanded_binding, render_expanded_type},
+    type_relation::parse_type_text,
+};
+use ropey::Rope;
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use std::str::FromStr;
+use tower_lsp_server::{
+    jsonrpc::{Error, Result},
+    ls_types::{ExecuteCommandParams, Location, Uri},
+};
+
+/// Generate bindings for a document; see [`ShowBindingsArgs`].
+pub const SHOW_BINDINGS: &str = "candid.showBindings";
+
+/// Generate an example value of a type or arguments of a method; see [`ExampleValueArgs`].
+pub const EXAMPLE_VALUE: &str = "candid.exampleValue";
+
+/// Decode a binary message of a service method; see [`DecodeMessageArgs`].
+pub const DECODE_MESSAGE: &str = "candid.decodeMessage";
+
+/// Encode a Candid value as a binary message of a service method; see [`EncodeMessageArgs`].
+pub const ENCODE_MESSAGE: &str = "candid.encodeMessage";
+
+/// Expand the type names of a type into their definitions; see [`ExpandTypeArgs`].
+pub const EXPAND_TYPE: &str = "candid.expandType";
+
+/// Commands advertised in the `executeCommandProvider` capability.
+pub const COMMANDS: &[&str] = &[
+    SHOW_BINDINGS,
+    EXAMPLE_VALUE,
+    DECODE_MESSAGE,
+    ENCODE_MESSAGE,
+    EXPAND_TYPE,
+];
+
+/// Arguments of `candid.showBindings`, passed as the first command argument.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShowBindingsArgs {
+    pub uri: Uri,
+    /// `rust` (default), `motoko`, `typescript`, or `javascript`.
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Type whose generated item is selected in the result.
+    #[serde(default, rename = "type")]
+    pub type_name: Option<String>,
+    /// Select the generated service instead of a type.
+    #[serde(default)]
+    pub service: bool,
+}
+
+/// Arguments of `candid.exampleValue`; exactly one of `type` and `method` is expected.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExampleValueArgs {
+    pub uri: Uri,
+    #[serde(default, rename = "type")]
+    pub type_name: Option<String>,
+    #[serde(default)]
+    pub method: Option<String>,
+    /// Overrides the configured `examples.seed`.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Overrides the configured `examples.maxDepth`.
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+}
+
+/// Arguments of `candid.decodeMessage`; exactly one of `hex` and `base64` is expected.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodeMessageArgs {
+    pub uri: Uri,
+    pub method: String,
+    /// Decode the method's results instead of its arguments.
+    #[serde(default)]
+    pub results: bool,
+    #[serde(default)]
+    pub hex: Option<String>,
+    #[serde(default)]
+    pub base64: Option<String>,
+}
+
+/// Arguments of `candid.encodeMessage`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncodeMessageArgs {
+    pub uri: Uri,
+    pub method: String,
+    /// Encode the method's results instead of its arguments.
+    #[serde(default)]
+    pub results: bool,
+    /// Argument list in Candid text syntax, e.g. `(42, "x")`.
+    pub value: String,
+    /// `hex` (default) or `base64`.
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+/// Arguments of `candid.expandType`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpandTypeArgs {
+    pub uri: Uri,
+    /// A type name of the document, or an inline type.
+    #[serde(rename = "type")]
+    pub type_text: String,
+    /// Overrides the configured `hover.expandDepth`.
+    #[serde(default)]
+    pub depth: Option<usize>,
+}
+
+/// Answer `workspace/executeCommand`.
+pub async fn execute_command(
+    server: &CandidLanguageServer,
+    params: ExecuteCommandParams,
+) -> Result<Option<Value>> {
+    match params.command.as_str() {
+        SHOW_BINDINGS => show_bindings(server, first_argument(params.arguments)?),
+        EXAMPLE_VALUE => example_value(server, first_argument(params.arguments)?),
+        DECODE_MESSAGE => decode_message(server, first_argument(params.arguments)?),
+        ENCODE_MESSAGE => encode_message(server, first_argument(params.arguments)?),
+        EXPAND_TYPE => expand_type(server, first_argument(params.arguments)?),
+        other => Err(Error::invalid_params(format!("unknown command `{other}`"))),
+    }
+}
+
+fn show_bindings(server: &CandidLanguageServer, args: ShowBindingsArgs) -> Result<Option<Value>> {
+    let language = match &args.language {
+        Some(language) => BindingLanguage::from_str(language).map_err(Error::invalid_params)?,
+        None => BindingLanguage::Rust,
+    };
+    let target = match (args.service, args.type_name) {
+        (true, _) => Some(BindingTarget::Service),
+        (false, name) => name.map(BindingTarget::Type),
+    };
+    let Some(snapshot) = server.analysis_map.get(args.uri.as_str()) else {
+        return Err(Error::invalid_params(format!(
+            "`{}` is not an open document",
+            args.uri.as_str()
+        )));
+    };
+    let (Some(ast), Some(env)) = (snapshot.ast(), snapshot.type_env()) else {
+        return Err(Error::invalid_params("the document does not parse"));
+    };
+    let document = bindings_document(&args.uri, ast, env, language, target.as_ref())
+        .map_err(|err| Error::invalid_params(format!("cannot generate bindings: {err}")))?;
+    Ok(serde_json::to_value(document).ok())
+}
+
+/// Returns `{ "value": "<candid text>" }`.
+fn example_value(server: &CandidLanguageServer, args: ExampleValueArgs) -> Result<Option<Value>> {
+    let target = match (args.type_name, args.method) {
+        (Some(name), None) => ExampleTarget::Type(name),
+        (None, Some(name)) => ExampleTarget::Method(name),
+        _ => {
+            return Err(Error::invalid_params(
+                "expected exactly one of `type` and `method`",
+            ));
+        }
+    };
+    let mut options = server.example_options(args.uri.as_str());
+    options.seed = args.seed.unwrap_or(options.seed);
+    options.max_depth = args.max_depth.unwrap_or(options.max_depth);
+    let Some(snapshot) = server.analysis_map.get(args.uri.as_str()) else {
+        return Err(Error::invalid_params(format!(
+            "`{}` is not an open document",
+            args.uri.as_str()
+        )));
+    };
+    // Only programs whose names all resolve are used, so generated values are well-typed.
+    let Some(env) = snapshot.type_env() else {
+        return Err(Error::invalid_params("the document does not parse"));
+    };
+    let value = example_for(&target, env, options).map_err(Error::invalid_params)?;
+    Ok(Some(json!({ "value": value })))
+}
+
+/// Returns a [`MessageDocument`](crate::lsp::message_codec::MessageDocument); decoding errors
+/// carry the location of the offending type as `data.location`.
+fn decode_message(server: &CandidLanguageServer, args: DecodeMessageArgs) -> Result<Option<Value>> {
+    let bytes = match (&args.hex, &args.base64) {
+        (Some(hex), None) => parse_message_bytes(hex, MessageFormat::Hex),
+        (None, Some(base64)) => parse_message_bytes(base64, MessageFormat::Base64),
+        _ => {
+            return Err(Error::invalid_params(
+                "expected exactly one of `hex` and `base64`",
+            ));
+        }
+    }
+    .map_err(Error::invalid_params)?;
+    let Some(snapshot) = server.analysis_map.get(args.uri.as_str()) else {
+        return Err(Error::invalid_params(format!(
+            "`{}` is not an open document",
+            args.uri.as_str()
+        )));
+    };
+    let (Some(env), Some(semantic)) = (snapshot.type_env(), snapshot.semantic()) else {
+        return Err(Error::invalid_params("the document has unresolved types"));
+    };
+    let types = MethodTypes::lookup(
+        semantic,
+        snapshot.rope(),
+        env,
+        &args.method,
+        role(args.results),
+    )
+    .map_err(|err| message_error(&args.uri, snapshot.rope(), err))?;
+    let document = decode_method_message(&args.uri, env, &types, &bytes)
+        .map_err(|err| message_error(&args.uri, snapshot.rope(), err))?;
+    Ok(serde_json::to_value(document).ok())
+}
+
+fn encode_message(server: &CandidLanguageServer, args: EncodeMessageArgs) -> Result<Option<Value>> {
+    let format = match &args.format {
+        Some(format) => MessageFormat::from_str(format).map_err(Error::invalid_params)?,
+        None => MessageFormat::Hex,
+    };
+    let Some(snapshot) = server.analysis_map.get(args.uri.as_str()) else {
+        return Err(Error::invalid_params(format!(
+            "`{}` is not an open document",
+            args.uri.as_str()
+        )));
+    };
+    let (Some(env), Some(semantic)) = (snapshot.type_env(), snapshot.semantic()) else {
+        return Err(Error::invalid_params("the document has unresolved types"));
+    };
+    let types = MethodTypes::lookup(
+        semantic,
+        snapshot.rope(),
+        env,
+        &args.method,
+        role(args.results),
+    )
+    .map_err(|err| message_error(&args.uri, snapshot.rope(), err))?;
+    let document = encode_method_message(&args.uri, env, &types, &args.value, format)
+        .map_err(|err| message_error(&args.uri, snapshot.rope(), err))?;
+    Ok(serde_json::to_value(document).ok())
+}
+
+/// Returns `{ "value": "<candid type>" }`, as a `type` definition when a name was given.
+fn expand_type(server: &CandidLanguageServer, args: ExpandTypeArgs) -> Result<Option<Value>> {
+    let depth = args
+        .depth
+        .unwrap_or_else(|| server.hover_expand_depth(args.uri.as_str()));
+    let Some(snapshot) = server.analysis_map.get(args.uri.as_str()) else {
+        return Err(Error::invalid_params(format!(
+            "`{}` is not an open document",
+            args.uri.as_str()
+        )));
+    };
+    let Some(env) = snapshot.type_env() else {
+        return Err(Error::invalid_params("the document does not parse"));
+    };
+    let name = args.type_text.trim();
+    let value = match render_expanded_binding(name, env, depth) {
+        Some(binding) => binding,
+        None => {
+            let ty = parse_type_text(name, env).map_err(Error::invalid_params)?;
+            render_expanded_type(&ty, env, depth)
+        }
+    };
+    Ok(Some(json!({ "value": value })))
+}
+
+fn role(results: bool) -> ParamRole {
+    if results {
+        ParamRole::Result
+    } else {
+        ParamRole::Argument
+    }
+}
+
+fn message_error(uri: &Uri, rope: &Rope, err: MessageError) -> Error {
+    let mut error = Error::invalid_params(err.message);
+    error.data = err
+        .span
+        .and_then(|span| span_to_range(&span, rope))
+        .map(|range| json!({ "location": Location::new(uri.clone(), range) }));
+    error
+}
+
+fn first_argument<T: DeserializeOwned>(arguments: Vec<Value>) -> Result<T> {
+    let argument = arguments
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::invalid_params("missing command argument"))?;
+    serde_json::from_value(argument).map_err(|err| Error::invalid_params(err.to_string()))
+}
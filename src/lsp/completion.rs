@@ -13,6 +13,8 @@ use crate::lsp::{
         KeywordDoc, TypeDoc, blob_doc, keyword_doc, keyword_kinds, primitive_doc, primitive_kinds,
         primitive_name,
     },
+    value_document::value_completion,
+    workspace::DocumentKind,
 };
 use candid_parser::{
     candid::types::internal::FuncMode,
@@ -441,9 +443,20 @@ pub async fn completion(
         (Rope::default(), None)
     };
     let offset = position_to_offset(position, &rope);
+    if DocumentKind::of(&uri) == DocumentKind::Values {
+        let analysis = server.analysis_map.get(&uri_key);
+        let target = analysis
+            .as_ref()
+            .and_then(|analysis| analysis.value_target());
+        let items = target
+            .zip(offset)
+            .map(|(target, offset)| value_completion(target, &rope.to_string(), offset))
+            .unwrap_or_default();
+        return Ok(Some(CompletionResponse::Array(items)));
+    }
     let cursor_context = offset.map(|offset| CursorContext::new(&rope, offset));
 
-    let completion_mode = server.completion_mode(&rope);
+    let completion_mode = server.completion_mode(&uri_key, &rope);
     let is_lightweight = matches!(completion_mode, CompletionEngineMode::Lightweight);
 
     let analysis_guard = server.analysis_map.get(&uri_key);
@@ -486,7 +499,7 @@ pub async fn completion(
             .unwrap_or(false)
     );
 
-    let snippet_style = server.service_snippet_style();
+    let snippet_style = server.service_snippet_style(&uri_key);
 
     let inside_service_block = cursor_context
         .as_ref()
@@ -1,6 +1,18 @@
+use crate::lsp::{
+    diagnostic_rules::{DiagnosticRule, DiagnosticRules, RuleLevel},
+    example_value::ExampleOptions,
+    format::{FormatOptions, TrailingSeparator},
+    naming::{CaseStyle, NamingConventions},
+};
 use ropey::Rope;
 use serde_json::Value;
-use std::{fmt, str::FromStr};
+use std::{
+    fmt,
+    hash::{DefaultHasher, Hash, Hasher},
+    str::FromStr,
+    time::Duration,
+};
+use tower_lsp_server::ls_types::{CodeDescription, Diagnostic, FormattingOptions};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum ServiceSnippetStyle {
@@ -165,6 +177,13 @@ pub struct FormatConfig {
     pub enabled: bool,
     pub indent_width: Option<usize>,
     pub blank_lines: Option<usize>,
+    /// `None` follows the editor's `insertSpaces` option.
+    pub use_tabs: Option<bool>,
+    pub align_colons: bool,
+    pub trailing_separator: TrailingSeparator,
+    pub max_width: Option<usize>,
+    pub sort_methods: bool,
+    pub sort_types: bool,
 }
 
 impl Default for FormatConfig {
@@ -173,6 +192,12 @@ impl Default for FormatConfig {
             enabled: true,
             indent_width: None,
             blank_lines: None,
+            use_tabs: None,
+            align_colons: false,
+            trailing_separator: TrailingSeparator::default(),
+            max_width: None,
+            sort_methods: false,
+            sort_types: false,
         }
     }
 }
@@ -195,6 +220,214 @@ impl FormatConfig {
             if let Some(lines) = get_u64(obj, "blankLines") {
                 self.blank_lines = Some(lines as usize);
             }
+            if let Some(use_tabs) = get_bool(obj, "useTabs") {
+                self.use_tabs = Some(use_tabs);
+            }
+            if let Some(align) = get_bool(obj, "alignColons") {
+                self.align_colons = align;
+            }
+            if let Some(Ok(separator)) = get_value(obj, "trailingSeparator")
+                .and_then(Value::as_str)
+                .map(TrailingSeparator::from_str)
+            {
+                self.trailing_separator = separator;
+            }
+            if let Some(width) = get_u64(obj, "maxWidth") {
+                self.max_width = (width > 0).then_some(width as usize);
+            }
+            if let Some(sort) = get_bool(obj, "sortMethods") {
+                self.sort_methods = sort;
+            }
+            if let Some(sort) = get_bool(obj, "sortTypes") {
+                self.sort_types = sort;
+            }
+        }
+    }
+
+    /// Formatter options from these settings, taking the indentation the editor asks for in
+    /// `client` unless it is configured here.
+    pub fn options(&self, client: &FormattingOptions) -> FormatOptions {
+        FormatOptions {
+            indent_width: self
+                .indent_width
+                .or((client.tab_size > 0).then_some(client.tab_size as usize)),
+            blank_lines: self.blank_lines,
+            use_tabs: self.use_tabs.unwrap_or(!client.insert_spaces),
+            align_colons: self.align_colons,
+            trailing_separator: self.trailing_separator,
+            max_width: self.max_width,
+            sort_methods: self.sort_methods,
+            sort_types: self.sort_types,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnalysisConfig {
+    pub debounce_ms: u64,
+}
+
+impl Default for AnalysisConfig {
+    fn default() -> Self {
+        Self { debounce_ms: 150 }
+    }
+}
+
+impl AnalysisConfig {
+    fn apply_section(&mut self, value: &Value) {
+        if let Some(delay) = value.as_u64() {
+            self.debounce_ms = delay;
+            return;
+        }
+        if let Some(obj) = value.as_object()
+            && let Some(delay) = get_u64(obj, "debounceMs")
+        {
+            self.debounce_ms = delay;
+        }
+    }
+}
+
+/// Settings from the `examples` section, for generated example values.
+#[derive(Debug, Clone, Default)]
+pub struct ExampleConfig {
+    pub options: ExampleOptions,
+}
+
+impl ExampleConfig {
+    fn apply_section(&mut self, value: &Value) {
+        let Some(obj) = value.as_object() else {
+            return;
+        };
+        if let Some(seed) = get_u64(obj, "seed") {
+            self.options.seed = seed;
+        }
+        if let Some(depth) = get_u64(obj, "maxDepth") {
+            self.options.max_depth = depth as usize;
+        }
+        if let Some(len) = get_u64(obj, "maxVecLength") {
+            self.options.max_vec_len = len as usize;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HoverConfig {
+    /// Append the Rust binding generated for hovered types.
+    pub rust_bindings: bool,
+    /// How many type names deep the "Resolved" section expands; `0` hides it.
+    pub expand_depth: usize,
+}
+
+impl Default for HoverConfig {
+    fn default() -> Self {
+        Self {
+            rust_bindings: false,
+            expand_depth: 2,
+        }
+    }
+}
+
+impl HoverConfig {
+    fn apply_section(&mut self, value: &Value) {
+        let Some(obj) = value.as_object() else {
+            return;
+        };
+        if let Some(enabled) = get_bool(obj, "rustBindings") {
+            self.rust_bindings = enabled;
+        }
+        if let Some(depth) = get_u64(obj, "expandDepth") {
+            self.expand_depth = depth as usize;
+        }
+    }
+}
+
+/// Where the `docs/diagnostics` pages of this repository are published.
+pub const DEFAULT_DOCS_BASE_URL: &str =
+    "https://github.com/wiyota/candid-language-server/blob/main/docs/diagnostics";
+
+/// Settings from the `diagnostics` section: rule levels and naming conventions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagnosticsConfig {
+    pub rules: DiagnosticRules,
+    pub naming: NamingConventions,
+    /// Location the `docs/diagnostics` pages are published at; diagnostics link to their rule's
+    /// page unless it is cleared.
+    pub docs_base_url: Option<String>,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            rules: DiagnosticRules::default(),
+            naming: NamingConventions::default(),
+            docs_base_url: Some(DEFAULT_DOCS_BASE_URL.to_string()),
+        }
+    }
+}
+
+impl DiagnosticsConfig {
+    /// Short fingerprint of non-default settings, used to invalidate pull-diagnostic result ids.
+    pub fn tag(&self) -> Option<String> {
+        let rules = self.rules.tag();
+        if rules.is_none()
+            && self.naming == NamingConventions::default()
+            && self.docs_base_url.as_deref() == Some(DEFAULT_DOCS_BASE_URL)
+        {
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        rules.hash(&mut hasher);
+        self.naming.hash(&mut hasher);
+        self.docs_base_url.hash(&mut hasher);
+        Some(format!("r{:08x}", hasher.finish() as u32))
+    }
+
+    /// Set the configured severities, dropping diagnostics whose rule is off, and link every
+    /// remaining rule to its documentation page.
+    pub fn apply(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        let mut diagnostics = self.rules.apply(diagnostics);
+        if let Some(base) = &self.docs_base_url {
+            for diagnostic in &mut diagnostics {
+                diagnostic.code_description = DiagnosticRule::of(diagnostic)
+                    .and_then(|rule| rule.docs_uri(base))
+                    .map(|href| CodeDescription { href });
+            }
+        }
+        diagnostics
+    }
+
+    fn apply_section(&mut self, value: &Value) {
+        let Some(obj) = value.as_object() else {
+            return;
+        };
+        if let Some(base) = get_value(obj, "docsBaseUrl").and_then(Value::as_str) {
+            self.docs_base_url = Some(base.to_string()).filter(|base| !base.is_empty());
+        }
+        if let Some(rules) = get_value(obj, "rules").and_then(Value::as_object) {
+            self.rules = DiagnosticRules::default();
+            for (code, level) in rules {
+                if let (Some(rule), Some(Ok(level))) = (
+                    DiagnosticRule::from_code(code),
+                    level.as_str().map(RuleLevel::from_str),
+                ) {
+                    self.rules.set(rule, level);
+                }
+            }
+        }
+        if let Some(naming) = get_value(obj, "naming").and_then(Value::as_object) {
+            let defaults = NamingConventions::default();
+            let style = |key: &str, fallback: CaseStyle| {
+                get_value(naming, key)
+                    .and_then(Value::as_str)
+                    .and_then(|raw| CaseStyle::from_str(raw).ok())
+                    .unwrap_or(fallback)
+            };
+            self.naming = NamingConventions {
+                type_names: style("typeNames", defaults.type_names),
+                field_labels: style("fieldLabels", defaults.field_labels),
+                method_names: style("methodNames", defaults.method_names),
+                variant_tags: style("variantTags", defaults.variant_tags),
+            };
         }
     }
 }
@@ -230,6 +463,65 @@ fn to_snake_case(key: &str) -> String {
     output
 }
 
+/// `value` without its `candidLanguageServer` wrapper and with every setting name in camelCase,
+/// so settings written in different places merge key by key. Rule codes under
+/// `diagnostics.rules` are written without their optional `candid::` prefix instead.
+pub(crate) fn normalize_settings(value: Value) -> Value {
+    let Value::Object(mut obj) = value else {
+        return value;
+    };
+    let root_key = [
+        "candidLanguageServer",
+        "candid_language_server",
+        "candid-language-server",
+    ]
+    .into_iter()
+    .find(|key| obj.contains_key(*key));
+    let mut normalized = match root_key.and_then(|key| obj.remove(key)) {
+        Some(Value::Object(root)) => normalize_keys(root, false),
+        _ => serde_json::Map::new(),
+    };
+    normalized.extend(normalize_keys(obj, false));
+    Value::Object(normalized)
+}
+
+fn normalize_keys(
+    obj: serde_json::Map<String, Value>,
+    rules: bool,
+) -> serde_json::Map<String, Value> {
+    obj.into_iter()
+        .map(|(key, value)| {
+            if rules {
+                let code =
+                    DiagnosticRule::from_code(&key).map_or(key, |rule| rule.name().to_string());
+                return (code, value);
+            }
+            let key = to_camel_case(&key);
+            let value = match value {
+                Value::Object(obj) => Value::Object(normalize_keys(obj, key == "rules")),
+                value => value,
+            };
+            (key, value)
+        })
+        .collect()
+}
+
+fn to_camel_case(key: &str) -> String {
+    let mut output = String::with_capacity(key.len());
+    let mut upper = false;
+    for ch in key.chars() {
+        if ch == '-' || ch == '_' {
+            upper = !output.is_empty();
+        } else if upper {
+            output.push(ch.to_ascii_uppercase());
+            upper = false;
+        } else {
+            output.push(ch);
+        }
+    }
+    output
+}
+
 fn to_kebab_case(key: &str) -> String {
     let mut output = String::with_capacity(key.len());
     for (idx, ch) in key.chars().enumerate() {
@@ -250,6 +542,10 @@ pub struct ServerConfig {
     service_snippets: ServiceSnippetConfig,
     completion: CompletionConfig,
     format: FormatConfig,
+    analysis: AnalysisConfig,
+    diagnostics: DiagnosticsConfig,
+    hover: HoverConfig,
+    examples: ExampleConfig,
 }
 
 impl ServerConfig {
@@ -265,12 +561,28 @@ impl ServerConfig {
         self.format.enabled
     }
 
-    pub fn format_indent_width(&self) -> Option<usize> {
-        self.format.indent_width
+    pub fn format_options(&self, client: &FormattingOptions) -> FormatOptions {
+        self.format.options(client)
+    }
+
+    pub fn analysis_debounce(&self) -> Duration {
+        Duration::from_millis(self.analysis.debounce_ms)
+    }
+
+    pub fn hover_rust_bindings(&self) -> bool {
+        self.hover.rust_bindings
+    }
+
+    pub fn hover_expand_depth(&self) -> usize {
+        self.hover.expand_depth
     }
 
-    pub fn format_blank_lines(&self) -> Option<usize> {
-        self.format.blank_lines
+    pub fn example_options(&self) -> ExampleOptions {
+        self.examples.options
+    }
+
+    pub fn diagnostics(&self) -> &DiagnosticsConfig {
+        &self.diagnostics
     }
 
     pub fn apply_settings(&mut self, value: Value) {
@@ -285,6 +597,18 @@ impl ServerConfig {
         if let Some(section) = format_section(&value) {
             self.format.apply_section(section);
         }
+        if let Some(section) = analysis_section(&value) {
+            self.analysis.apply_section(section);
+        }
+        if let Some(section) = diagnostics_section(&value) {
+            self.diagnostics.apply_section(section);
+        }
+        if let Some(section) = hover_section(&value) {
+            self.hover.apply_section(section);
+        }
+        if let Some(section) = examples_section(&value) {
+            self.examples.apply_section(section);
+        }
     }
 }
 
@@ -341,6 +665,54 @@ fn format_section(value: &Value) -> Option<&Value> {
     None
 }
 
+fn analysis_section(value: &Value) -> Option<&Value> {
+    if let Some(obj) = value.as_object() {
+        if let Some(section) = get_value(obj, "analysis") {
+            return Some(section);
+        }
+        if let Some(root) = get_value(obj, "candidLanguageServer") {
+            return analysis_section(root);
+        }
+    }
+    None
+}
+
+fn diagnostics_section(value: &Value) -> Option<&Value> {
+    if let Some(obj) = value.as_object() {
+        if let Some(section) = get_value(obj, "diagnostics") {
+            return Some(section);
+        }
+        if let Some(root) = get_value(obj, "candidLanguageServer") {
+            return diagnostics_section(root);
+        }
+    }
+    None
+}
+
+fn hover_section(value: &Value) -> Option<&Value> {
+    if let Some(obj) = value.as_object() {
+        if let Some(section) = get_value(obj, "hover") {
+            return Some(section);
+        }
+        if let Some(root) = get_value(obj, "candidLanguageServer") {
+            return hover_section(root);
+        }
+    }
+    None
+}
+
+fn examples_section(value: &Value) -> Option<&Value> {
+    if let Some(obj) = value.as_object() {
+        if let Some(section) = get_value(obj, "examples") {
+            return Some(section);
+        }
+        if let Some(root) = get_value(obj, "candidLanguageServer") {
+            return examples_section(root);
+        }
+    }
+    None
+}
+
 fn completion_mode_from_value(value: &Value) -> Option<CompletionModeSetting> {
     if let Some(text) = value.as_str() {
         return CompletionModeSetting::from_str(text).ok();
@@ -0,0 +1,248 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::{DefaultHasher, Hash, Hasher},
+    str::FromStr,
+};
+use tower_lsp_server::ls_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Uri};
+
+/// Prefix shared by every diagnostic code; optional in configuration keys.
+const CODE_PREFIX: &str = "candid::";
+
+/// A class of diagnostic with a stable code that can be configured independently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiagnosticRule {
+    LexError,
+    ParseError,
+    UnexpectedToken,
+    UnexpectedEof,
+    InvalidToken,
+    ExtraToken,
+    MissingSemicolon,
+    UnclosedDelimiter,
+    TypeError,
+    UndefinedType,
+    InconsistentVecType,
+    UnusedSuppression,
+    NamingTypeName,
+    NamingFieldLabel,
+    NamingMethodName,
+    NamingVariantTag,
+    MissingDocs,
+    ValueSyntax,
+    ValueTypeMismatch,
+    ValueTarget,
+    TestSyntax,
+    TestAssertionFailed,
+}
+
+impl DiagnosticRule {
+    pub const ALL: [DiagnosticRule; 22] = [
+        DiagnosticRule::LexError,
+        DiagnosticRule::ParseError,
+        DiagnosticRule::UnexpectedToken,
+        DiagnosticRule::UnexpectedEof,
+        DiagnosticRule::InvalidToken,
+        DiagnosticRule::ExtraToken,
+        DiagnosticRule::MissingSemicolon,
+        DiagnosticRule::UnclosedDelimiter,
+        DiagnosticRule::TypeError,
+        DiagnosticRule::UndefinedType,
+        DiagnosticRule::InconsistentVecType,
+        DiagnosticRule::UnusedSuppression,
+        DiagnosticRule::NamingTypeName,
+        DiagnosticRule::NamingFieldLabel,
+        DiagnosticRule::NamingMethodName,
+        DiagnosticRule::NamingVariantTag,
+        DiagnosticRule::MissingDocs,
+        DiagnosticRule::ValueSyntax,
+        DiagnosticRule::ValueTypeMismatch,
+        DiagnosticRule::ValueTarget,
+        DiagnosticRule::TestSyntax,
+        DiagnosticRule::TestAssertionFailed,
+    ];
+
+    /// The opt-in `candid::naming::*` lints.
+    pub const NAMING: [DiagnosticRule; 4] = [
+        DiagnosticRule::NamingTypeName,
+        DiagnosticRule::NamingFieldLabel,
+        DiagnosticRule::NamingMethodName,
+        DiagnosticRule::NamingVariantTag,
+    ];
+
+    /// Stable code reported in `Diagnostic::code`.
+    pub fn code(self) -> &'static str {
+        match self {
+            DiagnosticRule::LexError => "candid::lex::error",
+            DiagnosticRule::ParseError => "candid::parse::error",
+            DiagnosticRule::UnexpectedToken => "candid::parse::unexpected-token",
+            DiagnosticRule::UnexpectedEof => "candid::parse::unexpected-eof",
+            DiagnosticRule::InvalidToken => "candid::parse::invalid-token",
+            DiagnosticRule::ExtraToken => "candid::parse::extra-token",
+            DiagnosticRule::MissingSemicolon => "candid::parse::missing-semicolon",
+            DiagnosticRule::UnclosedDelimiter => "candid::parse::unclosed-delimiter",
+            DiagnosticRule::TypeError => "candid::type-error",
+            DiagnosticRule::UndefinedType => "candid::undefined-type",
+            DiagnosticRule::InconsistentVecType => "candid::inconsistent-vec-type",
+            DiagnosticRule::UnusedSuppression => "candid::unused-suppression",
+            DiagnosticRule::NamingTypeName => "candid::naming::type-name",
+            DiagnosticRule::NamingFieldLabel => "candid::naming::field-label",
+            DiagnosticRule::NamingMethodName => "candid::naming::method-name",
+            DiagnosticRule::NamingVariantTag => "candid::naming::variant-tag",
+            DiagnosticRule::MissingDocs => "candid::missing-docs",
+            DiagnosticRule::ValueSyntax => "candid::value::syntax",
+            DiagnosticRule::ValueTypeMismatch => "candid::value::type-mismatch",
+            DiagnosticRule::ValueTarget => "candid::value::unresolved-target",
+            DiagnosticRule::TestSyntax => "candid::test::syntax",
+            DiagnosticRule::TestAssertionFailed => "candid::test::assertion-failed",
+        }
+    }
+
+    /// The code without its `candid::` prefix, as written in settings and suppression comments.
+    pub fn name(self) -> &'static str {
+        &self.code()[CODE_PREFIX.len()..]
+    }
+
+    /// Look a rule up by code, with or without the `candid::` prefix.
+    pub fn from_code(code: &str) -> Option<Self> {
+        let code = code.trim();
+        let code = code.strip_prefix(CODE_PREFIX).unwrap_or(code);
+        Self::ALL.into_iter().find(|rule| rule.name() == code)
+    }
+
+    pub fn default_level(self) -> RuleLevel {
+        match self {
+            DiagnosticRule::UnusedSuppression => RuleLevel::Hint,
+            DiagnosticRule::MissingDocs
+            | DiagnosticRule::NamingTypeName
+            | DiagnosticRule::NamingFieldLabel
+            | DiagnosticRule::NamingMethodName
+            | DiagnosticRule::NamingVariantTag => RuleLevel::Off,
+            DiagnosticRule::ValueTarget => RuleLevel::Warning,
+            _ => RuleLevel::Error,
+        }
+    }
+
+    /// Link to the rule's page among the `docs/diagnostics` pages published under `base`.
+    pub fn docs_uri(self, base: &str) -> Option<Uri> {
+        let page = self.name().replace("::", "-");
+        format!("{}/{page}.md", base.trim_end_matches('/'))
+            .parse()
+            .ok()
+    }
+
+    /// Stamp the rule's code onto `diagnostic`.
+    pub fn annotate(self, mut diagnostic: Diagnostic) -> Diagnostic {
+        diagnostic.code = Some(NumberOrString::String(self.code().to_string()));
+        diagnostic
+    }
+
+    /// The rule a diagnostic was annotated with, if any.
+    pub fn of(diagnostic: &Diagnostic) -> Option<Self> {
+        match diagnostic.code.as_ref()? {
+            NumberOrString::String(code) => Self::from_code(code),
+            NumberOrString::Number(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// Severity a rule is reported with, or `Off` to drop it entirely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RuleLevel {
+    Off,
+    Hint,
+    Info,
+    Warning,
+    Error,
+}
+
+impl RuleLevel {
+    pub fn severity(self) -> Option<DiagnosticSeverity> {
+        match self {
+            RuleLevel::Off => None,
+            RuleLevel::Hint => Some(DiagnosticSeverity::HINT),
+            RuleLevel::Info => Some(DiagnosticSeverity::INFORMATION),
+            RuleLevel::Warning => Some(DiagnosticSeverity::WARNING),
+            RuleLevel::Error => Some(DiagnosticSeverity::ERROR),
+        }
+    }
+}
+
+impl FromStr for RuleLevel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" | "allow" => Ok(RuleLevel::Off),
+            "hint" => Ok(RuleLevel::Hint),
+            "info" | "information" => Ok(RuleLevel::Info),
+            "warning" | "warn" => Ok(RuleLevel::Warning),
+            "error" | "deny" => Ok(RuleLevel::Error),
+            other => Err(format!("Invalid rule level: {other}")),
+        }
+    }
+}
+
+impl fmt::Display for RuleLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleLevel::Off => f.write_str("off"),
+            RuleLevel::Hint => f.write_str("hint"),
+            RuleLevel::Info => f.write_str("info"),
+            RuleLevel::Warning => f.write_str("warning"),
+            RuleLevel::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// Per-rule level overrides from the `diagnostics.rules` setting.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiagnosticRules {
+    overrides: HashMap<DiagnosticRule, RuleLevel>,
+}
+
+impl DiagnosticRules {
+    pub fn level(&self, rule: DiagnosticRule) -> RuleLevel {
+        self.overrides
+            .get(&rule)
+            .copied()
+            .unwrap_or_else(|| rule.default_level())
+    }
+
+    pub fn set(&mut self, rule: DiagnosticRule, level: RuleLevel) {
+        self.overrides.insert(rule, level);
+    }
+
+    /// Drop diagnostics whose rule is off and set the configured severity on the rest.
+    ///
+    /// Diagnostics without a known code are passed through unchanged.
+    pub fn apply(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .filter_map(|mut diagnostic| {
+                if let Some(rule) = DiagnosticRule::of(&diagnostic) {
+                    diagnostic.severity = Some(self.level(rule).severity()?);
+                }
+                Some(diagnostic)
+            })
+            .collect()
+    }
+
+    /// Short fingerprint of the overrides, or `None` when every rule uses its default level.
+    pub fn tag(&self) -> Option<String> {
+        if self.overrides.is_empty() {
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        for rule in DiagnosticRule::ALL {
+            self.overrides.get(&rule).hash(&mut hasher);
+        }
+        Some(format!("r{:08x}", hasher.finish() as u32))
+    }
+}
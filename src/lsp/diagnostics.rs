@@ -0,0 +1,327 @@
+use crate::{
+    candid_lang::CandidError,
+    lsp::{
+        CandidLanguageServer,
+        analysis::analyze_document,
+        clean_diagnostic_message,
+        config::DiagnosticsConfig,
+        diagnostic_rules::{DiagnosticRule, RuleLevel},
+        error_span::{report_parser_error, report_to_diagnostic},
+        missing_docs::missing_doc_diagnostics,
+        naming::naming_diagnostics,
+        parse_diagnostics::parse_error_to_diagnostic,
+        position::span_to_range,
+        semantic_analyze::{Semantic, SemanticError},
+        workspace::{collect_did_files, path_to_uri, uri_to_path},
+    },
+};
+use ropey::Rope;
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+use tower_lsp_server::{jsonrpc::Result, ls_types::*};
+
+/// Identifier advertised in the diagnostic capability and echoed back by pull requests.
+pub const DIAGNOSTIC_IDENTIFIER: &str = "candid";
+
+/// Convert recorded lexer and parser errors into LSP diagnostics.
+pub fn parse_error_diagnostics(
+    errors: Vec<CandidError>,
+    uri: &Uri,
+    rope: &Rope,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::with_capacity(errors.len());
+    for item in errors {
+        let anchor = item.span();
+        let diag = match item {
+            CandidError::Parser(err) => match err {
+                candid_parser::Error::Parse(parse_err) => {
+                    Some(parse_error_to_diagnostic(parse_err, Some(uri), rope))
+                }
+                candid_parser::Error::Custom(_) => {
+                    Some(DiagnosticRule::ParseError.annotate(report_to_diagnostic(
+                        report_parser_error(&err, anchor.as_ref()),
+                        "parser",
+                        Some(uri),
+                        rope,
+                    )))
+                }
+                candid_parser::Error::CandidError(_) => {
+                    Some(DiagnosticRule::TypeError.annotate(report_to_diagnostic(
+                        report_parser_error(&err, anchor.as_ref()),
+                        "candid",
+                        Some(uri),
+                        rope,
+                    )))
+                }
+            },
+            CandidError::Lexer(err) => span_to_range(&err.span, rope).map(|range| {
+                DiagnosticRule::LexError.annotate(Diagnostic {
+                    range,
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    source: Some("lexer".to_string()),
+                    message: err.to_string(),
+                    related_information: None,
+                    ..Default::default()
+                })
+            }),
+        };
+
+        if let Some(mut diag) = diag {
+            diag.message = clean_diagnostic_message(diag.message);
+            diagnostics.push(diag);
+        }
+    }
+    diagnostics
+}
+
+/// Style lints run on a successfully analysed document; opt-in lints only run when enabled.
+pub fn lint_diagnostics(
+    semantic: &Semantic,
+    rope: &Rope,
+    config: &DiagnosticsConfig,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    if DiagnosticRule::NAMING
+        .iter()
+        .any(|rule| config.rules.level(*rule) != RuleLevel::Off)
+    {
+        diagnostics.extend(naming_diagnostics(semantic, rope, &config.naming));
+    }
+    if config.rules.level(DiagnosticRule::MissingDocs) != RuleLevel::Off {
+        diagnostics.extend(missing_doc_diagnostics(semantic, rope));
+    }
+    diagnostics
+}
+
+/// Convert a semantic analysis failure into a diagnostic anchored at the offending span.
+pub fn semantic_error_diagnostic(err: &SemanticError, rope: &Rope) -> Diagnostic {
+    let rule = match err {
+        SemanticError::UndefinedVariable { .. } => DiagnosticRule::UndefinedType,
+        SemanticError::ImConsistentArrayType { .. } => DiagnosticRule::InconsistentVecType,
+    };
+    rule.annotate(Diagnostic {
+        range: span_to_range(&err.span(), rope).unwrap_or_default(),
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("semantic".to_string()),
+        message: clean_diagnostic_message(format!("{err}")),
+        related_information: None,
+        ..Default::default()
+    })
+}
+
+/// Parse and analyse `text` from scratch, returning every diagnostic that is neither suppressed
+/// nor disabled by `rules`.
+pub fn diagnostics_for_source(
+    uri: &Uri,
+    text: &str,
+    config: &DiagnosticsConfig,
+) -> Vec<Diagnostic> {
+    analyze_document(uri, text, &Rope::from_str(text), config, None).diagnostics
+}
+
+/// Derive the pull-diagnostic result id for an open document.
+///
+/// Versioned documents use the version directly; unversioned ones (e.g. text supplied on save)
+/// fall back to a fingerprint of the diagnostics so identical reports still compare equal.
+pub fn document_result_id(version: Option<i32>, diagnostics: &[Diagnostic]) -> String {
+    match version {
+        Some(version) => format!("v{version}"),
+        None => format!("h{:016x}", diagnostics_fingerprint(diagnostics)),
+    }
+}
+
+/// Suffix `result_id` with the diagnostics configuration, so changing it invalidates earlier
+/// reports.
+pub fn tag_result_id(result_id: String, config: &DiagnosticsConfig) -> String {
+    match config.tag() {
+        Some(tag) => format!("{result_id}-{tag}"),
+        None => result_id,
+    }
+}
+
+fn diagnostics_fingerprint(diagnostics: &[Diagnostic]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for diag in diagnostics {
+        diag.range.start.line.hash(&mut hasher);
+        diag.range.start.character.hash(&mut hasher);
+        diag.range.end.line.hash(&mut hasher);
+        diag.range.end.character.hash(&mut hasher);
+        diag.message.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+fn file_result_id(path: &Path) -> Option<String> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!(
+        "m{}.{}-{}",
+        modified.as_secs(),
+        modified.subsec_nanos(),
+        metadata.len()
+    ))
+}
+
+/// Answer a `textDocument/diagnostic` request from the cached analysis snapshot.
+pub async fn document_diagnostic(
+    server: &CandidLanguageServer,
+    params: DocumentDiagnosticParams,
+) -> Result<DocumentDiagnosticReportResult> {
+    let uri_key = params.text_document.uri.to_string();
+    let Some(snapshot) = server.analysis_map.get(&uri_key) else {
+        return Ok(full_document_report(None, Vec::new()));
+    };
+    let result_id = snapshot.diagnostic_result_id().to_string();
+    if params.previous_result_id.as_deref() == Some(result_id.as_str()) {
+        return Ok(unchanged_document_report(result_id));
+    }
+    Ok(full_document_report(
+        Some(result_id),
+        snapshot.diagnostics().to_vec(),
+    ))
+}
+
+/// Answer a `workspace/diagnostic` request covering open documents and unopened `.did` files.
+pub async fn workspace_diagnostic(
+    server: &CandidLanguageServer,
+    params: WorkspaceDiagnosticParams,
+) -> Result<WorkspaceDiagnosticReportResult> {
+    let previous: HashMap<String, String> = params
+        .previous_result_ids
+        .into_iter()
+        .map(|entry| (entry.uri.to_string(), entry.value))
+        .collect();
+    // Files of one directory share their project configuration.
+    let mut configs: HashMap<PathBuf, DiagnosticsConfig> = HashMap::new();
+    let mut items = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+
+    let open_reports = server
+        .analysis_map
+        .iter()
+        .map(|entry| {
+            (
+                entry.key().clone(),
+                entry.version(),
+                entry.diagnostic_result_id().to_string(),
+                entry.diagnostics().to_vec(),
+            )
+        })
+        .collect::<Vec<_>>();
+    for (uri_key, version, result_id, diagnostics) in open_reports {
+        seen.insert(uri_key.clone());
+        let Ok(uri) = uri_key.parse::<Uri>() else {
+            continue;
+        };
+        let version = version.map(i64::from);
+        items.push(workspace_report(
+            uri,
+            version,
+            result_id,
+            diagnostics,
+            previous.get(&uri_key),
+        ));
+    }
+
+    for folder in server.workspace_folders() {
+        let Some(root) = uri_to_path(&folder) else {
+            continue;
+        };
+        let files = tokio::task::spawn_blocking(move || collect_did_files(&root))
+            .await
+            .unwrap_or_default();
+        for path in files {
+            let Some(uri) = path_to_uri(&path) else {
+                continue;
+            };
+            let uri_key = uri.to_string();
+            if !seen.insert(uri_key.clone()) {
+                continue;
+            }
+            let Some(result_id) = file_result_id(&path) else {
+                continue;
+            };
+            let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            let config = match configs.get(&dir) {
+                Some(config) => config.clone(),
+                None => {
+                    let config = server.diagnostics_config_for_path(&path).await;
+                    configs.insert(dir, config.clone());
+                    config
+                }
+            };
+            let result_id = tag_result_id(result_id, &config);
+            if previous.get(&uri_key) == Some(&result_id) {
+                items.push(WorkspaceDocumentDiagnosticReport::Unchanged(
+                    WorkspaceUnchangedDocumentDiagnosticReport {
+                        uri,
+                        version: None,
+                        unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                            result_id,
+                        },
+                    },
+                ));
+                continue;
+            }
+            let Ok(text) = tokio::fs::read_to_string(&path).await else {
+                continue;
+            };
+            let diagnostics = diagnostics_for_source(&uri, &text, &config);
+            items.push(workspace_report(uri, None, result_id, diagnostics, None));
+        }
+    }
+
+    Ok(WorkspaceDiagnosticReportResult::Report(
+        WorkspaceDiagnosticReport { items },
+    ))
+}
+
+fn full_document_report(
+    result_id: Option<String>,
+    items: Vec<Diagnostic>,
+) -> DocumentDiagnosticReportResult {
+    DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(
+        RelatedFullDocumentDiagnosticReport {
+            related_documents: None,
+            full_document_diagnostic_report: FullDocumentDiagnosticReport { result_id, items },
+        },
+    ))
+}
+
+fn unchanged_document_report(result_id: String) -> DocumentDiagnosticReportResult {
+    DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Unchanged(
+        RelatedUnchangedDocumentDiagnosticReport {
+            related_documents: None,
+            unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport { result_id },
+        },
+    ))
+}
+
+fn workspace_report(
+    uri: Uri,
+    version: Option<i64>,
+    result_id: String,
+    diagnostics: Vec<Diagnostic>,
+    previous: Option<&String>,
+) -> WorkspaceDocumentDiagnosticReport {
+    if previous == Some(&result_id) {
+        WorkspaceDocumentDiagnosticReport::Unchanged(WorkspaceUnchangedDocumentDiagnosticReport {
+            uri,
+            version,
+            unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport { result_id },
+        })
+    } else {
+        WorkspaceDocumentDiagnosticReport::Full(WorkspaceFullDocumentDiagnosticReport {
+            uri,
+            version,
+            full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                result_id: Some(result_id),
+                items: diagnostics,
+            },
+        })
+    }
+}
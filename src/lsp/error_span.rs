@@ -0,0 +1,139 @@
+use crate::lsp::{
+    clean_diagnostic_message, format_error_chain, position::span_to_range, span::Span,
+};
+use candid_parser::candid::Error as CandidCoreError;
+use ropey::Rope;
+use tower_lsp_server::ls_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location, Uri,
+};
+
+/// A message attached to a byte range of the document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanLabel {
+    pub span: Span,
+    pub message: String,
+}
+
+/// Source-anchored view of a Candid error, independent of how it is reported to the client.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorReport {
+    pub message: String,
+    pub primary: Option<SpanLabel>,
+    pub secondary: Vec<SpanLabel>,
+    pub notes: Vec<String>,
+}
+
+/// Map a `candid_parser::Error` onto document spans.
+///
+/// Parse errors carry their own token spans; other variants have none and are pinned to `anchor`,
+/// the construct that was being processed when the error occurred.
+pub fn report_parser_error(err: &candid_parser::Error, anchor: Option<&Span>) -> ErrorReport {
+    match err {
+        candid_parser::Error::Parse(_) => {
+            let report = err.report();
+            let mut labels = report.labels.into_iter().map(|label| SpanLabel {
+                span: label.range,
+                message: label.message,
+            });
+            ErrorReport {
+                message: report.message,
+                primary: labels.next(),
+                secondary: labels.collect(),
+                notes: report.notes,
+            }
+        }
+        candid_parser::Error::Custom(inner) => ErrorReport {
+            message: format!(
+                "custom parser error: {}",
+                format_error_chain(inner.as_ref())
+            ),
+            primary: anchored(anchor),
+            ..ErrorReport::default()
+        },
+        candid_parser::Error::CandidError(inner) => report_candid_error(inner, anchor),
+    }
+}
+
+/// Map a core `candid::Error` onto document spans.
+///
+/// Binary decoding errors count offsets into the decoded bytes rather than the document, so they
+/// are reported at `anchor`, with one note per decoding frame naming its byte offset.
+pub fn report_candid_error(err: &CandidCoreError, anchor: Option<&Span>) -> ErrorReport {
+    let mut report = ErrorReport {
+        message: format_error_chain(err),
+        primary: anchored(anchor),
+        ..ErrorReport::default()
+    };
+    if let CandidCoreError::Binread(_) = err {
+        // Frame labels point into the hex dump of the message, two digits per byte.
+        for label in err.report().labels {
+            let note = format!("{} at byte offset {}", label.message, label.range.start / 2);
+            if !report.message.contains(&note) {
+                report.notes.push(note);
+            }
+        }
+    }
+    report
+}
+
+fn anchored(anchor: Option<&Span>) -> Option<SpanLabel> {
+    anchor.map(|span| SpanLabel {
+        span: span.clone(),
+        message: String::new(),
+    })
+}
+
+/// Convert an [`ErrorReport`] into an LSP diagnostic.
+///
+/// Secondary labels become `related_information` when the document `uri` is known, and are folded
+/// into the message otherwise.
+pub fn report_to_diagnostic(
+    report: ErrorReport,
+    source: &str,
+    uri: Option<&Uri>,
+    rope: &Rope,
+) -> Diagnostic {
+    let ErrorReport {
+        mut message,
+        primary,
+        secondary,
+        notes,
+    } = report;
+    let range = primary
+        .as_ref()
+        .and_then(|label| span_to_range(&label.span, rope))
+        .unwrap_or_default();
+    if let Some(label) = primary.filter(|label| !label.message.is_empty())
+        && !message.contains(&label.message)
+    {
+        message.push_str(": ");
+        message.push_str(&label.message);
+    }
+
+    let mut related = Vec::new();
+    for label in secondary {
+        match (uri, span_to_range(&label.span, rope)) {
+            (Some(uri), Some(range)) => related.push(DiagnosticRelatedInformation {
+                location: Location::new(uri.clone(), range),
+                message: label.message,
+            }),
+            _ => {
+                message.push_str("\n  - ");
+                message.push_str(&label.message);
+            }
+        }
+    }
+    for note in notes {
+        message.push('\n');
+        message.push_str(&note);
+    }
+
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some(source.to_string()),
+        message: clean_diagnostic_message(message),
+        related_information: (!related.is_empty()).then_some(related),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,355 @@
+use crate::lsp::{
+    commands::EXAMPLE_VALUE,
+    normal_value::written_label,
+    span::Span,
+    value_check::{TypeEnv, undefined_type},
+};
+use candid_parser::{
+    candid::types::Label,
+    syntax::{Dec, IDLMergedProg, IDLType, IDLTypeWithSpan, PrimType, TypeField},
+};
+use serde_json::json;
+use std::fmt::Write;
+use tower_lsp_server::ls_types::{CodeAction, CodeActionKind, CodeActionOrCommand, Command, Uri};
+
+/// Kind of the "generate example value" code actions.
+pub const EXAMPLE_VALUE_KIND: CodeActionKind = CodeActionKind::new("source.exampleValue");
+
+const TEXT_SAMPLES: &[&str] = &["alice", "bob", "example", "hello", "candid"];
+/// Nesting beyond `max_depth` tolerated for fields that cannot be left out, before giving up on
+/// types that have no finite value.
+const DEPTH_SLACK: usize = 32;
+const PRINCIPAL_SAMPLES: &[&str] = &[
+    "aaaaa-aa",
+    "rrkah-fqaaa-aaaaa-aaaaq-cai",
+    "ryjl3-tyaaa-aaaaa-aaaba-cai",
+];
+
+/// Knobs for [`example_value`] and [`example_args`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExampleOptions {
+    /// Seed of the generator; the same seed always yields the same value.
+    pub seed: u64,
+    /// Nesting of records, variants, `opt`s and `vec`s allowed before falling back to the
+    /// smallest value that still type-checks.
+    pub max_depth: usize,
+    /// Upper bound on the number of `vec` elements.
+    pub max_vec_len: usize,
+}
+
+impl Default for ExampleOptions {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            max_depth: 4,
+            max_vec_len: 2,
+        }
+    }
+}
+
+/// A well-typed example value of `ty`, in Candid text syntax.
+pub fn example_value(ty: &IDLType, env: &TypeEnv, options: ExampleOptions) -> String {
+    let mut generator = Generator::new(env, options);
+    let mut out = String::new();
+    generator.value(&mut out, ty, options.max_depth);
+    out
+}
+
+/// An example argument list for `types`, e.g. `(record { name = "alice" }, 42)`.
+pub fn example_args(types: &[IDLTypeWithSpan], env: &TypeEnv, options: ExampleOptions) -> String {
+    let mut generator = Generator::new(env, options);
+    let mut out = String::from("(");
+    for (idx, ty) in types.iter().enumerate() {
+        if idx > 0 {
+            out.push_str(", ");
+        }
+        generator.value(&mut out, &ty.kind, options.max_depth);
+    }
+    out.push(')');
+    out
+}
+
+/// What an example is generated for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExampleTarget {
+    /// A value of the named type.
+    Type(String),
+    /// The argument list of the named service method.
+    Method(String),
+}
+
+/// The example for `target`; an error when it names no type or method of `env`, or when one of
+/// the types it needs does not resolve.
+pub fn example_for(
+    target: &ExampleTarget,
+    env: &TypeEnv,
+    options: ExampleOptions,
+) -> Result<String, String> {
+    env.checked()
+        .map_err(|err| format!("the document's types do not check: {err}"))?;
+    let types = match target {
+        ExampleTarget::Type(name) => {
+            std::slice::from_ref(env.get(name).ok_or_else(|| format!("no type `{name}`"))?)
+        }
+        ExampleTarget::Method(name) => env
+            .method(name)
+            .map(|func| func.args.as_slice())
+            .ok_or_else(|| format!("no service method `{name}`"))?,
+    };
+    if let Some(name) = types.iter().find_map(|ty| undefined_type(&ty.kind, env)) {
+        return Err(format!("type `{name}` is not defined"));
+    }
+    Ok(match target {
+        ExampleTarget::Type(_) => example_value(&types[0].kind, env, options),
+        ExampleTarget::Method(_) => example_args(types, env, options),
+    })
+}
+
+/// The type binding or service method whose declaration contains `offset`.
+pub fn example_target_at(
+    ast: &IDLMergedProg,
+    env: &TypeEnv,
+    offset: usize,
+) -> Option<ExampleTarget> {
+    let contains = |span: &Span| span.start <= offset && offset <= span.end;
+    if let Some(method) = env
+        .service_methods()
+        .iter()
+        .find(|method| contains(&method.span))
+    {
+        return Some(ExampleTarget::Method(method.id.clone()));
+    }
+    ast.decs().iter().find_map(|dec| match dec {
+        Dec::TypD(binding) if contains(&binding.span) => {
+            Some(ExampleTarget::Type(binding.id.clone()))
+        }
+        _ => None,
+    })
+}
+
+/// A code action running `candid.exampleValue` for the declaration at `offset`.
+pub fn example_actions(
+    uri: &Uri,
+    ast: &IDLMergedProg,
+    env: &TypeEnv,
+    offset: usize,
+) -> Vec<CodeActionOrCommand> {
+    let Some(target) = example_target_at(ast, env, offset) else {
+        return Vec::new();
+    };
+    let (title, argument) = match &target {
+        ExampleTarget::Type(name) => (
+            format!("Generate example value of `{name}`"),
+            json!({ "uri": uri.as_str(), "type": name }),
+        ),
+        ExampleTarget::Method(name) => (
+            format!("Generate example arguments for `{name}`"),
+            json!({ "uri": uri.as_str(), "method": name }),
+        ),
+    };
+    vec![CodeActionOrCommand::CodeAction(CodeAction {
+        title: title.clone(),
+        kind: Some(EXAMPLE_VALUE_KIND),
+        command: Some(Command {
+            title,
+            command: EXAMPLE_VALUE.to_string(),
+            arguments: Some(vec![argument]),
+        }),
+        ..CodeAction::default()
+    })]
+}
+
+struct Generator<'a> {
+    env: &'a TypeEnv,
+    options: ExampleOptions,
+    state: u64,
+    level: usize,
+}
+
+impl<'a> Generator<'a> {
+    fn new(env: &'a TypeEnv, options: ExampleOptions) -> Self {
+        Self {
+            env,
+            options,
+            state: options.seed,
+            level: 0,
+        }
+    }
+
+    /// SplitMix64: tiny, seedable, and good enough for sample data.
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        if bound == 0 { 0 } else { self.next() % bound }
+    }
+
+    fn pick<'s>(&mut self, samples: &[&'s str]) -> &'s str {
+        samples[self.below(samples.len() as u64) as usize]
+    }
+
+    fn value(&mut self, out: &mut String, ty: &IDLType, depth: usize) {
+        let Some(resolved) = self.env.resolve(ty) else {
+            out.push_str("null");
+            return;
+        };
+        if self.level > self.options.max_depth + DEPTH_SLACK {
+            out.push_str("null");
+            return;
+        }
+        self.level += 1;
+        self.resolved_value(out, resolved, depth);
+        self.level -= 1;
+    }
+
+    fn resolved_value(&mut self, out: &mut String, resolved: &IDLType, depth: usize) {
+        match resolved {
+            IDLType::PrimT(prim) => self.primitive(out, prim),
+            IDLType::PrincipalT => {
+                let principal = self.pick(PRINCIPAL_SAMPLES);
+                let _ = write!(out, "principal \"{principal}\"");
+            }
+            IDLType::OptT(inner) => {
+                if depth == 0 || self.below(2) == 0 {
+                    out.push_str("null");
+                } else {
+                    out.push_str("opt ");
+                    self.value(out, &inner.kind, depth - 1);
+                }
+            }
+            IDLType::VecT(inner)
+                if matches!(
+                    self.env.resolve(&inner.kind),
+                    Some(IDLType::PrimT(PrimType::Nat8))
+                ) =>
+            {
+                let len = self.vec_len(depth);
+                out.push_str("blob \"");
+                for _ in 0..len {
+                    let _ = write!(out, "\\{:02x}", self.below(256));
+                }
+                out.push('"');
+            }
+            IDLType::VecT(inner) => {
+                let len = self.vec_len(depth);
+                if len == 0 {
+                    out.push_str("vec {}");
+                    return;
+                }
+                out.push_str("vec { ");
+                for _ in 0..len {
+                    self.value(out, &inner.kind, depth - 1);
+                    out.push_str("; ");
+                }
+                out.push('}');
+            }
+            IDLType::RecordT(fields) => self.record(out, fields, depth),
+            IDLType::VariantT(cases) => self.variant(out, cases, depth),
+            IDLType::FuncT(_) => {
+                let principal = self.pick(PRINCIPAL_SAMPLES);
+                let _ = write!(out, "func \"{principal}\".method");
+            }
+            IDLType::ServT(_) | IDLType::ClassT(..) => {
+                let principal = self.pick(PRINCIPAL_SAMPLES);
+                let _ = write!(out, "service \"{principal}\"");
+            }
+            IDLType::VarT(_) => out.push_str("null"),
+        }
+    }
+
+    fn vec_len(&mut self, depth: usize) -> u64 {
+        if depth == 0 {
+            0
+        } else {
+            self.below(self.options.max_vec_len as u64 + 1)
+        }
+    }
+
+    fn primitive(&mut self, out: &mut String, prim: &PrimType) {
+        let _ = match prim {
+            PrimType::Nat => write!(out, "{}", self.below(1000)),
+            PrimType::Nat8 => write!(out, "{}", self.below(256)),
+            PrimType::Nat16 | PrimType::Nat32 | PrimType::Nat64 => {
+                write!(out, "{}", self.below(1000))
+            }
+            PrimType::Int | PrimType::Int16 | PrimType::Int32 | PrimType::Int64 => {
+                write!(out, "{}", self.below(2000) as i64 - 1000)
+            }
+            PrimType::Int8 => write!(out, "{}", self.below(256) as i64 - 128),
+            PrimType::Float32 | PrimType::Float64 => {
+                write!(out, "{}.{:02}", self.below(1000), self.below(100))
+            }
+            PrimType::Bool => write!(out, "{}", self.below(2) == 1),
+            PrimType::Text => write!(out, "\"{}\"", self.pick(TEXT_SAMPLES)),
+            PrimType::Null | PrimType::Reserved | PrimType::Empty => write!(out, "null"),
+        };
+    }
+
+    fn record(&mut self, out: &mut String, fields: &[TypeField], depth: usize) {
+        if fields.is_empty() {
+            out.push_str("record {}");
+            return;
+        }
+        let inner = depth.saturating_sub(1);
+        out.push_str("record { ");
+        for field in fields {
+            if !matches!(field.label, Label::Unnamed(_)) {
+                let _ = write!(out, "{} = ", written_label(&field.label));
+            }
+            self.value(out, &field.typ.kind, inner);
+            out.push_str("; ");
+        }
+        out.push('}');
+    }
+
+    /// The first case, unless it cannot be built within the remaining depth; recursive types
+    /// then end in the case that terminates soonest.
+    fn variant(&mut self, out: &mut String, cases: &[TypeField], depth: usize) {
+        let inner = depth.saturating_sub(1);
+        let Some(case) = (inner..=inner + DEPTH_SLACK)
+            .find_map(|budget| cases.iter().find(|case| self.fits(&case.typ.kind, budget)))
+            .or_else(|| cases.first())
+        else {
+            out.push_str("null");
+            return;
+        };
+        let label = written_label(&case.label);
+        if matches!(
+            self.env.resolve(&case.typ.kind),
+            Some(IDLType::PrimT(PrimType::Null))
+        ) {
+            let _ = write!(out, "variant {{ {label} }}");
+            return;
+        }
+        let _ = write!(out, "variant {{ {label} = ");
+        self.value(out, &case.typ.kind, inner);
+        out.push_str(" }");
+    }
+
+    /// Whether a value of `ty` can be built with at most `depth` further levels of records and
+    /// variants; `opt` and `vec` always fit as `null` and `vec {}`.
+    fn fits(&self, ty: &IDLType, depth: usize) -> bool {
+        match self.env.resolve(ty) {
+            None | Some(IDLType::PrimT(PrimType::Empty)) => false,
+            Some(IDLType::RecordT(fields)) => {
+                fields.is_empty()
+                    || depth > 0
+                        && fields
+                            .iter()
+                            .all(|field| self.fits(&field.typ.kind, depth - 1))
+            }
+            Some(IDLType::VariantT(cases)) => {
+                depth > 0
+                    && cases
+                        .iter()
+                        .any(|case| self.fits(&case.typ.kind, depth - 1))
+            }
+            Some(_) => true,
+        }
+    }
+}
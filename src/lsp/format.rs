@@ -1,490 +1,1070 @@
 use crate::CandidLanguageServer;
-use candid_parser::syntax::{IDLMergedProg, pretty_print};
+use crate::candid_lang::lex;
+use crate::lsp::{
+    position::position_to_offset,
+    span::Span,
+    text_diff::{LineHunk, hunk_edit, line_hunks, minimal_edits, split_lines},
+    trivia::{Comment, has_blank_line},
+    type_display::prim_to_str,
+    workspace::DocumentKind,
+};
+use candid_parser::{
+    candid::types::{FuncMode, Label},
+    syntax::{
+        Binding, Dec, FuncType, IDLActorType, IDLMergedProg, IDLType, IDLTypeWithSpan, PrimType,
+        TypeField,
+    },
+    token::Token,
+};
 use ropey::Rope;
+use std::{fmt, str::FromStr};
 use tower_lsp_server::jsonrpc::Result;
-use tower_lsp_server::ls_types::{DocumentFormattingParams, Position, Range, TextEdit};
+use tower_lsp_server::ls_types::{
+    DocumentFormattingParams, DocumentOnTypeFormattingParams, DocumentRangeFormattingParams,
+    Position, Range, TextEdit,
+};
+
+/// Characters that trigger on-type formatting.
+pub const ON_TYPE_TRIGGERS: [&str; 2] = ["}", ";"];
+
+/// Indentation width used when neither the settings nor the editor give one.
+const DEFAULT_INDENT_WIDTH: usize = 2;
 
 pub async fn format(
     server: &CandidLanguageServer,
     params: DocumentFormattingParams,
 ) -> Result<Option<Vec<TextEdit>>> {
-    if !server.format_enabled() {
+    let uri = params.text_document.uri.as_str();
+    if !server.format_enabled(uri) {
         return Ok(None);
     }
 
+    if let Some(snapshot) = server.analysis_map.get(uri)
+        && !snapshot.has_parse_errors()
+        && let Some(ast) = snapshot.ast()
+        && let Some(doc) = server.documents.get(uri)
+        && doc.version() == snapshot.version()
+    {
+        let options = server.format_options(uri, &params.options);
+        Ok(format_program_with_options(
+            ast,
+            snapshot.comments(),
+            &doc.rope,
+            &options,
+        ))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Answer `textDocument/rangeFormatting` for the top-level declarations intersecting the range.
+pub async fn format_range(
+    server: &CandidLanguageServer,
+    params: DocumentRangeFormattingParams,
+) -> Result<Option<Vec<TextEdit>>> {
     let uri = params.text_document.uri.as_str();
+    if !server.format_enabled(uri) {
+        return Ok(None);
+    }
 
     if let Some(snapshot) = server.analysis_map.get(uri)
         && !snapshot.has_parse_errors()
         && let Some(ast) = snapshot.ast()
         && let Some(doc) = server.documents.get(uri)
+        && doc.version() == snapshot.version()
     {
-        let options = FormatOptions {
-            indent_width: server.format_indent_width(),
-            blank_lines: server.format_blank_lines(),
-        };
-        Ok(format_program_with_options(ast, &doc.rope, &options))
+        let options = server.format_options(uri, &params.options);
+        Ok(format_range_with_options(
+            ast,
+            snapshot.comments(),
+            &doc.rope,
+            params.range,
+            &options,
+        ))
     } else {
         Ok(None)
     }
 }
 
-pub fn format_program(ast: &IDLMergedProg, rope: &Rope) -> Option<Vec<TextEdit>> {
-    format_program_with_options(ast, rope, &FormatOptions::default())
+/// Answer `textDocument/onTypeFormatting` by re-indenting the block or declaration just closed.
+pub async fn format_on_type(
+    server: &CandidLanguageServer,
+    params: DocumentOnTypeFormattingParams,
+) -> Result<Option<Vec<TextEdit>>> {
+    let uri = &params.text_document_position.text_document.uri;
+    if !server.format_enabled(uri.as_str()) || DocumentKind::of(uri) != DocumentKind::Interface {
+        return Ok(None);
+    }
+    let Some(doc) = server.documents.get(uri.as_str()) else {
+        return Ok(None);
+    };
+    let options = server.format_options(uri.as_str(), &params.options);
+    Ok(on_type_edits(
+        &doc.rope,
+        params.text_document_position.position,
+        &params.ch,
+        &options.indent_unit(),
+    ))
+}
+
+pub fn format_program(
+    ast: &IDLMergedProg,
+    comments: &[Comment],
+    rope: &Rope,
+) -> Option<Vec<TextEdit>> {
+    format_program_with_options(ast, comments, rope, &FormatOptions::default())
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct FormatOptions {
     pub indent_width: Option<usize>,
     pub blank_lines: Option<usize>,
+    /// Indent with tabs instead of spaces.
+    pub use_tabs: bool,
+    /// Pad the names of fields and methods so the `:` of a block line up.
+    pub align_colons: bool,
+    /// Whether the last field or tag of a multi-line record or variant ends with `;`.
+    pub trailing_separator: TrailingSeparator,
+    /// Records and variants that fit on one line within this width are written on one line.
+    pub max_width: Option<usize>,
+    /// Sort the methods of services by name.
+    pub sort_methods: bool,
+    /// Sort type definitions by name, after the imports.
+    pub sort_types: bool,
 }
 
-pub fn format_program_with_options(
-    ast: &IDLMergedProg,
-    rope: &Rope,
-    options: &FormatOptions,
-) -> Option<Vec<TextEdit>> {
-    let original_text = rope.to_string();
-    let (imports, service_name) = extract_imports_and_service_name(&original_text);
-    let orphan_comments = extract_orphan_comment_blocks(&original_text);
-    let mut formatted_text = pretty_print(ast);
-
-    if let Some(name) = service_name {
-        inject_service_name(&mut formatted_text, &name);
+impl FormatOptions {
+    /// The text of one indentation level.
+    pub fn indent_unit(&self) -> String {
+        if self.use_tabs {
+            "\t".to_string()
+        } else {
+            " ".repeat(self.indent_width_or_default())
+        }
     }
 
-    if !imports.is_empty() && !formatted_has_imports(&formatted_text) {
-        formatted_text = prepend_imports(&formatted_text, &imports);
-    }
-    if !orphan_comments.is_empty() {
-        formatted_text = inject_orphan_comment_blocks(&formatted_text, &orphan_comments);
-    }
-    if let Some(width) = options.indent_width {
-        formatted_text = apply_indent_width(&formatted_text, width);
-    }
-    if let Some(lines) = options.blank_lines {
-        formatted_text = collapse_blank_lines(&formatted_text, lines);
+    fn indent_width_or_default(&self) -> usize {
+        self.indent_width
+            .filter(|width| *width > 0)
+            .unwrap_or(DEFAULT_INDENT_WIDTH)
     }
+}
 
-    let last_line_idx = rope.len_lines().saturating_sub(1);
-    let last_line = rope.line(last_line_idx);
-    let last_char_col = last_line.len_chars();
-    let end_position = Position::new(last_line_idx as u32, last_char_col as u32);
-
-    let start_position = Position::new(0, 0);
-    let full_range = Range::new(start_position, end_position);
-
-    Some(vec![TextEdit {
-        range: full_range,
-        new_text: formatted_text,
-    }])
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrailingSeparator {
+    #[default]
+    Always,
+    Never,
 }
 
-fn extract_imports_and_service_name(src: &str) -> (Vec<String>, Option<String>) {
-    let mut imports = Vec::new();
-    let mut service_name = None;
-    let mut lines = src.lines().peekable();
-
-    while let Some(line) = lines.next() {
-        let trimmed = line.trim_start();
-        if trimmed.starts_with("import") {
-            let mut statement = String::from(line);
-            if !line.trim_end().ends_with(';') {
-                for next in &mut lines {
-                    statement.push('\n');
-                    statement.push_str(next);
-                    if next.trim_end().ends_with(';') {
-                        break;
-                    }
-                }
-            }
-            imports.push(statement);
-            continue;
+impl FromStr for TrailingSeparator {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "always" => Ok(TrailingSeparator::Always),
+            "never" => Ok(TrailingSeparator::Never),
+            _ => Err(format!("Invalid trailing separator: {}", value)),
         }
+    }
+}
 
-        if service_name.is_none() {
-            service_name = extract_service_name_from_line(trimmed);
+impl fmt::Display for TrailingSeparator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrailingSeparator::Always => f.write_str("always"),
+            TrailingSeparator::Never => f.write_str("never"),
         }
     }
+}
 
-    (imports, service_name)
+/// Minimal edits formatting the whole document; none when it is already formatted.
+pub fn format_program_with_options(
+    ast: &IDLMergedProg,
+    comments: &[Comment],
+    rope: &Rope,
+    options: &FormatOptions,
+) -> Option<Vec<TextEdit>> {
+    Some(minimal_edits(
+        rope,
+        &formatted_text(ast, comments, rope, options),
+    ))
 }
 
-fn extract_service_name_from_line(trimmed: &str) -> Option<String> {
-    if !trimmed.starts_with("service") {
+/// Edits formatting the top-level declarations that intersect `range`, leaving the rest of the
+/// document untouched. Only the changed lines are replaced.
+pub fn format_range_with_options(
+    ast: &IDLMergedProg,
+    comments: &[Comment],
+    rope: &Rope,
+    range: Range,
+    options: &FormatOptions,
+) -> Option<Vec<TextEdit>> {
+    let start = position_to_offset(range.start, rope)?;
+    let end = position_to_offset(range.end, rope)?.max(start + 1);
+    let decs = ast.decs();
+    let actor = ast.resolve_actor().ok().flatten();
+    let spans = top_level_items(&decs, actor.as_ref())
+        .iter()
+        .map(TopLevel::span)
+        .collect::<Vec<_>>();
+    let selected: Vec<_> = spans
+        .iter()
+        .enumerate()
+        .filter(|(_, span)| span.start < end && start < span.end.max(span.start + 1))
+        .map(|(idx, _)| idx)
+        .collect();
+    let old_starts = spans
+        .iter()
+        .map(|span| rope.try_char_to_line(span.start).ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    let original_text = rope.to_string();
+    let (formatted_text, new_starts) = formatted_program(ast, comments, rope, options);
+    let old = split_lines(&original_text);
+    let new = split_lines(&formatted_text);
+    let hunks =
+        declaration_hunks(&old, &new, &old_starts, &new_starts, &selected).unwrap_or_else(|| {
+            // Declarations sharing a line, or reordered by sorting: diff the whole document and
+            // keep the hunks touching the selection.
+            let lines: Vec<_> = selected
+                .iter()
+                .filter_map(|idx| {
+                    let last = rope.try_char_to_line(spans[*idx].end.saturating_sub(1));
+                    Some(old_starts[*idx]..last.ok()? + 1)
+                })
+                .collect();
+            line_hunks(&old, &new)
+                .into_iter()
+                .filter(|hunk| {
+                    lines.iter().any(|lines| {
+                        hunk.old.start < lines.end
+                            && lines.start < hunk.old.end.max(hunk.old.start + 1)
+                    })
+                })
+                .collect()
+        });
+    Some(
+        hunks
+            .iter()
+            .filter_map(|hunk| hunk_edit(hunk, &old, &new, rope))
+            .collect(),
+    )
+}
+
+/// Line hunks of the `selected` top-level items, each diffed against its formatted counterpart.
+///
+/// `old_starts` and `new_starts` give the line where each item's code starts on either side;
+/// `None` unless the items start on distinct lines, in the same order, on both sides.
+fn declaration_hunks(
+    old: &[&str],
+    new: &[&str],
+    old_starts: &[usize],
+    new_starts: &[usize],
+    selected: &[usize],
+) -> Option<Vec<LineHunk>> {
+    let bounds = |starts: &[usize], len: usize| -> Option<Vec<usize>> {
+        let bounds: Vec<usize> = std::iter::once(0)
+            .chain(starts.iter().skip(1).copied())
+            .chain(std::iter::once(len))
+            .collect();
+        bounds
+            .windows(2)
+            .all(|pair| pair[0] < pair[1])
+            .then_some(bounds)
+    };
+    if old_starts.len() != new_starts.len() {
         return None;
     }
+    let (old_bounds, new_bounds) = (
+        bounds(old_starts, old.len())?,
+        bounds(new_starts, new.len())?,
+    );
+    let mut hunks = Vec::new();
+    for &idx in selected {
+        let (old_start, new_start) = (old_bounds[idx], new_bounds[idx]);
+        hunks.extend(
+            line_hunks(
+                &old[old_start..old_bounds[idx + 1]],
+                &new[new_start..new_bounds[idx + 1]],
+            )
+            .into_iter()
+            .map(|hunk| LineHunk {
+                old: hunk.old.start + old_start..hunk.old.end + old_start,
+                new: hunk.new.start + new_start..hunk.new.end + new_start,
+            }),
+        );
+    }
+    Some(hunks)
+}
 
-    let rest = trimmed["service".len()..].trim_start();
-    let mut chars = rest.char_indices();
-    let (start_idx, first) = chars.next()?;
-    if !is_ident_start(first) {
+/// Edits re-indenting what the `trigger` character typed just before `position` closed: the block
+/// back to its `{` for `}`, or the declaration or field for `;`. Only lines whose indentation
+/// changes get an edit.
+pub fn on_type_edits(
+    rope: &Rope,
+    position: Position,
+    trigger: &str,
+    indent_unit: &str,
+) -> Option<Vec<TextEdit>> {
+    let trigger_offset = position_to_offset(position, rope)?.checked_sub(1)?;
+    let trigger_char = rope.get_char(trigger_offset)?;
+    if trigger.chars().next() != Some(trigger_char) {
         return None;
     }
 
-    let mut end_idx = start_idx + first.len_utf8();
-    for (idx, ch) in chars {
-        if !is_ident_continue(ch) {
-            end_idx = idx;
-            break;
-        }
-        end_idx = idx + ch.len_utf8();
+    // Brace depth comes from the lexer, so braces and `;` in strings and comments never count.
+    let trigger_byte = rope.try_char_to_byte(trigger_offset).ok()?;
+    let tokens = lex(&rope.to_string()).tokens;
+    let trigger_idx = tokens.partition_point(|(start, ..)| *start < trigger_byte);
+    let (_, trigger_token, _) = tokens.get(trigger_idx).filter(|(start, token, _)| {
+        *start == trigger_byte && matches!(token, Token::RBrace | Token::Semi)
+    })?;
+    let mut scan = BlockScan::new(rope.len_lines());
+    for (start, token, _) in &tokens[..trigger_idx] {
+        scan.push(rope, *start, token)?;
     }
+    let start_line = match trigger_char {
+        '}' => scan.open_braces.last().copied(),
+        _ => scan.statement_start.last().copied().flatten(),
+    }?;
+    let trigger_line = rope.try_char_to_line(trigger_offset).ok()?;
+    scan.push(rope, trigger_byte, trigger_token)?;
 
-    let name = &rest[start_idx..end_idx];
-    let after = rest[end_idx..].trim_start();
-    if after.starts_with(':') {
-        return Some(name.to_string());
+    let mut edits = Vec::new();
+    for line_idx in start_line..=trigger_line {
+        let Some(Some(depth)) = scan.line_depths.get(line_idx).copied() else {
+            continue;
+        };
+        let indent: String = rope
+            .line(line_idx)
+            .chars()
+            .take_while(|ch| *ch == ' ' || *ch == '\t')
+            .collect();
+        let desired = indent_unit.repeat(depth);
+        if indent == desired {
+            continue;
+        }
+        edits.push(TextEdit {
+            range: Range::new(
+                Position::new(line_idx as u32, 0),
+                Position::new(line_idx as u32, indent.chars().count() as u32),
+            ),
+            new_text: desired,
+        });
     }
-
-    None
+    Some(edits)
 }
 
-fn is_ident_start(ch: char) -> bool {
-    ch.is_ascii_alphabetic() || ch == '_'
+/// Brace nesting of a document's tokens.
+#[derive(Debug)]
+struct BlockScan {
+    /// Line of every `{` still open.
+    open_braces: Vec<usize>,
+    /// Per nesting level, the line where the current statement started, if it has.
+    statement_start: Vec<Option<usize>>,
+    /// Per line, the nesting depth of its first token; `None` for blank lines and lines starting
+    /// inside a string or comment.
+    line_depths: Vec<Option<usize>>,
 }
 
-fn is_ident_continue(ch: char) -> bool {
-    ch.is_ascii_alphanumeric() || ch == '_'
-}
+impl BlockScan {
+    fn new(lines: usize) -> Self {
+        Self {
+            open_braces: Vec::new(),
+            statement_start: vec![None],
+            line_depths: vec![None; lines],
+        }
+    }
 
-fn inject_service_name(formatted: &mut String, name: &str) -> bool {
-    let mut search_from = 0;
-    while let Some(idx) = formatted[search_from..].find("service") {
-        let start = search_from + idx;
-        if start > 0
-            && let Some(prev) = formatted[..start].chars().last()
-            && is_ident_continue(prev)
+    /// Account for `token`, which starts at byte `start` of `rope`.
+    fn push(&mut self, rope: &Rope, start: usize, token: &Token) -> Option<()> {
+        let offset = rope.try_byte_to_char(start).ok()?;
+        let line = rope.try_char_to_line(offset).ok()?;
+        let column = offset - rope.line_to_char(line);
+        let depth = self.open_braces.len();
+        if rope
+            .line(line)
+            .chars()
+            .take(column)
+            .all(char::is_whitespace)
         {
-            search_from = start + 1;
-            continue;
+            let depth = if matches!(token, Token::RBrace) {
+                depth.saturating_sub(1)
+            } else {
+                depth
+            };
+            *self.line_depths.get_mut(line)? = Some(depth);
         }
-
-        let cursor = start + "service".len();
-        let mut colon_idx = None;
-        for (offset, ch) in formatted[cursor..].char_indices() {
-            if ch.is_whitespace() {
-                continue;
+        match token {
+            Token::LBrace => {
+                self.note_statement(depth, line);
+                self.open_braces.push(line);
+                self.statement_start.push(None);
             }
-            if ch == ':' {
-                colon_idx = Some(cursor + offset);
+            Token::RBrace => {
+                self.open_braces.pop();
+                self.statement_start.pop();
+                if self.statement_start.is_empty() {
+                    self.statement_start.push(None);
+                }
+            }
+            Token::Semi => {
+                self.note_statement(depth, line);
+                if let Some(start) = self.statement_start.last_mut() {
+                    *start = None;
+                }
             }
-            break;
+            _ => self.note_statement(depth, line),
         }
+        Some(())
+    }
 
-        if let Some(colon_pos) = colon_idx {
-            formatted.replace_range(start..(colon_pos + 1), &format!("service {} :", name));
-            return true;
+    /// Remember `line` as the start of the statement at `depth`, unless it started earlier.
+    fn note_statement(&mut self, depth: usize, line: usize) {
+        if let Some(start) = self.statement_start.get_mut(depth)
+            && start.is_none()
+        {
+            *start = Some(line);
         }
-
-        search_from = start + 1;
     }
-
-    false
 }
 
-fn formatted_has_imports(formatted: &str) -> bool {
-    formatted
-        .lines()
-        .any(|line| line.trim_start().starts_with("import"))
+/// The document formatted as a whole, with imports, the service name and `comments` kept.
+pub fn formatted_text(
+    ast: &IDLMergedProg,
+    comments: &[Comment],
+    rope: &Rope,
+    options: &FormatOptions,
+) -> String {
+    formatted_program(ast, comments, rope, options).0
 }
 
-fn prepend_imports(formatted: &str, imports: &[String]) -> String {
-    let mut output = String::new();
-    output.push_str(&imports.join("\n"));
-    output.push('\n');
-    if !formatted.starts_with('\n') {
-        output.push('\n');
+/// The formatted document, with the line where the code of each top-level item starts, for the
+/// items in source order.
+fn formatted_program(
+    ast: &IDLMergedProg,
+    comments: &[Comment],
+    rope: &Rope,
+    options: &FormatOptions,
+) -> (String, Vec<usize>) {
+    let original_text = rope.to_string();
+    let decs = ast.decs();
+    let actor = ast.resolve_actor().ok().flatten();
+    let items = top_level_items(&decs, actor.as_ref());
+    let (formatted_text, item_starts) =
+        Printer::new(&original_text, comments, options).program(&items);
+    let newlines: Vec<usize> = formatted_text
+        .match_indices('\n')
+        .map(|(offset, _)| offset)
+        .collect();
+    let mut item_lines: Vec<usize> = item_starts
+        .iter()
+        .map(|start| newlines.partition_point(|newline| newline < start))
+        .collect();
+
+    let Some(lines) = options.blank_lines else {
+        return (formatted_text, item_lines);
+    };
+    let (collapsed, line_map) = collapse_blank_lines(&formatted_text, lines);
+    for line in &mut item_lines {
+        *line = line_map.get(*line).copied().unwrap_or(*line);
     }
-    output.push_str(formatted.trim_start_matches('\n'));
-    output
+    (collapsed, item_lines)
 }
 
-#[derive(Debug, Clone)]
-struct OrphanCommentBlock {
-    text: String,
-    anchor_next: Option<String>,
-    anchor_next_occurrence: usize,
-    anchor_prev: Option<String>,
-    anchor_prev_occurrence: usize,
-    before_first: bool,
+/// The declarations and the service of a program, in source order.
+fn top_level_items<'a>(decs: &'a [Dec], actor: Option<&'a IDLActorType>) -> Vec<TopLevel<'a>> {
+    let mut items: Vec<TopLevel> = decs.iter().map(TopLevel::Dec).collect();
+    items.extend(actor.map(TopLevel::Actor));
+    items.sort_by_key(|item| item.span().start);
+    items
 }
 
-fn extract_orphan_comment_blocks(src: &str) -> Vec<OrphanCommentBlock> {
-    let lines: Vec<&str> = src.lines().collect();
-    let mut normalized: Vec<Option<String>> = Vec::with_capacity(lines.len());
-    let mut line_occurrence = vec![0usize; lines.len()];
-    let mut seen_counts: std::collections::HashMap<String, usize> =
-        std::collections::HashMap::new();
-
-    for (idx, line) in lines.iter().enumerate() {
-        let trimmed = line.trim();
-        if trimmed.is_empty() || trimmed.starts_with("//") {
-            normalized.push(None);
-            continue;
-        }
-        let norm = normalize_line(trimmed);
-        let count = seen_counts
-            .entry(norm.clone())
-            .and_modify(|c| *c += 1)
-            .or_insert(1);
-        line_occurrence[idx] = *count;
-        normalized.push(Some(norm));
-    }
-
-    let mut blocks = Vec::new();
-    let mut idx = 0usize;
-    let mut last_non_comment_idx: Option<usize> = None;
-    while idx < lines.len() {
-        let line = lines[idx];
-        if !is_comment_line(line) {
-            if !line.trim().is_empty() {
-                last_non_comment_idx = Some(idx);
+#[derive(Debug, Clone, Copy)]
+enum TopLevel<'a> {
+    Dec(&'a Dec),
+    Actor(&'a IDLActorType),
+}
+
+impl TopLevel<'_> {
+    fn span(&self) -> Span {
+        match self {
+            TopLevel::Dec(Dec::TypD(binding)) => binding.span.clone(),
+            TopLevel::Dec(Dec::ImportType { span, .. } | Dec::ImportServ { span, .. }) => {
+                span.clone()
             }
-            idx += 1;
-            continue;
+            TopLevel::Actor(actor) => actor.span.clone(),
         }
+    }
 
-        let start = idx;
-        let mut end = idx;
-        while end + 1 < lines.len() && is_comment_line(lines[end + 1]) {
-            end += 1;
-        }
-
-        let next_idx = end + 1;
-        if next_idx < lines.len() && is_import_line(lines[next_idx]) {
-            let anchor_next_idx = Some(next_idx);
-            let anchor_next = anchor_next_idx.and_then(|i| normalized[i].as_ref().cloned());
-            let anchor_next_occurrence = anchor_next_idx.map(|i| line_occurrence[i]).unwrap_or(0);
-
-            let before_first = last_non_comment_idx.is_none();
-            let anchor_prev = last_non_comment_idx.and_then(|i| normalized[i].as_ref().cloned());
-            let anchor_prev_occurrence = last_non_comment_idx
-                .map(|i| line_occurrence[i])
-                .unwrap_or(0);
-
-            let text = lines[start..=end].join("\n");
-            blocks.push(OrphanCommentBlock {
-                text,
-                anchor_next,
-                anchor_next_occurrence,
-                anchor_prev,
-                anchor_prev_occurrence,
-                before_first,
-            });
-        } else if next_idx < lines.len() && lines[next_idx].trim().is_empty() {
-            let anchor_next_idx = (next_idx + 1..lines.len()).find(|i| {
-                let trimmed = lines[*i].trim();
-                !trimmed.is_empty() && !trimmed.starts_with("//")
-            });
-            let anchor_next = anchor_next_idx.and_then(|i| normalized[i].as_ref().cloned());
-            let anchor_next_occurrence = anchor_next_idx.map(|i| line_occurrence[i]).unwrap_or(0);
-
-            let before_first = last_non_comment_idx.is_none();
-            let anchor_prev = last_non_comment_idx.and_then(|i| normalized[i].as_ref().cloned());
-            let anchor_prev_occurrence = last_non_comment_idx
-                .map(|i| line_occurrence[i])
-                .unwrap_or(0);
-
-            let text = lines[start..=end].join("\n");
-            blocks.push(OrphanCommentBlock {
-                text,
-                anchor_next,
-                anchor_next_occurrence,
-                anchor_prev,
-                anchor_prev_occurrence,
-                before_first,
-            });
-        } else if let Some(last) = last_non_comment_idx
-            && !lines[last].trim().is_empty()
-        {
-            last_non_comment_idx = Some(last);
+    /// Position when type definitions are sorted: imports first, then types by name, then the
+    /// service.
+    fn sort_key(&self) -> (u8, &str) {
+        match self {
+            TopLevel::Dec(Dec::ImportType { .. } | Dec::ImportServ { .. }) => (0, ""),
+            TopLevel::Dec(Dec::TypD(binding)) => (1, &binding.id),
+            TopLevel::Actor(_) => (2, ""),
         }
+    }
+}
 
-        idx = end + 1;
+/// Indices of `len` items in source order, or sorted by `key` when `sort` is set.
+fn print_order<K: Ord>(len: usize, sort: bool, key: impl Fn(usize) -> K) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..len).collect();
+    if sort {
+        order.sort_by_key(|idx| key(*idx));
     }
+    order
+}
 
-    blocks
+/// Pretty printer walking the AST. Every comment of the source is written as leading trivia of
+/// the declaration, field, or method after it, as trailing trivia of the one it shares a line
+/// with, or inside the block that contains it; comments move along when items are sorted.
+struct Printer<'a> {
+    src: &'a str,
+    options: &'a FormatOptions,
+    indent_unit: String,
+    /// Comments in source order, and whether each has been written.
+    comments: &'a [Comment],
+    printed: Vec<bool>,
+    /// Tokens of `src`, for what the syntax tree does not record.
+    tokens: Vec<(usize, Token, usize)>,
+    out: String,
+    /// Set while trying a block on a single line.
+    inline: bool,
 }
 
-fn inject_orphan_comment_blocks(formatted: &str, blocks: &[OrphanCommentBlock]) -> String {
-    if blocks.is_empty() {
-        return formatted.to_string();
+impl<'a> Printer<'a> {
+    fn new(src: &'a str, comments: &'a [Comment], options: &'a FormatOptions) -> Self {
+        Self {
+            src,
+            options,
+            indent_unit: options.indent_unit(),
+            printed: vec![false; comments.len()],
+            comments,
+            tokens: lex(src).tokens,
+            out: String::new(),
+            inline: false,
+        }
     }
 
-    let mut lines: Vec<std::borrow::Cow<'_, str>> =
-        formatted.lines().map(std::borrow::Cow::Borrowed).collect();
-    for block in blocks {
-        if formatted.contains(&block.text) {
-            continue;
+    /// Print `items`, which are in source order; also returns the output offset where the code of
+    /// each item starts.
+    fn program(mut self, items: &[TopLevel]) -> (String, Vec<usize>) {
+        let mut item_starts = vec![0; items.len()];
+        let order = print_order(items.len(), self.options.sort_types, |idx| {
+            items[idx].sort_key()
+        });
+        for (position, idx) in order.into_iter().enumerate() {
+            let item = items[idx];
+            let span = item.span();
+            let from = idx
+                .checked_sub(1)
+                .map_or(0, |before| items[before].span().end);
+            let previous_end = (position > 0 && idx > 0).then_some(from);
+            self.leading(from, self.code_start(span.start), 0, previous_end, true);
+            item_starts[idx] = self.out.len();
+            match item {
+                TopLevel::Dec(Dec::TypD(binding)) => {
+                    self.out.push_str("type ");
+                    self.out.push_str(&binding.id);
+                    self.out.push_str(" = ");
+                    self.ty(&binding.typ, 0);
+                }
+                TopLevel::Dec(Dec::ImportType { path, .. }) => {
+                    self.out.push_str("import ");
+                    self.out.push_str(&quoted(path));
+                }
+                TopLevel::Dec(Dec::ImportServ { path, .. }) => {
+                    self.out.push_str("import service ");
+                    self.out.push_str(&quoted(path));
+                }
+                TopLevel::Actor(actor) => self.actor(actor),
+            }
+            if matches!(item, TopLevel::Dec(_)) {
+                self.out.push(';');
+            }
+            self.trailing(&span, 0);
         }
-
-        let mut inserted = false;
-        if let Some(anchor) = block.anchor_next.as_ref()
-            && let Some(insert_at) = find_anchor_index(&lines, anchor, block.anchor_next_occurrence)
-        {
-            insert_block_before(&mut lines, insert_at, block);
-            inserted = true;
+        let last_end = items.last().map(|item| item.span().end);
+        self.leading(0, self.src.len(), 0, last_end, false);
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
         }
+        (self.out, item_starts)
+    }
 
-        if !inserted
-            && block.before_first
-            && let Some(insert_at) = first_content_line_index(&lines)
+    fn actor(&mut self, actor: &IDLActorType) {
+        self.out.push_str("service");
+        if let [
+            (_, Token::Service, _),
+            (_, Token::Id(name), _),
+            (_, Token::Colon, _),
+            ..,
+        ] = self.tokens_in(actor.span.clone())
         {
-            insert_block_before(&mut lines, insert_at, block);
-            inserted = true;
+            let name = name.clone();
+            self.out.push(' ');
+            self.out.push_str(&name);
+        }
+        self.out.push_str(" : ");
+        match &actor.typ.kind {
+            IDLType::ServT(methods) => self.methods(methods, &actor.typ.span, 0),
+            _ => self.ty(&actor.typ, 0),
         }
+    }
 
-        if !inserted
-            && let Some(anchor) = block.anchor_prev.as_ref()
-            && let Some(insert_at) = find_anchor_index(&lines, anchor, block.anchor_prev_occurrence)
-        {
-            let after = insert_at + 1;
-            insert_block_after(&mut lines, after, block);
-            inserted = true;
+    fn ty(&mut self, ty: &IDLTypeWithSpan, indent: usize) {
+        match &ty.kind {
+            IDLType::PrimT(kind) => self.out.push_str(prim_to_str(kind)),
+            IDLType::VarT(name) => self.out.push_str(name),
+            IDLType::PrincipalT => self.out.push_str("principal"),
+            IDLType::OptT(inner) => {
+                self.out.push_str("opt ");
+                self.ty(inner, indent);
+            }
+            IDLType::VecT(inner) => {
+                if let [(_, Token::Blob, _), ..] = self.tokens_in(ty.span.clone()) {
+                    self.out.push_str("blob");
+                } else {
+                    self.out.push_str("vec ");
+                    self.ty(inner, indent);
+                }
+            }
+            IDLType::RecordT(fields) if ty.kind.is_tuple() => {
+                self.out.push_str("record { ");
+                for (idx, field) in fields.iter().enumerate() {
+                    if idx > 0 {
+                        self.out.push_str("; ");
+                    }
+                    self.ty(&field.typ, indent);
+                }
+                self.out.push_str(" }");
+            }
+            IDLType::RecordT(fields) => {
+                self.out.push_str("record ");
+                self.fields(fields, false, &ty.span, indent);
+            }
+            IDLType::VariantT(fields) => {
+                self.out.push_str("variant ");
+                self.fields(fields, true, &ty.span, indent);
+            }
+            IDLType::FuncT(func) => {
+                self.out.push_str("func ");
+                self.signature(func, &ty.span, indent);
+            }
+            IDLType::ServT(methods) => {
+                self.out.push_str("service ");
+                self.methods(methods, &ty.span, indent);
+            }
+            IDLType::ClassT(args, ret) => {
+                self.params(args, ty.span.start, indent);
+                self.out.push_str(" -> ");
+                // The service after `->` is written as a bare method block.
+                match &ret.kind {
+                    IDLType::ServT(methods) => self.methods(methods, &ret.span, indent),
+                    _ => self.ty(ret, indent),
+                }
+            }
         }
+    }
 
-        if !inserted {
-            if !lines.is_empty() && !lines.last().unwrap().as_ref().trim().is_empty() {
-                lines.push(std::borrow::Cow::Borrowed(""));
+    fn fields(&mut self, fields: &[TypeField], variant: bool, block: &Span, indent: usize) {
+        if fields.is_empty() && !self.has_comments_in(block) {
+            self.out.push_str("{}");
+            return;
+        }
+        if self.try_inline(block, |printer| {
+            printer.fields(fields, variant, block, indent)
+        }) {
+            return;
+        }
+        let has_type = |field: &TypeField| {
+            !(variant && matches!(field.typ.kind, IDLType::PrimT(PrimType::Null)))
+        };
+        if self.inline {
+            self.out.push_str("{ ");
+            for (idx, field) in fields.iter().enumerate() {
+                if idx > 0 {
+                    self.out.push_str("; ");
+                }
+                self.out.push_str(&label_text(&field.label));
+                if has_type(field) {
+                    self.out.push_str(" : ");
+                    self.ty(&field.typ, indent);
+                }
             }
-            lines.extend(
-                block
-                    .text
-                    .lines()
-                    .map(|line| std::borrow::Cow::Owned(line.to_string())),
+            self.out.push_str(" }");
+            return;
+        }
+
+        let width = self.align_width(
+            fields
+                .iter()
+                .filter(|field| has_type(field))
+                .map(|field| label_text(&field.label)),
+        );
+        self.out.push_str("{\n");
+        let mut previous_end = None;
+        for (idx, field) in fields.iter().enumerate() {
+            let from = previous_end.unwrap_or(block.start);
+            self.leading(
+                from,
+                self.code_start(field.span.start),
+                indent + 1,
+                previous_end,
+                true,
             );
-            lines.push(std::borrow::Cow::Borrowed(""));
+            self.indent(indent + 1);
+            let label = label_text(&field.label);
+            self.out.push_str(&label);
+            if has_type(field) {
+                self.pad(&label, width);
+                self.out.push_str(" : ");
+                self.ty(&field.typ, indent + 1);
+            }
+            if idx + 1 < fields.len()
+                || self.options.trailing_separator == TrailingSeparator::Always
+            {
+                self.out.push(';');
+            }
+            self.trailing(&field.span, indent + 1);
+            previous_end = Some(field.span.end);
         }
+        self.leading(block.start, block.end, indent + 1, previous_end, false);
+        self.indent(indent);
+        self.out.push('}');
     }
 
-    let mut output = String::new();
-    for (idx, line) in lines.iter().enumerate() {
-        if idx > 0 {
-            output.push('\n');
+    fn methods(&mut self, methods: &[Binding], block: &Span, indent: usize) {
+        if methods.is_empty() && !self.has_comments_in(block) {
+            self.out.push_str("{}");
+            return;
+        }
+        let order = print_order(methods.len(), self.options.sort_methods, |idx| {
+            methods[idx].id.as_str()
+        });
+        if self.inline {
+            self.out.push_str("{ ");
+            for (position, idx) in order.into_iter().enumerate() {
+                if position > 0 {
+                    self.out.push_str("; ");
+                }
+                self.method(&methods[idx], indent, 0);
+            }
+            self.out.push_str(" }");
+            return;
         }
-        output.push_str(line.as_ref());
-    }
-    output
-}
 
-fn first_content_line_index(lines: &[std::borrow::Cow<'_, str>]) -> Option<usize> {
-    for (idx, line) in lines.iter().enumerate() {
-        let trimmed = line.as_ref().trim();
-        if trimmed.is_empty() || trimmed.starts_with("//") {
-            continue;
+        let width = self.align_width(methods.iter().map(|method| identifier(&method.id)));
+        self.out.push_str("{\n");
+        for (position, idx) in order.into_iter().enumerate() {
+            let method = &methods[idx];
+            let from = idx
+                .checked_sub(1)
+                .map_or(block.start, |before| methods[before].span.end);
+            let previous_end = (position > 0 && idx > 0).then_some(from);
+            self.leading(
+                from,
+                self.code_start(method.span.start),
+                indent + 1,
+                previous_end,
+                true,
+            );
+            self.indent(indent + 1);
+            self.method(method, indent + 1, width);
+            self.out.push(';');
+            self.trailing(&method.span, indent + 1);
         }
-        return Some(idx);
+        let last_end = methods.last().map(|method| method.span.end);
+        self.leading(block.start, block.end, indent + 1, last_end, false);
+        self.indent(indent);
+        self.out.push('}');
     }
-    None
-}
 
-fn find_anchor_index(
-    lines: &[std::borrow::Cow<'_, str>],
-    anchor: &str,
-    occurrence: usize,
-) -> Option<usize> {
-    if occurrence == 0 {
-        return None;
+    /// Write `name : signature`, padding the name to `width` columns.
+    fn method(&mut self, method: &Binding, indent: usize, width: usize) {
+        let name = identifier(&method.id);
+        self.out.push_str(&name);
+        self.pad(&name, width);
+        self.out.push_str(" : ");
+        match &method.typ.kind {
+            IDLType::FuncT(func) => self.signature(func, &method.typ.span, indent),
+            _ => self.ty(&method.typ, indent),
+        }
     }
-    let mut count = 0usize;
-    for (idx, line) in lines.iter().enumerate() {
-        let trimmed = line.as_ref().trim();
-        if trimmed.is_empty() || trimmed.starts_with("//") {
-            continue;
+
+    fn signature(&mut self, func: &FuncType, span: &Span, indent: usize) {
+        self.params(&func.args, span.start, indent);
+        self.out.push_str(" -> ");
+        let rets_from = func.args.last().map_or(span.start, |arg| arg.span.end);
+        self.params(&func.rets, rets_from, indent);
+        for mode in &func.modes {
+            self.out.push_str(match mode {
+                FuncMode::Oneway => " oneway",
+                FuncMode::Query => " query",
+                FuncMode::CompositeQuery => " composite_query",
+            });
         }
-        if normalize_line(trimmed) == *anchor {
-            count += 1;
-            if count == occurrence {
-                return Some(idx);
+    }
+
+    /// Write a parenthesized parameter list, keeping the names written before the types from
+    /// `from` on.
+    fn params(&mut self, params: &[IDLTypeWithSpan], mut from: usize, indent: usize) {
+        self.out.push('(');
+        for (idx, param) in params.iter().enumerate() {
+            if idx > 0 {
+                self.out.push_str(", ");
             }
+            if let Some(name) = self.param_name(from, param) {
+                self.out.push_str(&name);
+                self.out.push_str(" : ");
+            }
+            self.ty(param, indent);
+            from = param.span.end;
         }
+        self.out.push(')');
     }
-    None
-}
 
-fn insert_block_before(
-    lines: &mut Vec<std::borrow::Cow<'_, str>>,
-    insert_at: usize,
-    block: &OrphanCommentBlock,
-) {
-    let mut chunk: Vec<std::borrow::Cow<'_, str>> = block
-        .text
-        .lines()
-        .map(|line| std::borrow::Cow::Owned(line.to_string()))
-        .collect();
-    if insert_at < lines.len() && !lines[insert_at].as_ref().trim().is_empty() {
-        chunk.push(std::borrow::Cow::Borrowed(""));
+    /// The name in `name : type` before `param`, if the source gives one.
+    fn param_name(&self, from: usize, param: &IDLTypeWithSpan) -> Option<String> {
+        match self.tokens_in(from..param.span.start) {
+            [.., (_, Token::Id(name), _), (_, Token::Colon, _)] => Some(name.clone()),
+            [.., (_, Token::Text(name), _), (_, Token::Colon, _)] => Some(quoted(name)),
+            _ => None,
+        }
     }
-    lines.splice(insert_at..insert_at, chunk);
-}
 
-fn insert_block_after(
-    lines: &mut Vec<std::borrow::Cow<'_, str>>,
-    insert_at: usize,
-    block: &OrphanCommentBlock,
-) {
-    let mut chunk: Vec<std::borrow::Cow<'_, str>> = Vec::new();
-    if insert_at > 0 && !lines[insert_at - 1].as_ref().trim().is_empty() {
-        chunk.push(std::borrow::Cow::Borrowed(""));
-    }
-    chunk.extend(
-        block
-            .text
-            .lines()
-            .map(|line| std::borrow::Cow::Owned(line.to_string())),
-    );
-    chunk.push(std::borrow::Cow::Borrowed(""));
-    lines.splice(insert_at..insert_at, chunk);
-}
+    /// The tokens starting in `range`.
+    fn tokens_in(&self, range: Span) -> &[(usize, Token, usize)] {
+        let start = self
+            .tokens
+            .partition_point(|(offset, ..)| *offset < range.start);
+        let end = self
+            .tokens
+            .partition_point(|(offset, ..)| *offset < range.end);
+        &self.tokens[start..end.max(start)]
+    }
 
-fn is_comment_line(line: &str) -> bool {
-    line.trim_start().starts_with("//")
-}
+    /// Indices of the comments starting in `range`.
+    fn comments_in(&self, range: Span) -> std::ops::Range<usize> {
+        let start = self
+            .comments
+            .partition_point(|comment| comment.span.start < range.start);
+        let end = self
+            .comments
+            .partition_point(|comment| comment.span.start < range.end);
+        start..end.max(start)
+    }
 
-fn is_import_line(line: &str) -> bool {
-    line.trim_start().starts_with("import")
-}
+    /// With a maximum width configured, print a block through `print` on a single line and keep
+    /// it when the line still fits. Blocks holding comments stay multi-line.
+    fn try_inline(&mut self, block: &Span, print: impl FnOnce(&mut Self)) -> bool {
+        let Some(max_width) = self.options.max_width else {
+            return false;
+        };
+        if self.inline || self.has_comments_in(block) {
+            return false;
+        }
+        let mark = self.out.len();
+        self.inline = true;
+        print(self);
+        self.inline = false;
+        // Leave room for the `;` that usually follows.
+        if !self.out[mark..].contains('\n') && self.column() < max_width {
+            return true;
+        }
+        self.out.truncate(mark);
+        false
+    }
 
-fn normalize_line(line: &str) -> String {
-    line.chars().filter(|ch| !ch.is_whitespace()).collect()
-}
+    /// Width of the current output line, counting a tab as one indentation level.
+    fn column(&self) -> usize {
+        let line = self.out.rsplit('\n').next().unwrap_or_default();
+        let tab_width = self.options.indent_width_or_default();
+        line.chars()
+            .map(|ch| if ch == '\t' { tab_width } else { 1 })
+            .sum()
+    }
+
+    /// Column the names of a block are padded to when colons are aligned; `0` otherwise.
+    fn align_width(&self, names: impl Iterator<Item = String>) -> usize {
+        if !self.options.align_colons {
+            return 0;
+        }
+        names.map(|name| name.chars().count()).max().unwrap_or(0)
+    }
 
-fn apply_indent_width(formatted: &str, indent_width: usize) -> String {
-    if indent_width == 0 {
-        return formatted.to_string();
+    fn pad(&mut self, name: &str, width: usize) {
+        let len = name.chars().count();
+        self.out
+            .extend(std::iter::repeat_n(' ', width.saturating_sub(len)));
     }
 
-    const BASE_INDENT_WIDTH: usize = 2;
-    let mut output = String::with_capacity(formatted.len());
-    for (idx, line) in formatted.lines().enumerate() {
-        if idx > 0 {
-            output.push('\n');
+    fn indent(&mut self, level: usize) {
+        for _ in 0..level {
+            self.out.push_str(&self.indent_unit);
         }
-        if line.trim().is_empty() {
-            continue;
+    }
+
+    /// Write the pending comments that start between `from` and `end`, each on its own line.
+    /// Blank lines around them are kept when there was an earlier sibling, ending at
+    /// `previous_end`, and before the code at `end` when `before_code` is set.
+    fn leading(
+        &mut self,
+        from: usize,
+        end: usize,
+        indent: usize,
+        previous_end: Option<usize>,
+        before_code: bool,
+    ) {
+        let mut gap_start = previous_end;
+        for idx in self.comments_in(from..end) {
+            if self.printed[idx] {
+                continue;
+            }
+            self.printed[idx] = true;
+            if gap_start.is_some() && self.comments[idx].blank_before {
+                self.blank_line();
+            }
+            self.indent(indent);
+            self.out.push_str(&self.comments[idx].text);
+            self.out.push('\n');
+            gap_start = Some(self.comments[idx].span.end);
         }
+        if before_code
+            && let Some(gap_start) = gap_start
+            && has_blank_line(self.src.get(gap_start..end).unwrap_or_default())
+        {
+            self.blank_line();
+        }
+    }
 
-        let leading_spaces = line.chars().take_while(|ch| *ch == ' ').count();
-        let remainder = &line[leading_spaces..];
-        if leading_spaces % BASE_INDENT_WIDTH != 0 {
-            output.push_str(line);
-            continue;
+    /// Finish the line of the item at `span` with the comments on the same source line, then
+    /// write comments left inside the item on their own lines.
+    fn trailing(&mut self, span: &Span, indent: usize) {
+        for idx in self.comments_in(span.end..self.src.len()) {
+            let start = self.comments[idx].span.start;
+            if self.printed[idx] {
+                continue;
+            }
+            let Some(gap) = self.src.get(span.end..start) else {
+                continue;
+            };
+            if gap.contains('\n') || !gap.trim().trim_start_matches(';').trim().is_empty() {
+                break;
+            }
+            self.printed[idx] = true;
+            self.out.push(' ');
+            self.out.push_str(&self.comments[idx].text);
+        }
+        self.out.push('\n');
+        for idx in self.comments_in(span.clone()) {
+            if !self.printed[idx] {
+                self.printed[idx] = true;
+                self.indent(indent);
+                self.out.push_str(&self.comments[idx].text);
+                self.out.push('\n');
+            }
         }
-        let level = leading_spaces / BASE_INDENT_WIDTH;
-        output.extend(std::iter::repeat_n(' ', level * indent_width));
-        output.push_str(remainder);
     }
-    if formatted.ends_with('\n') {
-        output.push('\n');
+
+    fn blank_line(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    fn has_comments_in(&self, span: &Span) -> bool {
+        self.comments_in(span.clone())
+            .any(|idx| !self.printed[idx] && self.comments[idx].span.end <= span.end)
+    }
+
+    /// First offset from `pos` that is neither whitespace nor a comment.
+    fn code_start(&self, mut pos: usize) -> usize {
+        loop {
+            let rest = self.src.get(pos..).unwrap_or_default();
+            pos += rest.len() - rest.trim_start().len();
+            match self
+                .comments
+                .binary_search_by_key(&pos, |comment| comment.span.start)
+            {
+                Ok(idx) => pos = self.comments[idx].span.end,
+                Err(_) => return pos,
+            }
+        }
     }
-    output
 }
 
-fn collapse_blank_lines(formatted: &str, max_blank_lines: usize) -> String {
+/// A field label as Candid source: a plain identifier, a quoted name, or a number.
+fn label_text(label: &Label) -> String {
+    match label {
+        Label::Named(name) => identifier(name),
+        Label::Id(id) | Label::Unnamed(id) => id.to_string(),
+    }
+}
+
+/// `name` as written in Candid, quoted unless it is an identifier other than a keyword.
+fn identifier(name: &str) -> String {
+    let mut chars = name.chars();
+    let plain = chars
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        && !KEYWORDS.contains(&name);
+    if plain {
+        name.to_string()
+    } else {
+        quoted(name)
+    }
+}
+
+fn quoted(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+const KEYWORDS: &[&str] = &[
+    "type",
+    "import",
+    "service",
+    "func",
+    "opt",
+    "vec",
+    "record",
+    "variant",
+    "blob",
+    "principal",
+    "oneway",
+    "query",
+    "composite_query",
+    "nat",
+    "nat8",
+    "nat16",
+    "nat32",
+    "nat64",
+    "int",
+    "int8",
+    "int16",
+    "int32",
+    "int64",
+    "float32",
+    "float64",
+    "bool",
+    "text",
+    "null",
+    "reserved",
+    "empty",
+];
+
+/// Collapse runs of blank lines longer than `max_blank_lines`; also returns the output line of
+/// every input line, or of the line after it when it was dropped.
+fn collapse_blank_lines(formatted: &str, max_blank_lines: usize) -> (String, Vec<usize>) {
     let mut lines = Vec::new();
+    let mut line_map = Vec::new();
     let mut blank_run = 0usize;
     for line in formatted.lines() {
+        line_map.push(lines.len());
         if line.trim().is_empty() {
             blank_run += 1;
             if blank_run <= max_blank_lines {
@@ -499,5 +1079,5 @@ fn collapse_blank_lines(formatted: &str, max_blank_lines: usize) -> String {
     if formatted.ends_with('\n') {
         output.push('\n');
     }
-    output
+    (output, line_map)
 }
@@ -1,5 +1,7 @@
 use crate::lsp::{
-    CandidLanguageServer, lookup_identifier,
+    CandidLanguageServer,
+    bindings::{BindingLanguage, BindingTarget, binding_item},
+    lookup_identifier,
     markdown::{self, MarkdownWriter},
     navigation::IdentifierInfo,
     semantic_analyze::{PrimitiveHover, Semantic},
@@ -7,7 +9,11 @@ use crate::lsp::{
     span_to_range,
     symbol_table::ImportKind,
     tasks::{DocumentTaskCancelled, DocumentTaskKind, DocumentTaskToken},
+    test_suite::{assertion_hover_markdown, parse_test_suite},
+    type_display::render_expanded_binding,
     type_docs::{TypeDoc, blob_doc, keyword_doc, primitive_doc},
+    value_check::TypeEnv,
+    workspace::DocumentKind,
 };
 use rapidhash::fast::RandomState;
 use ropey::Rope;
@@ -18,26 +24,32 @@ pub async fn hover(server: &CandidLanguageServer, params: HoverParams) -> Result
     let uri = params.text_document_position_params.text_document.uri;
     let uri_key = uri.to_string();
 
-    let (rope, version) = if let Some(doc) = server.documents.get(&uri_key) {
-        (doc.rope().clone(), doc.version())
-    } else {
-        return Ok(None);
-    };
-
     let token = server.task_token(&uri_key, DocumentTaskKind::Hover);
 
+    // Spans come from the last finished analysis, so resolve positions against its text.
     let analysis = server.analysis_map.get(&uri_key);
-    let semantic = match analysis.as_ref().and_then(|a| a.semantic()) {
-        Some(s) => s,
+    let (rope, version) = match analysis.as_ref() {
+        Some(a) => (a.rope().clone(), a.version()),
         None => return Ok(None),
     };
-
     let position = params.text_document_position_params.position;
     let offset = match server.cached_position_to_offset(&uri_key, position, &rope, version) {
         Some(o) => o,
         None => return Ok(None),
     };
 
+    if DocumentKind::of(&uri) == DocumentKind::TestSuite
+        && let Some(hover) =
+            assertion_hover(&rope, analysis.as_ref().and_then(|a| a.type_env()), offset)
+    {
+        return Ok(Some(hover));
+    }
+
+    let semantic = match analysis.as_ref().and_then(|a| a.semantic()) {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+
     if token.yield_and_check().await.is_err() {
         return Ok(None);
     }
@@ -52,10 +64,29 @@ pub async fn hover(server: &CandidLanguageServer, params: HoverParams) -> Result
         None => return Ok(None),
     };
 
-    let contents = match hover_contents(&rope, semantic, &info, Some(&token)).await {
+    let mut contents = match hover_contents(&rope, semantic, &info, Some(&token)).await {
         Ok(Some(c)) => c,
         _ => return Ok(None),
     };
+    let expand_depth = server.hover_expand_depth(&uri_key);
+    if expand_depth > 0
+        && info.symbol_id.is_some()
+        && let Some(env) = analysis.as_ref().and_then(|a| a.type_env())
+        && let Some(section) = resolved_type_section(env, &rope, &info.ident_span, expand_depth)
+        && let HoverContents::Markup(markup) = &mut contents
+    {
+        markup.value.push_str("\n\n");
+        markup.value.push_str(&section);
+    }
+    if server.hover_rust_bindings(&uri_key)
+        && info.symbol_id.is_some()
+        && let Some(bindings) = analysis.as_ref().and_then(|a| a.rust_bindings())
+        && let Some(section) = rust_binding_section(bindings, &rope, &info.ident_span)
+        && let HoverContents::Markup(markup) = &mut contents
+    {
+        markup.value.push_str("\n\n");
+        markup.value.push_str(&section);
+    }
 
     Ok(Some(Hover {
         contents,
@@ -63,6 +94,53 @@ pub async fn hover(server: &CandidLanguageServer, params: HoverParams) -> Result
     }))
 }
 
+/// Hover for the `.test.did` assertion at `offset`: whether it passes and what its inputs decode
+/// to.
+fn assertion_hover(rope: &Rope, env: Option<&TypeEnv>, offset: usize) -> Option<Hover> {
+    let suite = parse_test_suite(&rope.to_string());
+    let assertion = suite.assertion_at(offset)?;
+    let empty = TypeEnv::default();
+    let value = assertion_hover_markdown(assertion, env.unwrap_or(&empty))?;
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: span_to_range(&assertion.span, rope),
+    })
+}
+
+/// A "Resolved" section with the type named at `ident_span` expanded `depth` names deep, unless
+/// its definition names no other types.
+fn resolved_type_section(
+    env: &TypeEnv,
+    rope: &Rope,
+    ident_span: &Span,
+    depth: usize,
+) -> Option<String> {
+    let name = rope.get_slice(ident_span.clone())?.to_string();
+    let expanded = render_expanded_binding(&name, env, depth)?;
+    if Some(&expanded) == render_expanded_binding(&name, env, 0).as_ref() {
+        return None;
+    }
+    let mut writer = MarkdownWriter::default();
+    writer.push_rule();
+    writer.push_text("**Resolved**");
+    writer.push_code_block(&expanded);
+    writer.finish()
+}
+
+/// A "Rust" section with the item generated for the type named at `ident_span`.
+fn rust_binding_section(bindings: &str, rope: &Rope, ident_span: &Span) -> Option<String> {
+    let name = rope.get_slice(ident_span.clone())?.to_string();
+    let item = binding_item(bindings, BindingLanguage::Rust, &BindingTarget::Type(name))?;
+    let mut writer = MarkdownWriter::default();
+    writer.push_rule();
+    writer.push_text("**Rust**");
+    writer.push_code_block_in("rust", &item);
+    writer.finish()
+}
+
 /// Compose hover markup for the identifier located at `info.ident_span`.
 ///
 /// The rendering pipeline has three steps:
@@ -0,0 +1,366 @@
+use crate::{
+    candid_lang::{ImCompleteSemanticToken, ParserResult, clone_dec, parse_program},
+    lsp::{
+        semantic_analyze::{Result, Semantic},
+        span::Span,
+        trivia::Comment,
+    },
+};
+use candid_parser::syntax::{
+    Binding, Dec, IDLActorType, IDLMergedProg, IDLProg, IDLType, IDLTypeWithSpan, TypeField,
+};
+use ropey::Rope;
+
+/// A single replacement expressed in byte offsets: `start..old_end` in the previous text became
+/// `start..new_end` in the new text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextChange {
+    pub start: usize,
+    pub old_end: usize,
+    pub new_end: usize,
+}
+
+impl TextChange {
+    fn delta(&self) -> isize {
+        self.new_end as isize - self.old_end as isize
+    }
+
+    /// The smallest single change covering `self` followed by `next`, which is expressed in the
+    /// text `self` produced.
+    pub fn then(self, next: TextChange) -> TextChange {
+        TextChange {
+            start: self.start.min(next.start),
+            old_end: if next.old_end > self.new_end {
+                next.old_end - self.new_end + self.old_end
+            } else {
+                self.old_end
+            },
+            new_end: if self.new_end > next.old_end {
+                self.new_end - next.old_end + next.new_end
+            } else {
+                next.new_end
+            },
+        }
+    }
+}
+
+/// The ranged edits applied to a document since a given version of its text.
+#[derive(Debug, Clone, Default)]
+pub struct EditLog {
+    base: Option<i32>,
+    edits: Vec<(i32, TextChange)>,
+}
+
+impl EditLog {
+    /// An empty log for the text at `version`.
+    pub fn new(version: Option<i32>) -> Self {
+        Self {
+            base: version,
+            edits: Vec::new(),
+        }
+    }
+
+    /// Record that `change` produced the text at `version`.
+    pub fn record(&mut self, version: Option<i32>, change: TextChange) {
+        match version {
+            Some(version) if self.base.is_some() => self.edits.push((version, change)),
+            _ => *self = Self::new(version),
+        }
+    }
+
+    /// Forget the edits up to `version`, once an analysis of that text has been published.
+    pub fn prune(&mut self, version: Option<i32>) {
+        let (Some(base), Some(version)) = (self.base, version) else {
+            return;
+        };
+        if version >= base {
+            self.edits
+                .retain(|(edit_version, _)| *edit_version > version);
+            self.base = Some(version);
+        }
+    }
+
+    /// The change turning the text at `version` into the latest text, if the log reaches back
+    /// that far and anything changed.
+    pub fn since(&self, version: Option<i32>) -> Option<TextChange> {
+        let (base, version) = (self.base?, version?);
+        if version < base {
+            return None;
+        }
+        self.edits
+            .iter()
+            .filter(|(edit_version, _)| *edit_version > version)
+            .map(|(_, change)| *change)
+            .reduce(TextChange::then)
+    }
+}
+
+/// Where the top-level declarations of an error-free ASCII document end, with its lexer tokens.
+///
+/// Chunk `i` runs from the end of chunk `i - 1` to just past the `;` of declaration `i`; the
+/// actor and trailing trivia follow in the last chunks.
+#[derive(Debug)]
+pub struct ProgramChunks {
+    ends: Vec<usize>,
+    len: usize,
+    /// Tokens as lexed, before semantic analysis retyped identifiers.
+    tokens: Vec<ImCompleteSemanticToken>,
+}
+
+impl ProgramChunks {
+    /// Chunks for `parsed`, or `None` when the document has errors or non-ASCII text, whose
+    /// byte and character offsets would disagree once shifted.
+    pub fn new(parsed: &ParserResult, text: &str) -> Option<Self> {
+        let ast = parsed.ast.as_ref()?;
+        let decs: &[Dec] = &ast.decs();
+        let actor = ast.resolve_actor().ok().flatten();
+        let fits = chunks_fit(decs, actor.as_ref(), &parsed.declaration_ends);
+        (parsed.parse_errors.is_empty() && text.is_ascii() && fits).then(|| Self {
+            ends: parsed.declaration_ends.clone(),
+            len: text.len(),
+            tokens: parsed.semantic_tokens.clone(),
+        })
+    }
+
+    fn start(&self, chunk: usize) -> usize {
+        chunk
+            .checked_sub(1)
+            .map_or(0, |previous| self.end(previous))
+    }
+
+    fn end(&self, chunk: usize) -> usize {
+        self.ends.get(chunk).copied().unwrap_or(self.len)
+    }
+
+    fn chunk_at(&self, offset: usize) -> usize {
+        self.ends.partition_point(|&end| end <= offset)
+    }
+}
+
+/// Whether declaration `i` lies in chunk `i` and the actor after every declaration.
+fn chunks_fit(decs: &[Dec], actor: Option<&IDLActorType>, ends: &[usize]) -> bool {
+    let declared = ends.len() == decs.len() || (actor.is_some() && ends.len() == decs.len() + 1);
+    let mut start = 0;
+    for (dec, &end) in decs.iter().zip(ends) {
+        let span = dec_span(dec);
+        if span.start < start || span.end > end {
+            return false;
+        }
+        start = end;
+    }
+    declared && actor.is_none_or(|actor| actor.span.start >= start)
+}
+
+/// The previous analysis of a document, as far as [`splice`] reuses it.
+pub struct PreviousProgram<'a> {
+    pub ast: &'a IDLMergedProg,
+    pub semantic: Option<&'a Semantic>,
+    pub comments: &'a [Comment],
+    pub chunks: &'a ProgramChunks,
+}
+
+/// A parse assembled from the previous one and the re-parsed declarations.
+pub struct SplicedProgram {
+    /// The program, with lexer tokens that semantic analysis has not retyped yet.
+    pub parsed: ParserResult,
+    /// The spliced semantic analysis, when the previous one could be reused.
+    pub semantic: Option<Result<Semantic>>,
+}
+
+/// Re-parse only the top-level declarations `change` touched and splice them into `previous`.
+///
+/// Returns `None` whenever the result could differ from a full parse of `text`: when the edited
+/// declarations no longer parse cleanly, their last `;` stopped ending a declaration, or an actor
+/// appears before other declarations.
+pub fn splice(
+    previous: PreviousProgram<'_>,
+    text: &str,
+    rope: &Rope,
+    change: TextChange,
+) -> Option<SplicedProgram> {
+    let chunks = previous.chunks;
+    let delta = change.delta();
+    if change.start > change.old_end
+        || change.old_end > chunks.len
+        || chunks.len.checked_add_signed(delta) != Some(text.len())
+        || !text.is_ascii()
+    {
+        return None;
+    }
+    // Include the chunk before the edit as well, so touching a `;` never splits a declaration
+    // differently from a full parse.
+    let first = chunks.chunk_at(change.start.saturating_sub(1));
+    let last = chunks.chunk_at(change.old_end);
+    let region_start = chunks.start(first);
+    let old_region_end = chunks.end(last);
+    let region_end = old_region_end.checked_add_signed(delta)?;
+    let region = text.get(region_start..region_end)?;
+
+    let parsed = parse_program(region);
+    let program = parsed.program.filter(|_| parsed.parse_errors.is_empty())?;
+    let includes_tail = last == chunks.ends.len();
+    if !includes_tail && parsed.declaration_ends.last() != Some(&region.len()) {
+        return None;
+    }
+
+    let old_decs: &[Dec] = &previous.ast.decs();
+    let old_actor = previous.ast.resolve_actor().ok().flatten();
+    let shift = region_start as isize;
+    let mut decs: Vec<Dec> = old_decs.iter().take(first).map(clone_dec).collect();
+    decs.extend(program.decs.into_iter().map(|mut dec| {
+        shift_dec(&mut dec, shift);
+        dec
+    }));
+    decs.extend(old_decs.iter().skip(last + 1).map(|dec| {
+        let mut dec = clone_dec(dec);
+        shift_dec(&mut dec, delta);
+        dec
+    }));
+    let kept_actor = old_actor.and_then(|mut actor| {
+        let moved_by = if actor.span.start < region_start {
+            0
+        } else if actor.span.start >= old_region_end {
+            delta
+        } else {
+            return None;
+        };
+        shift_actor(&mut actor, moved_by);
+        Some(actor)
+    });
+    let actor = match (program.actor, kept_actor) {
+        (Some(_), Some(_)) => return None,
+        (Some(mut actor), None) => {
+            shift_actor(&mut actor, shift);
+            Some(actor)
+        }
+        (None, actor) => actor,
+    };
+
+    let mut ends = chunks.ends[..first].to_vec();
+    ends.extend(parsed.declaration_ends.iter().map(|end| end + region_start));
+    ends.extend(
+        chunks.ends[chunks.ends.len().min(last + 1)..]
+            .iter()
+            .map(|end| end.saturating_add_signed(delta)),
+    );
+    if !chunks_fit(&decs, actor.as_ref(), &ends) {
+        return None;
+    }
+
+    let tokens = &chunks.tokens;
+    let prefix = tokens.partition_point(|token| token.start < region_start);
+    let suffix = tokens.partition_point(|token| token.start < old_region_end);
+    let mut semantic_tokens = tokens[..prefix].to_vec();
+    semantic_tokens.extend(parsed.semantic_tokens.into_iter().map(|mut token| {
+        token.start += region_start;
+        token
+    }));
+    semantic_tokens.extend(
+        tokens[suffix..]
+            .iter()
+            .map(|token| ImCompleteSemanticToken {
+                start: token.start.saturating_add_signed(delta),
+                ..token.clone()
+            }),
+    );
+
+    let mut comments: Vec<Comment> = previous
+        .comments
+        .iter()
+        .take_while(|comment| comment.span.start < region_start)
+        .cloned()
+        .collect();
+    let region_comments = parsed
+        .comments
+        .iter()
+        .map(|comment| shifted(&comment.span, shift));
+    let suffix_comments = previous
+        .comments
+        .iter()
+        .filter(|comment| comment.span.start >= old_region_end)
+        .map(|comment| shifted(&comment.span, delta));
+    comments.extend(
+        region_comments
+            .chain(suffix_comments)
+            .map(|span| Comment::new(text, span)),
+    );
+
+    let ast = IDLMergedProg::new(IDLProg { decs, actor });
+    let semantic = previous
+        .semantic
+        .and_then(|semantic| semantic.splice(&ast, rope, region_start..old_region_end, delta));
+    Some(SplicedProgram {
+        parsed: ParserResult {
+            ast: Some(ast),
+            parse_errors: Vec::new(),
+            semantic_tokens,
+            comments,
+            declaration_ends: ends,
+        },
+        semantic,
+    })
+}
+
+fn dec_span(dec: &Dec) -> &Span {
+    match dec {
+        Dec::TypD(binding) => &binding.span,
+        Dec::ImportType { span, .. } | Dec::ImportServ { span, .. } => span,
+    }
+}
+
+fn shifted(span: &Span, delta: isize) -> Span {
+    span.start.saturating_add_signed(delta)..span.end.saturating_add_signed(delta)
+}
+
+fn shift_span(span: &mut Span, delta: isize) {
+    *span = shifted(span, delta);
+}
+
+fn shift_actor(actor: &mut IDLActorType, delta: isize) {
+    shift_span(&mut actor.span, delta);
+    shift_type(&mut actor.typ, delta);
+}
+
+fn shift_dec(dec: &mut Dec, delta: isize) {
+    match dec {
+        Dec::TypD(binding) => shift_binding(binding, delta),
+        Dec::ImportType { span, .. } | Dec::ImportServ { span, .. } => shift_span(span, delta),
+    }
+}
+
+fn shift_binding(binding: &mut Binding, delta: isize) {
+    shift_span(&mut binding.span, delta);
+    shift_type(&mut binding.typ, delta);
+}
+
+fn shift_fields(fields: &mut [TypeField], delta: isize) {
+    for field in fields {
+        shift_span(&mut field.span, delta);
+        shift_type(&mut field.typ, delta);
+    }
+}
+
+fn shift_type(idl_type: &mut IDLTypeWithSpan, delta: isize) {
+    shift_span(&mut idl_type.span, delta);
+    match &mut idl_type.kind {
+        IDLType::PrimT(_) | IDLType::VarT(_) | IDLType::PrincipalT => {}
+        IDLType::OptT(inner) | IDLType::VecT(inner) => shift_type(inner, delta),
+        IDLType::RecordT(fields) | IDLType::VariantT(fields) => shift_fields(fields, delta),
+        IDLType::FuncT(func_type) => {
+            for arg in func_type.args.iter_mut().chain(func_type.rets.iter_mut()) {
+                shift_type(arg, delta);
+            }
+        }
+        IDLType::ServT(bindings) => {
+            for binding in bindings {
+                shift_binding(binding, delta);
+            }
+        }
+        IDLType::ClassT(args, ret) => {
+            for arg in args {
+                shift_type(arg, delta);
+            }
+            shift_type(ret, delta);
+        }
+    }
+}
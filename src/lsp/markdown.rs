@@ -24,8 +24,13 @@ impl MarkdownWriter {
     }
 
     pub fn push_code_block(&mut self, snippet: &str) {
+        self.push_code_block_in("candid", snippet);
+    }
+
+    /// Push a code block fenced for `language`.
+    pub fn push_code_block_in(&mut self, language: &str, snippet: &str) {
         self.start_section();
-        let _ = writeln!(self.buffer, "```candid");
+        let _ = writeln!(self.buffer, "```{language}");
         self.buffer.push_str(snippet);
         if !snippet.ends_with('\n') {
             self.buffer.push('\n');
@@ -0,0 +1,254 @@
+use crate::lsp::{
+    normal_value::{decode_args, render_args_pretty},
+    semantic_analyze::{ParamRole, Semantic},
+    span::Span,
+    type_relation::parse_type_text,
+    value_check::TypeEnv,
+    value_document::{INTERFACE_DIRECTIVE, METHOD_DIRECTIVE},
+    workspace::uri_to_path,
+};
+use base64::{
+    Engine, alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig, general_purpose::STANDARD},
+};
+use candid_parser::{parse_idl_args, syntax::IDLType};
+use ropey::Rope;
+use serde::Serialize;
+use std::str::FromStr;
+use tower_lsp_server::ls_types::Uri;
+
+/// Column width decoded values are wrapped at.
+const PRETTY_WIDTH: usize = 80;
+
+/// Base64 input may leave out its padding.
+const LENIENT_BASE64: GeneralPurposeConfig =
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+
+/// Textual form of a binary Candid message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    Hex,
+    Base64,
+}
+
+impl MessageFormat {
+    pub fn id(self) -> &'static str {
+        match self {
+            MessageFormat::Hex => "hex",
+            MessageFormat::Base64 => "base64",
+        }
+    }
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "hex" => Ok(MessageFormat::Hex),
+            "base64" | "b64" => Ok(MessageFormat::Base64),
+            other => Err(format!("Invalid message format: {other}")),
+        }
+    }
+}
+
+/// Read message bytes written in `format`; whitespace is ignored, hex may start with `0x`, and
+/// base64 may use the URL-safe alphabet.
+pub fn parse_message_bytes(text: &str, format: MessageFormat) -> Result<Vec<u8>, String> {
+    let text: String = text.chars().filter(|ch| !ch.is_whitespace()).collect();
+    match format {
+        MessageFormat::Hex => {
+            let digits = text
+                .strip_prefix("0x")
+                .or_else(|| text.strip_prefix("0X"))
+                .unwrap_or(&text);
+            hex::decode(digits).map_err(|err| format!("invalid hex input: {err}"))
+        }
+        MessageFormat::Base64 => {
+            let alphabet = if text.contains(['-', '_']) {
+                &alphabet::URL_SAFE
+            } else {
+                &alphabet::STANDARD
+            };
+            GeneralPurpose::new(alphabet, LENIENT_BASE64)
+                .decode(&text)
+                .map_err(|err| format!("invalid base64 input: {err}"))
+        }
+    }
+}
+
+/// Write message bytes in `format`; base64 uses the standard, padded alphabet.
+pub fn format_message_bytes(bytes: &[u8], format: MessageFormat) -> String {
+    match format {
+        MessageFormat::Hex => hex::encode(bytes),
+        MessageFormat::Base64 => STANDARD.encode(bytes),
+    }
+}
+
+/// A decoded or encoded message, returned for the client to open read-only.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageDocument {
+    /// Virtual `candid-message:` URI naming the interface, method and side of the call.
+    pub uri: String,
+    pub language_id: String,
+    pub content: String,
+}
+
+/// Why a message could not be decoded or encoded, and where in the interface, if anywhere.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageError {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl MessageError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            span: None,
+        }
+    }
+}
+
+/// Argument or result types of a service method, read from its signature.
+#[derive(Debug, Clone)]
+pub struct MethodTypes {
+    pub method: String,
+    pub role: ParamRole,
+    pub types: Vec<IDLType>,
+    /// Type of the method in the interface, where errors about its messages are reported.
+    pub span: Option<Span>,
+}
+
+impl MethodTypes {
+    /// Find `method` among the methods of the service in `semantic` and read its `role` types
+    /// from the method signature.
+    pub fn lookup(
+        semantic: &Semantic,
+        rope: &Rope,
+        env: &TypeEnv,
+        method: &str,
+        role: ParamRole,
+    ) -> Result<Self, MessageError> {
+        let metadata = semantic
+            .actor_method(rope, method)
+            .ok_or_else(|| MessageError::new(format!("no service method `{method}`")))?;
+        let types = match &metadata.signature {
+            Some(signature) => {
+                let texts = match role {
+                    ParamRole::Argument => &signature.args,
+                    ParamRole::Result => &signature.rets,
+                };
+                texts
+                    .iter()
+                    .map(|text| parse_type_text(text, env))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(MessageError::new)?
+            }
+            // Methods typed by a name, as in `m : F`, have no signature of their own.
+            None => {
+                let text = metadata
+                    .type_span
+                    .clone()
+                    .and_then(|span| rope.get_slice(span))
+                    .map(|text| text.to_string())
+                    .unwrap_or_default();
+                let ty = parse_type_text(&text, env).map_err(MessageError::new)?;
+                let Some(IDLType::FuncT(func)) = env.resolve(&ty) else {
+                    return Err(MessageError::new(format!("`{method}` is not a function")));
+                };
+                let types = match role {
+                    ParamRole::Argument => &func.args,
+                    ParamRole::Result => &func.rets,
+                };
+                types.iter().map(|ty| ty.kind.clone()).collect()
+            }
+        };
+        Ok(Self {
+            method: method.to_string(),
+            role,
+            types,
+            span: metadata.type_span.clone(),
+        })
+    }
+}
+
+/// Decode `bytes` as the arguments or results of a method.
+///
+/// Decoded arguments carry the `.args` directives, so the document is checked like a value file.
+pub fn decode_method_message(
+    uri: &Uri,
+    env: &TypeEnv,
+    types: &MethodTypes,
+    bytes: &[u8],
+) -> Result<MessageDocument, MessageError> {
+    let MethodTypes { method, role, .. } = types;
+    let values = decode_args(bytes, &types.types, env).map_err(|err| MessageError {
+        message: format!("cannot decode {}: {err}", side(*role)),
+        span: types.span.clone(),
+    })?;
+    let mut content = String::new();
+    if *role == ParamRole::Argument {
+        if let Some(path) = uri_to_path(uri) {
+            content.push_str(&format!("// {INTERFACE_DIRECTIVE}: {}\n", path.display()));
+        }
+        content.push_str(&format!("// {METHOD_DIRECTIVE}: {method}\n"));
+    }
+    content.push_str(&render_args_pretty(&values, PRETTY_WIDTH));
+    content.push('\n');
+    let extension = match role {
+        ParamRole::Argument => "args",
+        ParamRole::Result => "results",
+    };
+    Ok(MessageDocument {
+        uri: message_uri(uri, method, extension),
+        language_id: "candid".to_string(),
+        content,
+    })
+}
+
+/// Encode the Candid text `value` as the arguments or results of a method.
+pub fn encode_method_message(
+    uri: &Uri,
+    env: &TypeEnv,
+    types: &MethodTypes,
+    value: &str,
+    format: MessageFormat,
+) -> Result<MessageDocument, MessageError> {
+    let checked = env.checked().map_err(MessageError::new)?;
+    let candid_types = types
+        .types
+        .iter()
+        .map(|ty| env.candid_type(ty))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(MessageError::new)?;
+    let args = parse_idl_args(value)
+        .map_err(|err| MessageError::new(format!("value does not parse: {err}")))?;
+    let bytes = args
+        .annotate_types(true, &checked.env, &candid_types)
+        .and_then(|args| args.to_bytes_with_types(&checked.env, &candid_types))
+        .map_err(|err| MessageError {
+            message: format!("cannot encode {}: {err}", side(types.role)),
+            span: types.span.clone(),
+        })?;
+    Ok(MessageDocument {
+        uri: message_uri(uri, &types.method, format.id()),
+        language_id: "plaintext".to_string(),
+        content: format_message_bytes(&bytes, format),
+    })
+}
+
+fn side(role: ParamRole) -> &'static str {
+    match role {
+        ParamRole::Argument => "arguments",
+        ParamRole::Result => "results",
+    }
+}
+
+/// `candid-message:<path>.<method>.<extension>` for a message of `method` in `uri`.
+fn message_uri(uri: &Uri, method: &str, extension: &str) -> String {
+    let text = uri.as_str();
+    let path = text.split_once(':').map_or(text, |(_, path)| path);
+    format!("candid-message:{path}.{method}.{extension}")
+}
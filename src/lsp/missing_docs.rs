@@ -0,0 +1,206 @@
+use crate::lsp::{
+    code_action::quick_fix,
+    diagnostic_rules::DiagnosticRule,
+    position::{offset_to_position, span_to_range},
+    semantic_analyze::{FieldKind, Semantic},
+    span::Span,
+    symbol_table::SymbolId,
+};
+use ropey::Rope;
+use std::collections::HashSet;
+use tower_lsp_server::ls_types::{
+    CodeActionOrCommand, Diagnostic, DiagnosticSeverity, Position, Range, TextEdit, Uri,
+};
+
+/// Kind of declaration that is expected to carry a `///` comment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocTarget {
+    Method,
+    Type,
+    VariantCase,
+}
+
+impl DocTarget {
+    fn subject(self) -> &'static str {
+        match self {
+            DocTarget::Method => "service method",
+            DocTarget::Type => "type",
+            DocTarget::VariantCase => "variant case",
+        }
+    }
+}
+
+/// A public declaration without a doc comment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingDoc {
+    pub target: DocTarget,
+    pub name: String,
+    /// Span of the declared name, where the diagnostic is reported.
+    pub name_span: Span,
+    /// Offset where the declaration starts; the stub is inserted above its line.
+    pub start: usize,
+}
+
+/// Undocumented service methods, actor-reachable types and their variant cases.
+pub fn missing_docs(semantic: &Semantic, rope: &Rope) -> Vec<MissingDoc> {
+    let Some(actor) = &semantic.actor else {
+        return Vec::new();
+    };
+    let reachable = reachable_types(semantic, &actor.span);
+    let mut public_spans = vec![actor.span.clone()];
+    let mut missing = Vec::new();
+
+    for symbol_id in &reachable {
+        let definition = &semantic.table.symbol_id_to_span[*symbol_id];
+        public_spans.push(definition.clone());
+        let documented = semantic
+            .type_docs
+            .get(*symbol_id)
+            .and_then(Option::as_ref)
+            .is_some_and(|doc| doc.docs.is_some());
+        if documented {
+            continue;
+        }
+        let (Some(Some(name)), Some(Some(name_span))) = (
+            semantic.symbol_ident_names.get(*symbol_id),
+            semantic.symbol_ident_spans.get(*symbol_id),
+        ) else {
+            continue;
+        };
+        missing.push(MissingDoc {
+            target: DocTarget::Type,
+            name: name.to_string(),
+            name_span: name_span.clone(),
+            start: definition.start,
+        });
+    }
+
+    let is_public = |span: &Span| {
+        public_spans
+            .iter()
+            .any(|outer| outer.start <= span.start && span.end <= outer.end)
+    };
+    for method in semantic.service_methods.iter() {
+        let Some(name_span) = &method.name_span else {
+            continue;
+        };
+        if method.docs.is_some() || !is_public(&method.span) {
+            continue;
+        }
+        missing.push(MissingDoc {
+            target: DocTarget::Method,
+            name: rope.slice(name_span.clone()).to_string(),
+            name_span: name_span.clone(),
+            start: method.span.start,
+        });
+    }
+    for field in semantic.fields.iter() {
+        let (Some(label), Some(label_span)) = (&field.label, &field.label_span) else {
+            continue;
+        };
+        if field.kind != FieldKind::Variant || field.docs.is_some() || !is_public(&field.span) {
+            continue;
+        }
+        missing.push(MissingDoc {
+            target: DocTarget::VariantCase,
+            name: label.to_string(),
+            name_span: label_span.clone(),
+            start: field.span.start,
+        });
+    }
+
+    missing.sort_by_key(|item| item.name_span.start);
+    missing
+}
+
+/// Report every undocumented public declaration on its name.
+pub fn missing_doc_diagnostics(semantic: &Semantic, rope: &Rope) -> Vec<Diagnostic> {
+    missing_docs(semantic, rope)
+        .into_iter()
+        .filter_map(|item| {
+            let range = span_to_range(&item.name_span, rope)?;
+            Some(DiagnosticRule::MissingDocs.annotate(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                source: Some("docs".to_string()),
+                message: format!(
+                    "{} `{}` has no doc comment",
+                    item.target.subject(),
+                    item.name
+                ),
+                ..Default::default()
+            }))
+        })
+        .collect()
+}
+
+/// Quick fix inserting a `///` stub above the declaration flagged by `diagnostic`.
+///
+/// Nothing is offered when the declaration shares its line with earlier code, since the
+/// comment would attach to that code instead.
+pub fn missing_doc_actions(
+    uri: &Uri,
+    diagnostic: &Diagnostic,
+    semantic: &Semantic,
+    rope: &Rope,
+) -> Vec<CodeActionOrCommand> {
+    if DiagnosticRule::of(diagnostic) != Some(DiagnosticRule::MissingDocs) {
+        return Vec::new();
+    }
+    let Some(item) = missing_docs(semantic, rope)
+        .into_iter()
+        .find(|item| span_to_range(&item.name_span, rope) == Some(diagnostic.range))
+    else {
+        return Vec::new();
+    };
+    let Some(start) = offset_to_position(item.start, rope) else {
+        return Vec::new();
+    };
+    let indent: String = rope
+        .line(start.line as usize)
+        .chars()
+        .take(start.character as usize)
+        .collect();
+    if !indent.chars().all(|ch| ch == ' ' || ch == '\t') {
+        return Vec::new();
+    }
+    let line_start = Position::new(start.line, 0);
+    let edit = TextEdit {
+        range: Range::new(line_start, line_start),
+        new_text: format!("{indent}/// TODO: document `{}`.\n", item.name),
+    };
+    vec![quick_fix(
+        format!("Add doc comment for `{}`", item.name),
+        uri,
+        diagnostic,
+        edit,
+    )]
+}
+
+/// Types referenced from `actor_span`, directly or through other type definitions.
+fn reachable_types(semantic: &Semantic, actor_span: &Span) -> Vec<SymbolId> {
+    let references_in = |span: &Span| -> Vec<SymbolId> {
+        semantic
+            .table
+            .reference_id_to_reference
+            .iter()
+            .filter(|reference| {
+                span.start <= reference.span.start && reference.span.end <= span.end
+            })
+            .filter_map(|reference| reference.symbol_id)
+            .collect()
+    };
+    let mut seen = HashSet::new();
+    let mut order = Vec::new();
+    let mut pending = references_in(actor_span);
+    while let Some(symbol_id) = pending.pop() {
+        if !seen.insert(symbol_id) {
+            continue;
+        }
+        order.push(symbol_id);
+        if let Some(definition) = semantic.table.symbol_id_to_span.get(symbol_id) {
+            pending.extend(references_in(definition));
+        }
+    }
+    order
+}
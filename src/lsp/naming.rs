@@ -0,0 +1,375 @@
+use crate::lsp::{
+    code_action::quick_fix_with_edits,
+    diagnostic_rules::DiagnosticRule,
+    position::span_to_range,
+    semantic_analyze::{FieldKind, Semantic},
+    span::Span,
+};
+use ropey::Rope;
+use std::{fmt, str::FromStr};
+use tower_lsp_server::ls_types::{
+    CodeActionOrCommand, Diagnostic, DiagnosticSeverity, TextEdit, Uri,
+};
+
+/// Words that cannot be used as bare identifiers, so renames never produce them.
+const KEYWORDS: [&str; 16] = [
+    "blob",
+    "composite_query",
+    "false",
+    "func",
+    "import",
+    "null",
+    "oneway",
+    "opt",
+    "principal",
+    "query",
+    "record",
+    "service",
+    "true",
+    "type",
+    "variant",
+    "vec",
+];
+
+/// Identifier casing enforced by the naming lints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CaseStyle {
+    PascalCase,
+    CamelCase,
+    SnakeCase,
+    ScreamingSnakeCase,
+}
+
+impl CaseStyle {
+    /// Whether `name` already follows the style. Acronyms such as `HTTPRequest` are accepted.
+    pub fn matches(self, name: &str) -> bool {
+        let Some(first) = name.chars().next() else {
+            return true;
+        };
+        match self {
+            CaseStyle::PascalCase => first.is_uppercase() && !name.contains(['_', '-']),
+            CaseStyle::CamelCase => first.is_lowercase() && !name.contains(['_', '-']),
+            CaseStyle::SnakeCase => name
+                .chars()
+                .all(|ch| ch.is_lowercase() || ch.is_ascii_digit() || ch == '_'),
+            CaseStyle::ScreamingSnakeCase => name
+                .chars()
+                .all(|ch| ch.is_uppercase() || ch.is_ascii_digit() || ch == '_'),
+        }
+    }
+
+    /// Rewrite `name` in this style.
+    pub fn convert(self, name: &str) -> String {
+        let words = split_words(name);
+        match self {
+            CaseStyle::PascalCase => words.iter().map(|word| capitalize(word)).collect(),
+            CaseStyle::CamelCase => words
+                .iter()
+                .enumerate()
+                .map(|(idx, word)| {
+                    if idx == 0 {
+                        word.to_lowercase()
+                    } else {
+                        capitalize(word)
+                    }
+                })
+                .collect(),
+            CaseStyle::SnakeCase => words
+                .iter()
+                .map(|word| word.to_lowercase())
+                .collect::<Vec<_>>()
+                .join("_"),
+            CaseStyle::ScreamingSnakeCase => words
+                .iter()
+                .map(|word| word.to_uppercase())
+                .collect::<Vec<_>>()
+                .join("_"),
+        }
+    }
+}
+
+impl FromStr for CaseStyle {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value
+            .trim()
+            .to_ascii_lowercase()
+            .replace(['-', '_'], "")
+            .as_str()
+        {
+            "pascalcase" | "pascal" | "uppercamelcase" => Ok(CaseStyle::PascalCase),
+            "camelcase" | "camel" | "lowercamelcase" => Ok(CaseStyle::CamelCase),
+            "snakecase" | "snake" => Ok(CaseStyle::SnakeCase),
+            "screamingsnakecase" | "screamingsnake" | "constantcase" => {
+                Ok(CaseStyle::ScreamingSnakeCase)
+            }
+            _ => Err(format!("Invalid case style: {value}")),
+        }
+    }
+}
+
+impl fmt::Display for CaseStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaseStyle::PascalCase => f.write_str("PascalCase"),
+            CaseStyle::CamelCase => f.write_str("camelCase"),
+            CaseStyle::SnakeCase => f.write_str("snake_case"),
+            CaseStyle::ScreamingSnakeCase => f.write_str("SCREAMING_SNAKE_CASE"),
+        }
+    }
+}
+
+/// Configured casing for each kind of name, from the `diagnostics.naming` setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NamingConventions {
+    pub type_names: CaseStyle,
+    pub field_labels: CaseStyle,
+    pub method_names: CaseStyle,
+    pub variant_tags: CaseStyle,
+}
+
+impl Default for NamingConventions {
+    fn default() -> Self {
+        Self {
+            type_names: CaseStyle::PascalCase,
+            field_labels: CaseStyle::SnakeCase,
+            method_names: CaseStyle::SnakeCase,
+            variant_tags: CaseStyle::PascalCase,
+        }
+    }
+}
+
+/// A name that does not follow its configured style.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamingViolation {
+    pub rule: DiagnosticRule,
+    pub name: String,
+    pub style: CaseStyle,
+    pub suggestion: String,
+    /// Span of the name where it is declared.
+    pub span: Span,
+    /// Spans referring to the declaration; only type names have references.
+    pub references: Vec<Span>,
+}
+
+impl NamingViolation {
+    fn subject(&self) -> &'static str {
+        match self.rule {
+            DiagnosticRule::NamingTypeName => "type name",
+            DiagnosticRule::NamingFieldLabel => "field label",
+            DiagnosticRule::NamingMethodName => "method name",
+            _ => "variant tag",
+        }
+    }
+
+    /// Whether the suggested name can replace the current one without further changes.
+    fn renamable(&self) -> bool {
+        !self.suggestion.is_empty()
+            && self.suggestion != self.name
+            && !KEYWORDS.contains(&self.suggestion.as_str())
+            && !self.suggestion.starts_with(|ch: char| ch.is_ascii_digit())
+    }
+}
+
+/// Check type names, field labels, variant tags and method names in `semantic`.
+pub fn naming_violations(
+    semantic: &Semantic,
+    rope: &Rope,
+    conventions: &NamingConventions,
+) -> Vec<NamingViolation> {
+    let mut violations = Vec::new();
+    let mut check = |rule, style: CaseStyle, name: &str, span: &Span, references: Vec<Span>| {
+        if style.matches(name) {
+            return;
+        }
+        violations.push(NamingViolation {
+            rule,
+            name: name.to_string(),
+            style,
+            suggestion: style.convert(name),
+            span: span.clone(),
+            references,
+        });
+    };
+
+    for (symbol_id, name) in semantic.symbol_ident_names.iter_enumerated() {
+        let (Some(name), Some(Some(span))) = (name, semantic.symbol_ident_spans.get(symbol_id))
+        else {
+            continue;
+        };
+        let references = semantic
+            .table
+            .symbol_id_to_references
+            .get(&symbol_id)
+            .into_iter()
+            .flatten()
+            .map(|reference_id| {
+                semantic.table.reference_id_to_reference[*reference_id]
+                    .span
+                    .clone()
+            })
+            .collect();
+        check(
+            DiagnosticRule::NamingTypeName,
+            conventions.type_names,
+            name,
+            span,
+            references,
+        );
+    }
+
+    for field in semantic.fields.iter() {
+        let (Some(label), Some(span)) = (&field.label, &field.label_span) else {
+            continue;
+        };
+        // Numeric labels (`0 : nat`, tuple fields) have no casing.
+        if label.starts_with(|ch: char| ch.is_ascii_digit()) {
+            continue;
+        }
+        let (rule, style) = match field.kind {
+            FieldKind::Record => (DiagnosticRule::NamingFieldLabel, conventions.field_labels),
+            FieldKind::Variant => (DiagnosticRule::NamingVariantTag, conventions.variant_tags),
+        };
+        check(rule, style, label, span, Vec::new());
+    }
+
+    for method in semantic.service_methods.iter() {
+        let Some(span) = &method.name_span else {
+            continue;
+        };
+        let name = rope.slice(span.clone()).to_string();
+        check(
+            DiagnosticRule::NamingMethodName,
+            conventions.method_names,
+            &name,
+            span,
+            Vec::new(),
+        );
+    }
+
+    violations.sort_by_key(|violation| violation.span.start);
+    violations
+}
+
+/// Report every naming violation as a diagnostic on the declared name.
+pub fn naming_diagnostics(
+    semantic: &Semantic,
+    rope: &Rope,
+    conventions: &NamingConventions,
+) -> Vec<Diagnostic> {
+    naming_violations(semantic, rope, conventions)
+        .into_iter()
+        .filter_map(|violation| {
+            let range = span_to_range(&violation.span, rope)?;
+            let message = format!(
+                "{} `{}` should be {}",
+                violation.subject(),
+                violation.name,
+                violation.style
+            );
+            Some(violation.rule.annotate(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                source: Some("naming".to_string()),
+                message,
+                ..Default::default()
+            }))
+        })
+        .collect()
+}
+
+/// Quick fix renaming the declaration flagged by `diagnostic`, together with its references.
+pub fn naming_actions(
+    uri: &Uri,
+    diagnostic: &Diagnostic,
+    semantic: &Semantic,
+    rope: &Rope,
+    conventions: &NamingConventions,
+) -> Vec<CodeActionOrCommand> {
+    let Some(rule) = DiagnosticRule::of(diagnostic) else {
+        return Vec::new();
+    };
+    let Some(violation) = naming_violations(semantic, rope, conventions)
+        .into_iter()
+        .find(|violation| {
+            violation.rule == rule && span_to_range(&violation.span, rope) == Some(diagnostic.range)
+        })
+    else {
+        return Vec::new();
+    };
+    if !violation.renamable() {
+        return Vec::new();
+    }
+    // Type names must stay unique; labels and methods are scoped to their parent.
+    let is_type = rule == DiagnosticRule::NamingTypeName;
+    if is_type
+        && semantic
+            .symbol_ident_names
+            .iter()
+            .flatten()
+            .any(|name| **name == *violation.suggestion)
+    {
+        return Vec::new();
+    }
+
+    let edits = std::iter::once(&violation.span)
+        .chain(&violation.references)
+        .filter_map(|span| {
+            Some(TextEdit {
+                range: span_to_range(span, rope)?,
+                new_text: violation.suggestion.clone(),
+            })
+        })
+        .collect();
+    let title = if is_type {
+        format!("Rename `{}` to `{}`", violation.name, violation.suggestion)
+    } else {
+        format!(
+            "Rename `{}` to `{}` (changes the wire format)",
+            violation.name, violation.suggestion
+        )
+    };
+    vec![quick_fix_with_edits(title, uri, diagnostic, edits, is_type)]
+}
+
+/// Split an identifier into words at `_`, `-`, and case boundaries, keeping acronyms together.
+fn split_words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words = Vec::new();
+    let mut current = String::new();
+    for (idx, &ch) in chars.iter().enumerate() {
+        if ch == '_' || ch == '-' {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        if ch.is_uppercase() && !current.is_empty() {
+            let prev = chars[idx - 1];
+            let next_is_lower = chars.get(idx + 1).is_some_and(|next| next.is_lowercase());
+            if prev.is_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_uppercase() && next_is_lower)
+            {
+                words.push(std::mem::take(&mut current));
+            }
+        }
+        current.push(ch);
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,466 @@
+use crate::lsp::{
+    value::{Value, ValueKind},
+    value_check::{TypeEnv, label_text},
+};
+use candid_parser::{
+    candid::{IDLArgs, IDLValue, Principal, types::Label},
+    syntax::{IDLType, IDLTypeWithSpan, PrimType},
+};
+use std::fmt;
+
+/// A Candid value shaped by the type it was read at, so decoded blobs and text literals can be
+/// compared and printed the same way.
+///
+/// Records list their fields in the order of the expected type, with absent optional fields
+/// filled in, so two values of the same type compare equal exactly when they denote the same
+/// Candid value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NormalValue {
+    Null,
+    Bool(bool),
+    /// An integer in canonical decimal form, with a leading `-` when negative.
+    Number(String),
+    Float(f64),
+    Text(String),
+    Reserved,
+    Opt(Option<Box<NormalValue>>),
+    Vec(Vec<NormalValue>),
+    Record(Vec<(Label, NormalValue)>),
+    Variant(Label, Box<NormalValue>),
+    Principal(Principal),
+    Service(Principal),
+    Func(Principal, String),
+}
+
+impl NormalValue {
+    /// Normalize a text literal that has already been checked against `ty`.
+    pub fn from_value(value: &Value, ty: &IDLType, env: &TypeEnv) -> NormalValue {
+        let Some(resolved) = env.resolve(ty) else {
+            return NormalValue::Reserved;
+        };
+        match (&value.kind, resolved) {
+            (ValueKind::Annotated { value, .. }, _) => NormalValue::from_value(value, ty, env),
+            (_, IDLType::PrimT(PrimType::Reserved)) => NormalValue::Reserved,
+            (ValueKind::Null, IDLType::OptT(_)) => NormalValue::Opt(None),
+            (ValueKind::Opt(inner), IDLType::OptT(inner_ty)) => NormalValue::Opt(Some(Box::new(
+                NormalValue::from_value(inner, &inner_ty.kind, env),
+            ))),
+            (_, IDLType::OptT(inner_ty)) => NormalValue::Opt(Some(Box::new(
+                NormalValue::from_value(value, &inner_ty.kind, env),
+            ))),
+            (ValueKind::Null, _) => NormalValue::Null,
+            (ValueKind::Bool(value), _) => NormalValue::Bool(*value),
+            (ValueKind::Text(text), _) => NormalValue::Text(text.clone()),
+            (ValueKind::Number { text, .. }, IDLType::PrimT(PrimType::Float32)) => {
+                NormalValue::Float(
+                    text.replace('_', "")
+                        .parse::<f32>()
+                        .map_or(f64::NAN, f64::from),
+                )
+            }
+            (ValueKind::Number { text, .. }, IDLType::PrimT(PrimType::Float64)) => {
+                NormalValue::Float(text.replace('_', "").parse().unwrap_or(f64::NAN))
+            }
+            (ValueKind::Number { text, .. }, _) => NormalValue::Number(canonical_integer(text)),
+            (ValueKind::Blob(text), _) => NormalValue::Vec(
+                text.as_bytes()
+                    .iter()
+                    .map(|byte| NormalValue::Number(byte.to_string()))
+                    .collect(),
+            ),
+            (ValueKind::Vec { items, .. }, IDLType::VecT(inner_ty)) => NormalValue::Vec(
+                items
+                    .iter()
+                    .map(|item| NormalValue::from_value(item, &inner_ty.kind, env))
+                    .collect(),
+            ),
+            (ValueKind::Record { fields, .. }, IDLType::RecordT(type_fields)) => {
+                NormalValue::Record(
+                    type_fields
+                        .iter()
+                        .map(|type_field| {
+                            let id = type_field.label.get_id();
+                            let value = fields
+                                .iter()
+                                .find(|field| field.label.get_id() == id)
+                                .and_then(|field| field.value.as_ref());
+                            let normal = match value {
+                                Some(value) => {
+                                    NormalValue::from_value(value, &type_field.typ.kind, env)
+                                }
+                                None => absent_value(&type_field.typ.kind, env)
+                                    .unwrap_or(NormalValue::Reserved),
+                            };
+                            (type_field.label.clone(), normal)
+                        })
+                        .collect(),
+                )
+            }
+            (
+                ValueKind::Variant {
+                    field: Some(field), ..
+                },
+                IDLType::VariantT(type_fields),
+            ) => {
+                let id = field.label.get_id();
+                let Some(type_field) = type_fields
+                    .iter()
+                    .find(|type_field| type_field.label.get_id() == id)
+                else {
+                    return NormalValue::Reserved;
+                };
+                let payload = match &field.value {
+                    Some(value) => NormalValue::from_value(value, &type_field.typ.kind, env),
+                    None => NormalValue::Null,
+                };
+                NormalValue::Variant(type_field.label.clone(), Box::new(payload))
+            }
+            (ValueKind::Principal(text), _) => {
+                Principal::from_text(text).map_or(NormalValue::Reserved, NormalValue::Principal)
+            }
+            (ValueKind::Service(text), _) => {
+                Principal::from_text(text).map_or(NormalValue::Reserved, NormalValue::Service)
+            }
+            (ValueKind::Func { principal, method }, _) => Principal::from_text(principal)
+                .map_or(NormalValue::Reserved, |principal| {
+                    NormalValue::Func(principal, method.clone())
+                }),
+            _ => NormalValue::Reserved,
+        }
+    }
+
+    /// Normalize a value `candid` decoded at `ty`.
+    pub fn from_idl(value: &IDLValue, ty: &IDLType, env: &TypeEnv) -> NormalValue {
+        let Some(resolved) = env.resolve(ty) else {
+            return NormalValue::Reserved;
+        };
+        match (value, resolved) {
+            (_, IDLType::PrimT(PrimType::Reserved)) | (IDLValue::Reserved, _) => {
+                NormalValue::Reserved
+            }
+            (IDLValue::None | IDLValue::Null, IDLType::OptT(_)) => NormalValue::Opt(None),
+            (IDLValue::Opt(inner), IDLType::OptT(inner_ty)) => NormalValue::Opt(Some(Box::new(
+                NormalValue::from_idl(inner, &inner_ty.kind, env),
+            ))),
+            (IDLValue::Null | IDLValue::None, _) => NormalValue::Null,
+            (IDLValue::Bool(value), _) => NormalValue::Bool(*value),
+            (IDLValue::Text(text), _) => NormalValue::Text(text.clone()),
+            (IDLValue::Number(text), _) => NormalValue::Number(canonical_integer(text)),
+            (IDLValue::Int(value), _) => NormalValue::Number(value.to_string()),
+            (IDLValue::Nat(value), _) => NormalValue::Number(value.to_string()),
+            (IDLValue::Nat8(value), _) => NormalValue::Number(value.to_string()),
+            (IDLValue::Nat16(value), _) => NormalValue::Number(value.to_string()),
+            (IDLValue::Nat32(value), _) => NormalValue::Number(value.to_string()),
+            (IDLValue::Nat64(value), _) => NormalValue::Number(value.to_string()),
+            (IDLValue::Int8(value), _) => NormalValue::Number(value.to_string()),
+            (IDLValue::Int16(value), _) => NormalValue::Number(value.to_string()),
+            (IDLValue::Int32(value), _) => NormalValue::Number(value.to_string()),
+            (IDLValue::Int64(value), _) => NormalValue::Number(value.to_string()),
+            (IDLValue::Float32(value), _) => NormalValue::Float(f64::from(*value)),
+            (IDLValue::Float64(value), _) => NormalValue::Float(*value),
+            (IDLValue::Blob(bytes), _) => NormalValue::Vec(
+                bytes
+                    .iter()
+                    .map(|byte| NormalValue::Number(byte.to_string()))
+                    .collect(),
+            ),
+            (IDLValue::Vec(items), IDLType::VecT(inner_ty)) => NormalValue::Vec(
+                items
+                    .iter()
+                    .map(|item| NormalValue::from_idl(item, &inner_ty.kind, env))
+                    .collect(),
+            ),
+            (IDLValue::Record(fields), IDLType::RecordT(type_fields)) => NormalValue::Record(
+                type_fields
+                    .iter()
+                    .map(|type_field| {
+                        let id = type_field.label.get_id();
+                        let normal = match fields.iter().find(|field| field.id.get_id() == id) {
+                            Some(field) => {
+                                NormalValue::from_idl(&field.val, &type_field.typ.kind, env)
+                            }
+                            None => absent_value(&type_field.typ.kind, env)
+                                .unwrap_or(NormalValue::Reserved),
+                        };
+                        (type_field.label.clone(), normal)
+                    })
+                    .collect(),
+            ),
+            (IDLValue::Variant(variant), IDLType::VariantT(type_fields)) => {
+                let id = variant.0.id.get_id();
+                let Some(type_field) = type_fields
+                    .iter()
+                    .find(|type_field| type_field.label.get_id() == id)
+                else {
+                    return NormalValue::Reserved;
+                };
+                NormalValue::Variant(
+                    type_field.label.clone(),
+                    Box::new(NormalValue::from_idl(
+                        &variant.0.val,
+                        &type_field.typ.kind,
+                        env,
+                    )),
+                )
+            }
+            (IDLValue::Principal(principal), _) => NormalValue::Principal(*principal),
+            (IDLValue::Service(principal), _) => NormalValue::Service(*principal),
+            (IDLValue::Func(principal, method), _) => NormalValue::Func(*principal, method.clone()),
+            _ => NormalValue::Reserved,
+        }
+    }
+}
+
+/// Decode a binary Candid message with `candid` and read its arguments at `types`, following
+/// the subtyping rules of the Candid spec.
+pub fn decode_args(
+    bytes: &[u8],
+    types: &[IDLType],
+    env: &TypeEnv,
+) -> Result<Vec<NormalValue>, String> {
+    let checked = env.checked().map_err(str::to_string)?;
+    let candid_types = types
+        .iter()
+        .map(|ty| env.candid_type(ty))
+        .collect::<Result<Vec<_>, _>>()?;
+    let args = IDLArgs::from_bytes_with_types(bytes, &checked.env, &candid_types)
+        .map_err(|err| err.to_string())?;
+    Ok(args
+        .args
+        .iter()
+        .zip(types)
+        .map(|(value, ty)| NormalValue::from_idl(value, ty, env))
+        .collect())
+}
+
+/// Normalize a checked argument list; arguments left out take their default value.
+pub fn normalize_args(
+    values: &[Value],
+    types: &[IDLTypeWithSpan],
+    env: &TypeEnv,
+) -> Vec<NormalValue> {
+    types
+        .iter()
+        .enumerate()
+        .map(|(idx, ty)| match values.get(idx) {
+            Some(value) => NormalValue::from_value(value, &ty.kind, env),
+            None => absent_value(&ty.kind, env).unwrap_or(NormalValue::Reserved),
+        })
+        .collect()
+}
+
+/// The value a missing field or argument of type `ty` decodes to, if it may be left out.
+pub fn absent_value(ty: &IDLType, env: &TypeEnv) -> Option<NormalValue> {
+    match env.resolve(ty)? {
+        IDLType::OptT(_) => Some(NormalValue::Opt(None)),
+        IDLType::PrimT(PrimType::Null) => Some(NormalValue::Null),
+        IDLType::PrimT(PrimType::Reserved) => Some(NormalValue::Reserved),
+        _ => None,
+    }
+}
+
+/// Render an argument list in Candid text syntax, e.g. `(42, opt "x")`.
+pub fn render_args(values: &[NormalValue]) -> String {
+    let values: Vec<_> = values.iter().map(ToString::to_string).collect();
+    format!("({})", values.join(", "))
+}
+
+/// Like [`render_args`], but values longer than `width` columns are broken over indented lines.
+pub fn render_args_pretty(values: &[NormalValue], width: usize) -> String {
+    let flat = render_args(values);
+    if flat.len() <= width {
+        return flat;
+    }
+    let mut out = String::from("(\n");
+    for (idx, value) in values.iter().enumerate() {
+        if idx > 0 {
+            out.push_str(",\n");
+        }
+        out.push_str(PRETTY_INDENT);
+        render_pretty(value, 1, width, &mut out);
+    }
+    out.push_str("\n)");
+    out
+}
+
+const PRETTY_INDENT: &str = "  ";
+
+fn render_pretty(value: &NormalValue, level: usize, width: usize, out: &mut String) {
+    let flat = value.to_string();
+    if level * PRETTY_INDENT.len() + flat.len() <= width {
+        out.push_str(&flat);
+        return;
+    }
+    let indent = PRETTY_INDENT.repeat(level);
+    match value {
+        NormalValue::Opt(Some(inner)) => {
+            out.push_str("opt ");
+            render_pretty(inner, level, width, out);
+        }
+        NormalValue::Vec(items) if !items.is_empty() => {
+            out.push_str("vec {\n");
+            for item in items {
+                out.push_str(&indent);
+                out.push_str(PRETTY_INDENT);
+                render_pretty(item, level + 1, width, out);
+                out.push_str(";\n");
+            }
+            out.push_str(&indent);
+            out.push('}');
+        }
+        NormalValue::Record(fields) if !fields.is_empty() => {
+            out.push_str("record {\n");
+            for (label, value) in fields {
+                out.push_str(&indent);
+                out.push_str(PRETTY_INDENT);
+                if !matches!(label, Label::Unnamed(_)) {
+                    out.push_str(&written_label(label));
+                    out.push_str(" = ");
+                }
+                render_pretty(value, level + 1, width, out);
+                out.push_str(";\n");
+            }
+            out.push_str(&indent);
+            out.push('}');
+        }
+        NormalValue::Variant(label, payload) if **payload != NormalValue::Null => {
+            out.push_str("variant { ");
+            out.push_str(&written_label(label));
+            out.push_str(" = ");
+            render_pretty(payload, level, width, out);
+            out.push_str(" }");
+        }
+        _ => out.push_str(&flat),
+    }
+}
+
+impl fmt::Display for NormalValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NormalValue::Null | NormalValue::Reserved | NormalValue::Opt(None) => {
+                f.write_str("null")
+            }
+            NormalValue::Bool(value) => write!(f, "{value}"),
+            NormalValue::Number(text) => f.write_str(text),
+            NormalValue::Float(value) => write!(f, "{value:?}"),
+            NormalValue::Text(text) => write!(f, "{text:?}"),
+            NormalValue::Opt(Some(inner)) => write!(f, "opt {inner}"),
+            NormalValue::Vec(items) if items.is_empty() => f.write_str("vec {}"),
+            NormalValue::Vec(items) => {
+                f.write_str("vec { ")?;
+                for item in items {
+                    write!(f, "{item}; ")?;
+                }
+                f.write_str("}")
+            }
+            NormalValue::Record(fields) if fields.is_empty() => f.write_str("record {}"),
+            NormalValue::Record(fields) => {
+                f.write_str("record { ")?;
+                for (label, value) in fields {
+                    match label {
+                        Label::Unnamed(_) => write!(f, "{value}; ")?,
+                        _ => write!(f, "{} = {value}; ", written_label(label))?,
+                    }
+                }
+                f.write_str("}")
+            }
+            NormalValue::Variant(label, payload) => match payload.as_ref() {
+                NormalValue::Null => write!(f, "variant {{ {} }}", written_label(label)),
+                payload => write!(f, "variant {{ {} = {payload} }}", written_label(label)),
+            },
+            NormalValue::Principal(principal) => write!(f, "principal {:?}", principal.to_text()),
+            NormalValue::Service(principal) => write!(f, "service {:?}", principal.to_text()),
+            NormalValue::Func(principal, method) => {
+                write!(f, "func {:?}.{method}", principal.to_text())
+            }
+        }
+    }
+}
+
+pub(crate) fn written_label(label: &Label) -> String {
+    let text = label_text(label);
+    let plain = text
+        .chars()
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && text
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_');
+    if plain || matches!(label, Label::Id(_) | Label::Unnamed(_)) {
+        text
+    } else {
+        format!("{text:?}")
+    }
+}
+
+/// Rewrite an integer literal (decimal or hex, with sign and `_` separators) in canonical
+/// decimal form.
+pub fn canonical_integer(text: &str) -> String {
+    let cleaned = text.replace('_', "");
+    let (negative, magnitude) = match cleaned.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, cleaned.strip_prefix('+').unwrap_or(&cleaned)),
+    };
+    let digits = match magnitude
+        .strip_prefix("0x")
+        .or_else(|| magnitude.strip_prefix("0X"))
+    {
+        Some(hex) => Decimal::from_digits(hex.chars().filter_map(|ch| ch.to_digit(16)), 16),
+        None => Decimal::from_digits(magnitude.chars().filter_map(|ch| ch.to_digit(10)), 10),
+    };
+    digits.signed(negative)
+}
+
+const DECIMAL_BASE: u64 = 1_000_000_000;
+
+/// Arbitrary-precision natural number, stored as little-endian base 10⁹ limbs.
+#[derive(Debug, Default)]
+struct Decimal(Vec<u32>);
+
+impl Decimal {
+    /// Build a number from its digits in `radix`, most significant first.
+    fn from_digits(digits: impl IntoIterator<Item = u32>, radix: u32) -> Self {
+        let mut number = Decimal::default();
+        for digit in digits {
+            number.mul_add(radix, digit);
+        }
+        number
+    }
+
+    fn mul_add(&mut self, mul: u32, add: u32) {
+        let mut carry = u64::from(add);
+        for limb in &mut self.0 {
+            let value = u64::from(*limb) * u64::from(mul) + carry;
+            *limb = (value % DECIMAL_BASE) as u32;
+            carry = value / DECIMAL_BASE;
+        }
+        while carry > 0 {
+            self.0.push((carry % DECIMAL_BASE) as u32);
+            carry /= DECIMAL_BASE;
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.iter().all(|limb| *limb == 0)
+    }
+
+    /// Decimal text, prefixed with `-` when `negative` and non-zero.
+    fn signed(&self, negative: bool) -> String {
+        let sign = if negative && !self.is_zero() { "-" } else { "" };
+        format!("{sign}{self}")
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut limbs = self.0.iter().rev().skip_while(|limb| **limb == 0);
+        let Some(first) = limbs.next() else {
+            return f.write_str("0");
+        };
+        write!(f, "{first}")?;
+        for limb in limbs {
+            write!(f, "{limb:09}")?;
+        }
+        Ok(())
+    }
+}
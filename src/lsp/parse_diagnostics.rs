@@ -0,0 +1,342 @@
+use crate::{
+    candid_lang::lex,
+    lsp::{
+        clean_diagnostic_message, diagnostic_rules::DiagnosticRule, position::offset_to_position,
+        span::Span,
+    },
+};
+use candid_parser::token::{LexicalError, Token};
+use lalrpop_util::ParseError;
+use ropey::Rope;
+use tower_lsp_server::ls_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location, Position, Range, Uri,
+};
+
+/// Expected-token lists longer than this are cut short in messages.
+const MAX_EXPECTED: usize = 6;
+
+/// Convert a LALRPOP parse error into a diagnostic with a prose message.
+///
+/// Common mistakes (a missing `;` between declarations, an unclosed `{` or `(`) get a dedicated
+/// message; the opening delimiter is attached as related information when `uri` is known.
+pub fn parse_error_to_diagnostic(
+    err: ParseError<usize, Token, LexicalError>,
+    uri: Option<&Uri>,
+    rope: &Rope,
+) -> Diagnostic {
+    match err {
+        ParseError::InvalidToken { location } => {
+            let message = match rope.get_char(location) {
+                Some(ch) if !ch.is_whitespace() => format!("unrecognized character `{ch}`"),
+                _ => "invalid token".to_string(),
+            };
+            parser_diagnostic(
+                DiagnosticRule::InvalidToken,
+                range_single_char(location, rope),
+                message,
+            )
+        }
+        ParseError::UnrecognizedEof { location, expected } => {
+            let location = location.min(rope.len_chars());
+            let tokens = tokens_before(rope, location);
+            if let Some((opener, closer)) =
+                expected_closer(&expected).and_then(|closer| unclosed_delimiter(&tokens, closer))
+            {
+                let message = format!(
+                    "unclosed `{}`: expected `{closer}` before the end of the file",
+                    opener_char(closer)
+                );
+                return parser_diagnostic(
+                    DiagnosticRule::UnclosedDelimiter,
+                    range_single_char(opener, rope),
+                    message,
+                );
+            }
+            if expects(&expected, ";") {
+                return missing_semicolon(rope, &tokens);
+            }
+            parser_diagnostic(
+                DiagnosticRule::UnexpectedEof,
+                range_single_char(location, rope),
+                format!(
+                    "unexpected end of file, expected {}",
+                    describe_expected(&expected)
+                ),
+            )
+        }
+        ParseError::UnrecognizedToken {
+            token: (start, token, end),
+            expected,
+        } => {
+            let starts_declaration = matches!(token, Token::Type | Token::Import | Token::Service);
+            let tokens = if starts_declaration {
+                tokens_before(rope, start)
+            } else {
+                Vec::new()
+            };
+            if starts_declaration
+                && let Some(closer) = expected_closer(&expected)
+                && let Some((opener, _)) = unclosed_delimiter(&tokens, closer)
+            {
+                let mut diagnostic = parser_diagnostic(
+                    DiagnosticRule::UnclosedDelimiter,
+                    range_offsets(start, end, rope),
+                    format!(
+                        "unclosed `{}`: expected `{closer}` before {}",
+                        opener_char(closer),
+                        describe_token(&token)
+                    ),
+                );
+                attach_related(
+                    &mut diagnostic,
+                    uri,
+                    range_single_char(opener, rope),
+                    format!("`{}` opened here", opener_char(closer)),
+                );
+                return diagnostic;
+            }
+            if starts_declaration && expects(&expected, ";") {
+                return missing_semicolon(rope, &tokens);
+            }
+            parser_diagnostic(
+                DiagnosticRule::UnexpectedToken,
+                range_offsets(start, end, rope),
+                format!(
+                    "unexpected {}, expected {}",
+                    describe_token(&token),
+                    describe_expected(&expected)
+                ),
+            )
+        }
+        ParseError::ExtraToken {
+            token: (start, token, end),
+        } => parser_diagnostic(
+            DiagnosticRule::ExtraToken,
+            range_offsets(start, end, rope),
+            format!(
+                "unexpected {} after the end of the input",
+                describe_token(&token)
+            ),
+        ),
+        ParseError::User { error } => {
+            // Errors raised without a span (`token::error`) carry `0..0`.
+            let range = match error.span {
+                Span { start: 0, end: 0 } => Range::default(),
+                Span { start, end } if start < end => range_offsets(start, end, rope),
+                Span { start, .. } => range_single_char(start, rope),
+            };
+            parser_diagnostic(DiagnosticRule::ParseError, range, error.err)
+        }
+    }
+}
+
+/// Describe a LALRPOP terminal name (as listed in `expected`) in prose.
+pub fn describe_terminal(name: &str) -> String {
+    let name = name.trim_matches('"');
+    match name {
+        "id" => "an identifier".to_string(),
+        "text" => "a text literal".to_string(),
+        "decimal" => "a number".to_string(),
+        "hex" => "a hexadecimal number".to_string(),
+        "float" => "a floating-point number".to_string(),
+        "bool" => "`true` or `false`".to_string(),
+        "sign" => "`+` or `-`".to_string(),
+        other => format!("`{other}`"),
+    }
+}
+
+fn describe_token(token: &Token) -> String {
+    match token {
+        Token::Id(name) => format!("identifier `{name}`"),
+        Token::Text(_) | Token::StartString => "text literal".to_string(),
+        Token::Decimal(value) | Token::Hex(value) | Token::Float(value) => {
+            format!("number `{value}`")
+        }
+        Token::Boolean(value) => format!("`{value}`"),
+        Token::Sign(sign) => format!("`{sign}`"),
+        Token::LineComment | Token::StartComment => "comment".to_string(),
+        other => match keyword_text(other) {
+            Some(text) => format!("`{text}`"),
+            None => "token".to_string(),
+        },
+    }
+}
+
+fn keyword_text(token: &Token) -> Option<&'static str> {
+    Some(match token {
+        Token::Equals => "=",
+        Token::LParen => "(",
+        Token::RParen => ")",
+        Token::LBrace => "{",
+        Token::RBrace => "}",
+        Token::Semi => ";",
+        Token::Comma => ",",
+        Token::Dot => ".",
+        Token::Colon => ":",
+        Token::Arrow => "->",
+        Token::Null => "null",
+        Token::Vec => "vec",
+        Token::Record => "record",
+        Token::Variant => "variant",
+        Token::Func => "func",
+        Token::Service => "service",
+        Token::Oneway => "oneway",
+        Token::Query => "query",
+        Token::CompositeQuery => "composite_query",
+        Token::Blob => "blob",
+        Token::Type => "type",
+        Token::Import => "import",
+        Token::Opt => "opt",
+        Token::TestEqual => "==",
+        Token::NotEqual => "!=",
+        Token::NotDecode => "!:",
+        Token::Principal => "principal",
+        _ => return None,
+    })
+}
+
+fn describe_expected(expected: &[String]) -> String {
+    let mut items: Vec<String> = Vec::with_capacity(expected.len());
+    for name in expected {
+        let item = describe_terminal(name);
+        if !items.contains(&item) {
+            items.push(item);
+        }
+    }
+    let truncated = items.len() > MAX_EXPECTED;
+    items.truncate(MAX_EXPECTED);
+    match items.as_slice() {
+        [] => "something else".to_string(),
+        [single] => single.clone(),
+        [init @ .., last] if !truncated => format!("{} or {last}", init.join(", ")),
+        _ => format!("one of {}, …", items.join(", ")),
+    }
+}
+
+fn expects(expected: &[String], terminal: &str) -> bool {
+    expected
+        .iter()
+        .any(|name| name.trim_matches('"') == terminal)
+}
+
+fn expected_closer(expected: &[String]) -> Option<char> {
+    if expects(expected, "}") {
+        Some('}')
+    } else if expects(expected, ")") {
+        Some(')')
+    } else {
+        None
+    }
+}
+
+fn opener_char(closer: char) -> char {
+    if closer == '}' { '{' } else { '(' }
+}
+
+/// The tokens of the document that start before `location`.
+///
+/// Comments are dropped by the lexer and text literals are single tokens, so delimiters inside
+/// either are never counted.
+fn tokens_before(rope: &Rope, location: usize) -> Vec<(usize, Token, usize)> {
+    let mut tokens = lex(&rope.to_string()).tokens;
+    tokens.retain(|(start, _, _)| *start < location);
+    tokens
+}
+
+/// Report a missing `;` after the last of `tokens`.
+fn missing_semicolon(rope: &Rope, tokens: &[(usize, Token, usize)]) -> Diagnostic {
+    let end = tokens.last().map_or(0, |(_, _, end)| *end);
+    let message = format!("missing `;` after {}", statement_kind(tokens));
+    parser_diagnostic(
+        DiagnosticRule::MissingSemicolon,
+        range_single_char(end.saturating_sub(1), rope),
+        message,
+    )
+}
+
+/// Name the kind of top-level declaration `tokens` end with, for use in messages.
+fn statement_kind(tokens: &[(usize, Token, usize)]) -> &'static str {
+    let mut depth = 0usize;
+    let mut statement_start = 0;
+    for (idx, (_, token, _)) in tokens.iter().enumerate() {
+        match token {
+            Token::LBrace | Token::LParen => depth += 1,
+            Token::RBrace | Token::RParen => depth = depth.saturating_sub(1),
+            Token::Semi if depth == 0 => statement_start = idx + 1,
+            _ => {}
+        }
+    }
+    match tokens.get(statement_start) {
+        Some((_, Token::Type, _)) => "type definition",
+        Some((_, Token::Import, _)) => "import",
+        _ => "declaration",
+    }
+}
+
+/// Find the innermost `{`/`(` among `tokens` that is still open, matching `closer`.
+fn unclosed_delimiter(tokens: &[(usize, Token, usize)], closer: char) -> Option<(usize, char)> {
+    let mut stack: Vec<(usize, char)> = Vec::new();
+    for (start, token, _) in tokens {
+        match token {
+            Token::LBrace => stack.push((*start, '{')),
+            Token::LParen => stack.push((*start, '(')),
+            Token::RBrace | Token::RParen => {
+                stack.pop();
+            }
+            _ => {}
+        }
+    }
+    stack
+        .pop()
+        .filter(|(_, opener)| *opener == opener_char(closer))
+}
+
+fn attach_related(diagnostic: &mut Diagnostic, uri: Option<&Uri>, range: Range, message: String) {
+    match uri {
+        Some(uri) => {
+            diagnostic
+                .related_information
+                .get_or_insert_with(Vec::new)
+                .push(DiagnosticRelatedInformation {
+                    location: Location::new(uri.clone(), range),
+                    message,
+                });
+        }
+        None => {
+            diagnostic
+                .message
+                .push_str(&format!(" ({message} at line {})", range.start.line + 1));
+        }
+    }
+}
+
+fn parser_diagnostic(rule: DiagnosticRule, range: Range, message: String) -> Diagnostic {
+    rule.annotate(Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("parser".to_string()),
+        message: clean_diagnostic_message(message),
+        ..Default::default()
+    })
+}
+
+fn range_single_char(offset: usize, rope: &Rope) -> Range {
+    if let Some(start) = offset_to_position(offset, rope) {
+        let end_offset = offset.saturating_add(1);
+        let end = offset_to_position(end_offset, rope)
+            .unwrap_or_else(|| Position::new(start.line, start.character + 1));
+        Range::new(start, end)
+    } else {
+        Range::default()
+    }
+}
+
+fn range_offsets(start_offset: usize, end_offset: usize, rope: &Rope) -> Range {
+    match (
+        offset_to_position(start_offset, rope),
+        offset_to_position(end_offset, rope),
+    ) {
+        (Some(start), Some(end)) => Range::new(start, end),
+        _ => Range::default(),
+    }
+}
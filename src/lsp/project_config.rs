@@ -0,0 +1,201 @@
+use crate::lsp::config::{ServerConfig, normalize_settings};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// File holding project settings, looked up from a document's directory upwards.
+pub const CONFIG_FILE_NAME: &str = "candid-ls.toml";
+
+/// Existing project files that may carry the settings in a `[candid-ls]` section instead.
+pub const PROJECT_FILE_NAMES: [&str; 1] = ["Cargo.toml"];
+
+/// Table holding the settings inside a project file.
+const SECTION: &str = "candid-ls";
+
+#[derive(Debug, Error)]
+pub enum ProjectConfigError {
+    #[error("cannot read {}: {source}", .path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid settings in {}: {source}", .path.display())]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+}
+
+/// Whether a file named `name` can hold project settings.
+pub fn is_config_file_name(name: &str) -> bool {
+    name == CONFIG_FILE_NAME || PROJECT_FILE_NAMES.contains(&name)
+}
+
+/// The configuration file governing `document`: the nearest `candid-ls.toml`, or project file
+/// with a `[candid-ls]` section, in its directory or above.
+pub fn discover(document: &Path) -> Option<PathBuf> {
+    for dir in document.ancestors().skip(1) {
+        let candidate = dir.join(CONFIG_FILE_NAME);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        for name in PROJECT_FILE_NAMES {
+            let candidate = dir.join(name);
+            if candidate.is_file() && matches!(read_settings(&candidate), Ok(Some(_))) {
+                return Some(candidate);
+            }
+        }
+    }
+    None
+}
+
+/// Settings in the configuration file at `path`, shaped like the editor settings: the whole of a
+/// `candid-ls.toml`, or the `[candid-ls]` section of a project file. Cargo manifests may also use
+/// `[package.metadata.candid-ls]` or `[workspace.metadata.candid-ls]`.
+pub fn read_settings(path: &Path) -> Result<Option<Value>, ProjectConfigError> {
+    let text = fs::read_to_string(path).map_err(|source| ProjectConfigError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mut table: toml::Table = text.parse().map_err(|source| ProjectConfigError::Parse {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let settings = if path
+        .file_name()
+        .is_some_and(|name| name == CONFIG_FILE_NAME)
+    {
+        Some(table)
+    } else if let Some(toml::Value::Table(section)) = table.remove(SECTION) {
+        Some(section)
+    } else {
+        ["package", "workspace"].iter().find_map(|root| {
+            table
+                .get(*root)?
+                .get("metadata")?
+                .get(SECTION)?
+                .as_table()
+                .cloned()
+        })
+    };
+    Ok(settings.and_then(|settings| serde_json::to_value(settings).ok()))
+}
+
+/// Settings for a file checked outside an editor session, as in command-line modes: the defaults
+/// overridden by the project configuration governing `path`, if any.
+pub fn config_for_path(path: &Path) -> Result<ServerConfig, ProjectConfigError> {
+    let mut config = ServerConfig::default();
+    if let Some(config_path) = discover(path)
+        && let Some(settings) = read_settings(&config_path)?
+    {
+        config.apply_settings(settings);
+    }
+    Ok(config)
+}
+
+/// Project configuration files in use, each merged under the editor settings, and the file
+/// governing every open document.
+#[derive(Debug, Default)]
+pub struct ProjectConfigs {
+    /// Every editor setting received so far, merged into one value.
+    editor: Value,
+    /// Settings per configuration file; `None` when the file has no valid settings.
+    files: HashMap<PathBuf, Option<ProjectFile>>,
+    /// Configuration file governing each document, by URI.
+    documents: HashMap<String, PathBuf>,
+}
+
+/// The settings of a configuration file and the configuration they give under the editor
+/// settings.
+#[derive(Debug)]
+struct ProjectFile {
+    settings: Value,
+    config: ServerConfig,
+}
+
+impl ProjectFile {
+    fn new(settings: Value, editor: &Value) -> Self {
+        let mut file = Self {
+            settings: normalize_settings(settings),
+            config: ServerConfig::default(),
+        };
+        file.merge_editor_settings(editor);
+        file
+    }
+
+    /// Rebuild the configuration from the file's settings with `editor` merged over them.
+    fn merge_editor_settings(&mut self, editor: &Value) {
+        let mut merged = self.settings.clone();
+        if !editor.is_null() {
+            merge_settings(&mut merged, editor.clone());
+        }
+        self.config = ServerConfig::default();
+        self.config.apply_settings(merged);
+    }
+}
+
+impl ProjectConfigs {
+    /// Record editor settings, which take precedence over every project file.
+    pub fn apply_editor_settings(&mut self, value: Value) {
+        merge_settings(&mut self.editor, normalize_settings(value));
+        for file in self.files.values_mut().flatten() {
+            file.merge_editor_settings(&self.editor);
+        }
+    }
+
+    pub fn is_loaded(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    /// Read the configuration file at `path` and merge the editor settings over it. A file that
+    /// fails to load is remembered as having no settings.
+    pub fn load(&mut self, path: &Path) -> Result<(), ProjectConfigError> {
+        let settings = read_settings(path);
+        let file = settings
+            .as_ref()
+            .ok()
+            .cloned()
+            .flatten()
+            .map(|settings| ProjectFile::new(settings, &self.editor));
+        self.files.insert(path.to_path_buf(), file);
+        settings.map(|_| ())
+    }
+
+    /// Forget every loaded file, e.g. after one changed on disk.
+    pub fn clear_files(&mut self) {
+        self.files.clear();
+    }
+
+    /// Let the configuration file at `path` govern the document `uri`, or none.
+    pub fn attach(&mut self, uri: &str, path: Option<PathBuf>) {
+        match path {
+            Some(path) => self.documents.insert(uri.to_string(), path),
+            None => self.documents.remove(uri),
+        };
+    }
+
+    pub fn config_at(&self, path: &Path) -> Option<&ServerConfig> {
+        Some(&self.files.get(path)?.as_ref()?.config)
+    }
+
+    /// Merged settings for the document `uri`; `None` leaves it to the editor settings alone.
+    pub fn config_for(&self, uri: &str) -> Option<&ServerConfig> {
+        self.config_at(self.documents.get(uri)?)
+    }
+}
+
+/// Merge `update` into `settings`: objects key by key, any other value replacing the old one.
+fn merge_settings(settings: &mut Value, update: Value) {
+    match (settings, update) {
+        (Value::Object(settings), Value::Object(update)) => {
+            for (key, value) in update {
+                merge_settings(settings.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (settings, update) => *settings = update,
+    }
+}
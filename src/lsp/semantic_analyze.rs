@@ -15,7 +15,7 @@ use candid_parser::{
 use oxc_index::IndexVec;
 use ropey::Rope;
 use rust_lapper::{Interval, Lapper};
-use std::sync::Arc;
+use std::{ops::Range, sync::Arc};
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, SemanticError>;
@@ -41,6 +41,12 @@ pub enum FieldPart {
     Type,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldKind {
+    Record,
+    Variant,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ParamRole {
     Argument,
@@ -68,6 +74,7 @@ pub struct FieldMetadata {
     pub docs: Option<Arc<str>>,
     pub parent_name: Option<Arc<str>>,
     pub label: Option<Arc<str>>,
+    pub kind: FieldKind,
 }
 
 #[derive(Debug, Clone)]
@@ -102,6 +109,8 @@ pub struct ActorMetadata {
     pub name_span: Option<Span>,
     pub docs: Option<Arc<str>>,
     pub definition: Option<Arc<str>>,
+    /// Type the service is declared by, as in `service : S` or `service : (nat) -> S`.
+    pub service_type: Option<Arc<str>>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -122,6 +131,24 @@ pub enum SemanticError {
     },
 }
 
+impl Semantic {
+    /// The method called `name` of the program's service, declared inline or by the service
+    /// type the actor names.
+    pub fn actor_method(&self, rope: &Rope, name: &str) -> Option<&MethodMetadata> {
+        let actor = self.actor.as_ref()?;
+        self.service_methods.iter().find(|method| {
+            let declared = (actor.span.start <= method.span.start
+                && method.span.end <= actor.span.end)
+                || (actor.service_type.is_some() && method.parent_name == actor.service_type);
+            declared
+                && method.name_span.as_ref().is_some_and(|span| {
+                    rope.get_slice(span.clone())
+                        .is_some_and(|text| text.to_string().trim_matches('"') == name)
+                })
+        })
+    }
+}
+
 impl SemanticError {
     pub fn span(&self) -> Span {
         match self {
@@ -167,6 +194,26 @@ pub struct Ctx<'a> {
 }
 
 impl<'a> Ctx<'a> {
+    fn new(rope: &'a Rope) -> Self {
+        Ctx {
+            env: im_rc::Vector::new(),
+            table: SymbolTable::default(),
+            fields: IndexVec::new(),
+            service_methods: IndexVec::new(),
+            params: IndexVec::new(),
+            locals: Vec::new(),
+            rope,
+            symbol_ident_spans: IndexVec::new(),
+            symbol_ident_names: IndexVec::new(),
+            type_docs: IndexVec::new(),
+            primitive_spans: Vec::new(),
+            keyword_spans: Vec::new(),
+            actor: None,
+            type_name_stack: Vec::new(),
+            scope_stack: Vec::new(),
+        }
+    }
+
     fn find_symbol(&self, name: &str) -> Option<Span> {
         self.env
             .iter()
@@ -213,13 +260,53 @@ impl<'a> Ctx<'a> {
         symbol_id
     }
 
+    /// Declare the symbol a top-level declaration introduces, so later declarations can refer
+    /// to it.
+    fn declare_dec(&mut self, dec: &Dec) -> SymbolId {
+        let (span, path, kind) = match dec {
+            Dec::TypD(binding) => {
+                return self.declare_symbol(binding.id.clone(), binding.span.clone());
+            }
+            Dec::ImportType { path, span } => (span, path, ImportKind::Type),
+            Dec::ImportServ { path, span } => (span, path, ImportKind::Service),
+        };
+        let symbol_id = self.table.add_import(span.clone(), path.clone(), kind);
+        self.register_symbol_slot();
+        symbol_id
+    }
+
+    /// Record the keyword, name span and hover text of a declared symbol.
+    fn describe_dec(&mut self, dec: &Dec, symbol_id: SymbolId) {
+        match dec {
+            Dec::TypD(binding) => {
+                self.register_keyword(binding.span.clone(), KeywordDoc::Type);
+                if let Some(ident_span) = compute_binding_ident_span(binding, self.rope)
+                    && let Some(slot) = self.symbol_ident_spans.get_mut(symbol_id)
+                {
+                    *slot = Some(ident_span);
+                }
+                if let Some(slot) = self.type_docs.get_mut(symbol_id) {
+                    let rendered = render_binding(binding);
+                    let doc_block = format_docs(&binding.docs);
+                    *slot = Some(TypeDoc {
+                        definition: Arc::<str>::from(rendered.into_boxed_str()),
+                        docs: doc_block,
+                    });
+                }
+            }
+            Dec::ImportType { path, span } | Dec::ImportServ { path, span } => {
+                self.register_import_keyword(span.clone(), path);
+            }
+        }
+    }
+
     fn register_symbol_slot(&mut self) {
         self.symbol_ident_spans.push(None);
         self.symbol_ident_names.push(None);
         self.type_docs.push(None);
     }
 
-    fn register_field(&mut self, field: &TypeField) {
+    fn register_field(&mut self, field: &TypeField, kind: FieldKind) {
         let label_span = compute_field_label_span(field, self.rope);
         let type_span = if field.typ.span.start < field.typ.span.end {
             Some(field.typ.span.clone())
@@ -243,6 +330,7 @@ impl<'a> Ctx<'a> {
             docs: format_docs(&field.docs),
             parent_name: self.current_type_name(),
             label: label_text,
+            kind,
         };
         self.fields.push(metadata);
 
@@ -380,11 +468,11 @@ impl<'a> Ctx<'a> {
         }
     }
 
-    fn register_import_keywords_from_text(&mut self) {
+    fn register_import_keywords_from_text(&mut self, lines: Range<usize>) {
         let needle = KeywordDoc::Import.keyword();
         let needle_len = needle.chars().count();
 
-        for line_idx in 0..self.rope.len_lines() {
+        for line_idx in lines {
             let line = self.rope.line(line_idx);
             let line_text = line.to_string();
             let trimmed = line_text.trim_start();
@@ -427,60 +515,10 @@ impl<'a> Ctx<'a> {
 }
 
 pub fn analyze_program(ast: &IDLMergedProg, rope: &Rope) -> Result<Semantic> {
-    let table = SymbolTable::default();
-    let env = im_rc::Vector::new();
-    let fields = IndexVec::new();
-    let service_methods = IndexVec::new();
-    let params = IndexVec::new();
-    let mut ctx = Ctx {
-        env,
-        table,
-        fields,
-        service_methods,
-        params,
-        locals: Vec::new(),
-        rope,
-        symbol_ident_spans: IndexVec::new(),
-        symbol_ident_names: IndexVec::new(),
-        type_docs: IndexVec::new(),
-        primitive_spans: Vec::new(),
-        keyword_spans: Vec::new(),
-        actor: None,
-        type_name_stack: Vec::new(),
-        scope_stack: Vec::new(),
-    };
+    let mut ctx = Ctx::new(rope);
     for dec in ast.decs().iter() {
-        match dec {
-            Dec::TypD(binding) => {
-                ctx.register_keyword(binding.span.clone(), KeywordDoc::Type);
-                let symbol_id = ctx.declare_symbol(binding.id.clone(), binding.span.clone());
-                if let Some(ident_span) = compute_binding_ident_span(binding, rope)
-                    && let Some(slot) = ctx.symbol_ident_spans.get_mut(symbol_id)
-                {
-                    *slot = Some(ident_span);
-                }
-                if let Some(slot) = ctx.type_docs.get_mut(symbol_id) {
-                    let rendered = render_binding(binding);
-                    let doc_block = format_docs(&binding.docs);
-                    *slot = Some(TypeDoc {
-                        definition: Arc::<str>::from(rendered.into_boxed_str()),
-                        docs: doc_block,
-                    });
-                }
-            }
-            Dec::ImportType { path, span } => {
-                ctx.table
-                    .add_import(span.clone(), path.clone(), ImportKind::Type);
-                ctx.register_import_keyword(span.clone(), path);
-                ctx.register_symbol_slot();
-            }
-            Dec::ImportServ { path, span } => {
-                ctx.table
-                    .add_import(span.clone(), path.clone(), ImportKind::Service);
-                ctx.register_import_keyword(span.clone(), path);
-                ctx.register_symbol_slot();
-            }
-        }
+        let symbol_id = ctx.declare_dec(dec);
+        ctx.describe_dec(dec, symbol_id);
     }
 
     for dec in ast.decs().iter() {
@@ -491,108 +529,245 @@ pub fn analyze_program(ast: &IDLMergedProg, rope: &Rope) -> Result<Semantic> {
         analyze_actor(actor, &mut ctx)?;
     }
 
-    ctx.register_import_keywords_from_text();
-
-    let mut ident_range = IdentRangeLapper::new(vec![]);
-    for (symbol_id, range) in ctx.table.symbol_id_to_span.iter_enumerated() {
-        let span = ctx
-            .symbol_ident_spans
-            .get(symbol_id)
-            .and_then(|opt| opt.clone())
-            .unwrap_or_else(|| range.clone());
-        ident_range.insert(Interval {
-            start: span.start,
-            stop: span.end,
-            val: IdentType::Binding(symbol_id),
-        });
-    }
-    for (reference_id, reference) in ctx.table.reference_id_to_reference.iter_enumerated() {
-        let range = &reference.span;
-        ident_range.insert(Interval {
-            start: range.start,
-            stop: range.end,
-            val: IdentType::Reference(reference_id),
-        });
-    }
-    for (field_id, metadata) in ctx.fields.iter_enumerated() {
-        if let Some(label_span) = &metadata.label_span {
-            ident_range.insert(Interval {
-                start: label_span.start,
-                stop: label_span.end,
-                val: IdentType::Field(field_id, FieldPart::Label),
-            });
+    ctx.register_import_keywords_from_text(0..rope.len_lines());
+    Ok(ctx.finish())
+}
+
+impl Semantic {
+    /// Analysis of `ast`, reusing what this analysis recorded outside the re-parsed `region`.
+    ///
+    /// `region` covers whole top-level declarations of the previous text and became
+    /// `region.start..region.end + delta` in `ast`. Only the declarations inside it are analysed
+    /// again; everything after it is shifted by `delta`. Returns `None` when the region declares
+    /// different names or contains imports, since references outside it could then resolve
+    /// differently.
+    pub fn splice(
+        &self,
+        ast: &IDLMergedProg,
+        rope: &Rope,
+        region: Span,
+        delta: isize,
+    ) -> Option<Result<Semantic>> {
+        let new_end = region.end.checked_add_signed(delta)?;
+        let in_new_region = |span: &Span| region.start <= span.start && span.start < new_end;
+        let place = |span: &Span| {
+            if span.start < region.start {
+                Some(0)
+            } else if span.start >= region.end {
+                Some(delta)
+            } else {
+                None
+            }
+        };
+
+        let decs: &[Dec] = &ast.decs();
+        if decs.len() != self.table.symbol_id_to_span.len() {
+            return None;
         }
-        if let Some(type_span) = &metadata.type_span
-            && type_span.start < type_span.end
-            && metadata.label_span.as_ref() != Some(type_span)
+        let mut ctx = Ctx::new(rope);
+        let mut region_decs = Vec::new();
+        for (dec, (symbol_id, old_span)) in decs
+            .iter()
+            .zip(self.table.symbol_id_to_span.iter_enumerated())
         {
-            ident_range.insert(Interval {
-                start: type_span.start,
-                stop: type_span.end,
-                val: IdentType::Field(field_id, FieldPart::Type),
+            let imported = self
+                .table
+                .imports
+                .iter()
+                .any(|import| import.symbol_id == symbol_id);
+            let old_shift = place(old_span);
+            match (dec, old_shift) {
+                (Dec::TypD(binding), None)
+                    if in_new_region(&binding.span)
+                        && !imported
+                        && self.symbol_ident_names[symbol_id].as_deref()
+                            == Some(binding.id.as_str()) =>
+                {
+                    ctx.declare_dec(dec);
+                    ctx.describe_dec(dec, symbol_id);
+                    region_decs.push(dec);
+                }
+                (_, Some(shift)) if !in_new_region(dec_span(dec)) => {
+                    ctx.declare_dec(dec);
+                    ctx.symbol_ident_spans[symbol_id] = self.symbol_ident_spans[symbol_id]
+                        .as_ref()
+                        .map(|span| shifted(span, shift));
+                    ctx.type_docs[symbol_id] = self.type_docs[symbol_id].clone();
+                }
+                _ => return None,
+            }
+        }
+
+        self.copy_into(&mut ctx, |span| span.start < region.start, 0);
+        for dec in region_decs {
+            if let Err(err) = analyze_dec(dec, &mut ctx) {
+                return Some(Err(err));
+            }
+        }
+        let actor = ast.resolve_actor().ok().flatten();
+        if let Some(actor) = actor.as_ref().filter(|actor| in_new_region(&actor.span))
+            && let Err(err) = analyze_actor(actor, &mut ctx)
+        {
+            return Some(Err(err));
+        }
+        self.copy_into(&mut ctx, |span| span.start >= region.end, delta);
+        if ctx.actor.is_none()
+            && let Some(previous) = &self.actor
+            && let Some(shift) = place(&previous.span)
+        {
+            ctx.actor = Some(ActorMetadata {
+                span: shifted(&previous.span, shift),
+                name_span: previous.name_span.as_ref().map(|span| shifted(span, shift)),
+                ..previous.clone()
             });
         }
+
+        let first_line = rope.char_to_line(region.start.min(rope.len_chars()));
+        let last_line = rope.char_to_line(new_end.min(rope.len_chars()));
+        ctx.register_import_keywords_from_text(first_line..last_line + 1);
+        Some(Ok(ctx.finish()))
     }
-    for (method_id, metadata) in ctx.service_methods.iter_enumerated() {
-        if let Some(name_span) = &metadata.name_span {
-            ident_range.insert(Interval {
-                start: name_span.start,
-                stop: name_span.end,
-                val: IdentType::ServiceMethod(method_id),
+
+    /// Copy the per-declaration results whose span satisfies `keep` into `ctx`, moved by `delta`.
+    fn copy_into(&self, ctx: &mut Ctx, keep: impl Fn(&Span) -> bool, delta: isize) {
+        let shift = |span: &Span| shifted(span, delta);
+        let shift_opt = |span: &Option<Span>| span.as_ref().map(shift);
+        for reference in self.table.reference_id_to_reference.iter() {
+            if keep(&reference.span) {
+                ctx.table
+                    .add_reference(shift(&reference.span), reference.symbol_id);
+            }
+        }
+        for field in self.fields.iter().filter(|field| keep(&field.span)) {
+            ctx.fields.push(FieldMetadata {
+                span: shift(&field.span),
+                label_span: shift_opt(&field.label_span),
+                type_span: shift_opt(&field.type_span),
+                ..field.clone()
             });
         }
-    }
-    for (param_id, metadata) in ctx.params.iter_enumerated() {
-        if let Some(name_span) = &metadata.name_span {
-            ident_range.insert(Interval {
-                start: name_span.start,
-                stop: name_span.end,
-                val: IdentType::FuncParam(param_id),
+        for method in self
+            .service_methods
+            .iter()
+            .filter(|method| keep(&method.span))
+        {
+            ctx.service_methods.push(MethodMetadata {
+                span: shift(&method.span),
+                name_span: shift_opt(&method.name_span),
+                type_span: shift_opt(&method.type_span),
+                ..method.clone()
             });
         }
-    }
-    for (span, kind) in ctx.primitive_spans.iter() {
-        if span.start < span.end {
-            ident_range.insert(Interval {
-                start: span.start,
-                stop: span.end,
-                val: IdentType::Primitive(kind.clone()),
+        for param in self.params.iter().filter(|param| keep(&param.span)) {
+            ctx.params.push(ParamMetadata {
+                span: shift(&param.span),
+                name_span: shift_opt(&param.name_span),
+                type_span: shift(&param.type_span),
+                role: param.role,
+            });
+        }
+        for local in self.locals.iter().filter(|local| keep(&local.span)) {
+            ctx.locals.push(LocalBinding {
+                name: local.name.clone(),
+                span: shift(&local.span),
+                scope: shift(&local.scope),
+                is_definition: local.is_definition,
             });
         }
+        for (span, kind) in self.primitive_spans.iter().filter(|(span, _)| keep(span)) {
+            ctx.primitive_spans.push((shift(span), kind.clone()));
+        }
+        for (span, keyword) in self.keyword_spans.iter().filter(|(span, _)| keep(span)) {
+            ctx.keyword_spans.push((shift(span), *keyword));
+        }
     }
-    for (span, keyword) in ctx.keyword_spans.iter() {
-        if span.start < span.end {
-            ident_range.insert(Interval {
+}
+
+impl Ctx<'_> {
+    /// Index every recorded identifier by its span.
+    fn finish(self) -> Semantic {
+        let mut intervals = Vec::new();
+        let mut push = |span: &Span, val: IdentType| {
+            intervals.push(Interval {
                 start: span.start,
                 stop: span.end,
-                val: IdentType::Keyword(*keyword),
+                val,
             });
+        };
+        for (symbol_id, range) in self.table.symbol_id_to_span.iter_enumerated() {
+            let span = self
+                .symbol_ident_spans
+                .get(symbol_id)
+                .and_then(|opt| opt.as_ref())
+                .unwrap_or(range);
+            push(span, IdentType::Binding(symbol_id));
+        }
+        for (reference_id, reference) in self.table.reference_id_to_reference.iter_enumerated() {
+            push(&reference.span, IdentType::Reference(reference_id));
+        }
+        for (field_id, metadata) in self.fields.iter_enumerated() {
+            if let Some(label_span) = &metadata.label_span {
+                push(label_span, IdentType::Field(field_id, FieldPart::Label));
+            }
+            if let Some(type_span) = &metadata.type_span
+                && type_span.start < type_span.end
+                && metadata.label_span.as_ref() != Some(type_span)
+            {
+                push(type_span, IdentType::Field(field_id, FieldPart::Type));
+            }
+        }
+        for (method_id, metadata) in self.service_methods.iter_enumerated() {
+            if let Some(name_span) = &metadata.name_span {
+                push(name_span, IdentType::ServiceMethod(method_id));
+            }
+        }
+        for (param_id, metadata) in self.params.iter_enumerated() {
+            if let Some(name_span) = &metadata.name_span {
+                push(name_span, IdentType::FuncParam(param_id));
+            }
+        }
+        for (span, kind) in self.primitive_spans.iter() {
+            if span.start < span.end {
+                push(span, IdentType::Primitive(kind.clone()));
+            }
+        }
+        for (span, keyword) in self.keyword_spans.iter() {
+            if span.start < span.end {
+                push(span, IdentType::Keyword(*keyword));
+            }
+        }
+        if let Some(actor) = &self.actor
+            && let Some(name_span) = &actor.name_span
+            && name_span.start < name_span.end
+        {
+            push(name_span, IdentType::Actor);
+        }
+        Semantic {
+            table: self.table,
+            ident_range: IdentRangeLapper::new(intervals),
+            fields: self.fields,
+            service_methods: self.service_methods,
+            params: self.params,
+            locals: self.locals,
+            symbol_ident_spans: self.symbol_ident_spans,
+            symbol_ident_names: self.symbol_ident_names,
+            type_docs: self.type_docs,
+            primitive_spans: self.primitive_spans,
+            keyword_spans: self.keyword_spans,
+            actor: self.actor,
         }
     }
-    if let Some(actor) = &ctx.actor
-        && let Some(name_span) = &actor.name_span
-        && name_span.start < name_span.end
-    {
-        ident_range.insert(Interval {
-            start: name_span.start,
-            stop: name_span.end,
-            val: IdentType::Actor,
-        });
-    }
-    Ok(Semantic {
-        table: ctx.table,
-        ident_range,
-        fields: ctx.fields,
-        service_methods: ctx.service_methods,
-        params: ctx.params,
-        locals: ctx.locals,
-        symbol_ident_spans: ctx.symbol_ident_spans,
-        symbol_ident_names: ctx.symbol_ident_names,
-        type_docs: ctx.type_docs,
-        primitive_spans: ctx.primitive_spans,
-        keyword_spans: ctx.keyword_spans,
-        actor: ctx.actor,
-    })
+}
+
+fn dec_span(dec: &Dec) -> &Span {
+    match dec {
+        Dec::TypD(binding) => &binding.span,
+        Dec::ImportType { span, .. } | Dec::ImportServ { span, .. } => span,
+    }
+}
+
+fn shifted(span: &Span, delta: isize) -> Span {
+    span.start.saturating_add_signed(delta)..span.end.saturating_add_signed(delta)
 }
 
 fn analyze_dec(dec: &Dec, ctx: &mut Ctx) -> Result<()> {
@@ -673,13 +848,13 @@ fn analyze_type(idl_type: &IDLTypeWithSpan, ctx: &mut Ctx) -> Result<()> {
         IDLType::RecordT(type_fields) => {
             ctx.register_keyword(idl_type.span.clone(), KeywordDoc::Record);
             ctx.push_scope(idl_type.span.clone());
-            analyze_type_fields(type_fields, ctx)?;
+            analyze_type_fields(type_fields, FieldKind::Record, ctx)?;
             ctx.pop_scope();
         }
         IDLType::VariantT(type_fields) => {
             ctx.register_keyword(idl_type.span.clone(), KeywordDoc::Variant);
             ctx.push_scope(idl_type.span.clone());
-            analyze_type_fields(type_fields, ctx)?;
+            analyze_type_fields(type_fields, FieldKind::Variant, ctx)?;
             ctx.pop_scope();
         }
         IDLType::ServT(bindings) => {
@@ -700,9 +875,9 @@ fn analyze_type(idl_type: &IDLTypeWithSpan, ctx: &mut Ctx) -> Result<()> {
     Ok(())
 }
 
-fn analyze_type_fields(fields: &[TypeField], ctx: &mut Ctx) -> Result<()> {
+fn analyze_type_fields(fields: &[TypeField], kind: FieldKind, ctx: &mut Ctx) -> Result<()> {
     for field in fields.iter() {
-        ctx.register_field(field);
+        ctx.register_field(field, kind);
         analyze_type(&field.typ, ctx)?;
     }
     Ok(())
@@ -717,11 +892,20 @@ fn analyze_actor(actor: &IDLActorType, ctx: &mut Ctx) -> Result<()> {
         .map(|span| ctx.rope.slice(span.clone()).to_string());
     let definition = render_actor_declaration(name_text.as_deref(), &actor.typ)
         .map(|text| Arc::<str>::from(text.into_boxed_str()));
+    let service_type = match &actor.typ.kind {
+        IDLType::ClassT(_, ret) => &ret.kind,
+        other => other,
+    };
+    let service_type = match service_type {
+        IDLType::VarT(name) => Some(Arc::<str>::from(name.as_str())),
+        _ => None,
+    };
     ctx.actor = Some(ActorMetadata {
         span: actor.span.clone(),
         name_span,
         docs,
         definition,
+        service_type,
     });
     ctx.push_type_name(name_text);
     let result = analyze_type(&actor.typ, ctx);
@@ -961,4 +1145,14 @@ impl MethodSignature {
             modes: func.modes.clone(),
         }
     }
+
+    /// The method's mode keyword, `update` when none is written.
+    pub fn mode_name(&self) -> &'static str {
+        match self.modes.first() {
+            Some(FuncMode::Query) => "query",
+            Some(FuncMode::CompositeQuery) => "composite_query",
+            Some(FuncMode::Oneway) => "oneway",
+            None => "update",
+        }
+    }
 }
@@ -1,5 +1,14 @@
-use candid_parser::token::Token;
-use tower_lsp_server::ls_types::SemanticTokenType;
+use crate::{
+    candid_lang::ImCompleteSemanticToken,
+    lsp::{
+        semantic_analyze::{FieldPart, IdentType, Semantic},
+        symbol_table::SymbolId,
+    },
+};
+use candid_parser::{candid::types::internal::FuncMode, token::Token};
+use tower_lsp_server::ls_types::{
+    SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensEdit,
+};
 
 // Additional token types used to enrich the semantic-token legend beyond the built-ins.
 const COMMENT_DELIMITER: SemanticTokenType = SemanticTokenType::new("commentDelimiter");
@@ -56,8 +65,24 @@ define_legend!(
     PunctuationBracket => PUNCTUATION_BRACKET,
     PunctuationDelimiter => PUNCTUATION_DELIMITER,
     Identifier => IDENTIFIER,
+    Property => SemanticTokenType::PROPERTY,
+    Method => SemanticTokenType::METHOD,
+    Parameter => SemanticTokenType::PARAMETER,
 );
 
+/// Ordered modifier legend; bit `n` of a token's modifier set refers to entry `n`.
+pub const LEGEND_MODIFIERS: &[SemanticTokenModifier; 4] = &[
+    SemanticTokenModifier::DECLARATION,
+    SemanticTokenModifier::DOCUMENTATION,
+    SemanticTokenModifier::DEPRECATED,
+    SemanticTokenModifier::READONLY,
+];
+
+pub const MODIFIER_DECLARATION: u32 = 1 << 0;
+pub const MODIFIER_DOCUMENTATION: u32 = 1 << 1;
+pub const MODIFIER_DEPRECATED: u32 = 1 << 2;
+pub const MODIFIER_READONLY: u32 = 1 << 3;
+
 /// Translate a lexed `Token` into the semantic-token index expected by the LSP legend.
 #[inline]
 pub fn semantic_token_type_index(token: &Token) -> usize {
@@ -109,3 +134,180 @@ pub fn semantic_token_type_index(token: &Token) -> usize {
         Token::Id(_) => idx(LegendIdx::Identifier),
     }
 }
+
+/// Modifiers implied by the lexeme alone, such as `documentation` on `///` comments.
+#[inline]
+pub fn lexical_token_modifiers(token: &Token, text: &str) -> u32 {
+    match token {
+        Token::LineComment if text.starts_with("///") => MODIFIER_DOCUMENTATION,
+        _ => 0,
+    }
+}
+
+/// Refine lexical tokens with what semantic analysis knows about each identifier.
+///
+/// Identifiers and quoted labels are matched against `Semantic::ident_range`; the narrowest
+/// enclosing interval decides the token type and modifiers.
+pub fn apply_semantic_tokens(tokens: &mut [ImCompleteSemanticToken], semantic: &Semantic) {
+    let identifier = idx(LegendIdx::Identifier);
+    let string = idx(LegendIdx::String);
+    for token in tokens.iter_mut() {
+        if token.token_type != identifier && token.token_type != string {
+            continue;
+        }
+        let start = token.start;
+        let end = start + token.length;
+        let ident = semantic
+            .ident_range
+            .find(start, end)
+            .filter(|interval| interval.start <= start && end <= interval.stop)
+            .min_by_key(|interval| interval.stop - interval.start);
+        let Some(ident) = ident else {
+            continue;
+        };
+        if let Some((token_type, modifiers)) = classify_ident(&ident.val, semantic) {
+            let labels_quoted = [idx(LegendIdx::Property), idx(LegendIdx::Method)];
+            if token.token_type == string && !labels_quoted.contains(&token_type) {
+                continue;
+            }
+            token.token_type = token_type;
+            token.token_modifiers |= modifiers;
+        }
+    }
+}
+
+/// Role of an identifier in a `.test.did` assertion, which the lexer alone cannot tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssertionToken {
+    Keyword,
+    Type,
+    Label,
+}
+
+/// Retype identifier tokens of `.test.did` assertions from roles keyed by start offset.
+pub fn apply_assertion_tokens(
+    tokens: &mut [ImCompleteSemanticToken],
+    roles: &[(usize, AssertionToken)],
+) {
+    let identifier = idx(LegendIdx::Identifier);
+    for token in tokens
+        .iter_mut()
+        .filter(|token| token.token_type == identifier)
+    {
+        let Some((_, role)) = roles.iter().find(|(start, _)| *start == token.start) else {
+            continue;
+        };
+        token.token_type = match role {
+            AssertionToken::Keyword => idx(LegendIdx::Keyword),
+            AssertionToken::Type => idx(LegendIdx::Type),
+            AssertionToken::Label => idx(LegendIdx::Property),
+        };
+    }
+}
+
+fn classify_ident(ident: &IdentType, semantic: &Semantic) -> Option<(usize, u32)> {
+    match ident {
+        IdentType::Binding(symbol_id) => {
+            if semantic
+                .table
+                .imports
+                .iter()
+                .any(|import| import.symbol_id == *symbol_id)
+            {
+                return None;
+            }
+            let mut modifiers = MODIFIER_DECLARATION;
+            if symbol_is_deprecated(*symbol_id, semantic) {
+                modifiers |= MODIFIER_DEPRECATED;
+            }
+            Some((idx(LegendIdx::Type), modifiers))
+        }
+        IdentType::Reference(reference_id) => {
+            let reference = semantic
+                .table
+                .reference_id_to_reference
+                .get(*reference_id)?;
+            let deprecated = reference
+                .symbol_id
+                .is_some_and(|symbol_id| symbol_is_deprecated(symbol_id, semantic));
+            let modifiers = if deprecated { MODIFIER_DEPRECATED } else { 0 };
+            Some((idx(LegendIdx::Type), modifiers))
+        }
+        IdentType::Field(field_id, FieldPart::Label) => {
+            let field = semantic.fields.get(*field_id)?;
+            let mut modifiers = MODIFIER_DECLARATION;
+            if is_deprecated_doc(field.docs.as_deref()) {
+                modifiers |= MODIFIER_DEPRECATED;
+            }
+            Some((idx(LegendIdx::Property), modifiers))
+        }
+        IdentType::ServiceMethod(method_id) => {
+            let method = semantic.service_methods.get(*method_id)?;
+            let mut modifiers = MODIFIER_DECLARATION;
+            if is_deprecated_doc(method.docs.as_deref()) {
+                modifiers |= MODIFIER_DEPRECATED;
+            }
+            let is_query = method.signature.as_ref().is_some_and(|signature| {
+                signature
+                    .modes
+                    .iter()
+                    .any(|mode| matches!(mode, FuncMode::Query | FuncMode::CompositeQuery))
+            });
+            if is_query {
+                modifiers |= MODIFIER_READONLY;
+            }
+            Some((idx(LegendIdx::Method), modifiers))
+        }
+        IdentType::FuncParam(_) => Some((idx(LegendIdx::Parameter), MODIFIER_DECLARATION)),
+        IdentType::Primitive(_) => Some((idx(LegendIdx::Type), 0)),
+        IdentType::Field(_, FieldPart::Type) | IdentType::Keyword(_) | IdentType::Actor => None,
+    }
+}
+
+fn symbol_is_deprecated(symbol_id: SymbolId, semantic: &Semantic) -> bool {
+    semantic
+        .type_docs
+        .get(symbol_id)
+        .and_then(|doc| doc.as_ref())
+        .is_some_and(|doc| is_deprecated_doc(doc.docs.as_deref()))
+}
+
+/// Doc comments mark an item deprecated with a line starting with `@deprecated`.
+fn is_deprecated_doc(docs: Option<&str>) -> bool {
+    docs.is_some_and(|docs| {
+        docs.lines()
+            .any(|line| line.trim_start().starts_with("@deprecated"))
+    })
+}
+
+/// Compute the edits turning the `previous` encoded token array into `current`.
+///
+/// Unchanged prefixes and suffixes are kept, so a typical keystroke yields a single small edit.
+/// Offsets count `u32` slots, five per token, as required by `semanticTokens/full/delta`.
+pub fn semantic_tokens_edits(
+    previous: &[SemanticToken],
+    current: &[SemanticToken],
+) -> Vec<SemanticTokensEdit> {
+    let prefix = previous
+        .iter()
+        .zip(current)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = previous[prefix..]
+        .iter()
+        .rev()
+        .zip(current[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let deleted = previous.len() - prefix - suffix;
+    let inserted = &current[prefix..current.len() - suffix];
+    if deleted == 0 && inserted.is_empty() {
+        return Vec::new();
+    }
+    let slots = |count: usize| u32::try_from(count * 5).unwrap_or(u32::MAX);
+    vec![SemanticTokensEdit {
+        start: slots(prefix),
+        delete_count: slots(deleted),
+        data: (!inserted.is_empty()).then(|| inserted.to_vec()),
+    }]
+}
@@ -0,0 +1,260 @@
+use crate::{
+    candid_lang::ImCompleteSemanticToken,
+    lsp::{
+        code_action::quick_fix,
+        diagnostic_rules::{DiagnosticRule, DiagnosticRules, RuleLevel},
+        position::{offset_to_position, span_to_range},
+        semantic_token::semantic_token_type_index,
+    },
+};
+use candid_parser::token::Token;
+use ropey::Rope;
+use tower_lsp_server::ls_types::{
+    CodeActionOrCommand, Diagnostic, DiagnosticSeverity, DiagnosticTag, Position, Range, TextEdit,
+    Uri,
+};
+
+/// Comment directive silencing diagnostics on the following line.
+pub const IGNORE_DIRECTIVE: &str = "candid-ls-ignore";
+/// Comment directive silencing diagnostics in the whole file; only valid before any declaration.
+pub const IGNORE_FILE_DIRECTIVE: &str = "candid-ls-ignore-file";
+
+/// What a suppression comment applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionScope {
+    /// Diagnostics starting on this (zero-based) line.
+    Line(u32),
+    File,
+}
+
+/// One rule silenced by a suppression comment; `rule` is `None` for a bare directive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suppression {
+    pub scope: SuppressionScope,
+    pub rule: Option<DiagnosticRule>,
+    /// Range of the comment holding the directive.
+    pub range: Range,
+}
+
+impl Suppression {
+    fn matches(&self, rule: Option<DiagnosticRule>, line: u32) -> bool {
+        let scope_matches = match self.scope {
+            SuppressionScope::Line(target) => target == line,
+            SuppressionScope::File => true,
+        };
+        scope_matches && (self.rule.is_none() || self.rule == rule)
+    }
+}
+
+/// Suppression comments found in a document, plus directives that could not be used.
+#[derive(Debug, Clone, Default)]
+pub struct Suppressions {
+    entries: Vec<Suppression>,
+    problems: Vec<(Range, String)>,
+}
+
+impl Suppressions {
+    /// Collect `candid-ls-ignore` directives from the line comments among `tokens`.
+    ///
+    /// A directive sharing its line with code applies to that line; otherwise it applies to the
+    /// line of the next token that is not a comment, so several directives can be stacked.
+    pub fn collect(text: &str, tokens: &[ImCompleteSemanticToken], rope: &Rope) -> Self {
+        let line_comment = semantic_token_type_index(&Token::LineComment);
+        let block_comment = semantic_token_type_index(&Token::StartComment);
+        let mut suppressions = Suppressions::default();
+        let mut pending = Vec::new();
+        let mut last_code_line = None;
+
+        for token in tokens {
+            let Some(start) = offset_to_position(token.start, rope) else {
+                continue;
+            };
+            if token.token_type == block_comment {
+                continue;
+            }
+            if token.token_type != line_comment {
+                for idx in pending.drain(..) {
+                    suppressions.entries[idx].scope = SuppressionScope::Line(start.line);
+                }
+                last_code_line = Some(start.line);
+                continue;
+            }
+
+            let span = token.start..token.start + token.length;
+            let Some(comment) = text.get(span.clone()) else {
+                continue;
+            };
+            let Some((is_file, rules)) = parse_directive(comment) else {
+                continue;
+            };
+            let range = span_to_range(&span, rope).unwrap_or_default();
+            let scope = if is_file {
+                if last_code_line.is_some() {
+                    suppressions.problems.push((
+                        range,
+                        format!("`{IGNORE_FILE_DIRECTIVE}` must come before the first declaration"),
+                    ));
+                    continue;
+                }
+                SuppressionScope::File
+            } else {
+                SuppressionScope::Line(start.line)
+            };
+            let trailing = !is_file && last_code_line == Some(start.line);
+
+            let mut resolved = Vec::new();
+            for name in &rules {
+                match DiagnosticRule::from_code(name) {
+                    Some(rule) => resolved.push(Some(rule)),
+                    None => suppressions
+                        .problems
+                        .push((range, format!("unknown rule `{name}` in suppression"))),
+                }
+            }
+            if rules.is_empty() {
+                resolved.push(None);
+            }
+            for rule in resolved {
+                if !is_file && !trailing {
+                    pending.push(suppressions.entries.len());
+                }
+                suppressions
+                    .entries
+                    .push(Suppression { scope, rule, range });
+            }
+        }
+        // Directives after the last declaration have nothing left to apply to.
+        for idx in pending {
+            suppressions.entries[idx].scope = SuppressionScope::Line(u32::MAX);
+        }
+        suppressions
+    }
+
+    pub fn entries(&self) -> &[Suppression] {
+        &self.entries
+    }
+
+    /// Drop suppressed diagnostics and report unused or invalid directives as hints.
+    ///
+    /// A directive naming a rule that `rules` turns off cannot be used, so it is not reported.
+    pub fn apply(&self, diagnostics: Vec<Diagnostic>, rules: &DiagnosticRules) -> Vec<Diagnostic> {
+        let mut used = vec![false; self.entries.len()];
+        let mut kept = Vec::with_capacity(diagnostics.len());
+        for diagnostic in diagnostics {
+            let rule = DiagnosticRule::of(&diagnostic);
+            let mut suppressed = false;
+            for (idx, entry) in self.entries.iter().enumerate() {
+                if entry.matches(rule, diagnostic.range.start.line) {
+                    used[idx] = true;
+                    suppressed = true;
+                }
+            }
+            if !suppressed {
+                kept.push(diagnostic);
+            }
+        }
+
+        for (entry, used) in self.entries.iter().zip(used) {
+            if used
+                || entry
+                    .rule
+                    .is_some_and(|rule| rules.level(rule) == RuleLevel::Off)
+            {
+                continue;
+            }
+            let message = match entry.rule {
+                Some(rule) => format!("unused suppression for `{}`", rule.name()),
+                None => "unused suppression".to_string(),
+            };
+            kept.push(suppression_hint(entry.range, message));
+        }
+        for (range, message) in &self.problems {
+            kept.push(suppression_hint(*range, message.clone()));
+        }
+        kept
+    }
+}
+
+/// Parse the body of a line comment; returns whether it is file-wide and the listed rule names.
+///
+/// Anything after `--` is treated as a free-form reason.
+fn parse_directive(comment: &str) -> Option<(bool, Vec<String>)> {
+    if comment.starts_with("///") {
+        return None;
+    }
+    let body = comment.strip_prefix("//")?.trim();
+    let (is_file, rest) = if let Some(rest) = body.strip_prefix(IGNORE_FILE_DIRECTIVE) {
+        (true, rest)
+    } else {
+        (false, body.strip_prefix(IGNORE_DIRECTIVE)?)
+    };
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let rules = rest
+        .split("--")
+        .next()
+        .unwrap_or_default()
+        .split(|ch: char| ch.is_whitespace() || ch == ',')
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect();
+    Some((is_file, rules))
+}
+
+fn suppression_hint(range: Range, message: String) -> Diagnostic {
+    DiagnosticRule::UnusedSuppression.annotate(Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::HINT),
+        source: Some("suppression".to_string()),
+        message,
+        tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+        ..Default::default()
+    })
+}
+
+/// Quick fixes inserting a line or file suppression for `diagnostic`.
+pub fn suppression_actions(
+    uri: &Uri,
+    diagnostic: &Diagnostic,
+    rope: &Rope,
+) -> Vec<CodeActionOrCommand> {
+    let Some(rule) = DiagnosticRule::of(diagnostic) else {
+        return Vec::new();
+    };
+    if rule == DiagnosticRule::UnusedSuppression {
+        return Vec::new();
+    }
+    let line = diagnostic.range.start.line;
+    let indent: String = rope
+        .get_line(line as usize)
+        .map(|text| {
+            text.chars()
+                .take_while(|ch| *ch == ' ' || *ch == '\t')
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let line_edit = TextEdit {
+        range: Range::new(Position::new(line, 0), Position::new(line, 0)),
+        new_text: format!("{indent}// {IGNORE_DIRECTIVE} {}\n", rule.name()),
+    };
+    let file_edit = TextEdit {
+        range: Range::default(),
+        new_text: format!("// {IGNORE_FILE_DIRECTIVE} {}\n", rule.name()),
+    };
+    vec![
+        quick_fix(
+            format!("Suppress `{}` on this line", rule.name()),
+            uri,
+            diagnostic,
+            line_edit,
+        ),
+        quick_fix(
+            format!("Suppress `{}` in this file", rule.name()),
+            uri,
+            diagnostic,
+            file_edit,
+        ),
+    ]
+}
@@ -0,0 +1,561 @@
+use crate::{
+    candid_lang::{ImCompleteSemanticToken, LexResult, ParserResult, lex, parse},
+    lsp::{
+        diagnostic_rules::DiagnosticRule,
+        markdown::MarkdownWriter,
+        normal_value::{NormalValue, decode_args, normalize_args, render_args},
+        position::span_to_range,
+        semantic_token::{AssertionToken, apply_assertion_tokens},
+        span::Span,
+        type_display::render_type_summary,
+        value::{ValueError, parse_value_args},
+        value_check::{TypeEnv, check_args, undefined_type},
+    },
+};
+use candid_parser::{
+    syntax::{Dec, IDLType, IDLTypeWithSpan},
+    token::Token,
+};
+use ropey::Rope;
+use tower_lsp_server::ls_types::{Diagnostic, DiagnosticSeverity};
+
+/// Identifier that starts an assertion in a `.test.did` file.
+pub const ASSERT_KEYWORD: &str = "assert";
+
+/// The operator of an assertion, deciding what counts as passing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssertionKind {
+    /// `:` — the input decodes at the types.
+    Decodes,
+    /// `!:` — the input does not decode at the types.
+    Rejects,
+    /// `==` — both inputs decode to the same value.
+    Equal,
+    /// `!=` — both inputs decode, to different values.
+    NotEqual,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestInput {
+    /// A text-format argument list such as `"(42)"`.
+    Text(String),
+    /// A binary message written as `blob "DIDL\00\00"`.
+    Blob(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputLiteral {
+    pub input: TestInput,
+    pub span: Span,
+}
+
+/// `assert <input> [== | != <input>] : (<types>) ["description"];`
+#[derive(Debug, Clone)]
+pub struct Assertion {
+    /// From `assert` to the end of the description, or of the types without one.
+    pub span: Span,
+    pub kind: AssertionKind,
+    pub left: InputLiteral,
+    pub right: Option<InputLiteral>,
+    pub types: Vec<IDLTypeWithSpan>,
+    pub types_span: Span,
+    pub description: Option<String>,
+}
+
+/// The assertions of a `.test.did` file, which follow its type definitions.
+#[derive(Debug, Default)]
+pub struct TestSuite {
+    /// Offset where the first assertion starts; the text before it is an ordinary program.
+    pub defs_end: usize,
+    pub assertions: Vec<Assertion>,
+    pub errors: Vec<ValueError>,
+    /// Identifiers whose role the lexer cannot tell, keyed by start offset.
+    pub token_roles: Vec<(usize, AssertionToken)>,
+}
+
+impl TestSuite {
+    /// The assertion whose span contains `offset`.
+    pub fn assertion_at(&self, offset: usize) -> Option<&Assertion> {
+        self.assertions
+            .iter()
+            .find(|assertion| assertion.span.start <= offset && offset <= assertion.span.end)
+    }
+}
+
+/// Split a `.test.did` file into its definitions and assertions, and parse the assertions.
+pub fn parse_test_suite(src: &str) -> TestSuite {
+    let LexResult {
+        tokens,
+        lexer_errors,
+        ..
+    } = lex(src);
+    let first = first_assertion(&tokens);
+    let defs_end = tokens.get(first).map_or(src.len(), |(start, _, _)| *start);
+    let mut parser = AssertionParser {
+        src,
+        tokens,
+        pos: first,
+        suite: TestSuite {
+            defs_end,
+            errors: lexer_errors
+                .into_iter()
+                .filter(|err| err.span.start >= defs_end)
+                .map(|err| ValueError {
+                    span: err.span.clone(),
+                    message: err.err.to_string(),
+                })
+                .collect(),
+            ..TestSuite::default()
+        },
+    };
+    parser.parse_assertions();
+    parser.suite
+}
+
+/// Index of the first top-level `assert` that starts a statement.
+fn first_assertion(tokens: &[(usize, Token, usize)]) -> usize {
+    let mut depth = 0usize;
+    let mut at_statement_start = true;
+    for (idx, (_, token, _)) in tokens.iter().enumerate() {
+        match token {
+            Token::Id(name) if at_statement_start && name == ASSERT_KEYWORD => return idx,
+            Token::LParen | Token::LBrace => depth += 1,
+            Token::RParen | Token::RBrace => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        at_statement_start = depth == 0 && *token == Token::Semi;
+    }
+    tokens.len()
+}
+
+struct AssertionParser<'src> {
+    src: &'src str,
+    tokens: Vec<(usize, Token, usize)>,
+    pos: usize,
+    suite: TestSuite,
+}
+
+impl AssertionParser<'_> {
+    fn peek(&self) -> Option<&(usize, Token, usize)> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_token(&self) -> Option<&Token> {
+        self.peek().map(|(_, token, _)| token)
+    }
+
+    fn last_end(&self) -> usize {
+        self.pos
+            .checked_sub(1)
+            .and_then(|idx| self.tokens.get(idx))
+            .map_or(self.suite.defs_end, |(_, _, end)| *end)
+    }
+
+    fn error(&mut self, span: Span, message: String) {
+        self.suite.errors.push(ValueError { span, message });
+    }
+
+    fn error_here(&mut self, expected: &str) {
+        let (span, found) = match self.peek() {
+            Some((start, token, end)) => (*start..*end, describe(token)),
+            None => (self.src.len()..self.src.len(), "end of input".to_string()),
+        };
+        self.error(span, format!("unexpected {found}, expected {expected}"));
+    }
+
+    /// Skip to the next `;` outside any delimiters, without consuming it.
+    fn recover(&mut self) {
+        let mut depth = 0usize;
+        while let Some(token) = self.peek_token() {
+            match token {
+                Token::Semi if depth == 0 => return,
+                Token::LParen | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBrace => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn parse_assertions(&mut self) {
+        while self.peek().is_some() {
+            let parsed = self.assertion();
+            let ok = parsed.is_some();
+            match parsed {
+                Some(assertion) => self.suite.assertions.push(assertion),
+                None => self.recover(),
+            }
+            if self.peek_token() == Some(&Token::Semi) || self.peek().is_none() {
+                self.pos += 1;
+                continue;
+            }
+            if ok {
+                self.error_here("`;` after the assertion");
+                self.recover();
+                self.pos += 1;
+            }
+        }
+    }
+
+    fn assertion(&mut self) -> Option<Assertion> {
+        let (start, token, _) = self.peek().cloned()?;
+        if !matches!(&token, Token::Id(name) if name == ASSERT_KEYWORD) {
+            self.error_here("`assert`");
+            return None;
+        }
+        self.suite
+            .token_roles
+            .push((start, AssertionToken::Keyword));
+        self.pos += 1;
+
+        let left = self.input()?;
+        let (kind, right) = match self.peek_token() {
+            Some(Token::Colon) => {
+                self.pos += 1;
+                (AssertionKind::Decodes, None)
+            }
+            Some(Token::NotDecode) => {
+                self.pos += 1;
+                (AssertionKind::Rejects, None)
+            }
+            Some(Token::TestEqual | Token::NotEqual) => {
+                let kind = if self.peek_token() == Some(&Token::TestEqual) {
+                    AssertionKind::Equal
+                } else {
+                    AssertionKind::NotEqual
+                };
+                self.pos += 1;
+                let right = self.input()?;
+                if self.peek_token() != Some(&Token::Colon) {
+                    self.error_here("`:` before the argument types");
+                    return None;
+                }
+                self.pos += 1;
+                (kind, Some(right))
+            }
+            _ => {
+                self.error_here("`:`, `!:`, `==` or `!=`");
+                return None;
+            }
+        };
+        let (types_span, types) = self.types()?;
+        let description = match self.peek_token() {
+            Some(Token::Text(text)) => {
+                let text = text.clone();
+                self.pos += 1;
+                Some(text)
+            }
+            _ => None,
+        };
+        Some(Assertion {
+            span: start..self.last_end(),
+            kind,
+            left,
+            right,
+            types,
+            types_span,
+            description,
+        })
+    }
+
+    fn input(&mut self) -> Option<InputLiteral> {
+        let (start, token, end) = self.peek().cloned()?;
+        match token {
+            Token::Text(text) => {
+                self.pos += 1;
+                Some(InputLiteral {
+                    input: TestInput::Text(text),
+                    span: start..end,
+                })
+            }
+            Token::Blob => {
+                self.pos += 1;
+                match self.peek().cloned() {
+                    Some((_, Token::Text(text), end)) => {
+                        self.pos += 1;
+                        Some(InputLiteral {
+                            input: TestInput::Blob(text.into_bytes()),
+                            span: start..end,
+                        })
+                    }
+                    _ => {
+                        self.error_here("a text literal after `blob`");
+                        None
+                    }
+                }
+            }
+            _ => {
+                self.error_here("a `blob \"…\"` or text input");
+                None
+            }
+        }
+    }
+
+    /// The parenthesised argument types, parsed as the parameters of a function type.
+    fn types(&mut self) -> Option<(Span, Vec<IDLTypeWithSpan>)> {
+        let Some((open, Token::LParen, _)) = self.peek().cloned() else {
+            self.error_here("`(` starting the argument types");
+            return None;
+        };
+        let mut depth = 0usize;
+        let mut close = None;
+        for idx in self.pos..self.tokens.len() {
+            match &self.tokens[idx].1 {
+                Token::LParen | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = Some(idx);
+                        break;
+                    }
+                }
+                Token::Id(_) => {
+                    let role = match self.tokens.get(idx + 1) {
+                        Some((_, Token::Colon, _)) => AssertionToken::Label,
+                        _ => AssertionToken::Type,
+                    };
+                    self.suite.token_roles.push((self.tokens[idx].0, role));
+                }
+                _ => {}
+            }
+        }
+        let Some(close) = close else {
+            self.error(open..open + 1, "unclosed `(`: expected `)`".to_string());
+            self.pos = self.tokens.len();
+            return None;
+        };
+        let span = open..self.tokens[close].2;
+        self.pos = close + 1;
+
+        let text = self.src.get(span.clone()).unwrap_or_default();
+        let ParserResult {
+            ast, parse_errors, ..
+        } = parse(&format!("type assertion_types = func {text} -> ();"));
+        let args = ast.filter(|_| parse_errors.is_empty()).and_then(|ast| {
+            ast.decs().iter().find_map(|dec| match dec {
+                Dec::TypD(binding) => match &binding.typ.kind {
+                    IDLType::FuncT(func) => Some(func.args.clone()),
+                    _ => None,
+                },
+                _ => None,
+            })
+        });
+        match args {
+            Some(args) => Some((span, args)),
+            None => {
+                self.error(span, "invalid argument types".to_string());
+                None
+            }
+        }
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Id(name) => format!("identifier `{name}`"),
+        Token::Text(_) => "text literal".to_string(),
+        Token::Semi => "`;`".to_string(),
+        Token::Colon => "`:`".to_string(),
+        Token::LParen => "`(`".to_string(),
+        Token::RParen => "`)`".to_string(),
+        Token::TestEqual => "`==`".to_string(),
+        Token::NotEqual => "`!=`".to_string(),
+        Token::NotDecode => "`!:`".to_string(),
+        _ => "token".to_string(),
+    }
+}
+
+/// Result of running one assertion.
+#[derive(Debug, Clone)]
+pub struct AssertionOutcome {
+    pub passed: bool,
+    /// Why the assertion failed; empty when it passed.
+    pub message: String,
+    /// Where a failure is reported.
+    pub span: Span,
+    pub rule: DiagnosticRule,
+    pub left: Result<Vec<NormalValue>, String>,
+    pub right: Option<Result<Vec<NormalValue>, String>>,
+}
+
+/// Decode or parse the inputs of `assertion` at its types and compare them.
+pub fn run_assertion(assertion: &Assertion, env: &TypeEnv) -> AssertionOutcome {
+    let types = render_types(&assertion.types);
+    if let Some(name) = assertion
+        .types
+        .iter()
+        .find_map(|ty| undefined_type(&ty.kind, env))
+    {
+        let message = format!("type `{name}` is not defined");
+        return AssertionOutcome {
+            passed: false,
+            span: assertion.types_span.clone(),
+            rule: DiagnosticRule::UndefinedType,
+            left: Err(message.clone()),
+            right: None,
+            message,
+        };
+    }
+
+    let left = evaluate(&assertion.left.input, &assertion.types, env);
+    let right = assertion
+        .right
+        .as_ref()
+        .map(|right| evaluate(&right.input, &assertion.types, env));
+    let what = match assertion.left.input {
+        TestInput::Text(_) => "text",
+        TestInput::Blob(_) => "blob",
+    };
+    let (passed, message, span) = match (assertion.kind, &left, &right) {
+        (AssertionKind::Decodes, Ok(_), _) | (AssertionKind::Rejects, Err(_), _) => {
+            (true, String::new(), assertion.left.span.clone())
+        }
+        (AssertionKind::Decodes, Err(err), _) => (
+            false,
+            format!("{what} does not decode as `{types}`: {err}"),
+            assertion.left.span.clone(),
+        ),
+        (AssertionKind::Rejects, Ok(values), _) => (
+            false,
+            format!(
+                "{what} should not decode as `{types}`, but decodes to `{}`",
+                render_args(values)
+            ),
+            assertion.left.span.clone(),
+        ),
+        (_, Err(err), _) => (
+            false,
+            format!("left side does not decode as `{types}`: {err}"),
+            assertion.left.span.clone(),
+        ),
+        (_, Ok(_), Some(Err(err))) => (
+            false,
+            format!("right side does not decode as `{types}`: {err}"),
+            assertion
+                .right
+                .as_ref()
+                .map_or(assertion.span.clone(), |right| right.span.clone()),
+        ),
+        (kind, Ok(left), Some(Ok(right))) => {
+            let equal = left == right;
+            let passed = equal == (kind == AssertionKind::Equal);
+            let message = match (passed, equal) {
+                (true, _) => String::new(),
+                (false, true) => format!("both sides decode to `{}`", render_args(left)),
+                (false, false) => format!(
+                    "values differ: `{}` and `{}`",
+                    render_args(left),
+                    render_args(right)
+                ),
+            };
+            (passed, message, assertion.span.clone())
+        }
+        (_, Ok(_), None) => (true, String::new(), assertion.span.clone()),
+    };
+    AssertionOutcome {
+        passed,
+        message,
+        span,
+        rule: DiagnosticRule::TestAssertionFailed,
+        left,
+        right,
+    }
+}
+
+fn evaluate(
+    input: &TestInput,
+    types: &[IDLTypeWithSpan],
+    env: &TypeEnv,
+) -> Result<Vec<NormalValue>, String> {
+    match input {
+        TestInput::Blob(bytes) => {
+            let types: Vec<IDLType> = types.iter().map(|ty| ty.kind.clone()).collect();
+            decode_args(bytes, &types, env)
+        }
+        TestInput::Text(text) => {
+            let parsed = parse_value_args(text);
+            if let Some(err) = parsed.errors.first() {
+                return Err(format!("text does not parse: {}", err.message));
+            }
+            let args = parsed.args.unwrap_or_default();
+            if let Some(err) = check_args(&args, types, env).first() {
+                return Err(err.message.clone());
+            }
+            Ok(normalize_args(&args.values, types, env))
+        }
+    }
+}
+
+fn render_types(types: &[IDLTypeWithSpan]) -> String {
+    let types: Vec<_> = types
+        .iter()
+        .map(|ty| render_type_summary(&ty.kind))
+        .collect();
+    format!("({})", types.join(", "))
+}
+
+/// Syntax errors and failing assertions of a test suite.
+pub fn test_suite_diagnostics(suite: &TestSuite, env: &TypeEnv, rope: &Rope) -> Vec<Diagnostic> {
+    let syntax = suite.errors.iter().filter_map(|err| {
+        Some(DiagnosticRule::TestSyntax.annotate(Diagnostic {
+            range: span_to_range(&err.span, rope)?,
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("test".to_string()),
+            message: err.message.clone(),
+            ..Default::default()
+        }))
+    });
+    let failures = suite.assertions.iter().filter_map(|assertion| {
+        let outcome = run_assertion(assertion, env);
+        if outcome.passed {
+            return None;
+        }
+        let message = match &assertion.description {
+            Some(description) => format!("{description}: {}", outcome.message),
+            None => outcome.message,
+        };
+        Some(outcome.rule.annotate(Diagnostic {
+            range: span_to_range(&outcome.span, rope)?,
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("test".to_string()),
+            message,
+            ..Default::default()
+        }))
+    });
+    syntax.chain(failures).collect()
+}
+
+/// Lexical semantic tokens for the assertions, with keywords, types and labels told apart.
+pub fn test_suite_semantic_tokens(src: &str, suite: &TestSuite) -> Vec<ImCompleteSemanticToken> {
+    let mut tokens: Vec<_> = lex(src)
+        .semantic_tokens
+        .into_iter()
+        .filter(|token| token.start >= suite.defs_end)
+        .collect();
+    apply_assertion_tokens(&mut tokens, &suite.token_roles);
+    tokens
+}
+
+/// Hover markup for an assertion: whether it passes and what its inputs decode to.
+pub fn assertion_hover_markdown(assertion: &Assertion, env: &TypeEnv) -> Option<String> {
+    let outcome = run_assertion(assertion, env);
+    let mut writer = MarkdownWriter::default();
+    let status = if outcome.passed { "passes" } else { "fails" };
+    match &assertion.description {
+        Some(description) => writer.push_text(format!("**{description}** — assertion {status}")),
+        None => writer.push_text(format!("Assertion {status}")),
+    }
+    if !outcome.passed {
+        writer.push_text(&outcome.message);
+    }
+    if let Ok(values) = &outcome.left {
+        writer.push_code_block(&render_args(values));
+    }
+    if let Some(Ok(values)) = &outcome.right {
+        writer.push_code_block(&render_args(values));
+    }
+    if let TestInput::Blob(bytes) = &assertion.left.input {
+        writer.push_text(format!("{} bytes", bytes.len()));
+    }
+    writer.finish()
+}
@@ -0,0 +1,79 @@
+use crate::lsp::position::offset_to_position;
+use ropey::Rope;
+use similar::{Algorithm, DiffTag, capture_diff_slices};
+use std::ops::Range as LineRange;
+use tower_lsp_server::ls_types::{Range, TextEdit};
+
+/// A run of lines replaced between two texts; line indices refer to each side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineHunk {
+    pub old: LineRange<usize>,
+    pub new: LineRange<usize>,
+}
+
+/// Lines of `text`, each keeping its line terminator.
+pub fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Hunks turning `old` into `new`, in order, from a Myers diff of their lines. Identical texts
+/// give no hunks.
+pub fn line_hunks(old: &[&str], new: &[&str]) -> Vec<LineHunk> {
+    let mut hunks: Vec<LineHunk> = Vec::new();
+    for op in capture_diff_slices(Algorithm::Myers, old, new) {
+        let (tag, old, new) = op.as_tag_tuple();
+        if tag == DiffTag::Equal {
+            continue;
+        }
+        // Adjacent deletions and insertions form one hunk.
+        match hunks.last_mut() {
+            Some(last) if last.old.end == old.start && last.new.end == new.start => {
+                last.old.end = old.end;
+                last.new.end = new.end;
+            }
+            _ => hunks.push(LineHunk { old, new }),
+        }
+    }
+    hunks
+}
+
+/// Edits turning the document `rope` into `new_text`: one per changed run of lines, each
+/// narrowed to the characters that differ. An unchanged document gives no edits.
+pub fn minimal_edits(rope: &Rope, new_text: &str) -> Vec<TextEdit> {
+    let old_text = rope.to_string();
+    let old = split_lines(&old_text);
+    let new = split_lines(new_text);
+    line_hunks(&old, &new)
+        .iter()
+        .filter_map(|hunk| hunk_edit(hunk, &old, &new, rope))
+        .collect()
+}
+
+/// Text edit applying `hunk` to the document `rope`, whose lines are `old`, trimmed to the
+/// characters that differ.
+pub fn hunk_edit(hunk: &LineHunk, old: &[&str], new: &[&str], rope: &Rope) -> Option<TextEdit> {
+    let start: usize = old[..hunk.old.start]
+        .iter()
+        .map(|line| line.chars().count())
+        .sum();
+    let old_chars: Vec<char> = old[hunk.old.clone()].concat().chars().collect();
+    let new_chars: Vec<char> = new[hunk.new.clone()].concat().chars().collect();
+    let prefix = old_chars
+        .iter()
+        .zip(&new_chars)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old_chars[prefix..]
+        .iter()
+        .rev()
+        .zip(new_chars[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    Some(TextEdit {
+        range: Range::new(
+            offset_to_position(start + prefix, rope)?,
+            offset_to_position(start + old_chars.len() - suffix, rope)?,
+        ),
+        new_text: new_chars[prefix..new_chars.len() - suffix].iter().collect(),
+    })
+}
@@ -0,0 +1,68 @@
+use crate::lsp::span::Span;
+use candid_parser::token::TriviaMap;
+
+/// A comment in the source, with the layout around it that formatting preserves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub span: Span,
+    /// The comment as written, without trailing whitespace.
+    pub text: String,
+    /// Whether only whitespace precedes the comment on its line.
+    pub own_line: bool,
+    /// Whether a blank line separates the comment from the code or comment before it.
+    pub blank_before: bool,
+}
+
+/// The comments the tokenizer recorded in `trivia`, in source order.
+///
+/// Line comments are lexed whole, so `line_comments` are their spans. A block comment is lexed
+/// as its opening `/*` at an offset in `block_starts`; its extent is the length of its text in
+/// the trivia, whose block comments come in the same order.
+pub fn collect_comments(
+    src: &str,
+    trivia: &TriviaMap,
+    line_comments: &[Span],
+    block_starts: &[usize],
+) -> Vec<Comment> {
+    let trivia = trivia.borrow();
+    let mut entries: Vec<_> = trivia.iter().collect();
+    entries.sort_by_key(|(offset, _)| **offset);
+    let block_texts = entries
+        .into_iter()
+        .flat_map(|(_, texts)| texts)
+        .filter(|text| text.starts_with("/*"));
+    let blocks = block_starts
+        .iter()
+        .zip(block_texts)
+        .map(|(start, text)| *start..start + text.len());
+    let mut spans: Vec<Span> = line_comments.iter().cloned().chain(blocks).collect();
+    spans.sort_by_key(|span| span.start);
+    spans
+        .into_iter()
+        .filter(|span| src.get(span.clone()).is_some())
+        .map(|span| Comment::new(src, span))
+        .collect()
+}
+
+impl Comment {
+    /// The comment at `span` in `src`, with its layout read from the surrounding text.
+    pub fn new(src: &str, span: Span) -> Self {
+        let before = &src[..span.start];
+        let line_start = before.rfind('\n').map_or(0, |offset| offset + 1);
+        Comment {
+            text: src[span.clone()].trim_end().to_string(),
+            own_line: src[line_start..span.start].trim().is_empty(),
+            blank_before: has_blank_line(&before[before.trim_end().len()..]),
+            span,
+        }
+    }
+}
+
+/// Whether the whitespace `gap` between two pieces of code contains an empty line.
+pub fn has_blank_line(gap: &str) -> bool {
+    let lines: Vec<_> = gap.split('\n').collect();
+    lines.len() > 2
+        && lines[1..lines.len() - 1]
+            .iter()
+            .any(|line| line.trim().is_empty())
+}
@@ -1,3 +1,4 @@
+use crate::lsp::value_check::TypeEnv;
 use candid_parser::{
     candid::types::{FuncMode, Label},
     syntax::{Binding, FuncType, IDLType, IDLTypeWithSpan, PrimType, TypeField},
@@ -21,6 +22,90 @@ pub fn render_inline_type(ty: &IDLTypeWithSpan) -> String {
     buf
 }
 
+/// Render `ty` with type names replaced by their definitions, up to `depth` names deep.
+///
+/// Names that refer back to a definition being expanded are left as they are, so recursive
+/// types stay finite.
+pub fn render_expanded_type(ty: &IDLType, env: &TypeEnv, depth: usize) -> String {
+    let mut expanded = ty.clone();
+    expand_names(&mut expanded, env, depth, &mut Vec::new());
+    let mut buf = String::new();
+    render_type_kind(&expanded, 0, &mut buf);
+    buf
+}
+
+/// The definition of the type `name` as `type name = …`, expanded like
+/// [`render_expanded_type`]; `name` itself is never expanded again inside its definition.
+pub fn render_expanded_binding(name: &str, env: &TypeEnv, depth: usize) -> Option<String> {
+    let mut expanded = env.get(name)?.kind.clone();
+    expand_names(&mut expanded, env, depth, &mut vec![name.to_string()]);
+    let mut buf = format!("type {name} = ");
+    render_type_kind(&expanded, 0, &mut buf);
+    Some(buf)
+}
+
+fn expand_names(ty: &mut IDLType, env: &TypeEnv, depth: usize, expanding: &mut Vec<String>) {
+    if let IDLType::VarT(name) = ty {
+        if depth == 0 || expanding.contains(name) {
+            return;
+        }
+        let Some(definition) = env.get(name) else {
+            return;
+        };
+        expanding.push(name.clone());
+        *ty = definition.kind.clone();
+        expand_names(ty, env, depth - 1, expanding);
+        expanding.pop();
+        return;
+    }
+    match ty {
+        IDLType::OptT(inner) | IDLType::VecT(inner) => {
+            expand_names(&mut inner.kind, env, depth, expanding)
+        }
+        IDLType::RecordT(fields) | IDLType::VariantT(fields) => {
+            for field in fields {
+                expand_names(&mut field.typ.kind, env, depth, expanding);
+            }
+        }
+        IDLType::FuncT(func) => {
+            for arg in func.args.iter_mut().chain(func.rets.iter_mut()) {
+                expand_names(&mut arg.kind, env, depth, expanding);
+            }
+        }
+        IDLType::ServT(methods) => {
+            for method in methods {
+                expand_names(&mut method.typ.kind, env, depth, expanding);
+            }
+        }
+        IDLType::ClassT(args, ret) => {
+            for arg in args {
+                expand_names(&mut arg.kind, env, depth, expanding);
+            }
+            expand_names(&mut ret.kind, env, depth, expanding);
+        }
+        IDLType::PrimT(_) | IDLType::PrincipalT | IDLType::VarT(_) => {}
+    }
+}
+
+/// Single-line description of a type for messages, eliding record and variant bodies.
+pub fn render_type_summary(ty: &IDLType) -> String {
+    match ty {
+        IDLType::PrimT(kind) => prim_to_str(kind).to_string(),
+        IDLType::VarT(name) => name.clone(),
+        IDLType::PrincipalT => "principal".to_string(),
+        IDLType::OptT(inner) => format!("opt {}", render_type_summary(&inner.kind)),
+        IDLType::VecT(inner) if matches!(inner.kind, IDLType::PrimT(PrimType::Nat8)) => {
+            "blob".to_string()
+        }
+        IDLType::VecT(inner) => format!("vec {}", render_type_summary(&inner.kind)),
+        IDLType::RecordT(fields) if fields.is_empty() => "record {}".to_string(),
+        IDLType::RecordT(_) => "record { … }".to_string(),
+        IDLType::VariantT(_) => "variant { … }".to_string(),
+        IDLType::FuncT(_) => "func".to_string(),
+        IDLType::ServT(_) | IDLType::ClassT(..) => "service".to_string(),
+    }
+}
+
 fn render_type(ty: &IDLTypeWithSpan, indent: usize, buf: &mut String) {
     render_type_kind(&ty.kind, indent, buf)
 }
@@ -191,7 +276,7 @@ fn push_indent(buf: &mut String, level: usize) {
     }
 }
 
-fn prim_to_str(prim: &PrimType) -> &'static str {
+pub(crate) fn prim_to_str(prim: &PrimType) -> &'static str {
     match prim {
         PrimType::Nat => "nat",
         PrimType::Nat8 => "nat8",
@@ -269,4 +354,26 @@ mod tests {
             "type Citation = variant {\n  Url : text;\n  Paper;\n  Other : text;\n}"
         );
     }
+
+    #[test]
+    fn expands_names_up_to_the_depth() {
+        let source = "type Foo = record { id : nat };\ntype FooList = vec Foo;\ntype Outer = opt FooList;\ntype Tree = record { children : vec Tree };";
+        let env = TypeEnv::new(
+            &crate::candid_lang::parse(source).ast.expect("parses"),
+            None,
+        );
+        assert_eq!(
+            render_expanded_binding("FooList", &env, 2).as_deref(),
+            Some("type FooList = vec record {\n  id : nat;\n}")
+        );
+        assert_eq!(
+            render_expanded_type(&IDLType::VarT("Outer".to_string()), &env, 2),
+            "opt vec Foo"
+        );
+        assert_eq!(
+            render_expanded_binding("Tree", &env, 3).as_deref(),
+            Some("type Tree = record {\n  children : vec Tree;\n}")
+        );
+        assert_eq!(render_expanded_binding("Missing", &env, 1), None);
+    }
 }
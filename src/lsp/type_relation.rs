@@ -0,0 +1,142 @@
+use crate::{
+    candid_lang::{ParserResult, parse},
+    lsp::{
+        CandidLanguageServer,
+        value_check::{TypeEnv, undefined_type},
+    },
+};
+use candid_parser::{
+    candid::types::subtype::{Gamma, OptReport, equal, subtype_with_config},
+    syntax::{Dec, IDLType},
+};
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::{
+    jsonrpc::{Error, Result},
+    ls_types::Uri,
+};
+
+/// Custom request asking whether `typeA <: typeB`.
+pub const IS_SUBTYPE_METHOD: &str = "candid/isSubtype";
+/// Custom request asking whether `typeA` and `typeB` are structurally equal.
+pub const TYPE_EQUALS_METHOD: &str = "candid/typeEquals";
+
+/// Name of the definition inline type texts are parsed as.
+const QUERY_TYPE: &str = "candid_ls_query";
+
+/// Parameters of [`IS_SUBTYPE_METHOD`] and [`TYPE_EQUALS_METHOD`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeRelationParams {
+    pub uri: Uri,
+    /// A type name of the document, or an inline type such as `record { id : nat }`.
+    pub type_a: String,
+    pub type_b: String,
+}
+
+/// Which relation is asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    Subtype,
+    Equal,
+}
+
+/// Answer to a relation query.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeRelation {
+    pub holds: bool,
+    /// Why the relation does not hold: `candid`'s error and its context, outermost first.
+    pub explanation: Option<String>,
+    /// The context of the error alone, e.g. the record field that holds the mismatch.
+    pub path: Vec<String>,
+    /// Places where subtyping only holds through Candid's special `opt` rule, which decodes
+    /// mismatched values as `null`.
+    pub warnings: Vec<String>,
+}
+
+/// Answer a relation request against the open document at `params.uri`.
+pub fn type_relation(
+    server: &CandidLanguageServer,
+    params: TypeRelationParams,
+    relation: Relation,
+) -> Result<TypeRelation> {
+    let Some(snapshot) = server.analysis_map.get(params.uri.as_str()) else {
+        return Err(Error::invalid_params(format!(
+            "`{}` is not an open document",
+            params.uri.as_str()
+        )));
+    };
+    let Some(env) = snapshot.type_env() else {
+        return Err(Error::invalid_params("the document does not parse"));
+    };
+    let a = parse_type_text(&params.type_a, env).map_err(Error::invalid_params)?;
+    let b = parse_type_text(&params.type_b, env).map_err(Error::invalid_params)?;
+    check_relation(&a, &b, env, relation).map_err(Error::invalid_params)
+}
+
+/// Read `text` as a type of `env`: a defined name, or an inline type whose names are defined.
+pub fn parse_type_text(text: &str, env: &TypeEnv) -> std::result::Result<IDLType, String> {
+    let text = text.trim();
+    if env.get(text).is_some() {
+        return Ok(IDLType::VarT(text.to_string()));
+    }
+    let ParserResult {
+        ast, parse_errors, ..
+    } = parse(&format!("type {QUERY_TYPE} = {text};"));
+    let ty = ast
+        .filter(|_| parse_errors.is_empty())
+        .and_then(|ast| {
+            ast.decs().iter().find_map(|dec| match dec {
+                Dec::TypD(binding) => Some(binding.typ.kind.clone()),
+                _ => None,
+            })
+        })
+        .ok_or_else(|| format!("`{text}` is not a type"))?;
+    match undefined_type(&ty, env) {
+        Some(name) => Err(format!("type `{name}` is not defined")),
+        None => Ok(ty),
+    }
+}
+
+/// Decide whether `a` and `b` are related by `relation` under `env`, using `candid`'s own
+/// subtype and equality checks.
+///
+/// Subtyping follows the Candid spec, including the special rule that makes any type a subtype
+/// of an `opt` type; equality compares the types structurally, looking through names.
+pub fn check_relation(
+    a: &IDLType,
+    b: &IDLType,
+    env: &TypeEnv,
+    relation: Relation,
+) -> std::result::Result<TypeRelation, String> {
+    let checked = env.checked()?;
+    let a = env.candid_type(a)?;
+    let b = env.candid_type(b)?;
+    let check = |report| match relation {
+        Relation::Subtype => subtype_with_config(report, &mut Gamma::new(), &checked.env, &a, &b),
+        Relation::Equal => equal(&mut Gamma::new(), &checked.env, &a, &b),
+    };
+    let Err(err) = check(OptReport::Error) else {
+        return Ok(TypeRelation {
+            holds: true,
+            ..TypeRelation::default()
+        });
+    };
+    let mut chain: Vec<String> = err.chain().map(ToString::to_string).collect();
+    // The special `opt` rule only fails when it is reported as an error.
+    if relation == Relation::Subtype && check(OptReport::Silence).is_ok() {
+        return Ok(TypeRelation {
+            holds: true,
+            warnings: vec![chain.join(": ")],
+            ..TypeRelation::default()
+        });
+    }
+    let explanation = chain.join(": ");
+    chain.pop();
+    Ok(TypeRelation {
+        holds: false,
+        explanation: Some(explanation),
+        path: chain,
+        warnings: Vec::new(),
+    })
+}
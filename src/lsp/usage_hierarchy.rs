@@ -0,0 +1,398 @@
+use crate::lsp::{
+    CandidLanguageServer,
+    navigation::lookup_identifier,
+    position::{position_to_offset, span_to_range},
+    semantic_analyze::{MethodId, Semantic},
+    span::Span,
+    symbol_table::{ReferenceId, SymbolId},
+    workspace::DocumentKind,
+};
+use oxc_index::IndexVec;
+use ropey::Rope;
+use serde_json::json;
+use tower_lsp_server::{
+    jsonrpc::Result,
+    ls_types::{
+        CallHierarchyIncomingCall, CallHierarchyIncomingCallsParams, CallHierarchyItem,
+        CallHierarchyOutgoingCall, CallHierarchyOutgoingCallsParams, CallHierarchyPrepareParams,
+        Range, SymbolKind, Uri,
+    },
+};
+
+/// A declaration shown in the usage hierarchy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageNode {
+    Type(SymbolId),
+    Method(MethodId),
+    Service,
+}
+
+/// The declaration around every reference of a `Semantic`, found once per snapshot so usage
+/// queries do not rescan the declarations for each reference.
+#[derive(Debug, Clone, Default)]
+pub struct UsageIndex {
+    enclosing: IndexVec<ReferenceId, Option<UsageNode>>,
+}
+
+impl UsageIndex {
+    pub fn build(semantic: &Semantic) -> Self {
+        let enclosing = semantic
+            .table
+            .reference_id_to_reference
+            .iter()
+            .map(|reference| enclosing_node(semantic, reference.span.start))
+            .collect();
+        Self { enclosing }
+    }
+
+    /// The declaration containing the reference `reference_id`.
+    pub fn enclosing(&self, reference_id: ReferenceId) -> Option<UsageNode> {
+        self.enclosing.get(reference_id).copied().flatten()
+    }
+}
+
+/// Answer `textDocument/prepareCallHierarchy` with the declaration under the cursor.
+pub async fn prepare_usage_hierarchy(
+    server: &CandidLanguageServer,
+    params: CallHierarchyPrepareParams,
+) -> Result<Option<Vec<CallHierarchyItem>>> {
+    let uri = params.text_document_position_params.text_document.uri;
+    if DocumentKind::of(&uri) != DocumentKind::Interface {
+        return Ok(None);
+    }
+    let Some(snapshot) = server.analysis_map.get(uri.as_str()) else {
+        return Ok(None);
+    };
+    let (Some(semantic), rope) = (snapshot.semantic(), snapshot.rope()) else {
+        return Ok(None);
+    };
+    let item = position_to_offset(params.text_document_position_params.position, rope)
+        .and_then(|offset| node_at(semantic, offset))
+        .and_then(|node| node_item(&uri, semantic, rope, node));
+    Ok(item.map(|item| vec![item]))
+}
+
+/// Answer `callHierarchy/incomingCalls`: the declarations using the item's type.
+pub async fn incoming_usages(
+    server: &CandidLanguageServer,
+    params: CallHierarchyIncomingCallsParams,
+) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+    let uri = params.item.uri.clone();
+    let Some(snapshot) = server.analysis_map.get(uri.as_str()) else {
+        return Ok(None);
+    };
+    let (Some(semantic), Some(index), rope) =
+        (snapshot.semantic(), snapshot.usage_index(), snapshot.rope())
+    else {
+        return Ok(None);
+    };
+    let Some(node) = node_of_item(semantic, rope, &params.item) else {
+        return Ok(None);
+    };
+    Ok(Some(
+        used_by(semantic, index, node)
+            .into_iter()
+            .filter_map(|(user, spans)| {
+                Some(CallHierarchyIncomingCall {
+                    from: node_item(&uri, semantic, rope, user)?,
+                    from_ranges: ranges(&spans, rope),
+                })
+            })
+            .collect(),
+    ))
+}
+
+/// Answer `callHierarchy/outgoingCalls`: the types the item refers to.
+pub async fn outgoing_usages(
+    server: &CandidLanguageServer,
+    params: CallHierarchyOutgoingCallsParams,
+) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+    let uri = params.item.uri.clone();
+    let Some(snapshot) = server.analysis_map.get(uri.as_str()) else {
+        return Ok(None);
+    };
+    let (Some(semantic), rope) = (snapshot.semantic(), snapshot.rope()) else {
+        return Ok(None);
+    };
+    let Some(node) = node_of_item(semantic, rope, &params.item) else {
+        return Ok(None);
+    };
+    Ok(Some(
+        uses(semantic, node)
+            .into_iter()
+            .filter_map(|(used, spans)| {
+                Some(CallHierarchyOutgoingCall {
+                    to: node_item(&uri, semantic, rope, used)?,
+                    from_ranges: ranges(&spans, rope),
+                })
+            })
+            .collect(),
+    ))
+}
+
+/// The type definition, service method, or service named at `offset`.
+pub fn node_at(semantic: &Semantic, offset: usize) -> Option<UsageNode> {
+    let info = lookup_identifier(semantic, offset)?;
+    if let Some(symbol_id) = info.symbol_id {
+        return is_type(semantic, symbol_id).then_some(UsageNode::Type(symbol_id));
+    }
+    if let Some(method) = info.service_method {
+        return Some(UsageNode::Method(method.id));
+    }
+    info.actor.map(|_| UsageNode::Service)
+}
+
+/// Declarations referring to the type `node`, each with the spans of its references, in source
+/// order. Methods and the service use nothing in this direction, and self-references are skipped.
+pub fn used_by(
+    semantic: &Semantic,
+    index: &UsageIndex,
+    node: UsageNode,
+) -> Vec<(UsageNode, Vec<Span>)> {
+    let UsageNode::Type(symbol_id) = node else {
+        return Vec::new();
+    };
+    let mut users: Vec<(UsageNode, Vec<Span>)> = Vec::new();
+    let references = semantic.table.symbol_id_to_references.get(&symbol_id);
+    for reference_id in references.into_iter().flatten() {
+        let Some(reference) = semantic.table.reference_id_to_reference.get(*reference_id) else {
+            continue;
+        };
+        let Some(user) = index.enclosing(*reference_id) else {
+            continue;
+        };
+        if user == node {
+            continue;
+        }
+        match users.iter_mut().find(|(existing, _)| *existing == user) {
+            Some((_, spans)) => spans.push(reference.span.clone()),
+            None => users.push((user, vec![reference.span.clone()])),
+        }
+    }
+    users.sort_by_key(|(user, _)| node_span(semantic, *user).map_or(0, |span| span.start));
+    users
+}
+
+/// Types referred to inside `node`, each with the spans of the references, in source order.
+pub fn uses(semantic: &Semantic, node: UsageNode) -> Vec<(UsageNode, Vec<Span>)> {
+    let Some(span) = node_span(semantic, node) else {
+        return Vec::new();
+    };
+    let mut used: Vec<(UsageNode, Vec<Span>)> = Vec::new();
+    let mut references: Vec<_> = semantic
+        .table
+        .reference_id_to_reference
+        .iter()
+        .filter(|reference| span.start <= reference.span.start && reference.span.end <= span.end)
+        .collect();
+    references.sort_by_key(|reference| reference.span.start);
+    for reference in references {
+        let Some(target) = reference
+            .symbol_id
+            .filter(|symbol_id| is_type(semantic, *symbol_id))
+            .map(UsageNode::Type)
+        else {
+            continue;
+        };
+        if target == node {
+            continue;
+        }
+        match used.iter_mut().find(|(existing, _)| *existing == target) {
+            Some((_, spans)) => spans.push(reference.span.clone()),
+            None => used.push((target, vec![reference.span.clone()])),
+        }
+    }
+    used
+}
+
+/// Service methods that use the type `node`, directly or through other types, in source order.
+pub fn dependent_methods(
+    semantic: &Semantic,
+    index: &UsageIndex,
+    node: UsageNode,
+) -> Vec<MethodId> {
+    let mut seen = vec![node];
+    let mut pending = vec![node];
+    let mut methods = Vec::new();
+    while let Some(current) = pending.pop() {
+        for (user, _) in used_by(semantic, index, current) {
+            if seen.contains(&user) {
+                continue;
+            }
+            seen.push(user);
+            match user {
+                UsageNode::Method(method_id) => methods.push(method_id),
+                _ => pending.push(user),
+            }
+        }
+    }
+    methods.sort_by_key(|method_id| {
+        semantic
+            .service_methods
+            .get(*method_id)
+            .map_or(0, |metadata| metadata.span.start)
+    });
+    methods
+}
+
+/// Name of the service method `method_id`, as written in the source.
+pub fn method_name(semantic: &Semantic, rope: &Rope, method_id: MethodId) -> Option<String> {
+    let name_span = semantic.service_methods.get(method_id)?.name_span.clone()?;
+    Some(rope.slice(name_span).to_string())
+}
+
+/// The innermost declaration around `offset`: a service method, else a type definition, else the
+/// service.
+fn enclosing_node(semantic: &Semantic, offset: usize) -> Option<UsageNode> {
+    let contains = |span: &Span| span.start <= offset && offset < span.end;
+    let method = semantic
+        .service_methods
+        .iter_enumerated()
+        .filter(|(_, metadata)| contains(&metadata.span))
+        .min_by_key(|(_, metadata)| metadata.span.len())
+        .map(|(method_id, _)| UsageNode::Method(method_id));
+    let ty = || {
+        semantic
+            .table
+            .symbol_id_to_span
+            .iter_enumerated()
+            .find(|(symbol_id, span)| is_type(semantic, *symbol_id) && contains(span))
+            .map(|(symbol_id, _)| UsageNode::Type(symbol_id))
+    };
+    let service = || {
+        semantic
+            .actor
+            .as_ref()
+            .filter(|actor| contains(&actor.span))
+            .map(|_| UsageNode::Service)
+    };
+    method.or_else(ty).or_else(service)
+}
+
+/// Whether `symbol_id` is a type definition rather than an import.
+fn is_type(semantic: &Semantic, symbol_id: SymbolId) -> bool {
+    semantic
+        .symbol_ident_names
+        .get(symbol_id)
+        .is_some_and(Option::is_some)
+}
+
+fn node_span(semantic: &Semantic, node: UsageNode) -> Option<Span> {
+    match node {
+        UsageNode::Type(symbol_id) => semantic.table.symbol_id_to_span.get(symbol_id).cloned(),
+        UsageNode::Method(method_id) => semantic
+            .service_methods
+            .get(method_id)
+            .map(|metadata| metadata.span.clone()),
+        UsageNode::Service => semantic.actor.as_ref().map(|actor| actor.span.clone()),
+    }
+}
+
+/// Hierarchy item for `node`; its `data` names the declaration so later requests can find it.
+pub fn node_item(
+    uri: &Uri,
+    semantic: &Semantic,
+    rope: &Rope,
+    node: UsageNode,
+) -> Option<CallHierarchyItem> {
+    let span = node_span(semantic, node)?;
+    let (name, kind, detail, selection, data) = match node {
+        UsageNode::Type(symbol_id) => {
+            let name = semantic.symbol_ident_names.get(symbol_id)?.as_deref()?;
+            let selection = semantic
+                .symbol_ident_spans
+                .get(symbol_id)
+                .cloned()
+                .flatten();
+            (
+                name.to_string(),
+                SymbolKind::STRUCT,
+                None,
+                selection,
+                json!({ "type": name }),
+            )
+        }
+        UsageNode::Method(method_id) => {
+            let metadata = semantic.service_methods.get(method_id)?;
+            let name = method_name(semantic, rope, method_id)?;
+            let service = metadata.parent_name.as_deref();
+            (
+                name.clone(),
+                SymbolKind::METHOD,
+                service.map(str::to_string),
+                metadata.name_span.clone(),
+                json!({ "method": name, "service": service }),
+            )
+        }
+        UsageNode::Service => {
+            let actor = semantic.actor.as_ref()?;
+            let name = actor.name_span.as_ref().map_or_else(
+                || "service".to_string(),
+                |span| rope.slice(span.clone()).to_string(),
+            );
+            (
+                name,
+                SymbolKind::INTERFACE,
+                None,
+                actor.name_span.clone(),
+                json!({ "service": true }),
+            )
+        }
+    };
+    let range = span_to_range(&span, rope)?;
+    let selection_range = selection
+        .and_then(|selection| span_to_range(&selection, rope))
+        .unwrap_or(range);
+    Some(CallHierarchyItem {
+        name,
+        kind,
+        tags: None,
+        detail,
+        uri: uri.clone(),
+        range,
+        selection_range,
+        data: Some(data),
+    })
+}
+
+/// Find the declaration an item from an earlier request stands for, by the name in its `data`.
+pub fn node_of_item(
+    semantic: &Semantic,
+    rope: &Rope,
+    item: &CallHierarchyItem,
+) -> Option<UsageNode> {
+    let data = item.data.as_ref()?;
+    if let Some(name) = data.get("type").and_then(|name| name.as_str()) {
+        return semantic
+            .symbol_ident_names
+            .iter_enumerated()
+            .find(|(_, ident)| ident.as_deref() == Some(name))
+            .map(|(symbol_id, _)| UsageNode::Type(symbol_id));
+    }
+    if let Some(name) = data.get("method").and_then(|name| name.as_str()) {
+        let service = data.get("service").and_then(|service| service.as_str());
+        return semantic
+            .service_methods
+            .iter_enumerated()
+            .find(|(_, metadata)| {
+                metadata.parent_name.as_deref() == service
+                    && metadata
+                        .name_span
+                        .as_ref()
+                        .is_some_and(|span| rope.slice(span.clone()) == name)
+            })
+            .map(|(method_id, _)| UsageNode::Method(method_id));
+    }
+    data.get("service")
+        .and_then(|service| service.as_bool())
+        .filter(|service| *service)
+        .and(semantic.actor.as_ref())
+        .map(|_| UsageNode::Service)
+}
+
+fn ranges(spans: &[Span], rope: &Rope) -> Vec<Range> {
+    spans
+        .iter()
+        .filter_map(|span| span_to_range(span, rope))
+        .collect()
+}
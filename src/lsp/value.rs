@@ -0,0 +1,638 @@
+use crate::{
+    candid_lang::{ImCompleteSemanticToken, LexResult, lex},
+    lsp::span::Span,
+};
+use candid_parser::{candid::types::Label, token::Token};
+
+/// A Candid value literal, as written in `.args` files and `dfx canister call`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Value {
+    pub kind: ValueKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueKind {
+    Null,
+    Bool(bool),
+    /// Numeric literal as written, including its sign.
+    Number {
+        text: String,
+        float: bool,
+    },
+    Text(String),
+    Blob(String),
+    Opt(Box<Value>),
+    /// `body` covers the text between the braces, up to the end of input when unclosed.
+    Vec {
+        items: Vec<Value>,
+        body: Span,
+    },
+    Record {
+        fields: Vec<ValueField>,
+        body: Span,
+    },
+    Variant {
+        field: Option<Box<ValueField>>,
+        body: Span,
+    },
+    Principal(String),
+    Service(String),
+    Func {
+        principal: String,
+        method: String,
+    },
+    /// `value : type`; only the annotation text is kept.
+    Annotated {
+        value: Box<Value>,
+        annotation: String,
+        annotation_span: Span,
+    },
+}
+
+/// A record field or variant case. Positional record fields get `Label::Unnamed`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueField {
+    pub label: Label,
+    pub label_span: Option<Span>,
+    /// `None` for a variant case written without `= value`.
+    pub value: Option<Value>,
+    pub span: Span,
+}
+
+/// A parenthesised argument list such as `(42, record { a = "x" })`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ValueArgs {
+    pub values: Vec<Value>,
+    /// Span from `(` to `)`, or to the end of input when unclosed.
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueError {
+    pub span: Span,
+    pub message: String,
+}
+
+/// Parse output with the errors recovered from along the way.
+#[derive(Debug)]
+pub struct ValueParseResult {
+    pub args: Option<ValueArgs>,
+    pub errors: Vec<ValueError>,
+    pub semantic_tokens: Vec<ImCompleteSemanticToken>,
+}
+
+/// Parse an argument list, recovering at `,`, `;` and closing delimiters after errors.
+pub fn parse_value_args(src: &str) -> ValueParseResult {
+    let LexResult {
+        tokens,
+        lexer_errors,
+        semantic_tokens,
+    } = lex(src);
+    let mut parser = ValueParser {
+        src,
+        tokens,
+        pos: 0,
+        errors: lexer_errors
+            .into_iter()
+            .map(|err| ValueError {
+                span: err.span.clone(),
+                message: err.err.to_string(),
+            })
+            .collect(),
+    };
+    let args = parser.parse_args();
+    if let Some((start, token, end)) = parser.peek().cloned() {
+        parser.error(
+            start..end,
+            format!("unexpected {} after the argument list", describe(&token)),
+        );
+    }
+    ValueParseResult {
+        args,
+        errors: parser.errors,
+        semantic_tokens,
+    }
+}
+
+struct ValueParser<'src> {
+    src: &'src str,
+    tokens: Vec<(usize, Token, usize)>,
+    pos: usize,
+    errors: Vec<ValueError>,
+}
+
+impl ValueParser<'_> {
+    fn peek(&self) -> Option<&(usize, Token, usize)> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_token(&self) -> Option<&Token> {
+        self.peek().map(|(_, token, _)| token)
+    }
+
+    fn nth_token(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + n).map(|(_, token, _)| token)
+    }
+
+    fn bump(&mut self) -> Option<(usize, Token, usize)> {
+        let token = self.tokens.get(self.pos).cloned()?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    fn eat(&mut self, expected: &Token) -> Option<Span> {
+        match self.peek() {
+            Some((start, token, end)) if token == expected => {
+                let span = *start..*end;
+                self.pos += 1;
+                Some(span)
+            }
+            _ => None,
+        }
+    }
+
+    fn end_of_input(&self) -> usize {
+        self.src.len()
+    }
+
+    /// Offset where the next token starts, or the end of input.
+    fn next_start(&self) -> usize {
+        self.peek()
+            .map_or_else(|| self.end_of_input(), |(start, _, _)| *start)
+    }
+
+    /// End offset of the last consumed token.
+    fn last_end(&self) -> usize {
+        self.pos
+            .checked_sub(1)
+            .and_then(|idx| self.tokens.get(idx))
+            .map_or(0, |(_, _, end)| *end)
+    }
+
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(ValueError { span, message });
+    }
+
+    fn error_here(&mut self, expected: &str) {
+        match self.peek().cloned() {
+            Some((start, token, end)) => self.error(
+                start..end,
+                format!("unexpected {}, expected {expected}", describe(&token)),
+            ),
+            None => {
+                let end = self.end_of_input();
+                self.error(
+                    end..end,
+                    format!("unexpected end of input, expected {expected}"),
+                );
+            }
+        }
+    }
+
+    /// Skip to the next token in `stops` that is not nested in another delimiter pair.
+    fn recover(&mut self, stops: &[Token]) {
+        let mut depth = 0usize;
+        while let Some(token) = self.peek_token() {
+            match token {
+                Token::LParen | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBrace if depth > 0 => depth -= 1,
+                token if depth == 0 && stops.contains(token) => return,
+                Token::RParen | Token::RBrace => return,
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn parse_args(&mut self) -> Option<ValueArgs> {
+        let Some(open) = self.eat(&Token::LParen) else {
+            self.error_here("`(`");
+            return None;
+        };
+        let mut values = Vec::new();
+        loop {
+            if let Some(close) = self.eat(&Token::RParen) {
+                return Some(ValueArgs {
+                    values,
+                    span: open.start..close.end,
+                });
+            }
+            if self.peek().is_none() {
+                let end = self.end_of_input();
+                self.error(open, "unclosed `(`: expected `)`".to_string());
+                return Some(ValueArgs {
+                    values,
+                    span: open.start..end,
+                });
+            }
+            let before = self.pos;
+            let parsed = self.parse_annotated();
+            let ok = parsed.is_some();
+            match parsed {
+                Some(value) => values.push(value),
+                None => self.recover(&[Token::Comma]),
+            }
+            if self.eat(&Token::Comma).is_some()
+                || matches!(self.peek_token(), None | Some(Token::RParen))
+            {
+                continue;
+            }
+            if ok {
+                self.error_here("`,` or `)`");
+                self.recover(&[Token::Comma]);
+                self.eat(&Token::Comma);
+            }
+            if self.pos == before {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// A value optionally followed by `: type`.
+    fn parse_annotated(&mut self) -> Option<Value> {
+        let value = self.parse_value()?;
+        let Some(colon) = self.eat(&Token::Colon) else {
+            return Some(value);
+        };
+        let annotation_start = self.next_start();
+        self.recover(&[Token::Comma, Token::Semi]);
+        let annotation_end = self.last_end().max(annotation_start);
+        let annotation = self
+            .src
+            .get(annotation_start..annotation_end)
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        if annotation.is_empty() {
+            self.error(colon, "expected a type after `:`".to_string());
+            return Some(value);
+        }
+        let span = value.span.start..annotation_end;
+        Some(Value {
+            kind: ValueKind::Annotated {
+                value: Box::new(value),
+                annotation,
+                annotation_span: annotation_start..annotation_end,
+            },
+            span,
+        })
+    }
+
+    fn parse_value(&mut self) -> Option<Value> {
+        let Some((start, token, end)) = self.peek().cloned() else {
+            self.error_here("a value");
+            return None;
+        };
+        let simple = |kind| {
+            Some(Value {
+                kind,
+                span: start..end,
+            })
+        };
+        match token {
+            Token::Null => {
+                self.pos += 1;
+                simple(ValueKind::Null)
+            }
+            Token::Boolean(value) => {
+                self.pos += 1;
+                simple(ValueKind::Bool(value))
+            }
+            Token::Text(text) => {
+                self.pos += 1;
+                simple(ValueKind::Text(text))
+            }
+            Token::Decimal(_) | Token::Hex(_) | Token::Float(_) | Token::Sign(_) => {
+                self.parse_number()
+            }
+            Token::Opt => {
+                self.pos += 1;
+                let inner = self.parse_value()?;
+                let span = start..inner.span.end;
+                Some(Value {
+                    kind: ValueKind::Opt(Box::new(inner)),
+                    span,
+                })
+            }
+            Token::Blob => {
+                self.pos += 1;
+                let text = self.expect_text("a text literal after `blob`")?;
+                Some(Value {
+                    kind: ValueKind::Blob(text),
+                    span: start..self.last_end(),
+                })
+            }
+            Token::Principal => {
+                self.pos += 1;
+                let text = self.expect_text("a principal text after `principal`")?;
+                Some(Value {
+                    kind: ValueKind::Principal(text),
+                    span: start..self.last_end(),
+                })
+            }
+            Token::Service => {
+                self.pos += 1;
+                let text = self.expect_text("a principal text after `service`")?;
+                Some(Value {
+                    kind: ValueKind::Service(text),
+                    span: start..self.last_end(),
+                })
+            }
+            Token::Func => {
+                self.pos += 1;
+                let principal = self.expect_text("a principal text after `func`")?;
+                if self.eat(&Token::Dot).is_none() {
+                    self.error_here("`.` and a method name");
+                    return None;
+                }
+                let method = match self.peek_token() {
+                    Some(Token::Id(name) | Token::Text(name)) => name.clone(),
+                    _ => {
+                        self.error_here("a method name");
+                        return None;
+                    }
+                };
+                self.pos += 1;
+                Some(Value {
+                    kind: ValueKind::Func { principal, method },
+                    span: start..self.last_end(),
+                })
+            }
+            Token::Vec => self.parse_vec(start),
+            Token::Record => self.parse_record(start),
+            Token::Variant => self.parse_variant(start),
+            Token::LParen => {
+                self.pos += 1;
+                let inner = self.parse_annotated()?;
+                if self.eat(&Token::RParen).is_none() {
+                    self.error_here("`)`");
+                }
+                Some(Value {
+                    kind: inner.kind,
+                    span: start..self.last_end(),
+                })
+            }
+            _ => {
+                self.error_here("a value");
+                None
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> Option<Value> {
+        let (start, _, _) = self.peek().cloned()?;
+        let sign = match self.peek_token() {
+            Some(Token::Sign(sign)) => {
+                let sign = *sign;
+                self.pos += 1;
+                Some(sign)
+            }
+            _ => None,
+        };
+        match self.bump() {
+            Some((
+                num_start,
+                token @ (Token::Decimal(_) | Token::Hex(_) | Token::Float(_)),
+                end,
+            )) => {
+                let digits = self.src.get(num_start..end).unwrap_or_default();
+                let text = match sign {
+                    Some(sign) => format!("{sign}{digits}"),
+                    None => digits.to_string(),
+                };
+                Some(Value {
+                    kind: ValueKind::Number {
+                        text,
+                        float: matches!(token, Token::Float(_)),
+                    },
+                    span: start..end,
+                })
+            }
+            Some(_) => {
+                self.pos -= 1;
+                self.error_here("a number");
+                None
+            }
+            None => {
+                self.error_here("a number");
+                None
+            }
+        }
+    }
+
+    fn expect_text(&mut self, expected: &str) -> Option<String> {
+        match self.peek_token() {
+            Some(Token::Text(text)) => {
+                let text = text.clone();
+                self.pos += 1;
+                Some(text)
+            }
+            _ => {
+                self.error_here(expected);
+                None
+            }
+        }
+    }
+
+    /// Consume `{` and return the offset just after it.
+    fn open_body(&mut self, keyword: &str) -> Option<usize> {
+        match self.eat(&Token::LBrace) {
+            Some(open) => Some(open.end),
+            None => {
+                self.error_here(&format!("`{{` after `{keyword}`"));
+                None
+            }
+        }
+    }
+
+    /// Consume `}` and return the offset just before it, or report the unclosed brace.
+    fn close_body(&mut self, body_start: usize) -> usize {
+        match self.peek().cloned() {
+            Some((start, Token::RBrace, _)) => {
+                self.pos += 1;
+                start
+            }
+            _ => {
+                let open = body_start.saturating_sub(1)..body_start;
+                self.error(open, "unclosed `{`: expected `}`".to_string());
+                self.end_of_input()
+            }
+        }
+    }
+
+    fn parse_vec(&mut self, start: usize) -> Option<Value> {
+        self.pos += 1;
+        let body_start = self.open_body("vec")?;
+        let mut items = Vec::new();
+        while !self.at_body_end() {
+            let before = self.pos;
+            let parsed = self.parse_annotated();
+            let ok = parsed.is_some();
+            match parsed {
+                Some(value) => items.push(value),
+                None => self.recover(&[Token::Semi]),
+            }
+            self.finish_entry(before, ok);
+        }
+        let body_end = self.close_body(body_start);
+        Some(Value {
+            kind: ValueKind::Vec {
+                items,
+                body: body_start..body_end,
+            },
+            span: start..self.last_end().max(body_end),
+        })
+    }
+
+    fn parse_record(&mut self, start: usize) -> Option<Value> {
+        self.pos += 1;
+        let body_start = self.open_body("record")?;
+        let mut fields = Vec::new();
+        let mut position = 0u32;
+        while !self.at_body_end() {
+            let before = self.pos;
+            let parsed = self.parse_field(false, &mut position);
+            let ok = parsed.is_some();
+            match parsed {
+                Some(field) => fields.push(field),
+                None => self.recover(&[Token::Semi]),
+            }
+            self.finish_entry(before, ok);
+        }
+        let body_end = self.close_body(body_start);
+        Some(Value {
+            kind: ValueKind::Record {
+                fields,
+                body: body_start..body_end,
+            },
+            span: start..self.last_end().max(body_end),
+        })
+    }
+
+    fn parse_variant(&mut self, start: usize) -> Option<Value> {
+        self.pos += 1;
+        let body_start = self.open_body("variant")?;
+        let mut field = None;
+        if !self.at_body_end() {
+            let before = self.pos;
+            field = self.parse_field(true, &mut 0);
+            if field.is_none() {
+                self.recover(&[Token::Semi]);
+            }
+            self.finish_entry(before, field.is_some());
+            if !self.at_body_end() {
+                let extra_start = self.next_start();
+                self.recover(&[]);
+                let extra_end = self.last_end();
+                self.error(
+                    extra_start..extra_end,
+                    "a variant value has exactly one case".to_string(),
+                );
+            }
+        } else if self.peek().is_some() {
+            let (rbrace_start, _, rbrace_end) = self.peek().cloned()?;
+            self.error(
+                rbrace_start..rbrace_end,
+                "expected a variant case before `}`".to_string(),
+            );
+        }
+        let body_end = self.close_body(body_start);
+        Some(Value {
+            kind: ValueKind::Variant {
+                field: field.map(Box::new),
+                body: body_start..body_end,
+            },
+            span: start..self.last_end().max(body_end),
+        })
+    }
+
+    /// Whether the next token closes a `{ ... }` body; a stray `)` closes it too.
+    fn at_body_end(&self) -> bool {
+        matches!(
+            self.peek_token(),
+            None | Some(Token::RBrace) | Some(Token::RParen)
+        )
+    }
+
+    /// Expect `;` or `}` after a vec item or field, recovering past anything else.
+    ///
+    /// `ok` is false when the entry already reported an error, so it is not reported twice.
+    fn finish_entry(&mut self, before: usize, ok: bool) {
+        if self.eat(&Token::Semi).is_some() || self.at_body_end() {
+            return;
+        }
+        if ok {
+            self.error_here("`;` or `}`");
+            self.recover(&[Token::Semi]);
+            self.eat(&Token::Semi);
+        }
+        if self.pos == before {
+            self.pos += 1;
+        }
+    }
+
+    /// `label = value`, a bare variant tag, or a positional record field.
+    fn parse_field(&mut self, variant: bool, position: &mut u32) -> Option<ValueField> {
+        let (start, token, end) = self.peek().cloned()?;
+        let label = match &token {
+            Token::Id(name) | Token::Text(name) => Some(Label::Named(name.clone())),
+            Token::Decimal(digits) => digits.replace('_', "").parse().ok().map(Label::Id),
+            _ => None,
+        };
+        let labelled = label.is_some() && (variant || self.nth_token(1) == Some(&Token::Equals));
+        if !labelled {
+            if variant {
+                self.error_here("a variant tag");
+                return None;
+            }
+            let value = self.parse_annotated()?;
+            let label = Label::Unnamed(*position);
+            *position += 1;
+            return Some(ValueField {
+                label,
+                label_span: None,
+                span: value.span.clone(),
+                value: Some(value),
+            });
+        }
+        self.pos += 1;
+        let label = label?;
+        if let Label::Id(id) = label {
+            *position = id.saturating_add(1);
+        }
+        let value = if self.eat(&Token::Equals).is_some() {
+            Some(self.parse_annotated()?)
+        } else {
+            None
+        };
+        let span_end = value.as_ref().map_or(end, |value| value.span.end);
+        Some(ValueField {
+            label,
+            label_span: Some(start..end),
+            value,
+            span: start..span_end,
+        })
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Id(name) => format!("identifier `{name}`"),
+        Token::Text(_) => "text literal".to_string(),
+        Token::Decimal(value) | Token::Hex(value) | Token::Float(value) => {
+            format!("number `{value}`")
+        }
+        Token::Boolean(value) => format!("`{value}`"),
+        Token::Sign(sign) => format!("`{sign}`"),
+        Token::LParen => "`(`".to_string(),
+        Token::RParen => "`)`".to_string(),
+        Token::LBrace => "`{`".to_string(),
+        Token::RBrace => "`}`".to_string(),
+        Token::Semi => "`;`".to_string(),
+        Token::Comma => "`,`".to_string(),
+        Token::Colon => "`:`".to_string(),
+        Token::Equals => "`=`".to_string(),
+        _ => "keyword".to_string(),
+    }
+}
@@ -0,0 +1,694 @@
+use crate::{
+    candid_lang::{clone_dec, parse_program},
+    lsp::{
+        span::Span,
+        type_display::{prim_to_str, render_type_summary},
+        value::{Value, ValueArgs, ValueError, ValueField, ValueKind},
+    },
+};
+use candid_parser::{
+    candid::{
+        Principal, TypeEnv as CheckedEnv,
+        types::{Label, Type},
+    },
+    syntax::{
+        Binding, Dec, FuncType, IDLActorType, IDLMergedProg, IDLProg, IDLType, IDLTypeWithSpan,
+        PrimType, TypeField,
+    },
+    typing::{ast_to_type, check_prog},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+/// Longest chain of type aliases followed before a name is treated as cyclic.
+const MAX_ALIAS_DEPTH: usize = 64;
+
+/// Type definitions and the service of a Candid program, for checking values against them.
+///
+/// Holds both the syntactic definitions, which keep source spans for diagnostics, and the
+/// environment `candid_parser::typing::check_prog` builds from them.
+#[derive(Debug)]
+pub struct TypeEnv {
+    types: HashMap<String, IDLTypeWithSpan>,
+    actor: Option<IDLTypeWithSpan>,
+    checked: Result<CheckedTypes, String>,
+}
+
+/// Output of `check_prog` over a program and its imports.
+#[derive(Debug, Default)]
+pub struct CheckedTypes {
+    pub env: CheckedEnv,
+    pub actor: Option<Type>,
+}
+
+impl Default for TypeEnv {
+    fn default() -> Self {
+        Self {
+            types: HashMap::new(),
+            actor: None,
+            checked: Ok(CheckedTypes::default()),
+        }
+    }
+}
+
+impl TypeEnv {
+    /// Collect the definitions of `ast`, following its imports relative to `document` when the
+    /// program lives on disk, and type-check them.
+    pub fn new(ast: &IDLMergedProg, document: Option<&Path>) -> Self {
+        let mut imported = Vec::new();
+        let mut imported_actor = None;
+        if let Some(dir) = document.and_then(Path::parent) {
+            let mut visited = HashSet::new();
+            if let Some(document) = document {
+                visited.insert(document.to_path_buf());
+            }
+            for dec in ast.decs().iter() {
+                load_import(dec, dir, &mut visited, &mut imported, &mut imported_actor);
+            }
+        }
+        let own = ast.decs().iter().filter(|dec| matches!(dec, Dec::TypD(_)));
+        let decs: Vec<Dec> = imported.iter().chain(own).map(clone_dec).collect();
+        let actor = match ast.resolve_actor() {
+            Ok(Some(actor)) => Some(actor),
+            Ok(None) => imported_actor,
+            Err(_) => None,
+        };
+        let types = decs
+            .iter()
+            .filter_map(|dec| match dec {
+                Dec::TypD(binding) => Some((binding.id.clone(), binding.typ.clone())),
+                Dec::ImportType { .. } | Dec::ImportServ { .. } => None,
+            })
+            .collect();
+        let syntax_actor = actor.as_ref().map(|actor| actor.typ.clone());
+        let mut env = CheckedEnv::new();
+        let checked = check_prog(&mut env, &IDLProg { decs, actor })
+            .map(|actor| CheckedTypes { env, actor })
+            .map_err(|err| err.to_string());
+        Self {
+            types,
+            actor: syntax_actor,
+            checked,
+        }
+    }
+
+    /// The `candid` type environment, or the error that stopped the program from checking.
+    pub fn checked(&self) -> Result<&CheckedTypes, &str> {
+        self.checked.as_ref().map_err(String::as_str)
+    }
+
+    /// `ty` as a `candid` type of the checked environment.
+    pub fn candid_type(&self, ty: &IDLType) -> Result<Type, String> {
+        let checked = self.checked().map_err(str::to_string)?;
+        ast_to_type(&checked.env, ty).map_err(|err| err.to_string())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&IDLTypeWithSpan> {
+        self.types.get(name)
+    }
+
+    /// Follow type names to a structural type; `None` for undefined or cyclic names.
+    pub fn resolve<'a>(&'a self, mut ty: &'a IDLType) -> Option<&'a IDLType> {
+        for _ in 0..MAX_ALIAS_DEPTH {
+            match ty {
+                IDLType::VarT(name) => ty = &self.types.get(name)?.kind,
+                other => return Some(other),
+            }
+        }
+        None
+    }
+
+    /// Methods of the program's service, following names and class constructors.
+    pub fn service_methods(&self) -> &[Binding] {
+        let mut ty = self.actor.as_ref().map(|actor| &actor.kind);
+        while let Some(current) = ty {
+            match self.resolve(current) {
+                Some(IDLType::ServT(methods)) => return methods,
+                Some(IDLType::ClassT(_, ret)) => ty = Some(&ret.kind),
+                _ => break,
+            }
+        }
+        &[]
+    }
+
+    /// Function type of the service method called `name`.
+    pub fn method(&self, name: &str) -> Option<&FuncType> {
+        let binding = self
+            .service_methods()
+            .iter()
+            .find(|binding| binding.id == name)?;
+        match self.resolve(&binding.typ.kind)? {
+            IDLType::FuncT(func) => Some(func),
+            _ => None,
+        }
+    }
+}
+
+/// Read the file an import declaration names and collect its type definitions, following its own
+/// imports; a service import also yields the imported service.
+fn load_import(
+    dec: &Dec,
+    dir: &Path,
+    visited: &mut HashSet<PathBuf>,
+    decs: &mut Vec<Dec>,
+    actor: &mut Option<IDLActorType>,
+) {
+    let (path, is_service) = match dec {
+        Dec::ImportType { path, .. } => (path, false),
+        Dec::ImportServ { path, .. } => (path, true),
+        Dec::TypD(_) => return,
+    };
+    let path = dir.join(path);
+    let path = path.canonicalize().unwrap_or(path);
+    if !visited.insert(path.clone()) {
+        return;
+    }
+    let Some(program) = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|source| parse_program(&source).program)
+    else {
+        return;
+    };
+    let program = IDLMergedProg::new(program);
+    let inner_dir = path.parent().unwrap_or(dir);
+    for inner in program.decs().iter() {
+        load_import(inner, inner_dir, visited, decs, actor);
+    }
+    decs.extend(
+        program
+            .decs()
+            .iter()
+            .filter(|dec| matches!(dec, Dec::TypD(_)))
+            .map(clone_dec),
+    );
+    if is_service && actor.is_none() {
+        *actor = program.resolve_actor().ok().flatten();
+    }
+}
+
+/// The first type name used in `ty` that `env` does not define.
+pub(crate) fn undefined_type(ty: &IDLType, env: &TypeEnv) -> Option<String> {
+    match ty {
+        IDLType::VarT(name) => env.get(name).is_none().then(|| name.clone()),
+        IDLType::OptT(inner) | IDLType::VecT(inner) => undefined_type(&inner.kind, env),
+        IDLType::RecordT(fields) | IDLType::VariantT(fields) => fields
+            .iter()
+            .find_map(|field| undefined_type(&field.typ.kind, env)),
+        IDLType::FuncT(func) => func
+            .args
+            .iter()
+            .chain(&func.rets)
+            .find_map(|ty| undefined_type(&ty.kind, env)),
+        IDLType::ServT(methods) => methods
+            .iter()
+            .find_map(|method| undefined_type(&method.typ.kind, env)),
+        IDLType::ClassT(args, ret) => args
+            .iter()
+            .chain(std::iter::once(ret.as_ref()))
+            .find_map(|ty| undefined_type(&ty.kind, env)),
+        IDLType::PrimT(_) | IDLType::PrincipalT => None,
+    }
+}
+
+/// Type-check an argument list against the parameter types of a method.
+pub fn check_args(args: &ValueArgs, types: &[IDLTypeWithSpan], env: &TypeEnv) -> Vec<ValueError> {
+    let mut checker = Checker {
+        env,
+        errors: Vec::new(),
+    };
+    for (value, ty) in args.values.iter().zip(types) {
+        checker.check(value, &ty.kind);
+    }
+    for value in args.values.iter().skip(types.len()) {
+        checker.error(
+            value.span.clone(),
+            format!(
+                "unexpected argument: the method takes {} argument{}",
+                types.len(),
+                if types.len() == 1 { "" } else { "s" }
+            ),
+        );
+    }
+    let close = args.span.end.saturating_sub(1)..args.span.end;
+    for (idx, ty) in types.iter().enumerate().skip(args.values.len()) {
+        if !checker.accepts_absence(&ty.kind) {
+            checker.error(
+                close.clone(),
+                format!(
+                    "missing argument {} of type `{}`",
+                    idx + 1,
+                    render_type_summary(&ty.kind)
+                ),
+            );
+        }
+    }
+    checker.errors
+}
+
+/// Check a single value against `ty`.
+pub fn check_value(value: &Value, ty: &IDLType, env: &TypeEnv) -> Vec<ValueError> {
+    let mut checker = Checker {
+        env,
+        errors: Vec::new(),
+    };
+    checker.check(value, ty);
+    checker.errors
+}
+
+struct Checker<'a> {
+    env: &'a TypeEnv,
+    errors: Vec<ValueError>,
+}
+
+impl Checker<'_> {
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(ValueError { span, message });
+    }
+
+    fn mismatch(&mut self, value: &Value, ty: &IDLType) {
+        self.error(
+            value.span.clone(),
+            format!(
+                "expected `{}`, found {}",
+                render_type_summary(ty),
+                describe_value(&value.kind)
+            ),
+        );
+    }
+
+    /// Whether a missing record field or argument of type `ty` decodes as a default.
+    fn accepts_absence(&self, ty: &IDLType) -> bool {
+        matches!(
+            self.env.resolve(ty),
+            Some(
+                IDLType::OptT(_)
+                    | IDLType::PrimT(PrimType::Null)
+                    | IDLType::PrimT(PrimType::Reserved)
+            )
+        )
+    }
+
+    fn check(&mut self, value: &Value, ty: &IDLType) {
+        let Some(resolved) = self.env.resolve(ty) else {
+            return;
+        };
+        match (&value.kind, resolved) {
+            (_, IDLType::PrimT(PrimType::Reserved)) => {}
+            (_, IDLType::PrimT(PrimType::Empty)) => {
+                self.error(value.span.clone(), "no value has type `empty`".to_string())
+            }
+            (
+                ValueKind::Annotated {
+                    value: inner,
+                    annotation,
+                    annotation_span,
+                },
+                _,
+            ) => {
+                if let (Some(annotated), IDLType::PrimT(expected)) =
+                    (prim_from_name(annotation), resolved)
+                    && annotated != *expected
+                {
+                    self.error(
+                        annotation_span.clone(),
+                        format!(
+                            "annotation `{annotation}` does not match the expected type `{}`",
+                            prim_to_str(expected)
+                        ),
+                    );
+                }
+                self.check(inner, ty);
+            }
+            (ValueKind::Null, IDLType::PrimT(PrimType::Null) | IDLType::OptT(_)) => {}
+            (ValueKind::Bool(_), IDLType::PrimT(PrimType::Bool)) => {}
+            (ValueKind::Text(_), IDLType::PrimT(PrimType::Text)) => {}
+            (ValueKind::Number { text, float }, IDLType::PrimT(prim)) => {
+                if let Err(message) = check_number(text, *float, prim) {
+                    self.error(value.span.clone(), message);
+                }
+            }
+            (ValueKind::Opt(inner), IDLType::OptT(inner_ty)) => self.check(inner, &inner_ty.kind),
+            // A bare value is accepted where an `opt` is expected.
+            (_, IDLType::OptT(inner_ty)) => self.check(value, &inner_ty.kind),
+            (ValueKind::Vec { items, .. }, IDLType::VecT(inner_ty)) => {
+                for item in items {
+                    self.check(item, &inner_ty.kind);
+                }
+            }
+            (ValueKind::Blob(_), IDLType::VecT(inner_ty))
+                if matches!(
+                    self.env.resolve(&inner_ty.kind),
+                    Some(IDLType::PrimT(PrimType::Nat8))
+                ) => {}
+            (ValueKind::Record { fields, body }, IDLType::RecordT(type_fields)) => {
+                let header = value.span.start..body.start;
+                self.check_record(fields, type_fields, header);
+            }
+            (ValueKind::Variant { field, .. }, IDLType::VariantT(type_fields)) => {
+                if let Some(field) = field {
+                    self.check_variant(field, type_fields);
+                }
+            }
+            (ValueKind::Principal(text), IDLType::PrincipalT)
+            | (ValueKind::Service(text), IDLType::ServT(_))
+            | (
+                ValueKind::Func {
+                    principal: text, ..
+                },
+                IDLType::FuncT(_),
+            ) => {
+                if Principal::from_text(text).is_err() {
+                    self.error(value.span.clone(), format!("invalid principal `{text}`"));
+                }
+            }
+            _ => self.mismatch(value, resolved),
+        }
+    }
+
+    fn check_record(&mut self, fields: &[ValueField], type_fields: &[TypeField], header: Span) {
+        let mut seen = HashSet::new();
+        for field in fields {
+            let id = field.label.get_id();
+            let span = field
+                .label_span
+                .clone()
+                .unwrap_or_else(|| field.span.clone());
+            if !seen.insert(id) {
+                self.error(
+                    span,
+                    format!(
+                        "field `{}` is given more than once",
+                        label_text(&field.label)
+                    ),
+                );
+                continue;
+            }
+            let Some(type_field) = find_field(type_fields, &field.label) else {
+                self.error(
+                    span,
+                    format!("record has no field `{}`", label_text(&field.label)),
+                );
+                continue;
+            };
+            if let Some(value) = &field.value {
+                self.check(value, &type_field.typ.kind);
+            }
+        }
+        let missing: Vec<_> = type_fields
+            .iter()
+            .filter(|type_field| !seen.contains(&type_field.label.get_id()))
+            .filter(|type_field| !self.accepts_absence(&type_field.typ.kind))
+            .map(|type_field| format!("`{}`", label_text(&type_field.label)))
+            .collect();
+        if !missing.is_empty() {
+            self.error(
+                header,
+                format!(
+                    "missing field{} {}",
+                    if missing.len() == 1 { "" } else { "s" },
+                    missing.join(", ")
+                ),
+            );
+        }
+    }
+
+    fn check_variant(&mut self, field: &ValueField, type_fields: &[TypeField]) {
+        let span = field
+            .label_span
+            .clone()
+            .unwrap_or_else(|| field.span.clone());
+        let Some(type_field) = find_field(type_fields, &field.label) else {
+            self.error(
+                span,
+                format!("variant has no case `{}`", label_text(&field.label)),
+            );
+            return;
+        };
+        match &field.value {
+            Some(value) => self.check(value, &type_field.typ.kind),
+            None => {
+                let unit = matches!(
+                    self.env.resolve(&type_field.typ.kind),
+                    Some(IDLType::PrimT(PrimType::Null | PrimType::Reserved))
+                );
+                if !unit {
+                    self.error(
+                        span,
+                        format!(
+                            "case `{}` needs a value of type `{}`",
+                            label_text(&field.label),
+                            render_type_summary(&type_field.typ.kind)
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Where the cursor sits inside an argument list, with the type expected there.
+#[derive(Debug, Clone)]
+pub enum CompletionSite<'a> {
+    /// A label position in a record or variant body.
+    Labels {
+        fields: &'a [TypeField],
+        /// Ids of the labels already present in the record.
+        present: Vec<u32>,
+        variant: bool,
+    },
+    /// A value position expecting the given type.
+    Value(&'a IDLType),
+}
+
+/// Find what should be completed at `offset` in `src`, given the parsed arguments.
+pub fn completion_site<'a>(
+    args: &ValueArgs,
+    types: &'a [IDLTypeWithSpan],
+    env: &'a TypeEnv,
+    src: &str,
+    offset: usize,
+) -> Option<CompletionSite<'a>> {
+    if offset <= args.span.start || offset > args.span.end {
+        return None;
+    }
+    if let Some((idx, value)) = args
+        .values
+        .iter()
+        .enumerate()
+        .find(|(_, value)| contains(&value.span, offset))
+    {
+        return locate(value, &types.get(idx)?.kind, env, src, offset);
+    }
+    let idx = args
+        .values
+        .iter()
+        .filter(|value| value.span.end <= offset)
+        .count();
+    Some(CompletionSite::Value(&types.get(idx)?.kind))
+}
+
+fn locate<'a>(
+    value: &Value,
+    ty: &'a IDLType,
+    env: &'a TypeEnv,
+    src: &str,
+    offset: usize,
+) -> Option<CompletionSite<'a>> {
+    let resolved = env.resolve(ty)?;
+    match (&value.kind, resolved) {
+        (ValueKind::Annotated { value: inner, .. }, _) => locate(inner, ty, env, src, offset),
+        (ValueKind::Opt(inner), IDLType::OptT(inner_ty)) if contains(&inner.span, offset) => {
+            locate(inner, &inner_ty.kind, env, src, offset)
+        }
+        (ValueKind::Vec { items, body }, IDLType::VecT(inner_ty)) if contains(body, offset) => {
+            match items.iter().find(|item| contains(&item.span, offset)) {
+                Some(item) => locate(item, &inner_ty.kind, env, src, offset),
+                None => Some(CompletionSite::Value(&inner_ty.kind)),
+            }
+        }
+        (ValueKind::Record { fields, body }, IDLType::RecordT(type_fields))
+            if contains(body, offset) =>
+        {
+            let current = fields.iter().find_map(|field| {
+                let value = field
+                    .value
+                    .as_ref()
+                    .filter(|value| contains(&value.span, offset))?;
+                Some((value, find_field(type_fields, &field.label)))
+            });
+            if let Some((value, type_field)) = current {
+                return locate(value, &type_field?.typ.kind, env, src, offset);
+            }
+            match pending_label(src, body, offset) {
+                Some(label) => {
+                    let type_field = find_field(type_fields, &label)?;
+                    Some(CompletionSite::Value(&type_field.typ.kind))
+                }
+                None => Some(CompletionSite::Labels {
+                    fields: type_fields,
+                    present: fields.iter().map(|field| field.label.get_id()).collect(),
+                    variant: false,
+                }),
+            }
+        }
+        (ValueKind::Variant { field, body }, IDLType::VariantT(type_fields))
+            if contains(body, offset) =>
+        {
+            if let Some(field) = field
+                && let Some(inner) = &field.value
+                && contains(&inner.span, offset)
+            {
+                let type_field = find_field(type_fields, &field.label)?;
+                return locate(inner, &type_field.typ.kind, env, src, offset);
+            }
+            match pending_label(src, body, offset) {
+                Some(label) => {
+                    let type_field = find_field(type_fields, &label)?;
+                    Some(CompletionSite::Value(&type_field.typ.kind))
+                }
+                None => Some(CompletionSite::Labels {
+                    fields: type_fields,
+                    present: Vec::new(),
+                    variant: true,
+                }),
+            }
+        }
+        (
+            ValueKind::Record { .. }
+            | ValueKind::Variant { .. }
+            | ValueKind::Vec { .. }
+            | ValueKind::Opt(_),
+            _,
+        ) => None,
+        _ => Some(CompletionSite::Value(ty)),
+    }
+}
+
+/// The label before `=` in the body entry the cursor is in, if the cursor is past the `=`.
+fn pending_label(src: &str, body: &Span, offset: usize) -> Option<Label> {
+    let entry = src.get(body.start..offset)?.rsplit(';').next()?;
+    let (label, _) = entry.split_once('=')?;
+    let label = label.trim().trim_matches('"');
+    Some(match label.parse::<u32>() {
+        Ok(id) => Label::Id(id),
+        Err(_) => Label::Named(label.to_string()),
+    })
+}
+
+fn contains(span: &Span, offset: usize) -> bool {
+    span.start <= offset && offset <= span.end
+}
+
+fn find_field<'a>(type_fields: &'a [TypeField], label: &Label) -> Option<&'a TypeField> {
+    let id = label.get_id();
+    type_fields
+        .iter()
+        .find(|type_field| type_field.label.get_id() == id)
+}
+
+/// A label as written in source: the name, or the number for numeric and positional labels.
+pub fn label_text(label: &Label) -> String {
+    match label {
+        Label::Named(name) => name.clone(),
+        Label::Id(id) | Label::Unnamed(id) => id.to_string(),
+    }
+}
+
+fn describe_value(kind: &ValueKind) -> String {
+    match kind {
+        ValueKind::Null => "`null`".to_string(),
+        ValueKind::Bool(value) => format!("`{value}`"),
+        ValueKind::Number { text, .. } => format!("number `{text}`"),
+        ValueKind::Text(_) => "a text literal".to_string(),
+        ValueKind::Blob(_) => "a blob".to_string(),
+        ValueKind::Opt(_) => "an `opt` value".to_string(),
+        ValueKind::Vec { .. } => "a `vec`".to_string(),
+        ValueKind::Record { .. } => "a `record`".to_string(),
+        ValueKind::Variant { .. } => "a `variant`".to_string(),
+        ValueKind::Principal(_) => "a principal".to_string(),
+        ValueKind::Service(_) => "a service reference".to_string(),
+        ValueKind::Func { .. } => "a function reference".to_string(),
+        ValueKind::Annotated { value, .. } => describe_value(&value.kind),
+    }
+}
+
+fn prim_from_name(name: &str) -> Option<PrimType> {
+    Some(match name {
+        "nat" => PrimType::Nat,
+        "nat8" => PrimType::Nat8,
+        "nat16" => PrimType::Nat16,
+        "nat32" => PrimType::Nat32,
+        "nat64" => PrimType::Nat64,
+        "int" => PrimType::Int,
+        "int8" => PrimType::Int8,
+        "int16" => PrimType::Int16,
+        "int32" => PrimType::Int32,
+        "int64" => PrimType::Int64,
+        "float32" => PrimType::Float32,
+        "float64" => PrimType::Float64,
+        "bool" => PrimType::Bool,
+        "text" => PrimType::Text,
+        "null" => PrimType::Null,
+        "reserved" => PrimType::Reserved,
+        "empty" => PrimType::Empty,
+        _ => return None,
+    })
+}
+
+/// Check a numeric literal against a primitive type, including the range of sized integers.
+fn check_number(text: &str, float: bool, prim: &PrimType) -> Result<(), String> {
+    let name = prim_to_str(prim);
+    let (unsigned, bits) = match prim {
+        PrimType::Float32 | PrimType::Float64 => return Ok(()),
+        PrimType::Nat => (true, None),
+        PrimType::Nat8 => (true, Some(8)),
+        PrimType::Nat16 => (true, Some(16)),
+        PrimType::Nat32 => (true, Some(32)),
+        PrimType::Nat64 => (true, Some(64)),
+        PrimType::Int => (false, None),
+        PrimType::Int8 => (false, Some(8)),
+        PrimType::Int16 => (false, Some(16)),
+        PrimType::Int32 => (false, Some(32)),
+        PrimType::Int64 => (false, Some(64)),
+        _ => return Err(format!("expected `{name}`, found number `{text}`")),
+    };
+    if float {
+        return Err(format!("expected an integer for `{name}`, found `{text}`"));
+    }
+    let cleaned = text.replace('_', "");
+    let (negative, magnitude) = match cleaned.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, cleaned.strip_prefix('+').unwrap_or(&cleaned)),
+    };
+    let parsed = match magnitude
+        .strip_prefix("0x")
+        .or_else(|| magnitude.strip_prefix("0X"))
+    {
+        Some(hex) => u128::from_str_radix(hex, 16),
+        None => magnitude.parse::<u128>(),
+    };
+    let is_zero = parsed.as_ref().is_ok_and(|value| *value == 0);
+    if unsigned && negative && !is_zero {
+        return Err(format!("`{name}` cannot be negative"));
+    }
+    let (Some(bits), Ok(value)) = (bits, parsed) else {
+        // Unbounded types, or literals too large for `u128` that only fit unbounded types.
+        return match (bits, parsed) {
+            (Some(_), Err(_)) => Err(format!("`{text}` is out of range for `{name}`")),
+            _ => Ok(()),
+        };
+    };
+    let max = if unsigned {
+        (1u128 << bits) - 1
+    } else if negative {
+        1u128 << (bits - 1)
+    } else {
+        (1u128 << (bits - 1)) - 1
+    };
+    if value > max {
+        return Err(format!("`{text}` is out of range for `{name}`"));
+    }
+    Ok(())
+}
@@ -0,0 +1,349 @@
+use crate::{
+    candid_lang::{ImCompleteSemanticToken, ParserResult, parse},
+    lsp::{
+        diagnostic_rules::DiagnosticRule,
+        position::span_to_range,
+        span::Span,
+        type_display::render_type_summary,
+        value::{ValueError, parse_value_args},
+        value_check::{CompletionSite, TypeEnv, check_args, completion_site, label_text},
+        workspace::{is_candid_file, uri_to_path},
+    },
+};
+use candid_parser::syntax::{IDLType, IDLTypeWithSpan, PrimType, TypeField};
+use ropey::Rope;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tower_lsp_server::ls_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity, InsertTextFormat, Uri,
+};
+
+/// Comment directive naming the method whose arguments a value document holds.
+pub const METHOD_DIRECTIVE: &str = "candid-ls-method";
+/// Comment directive naming the interface file, relative to the value document.
+pub const INTERFACE_DIRECTIVE: &str = "candid-ls-did";
+
+/// The method a value document is checked against.
+#[derive(Debug)]
+pub struct ValueTarget {
+    pub interface: PathBuf,
+    pub method: String,
+    pub env: TypeEnv,
+    pub arg_types: Vec<IDLTypeWithSpan>,
+}
+
+/// Why the target of a value document could not be resolved, and where to report it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetError {
+    pub span: Option<Span>,
+    pub message: String,
+}
+
+/// Directive values with the span of the comment they were found in.
+#[derive(Debug, Default)]
+struct Directives {
+    method: Option<(String, Span)>,
+    interface: Option<(String, Span)>,
+}
+
+fn directives(src: &str) -> Directives {
+    let mut found = Directives::default();
+    let mut offset = 0;
+    for line in src.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let Some(comment) = line.trim().strip_prefix("//") else {
+            continue;
+        };
+        let comment = comment.trim();
+        let span = start..start + line.trim_end().len();
+        for (directive, slot) in [
+            (METHOD_DIRECTIVE, &mut found.method),
+            (INTERFACE_DIRECTIVE, &mut found.interface),
+        ] {
+            if let Some(value) = comment.strip_prefix(directive) {
+                let value = value.trim_start_matches(':').trim();
+                if !value.is_empty() {
+                    *slot = Some((value.to_string(), span.clone()));
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Find the interface and method for the value document at `uri`.
+///
+/// Without directives the interface is the sibling `<name>.did`, or the only `.did` file in the
+/// same directory, and the method is the one named like the document or the service's only
+/// method. `Ok(None)` means no target could be inferred and nothing was asked for explicitly.
+pub fn resolve_target(uri: &Uri, src: &str) -> Result<Option<ValueTarget>, TargetError> {
+    let Directives { method, interface } = directives(src);
+    let path = uri_to_path(uri);
+    let dir = path.as_deref().and_then(Path::parent);
+    let stem = path
+        .as_deref()
+        .and_then(Path::file_stem)
+        .map(|stem| stem.to_string_lossy().into_owned());
+
+    let interface_path = match (&interface, dir) {
+        (Some((relative, _)), Some(dir)) => Some(dir.join(relative)),
+        (Some((relative, _)), None) => Some(PathBuf::from(relative)),
+        (None, Some(dir)) => default_interface(dir, stem.as_deref()),
+        (None, None) => None,
+    };
+    let Some(interface_path) = interface_path else {
+        return match method {
+            Some((_, span)) => Err(TargetError {
+                span: Some(span),
+                message: format!(
+                    "no interface found for this file; add `// {INTERFACE_DIRECTIVE}: <path>.did`"
+                ),
+            }),
+            None => Ok(None),
+        };
+    };
+    let interface_span = interface.as_ref().map(|(_, span)| span.clone());
+    let display = interface_path.display().to_string();
+    let text = fs::read_to_string(&interface_path).map_err(|err| TargetError {
+        span: interface_span.clone(),
+        message: format!("cannot read `{display}`: {err}"),
+    })?;
+    let ParserResult { ast, .. } = parse(&text);
+    let Some(ast) = ast else {
+        return Err(TargetError {
+            span: interface_span,
+            message: format!("`{display}` has syntax errors"),
+        });
+    };
+    let env = TypeEnv::new(&ast, Some(&interface_path));
+
+    let method_name = match &method {
+        Some((name, _)) => Some(name.clone()),
+        None => {
+            let methods = env.service_methods();
+            stem.as_ref()
+                .filter(|stem| methods.iter().any(|binding| &binding.id == *stem))
+                .cloned()
+                .or_else(|| match methods {
+                    [only] => Some(only.id.clone()),
+                    _ => None,
+                })
+        }
+    };
+    let Some(method_name) = method_name else {
+        return match interface_span {
+            Some(span) => Err(TargetError {
+                span: Some(span),
+                message: format!("add `// {METHOD_DIRECTIVE}: <name>` to choose a method"),
+            }),
+            None => Ok(None),
+        };
+    };
+    let Some(func) = env.method(&method_name) else {
+        return Err(TargetError {
+            span: method.map(|(_, span)| span),
+            message: format!("`{display}` has no method `{method_name}`"),
+        });
+    };
+    let arg_types = func.args.clone();
+    Ok(Some(ValueTarget {
+        interface: interface_path,
+        method: method_name,
+        env,
+        arg_types,
+    }))
+}
+
+fn default_interface(dir: &Path, stem: Option<&str>) -> Option<PathBuf> {
+    if let Some(stem) = stem {
+        let sibling = dir.join(format!("{stem}.did"));
+        if sibling.is_file() {
+            return Some(sibling);
+        }
+    }
+    let mut candidates = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_candid_file(path));
+    let only = candidates.next()?;
+    candidates.next().is_none().then_some(only)
+}
+
+/// Diagnostics and semantic tokens for a value document.
+#[derive(Debug)]
+pub struct ValueDocumentAnalysis {
+    pub diagnostics: Vec<Diagnostic>,
+    pub semantic_tokens: Vec<ImCompleteSemanticToken>,
+    pub parse_errors: usize,
+    /// Method the values were checked against, if one was resolved.
+    pub target: Option<ValueTarget>,
+}
+
+/// Parse a value document and, when it parses cleanly, check it against its target method.
+pub fn analyze_value_document(uri: &Uri, text: &str, rope: &Rope) -> ValueDocumentAnalysis {
+    let parsed = parse_value_args(text);
+    let parse_errors = parsed.errors.len();
+    let mut diagnostics: Vec<_> = parsed
+        .errors
+        .iter()
+        .filter_map(|err| value_diagnostic(DiagnosticRule::ValueSyntax, err, rope))
+        .collect();
+
+    let mut target = None;
+    match resolve_target(uri, text) {
+        Ok(Some(resolved)) => {
+            if let Some(args) = parsed.args.as_ref().filter(|_| parse_errors == 0) {
+                diagnostics.extend(
+                    check_args(args, &resolved.arg_types, &resolved.env)
+                        .iter()
+                        .filter_map(|err| {
+                            value_diagnostic(DiagnosticRule::ValueTypeMismatch, err, rope)
+                        }),
+                );
+            }
+            target = Some(resolved);
+        }
+        Ok(None) => {}
+        Err(err) => diagnostics.push(
+            DiagnosticRule::ValueTarget.annotate(Diagnostic {
+                range: err
+                    .span
+                    .and_then(|span| span_to_range(&span, rope))
+                    .unwrap_or_default(),
+                severity: Some(DiagnosticSeverity::WARNING),
+                source: Some("value".to_string()),
+                message: err.message,
+                ..Default::default()
+            }),
+        ),
+    }
+
+    ValueDocumentAnalysis {
+        diagnostics,
+        semantic_tokens: parsed.semantic_tokens,
+        parse_errors,
+        target,
+    }
+}
+
+fn value_diagnostic(rule: DiagnosticRule, err: &ValueError, rope: &Rope) -> Option<Diagnostic> {
+    Some(rule.annotate(Diagnostic {
+        range: span_to_range(&err.span, rope)?,
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("value".to_string()),
+        message: err.message.clone(),
+        ..Default::default()
+    }))
+}
+
+/// Completion items for the value expected at `offset` against the `target` resolved by the
+/// last analysis: field labels, variant tags, or literals of the expected type.
+pub fn value_completion(target: &ValueTarget, text: &str, offset: usize) -> Vec<CompletionItem> {
+    let parsed = parse_value_args(text);
+    let Some(args) = parsed.args else {
+        return Vec::new();
+    };
+    let Some(site) = completion_site(&args, &target.arg_types, &target.env, text, offset) else {
+        return Vec::new();
+    };
+    match site {
+        CompletionSite::Labels {
+            fields,
+            present,
+            variant,
+        } => fields
+            .iter()
+            .filter(|field| !present.contains(&field.label.get_id()))
+            .map(|field| label_item(field, variant, &target.env))
+            .collect(),
+        CompletionSite::Value(ty) => literal_items(ty, &target.env),
+    }
+}
+
+fn label_item(field: &TypeField, variant: bool, env: &TypeEnv) -> CompletionItem {
+    let label = label_text(&field.label);
+    let written = if label
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+    {
+        label.clone()
+    } else {
+        format!("{label:?}")
+    };
+    let unit = variant
+        && matches!(
+            env.resolve(&field.typ.kind),
+            Some(IDLType::PrimT(PrimType::Null))
+        );
+    CompletionItem {
+        label,
+        kind: Some(if variant {
+            CompletionItemKind::ENUM_MEMBER
+        } else {
+            CompletionItemKind::FIELD
+        }),
+        detail: Some(render_type_summary(&field.typ.kind)),
+        insert_text: Some(if unit {
+            written
+        } else {
+            format!("{written} = ")
+        }),
+        ..CompletionItem::default()
+    }
+}
+
+fn literal_items(ty: &IDLType, env: &TypeEnv) -> Vec<CompletionItem> {
+    let detail = render_type_summary(ty);
+    let item = |label: &str, snippet: Option<String>| CompletionItem {
+        label: label.to_string(),
+        kind: Some(CompletionItemKind::VALUE),
+        detail: Some(detail.clone()),
+        insert_text_format: snippet.as_ref().map(|_| InsertTextFormat::SNIPPET),
+        insert_text: snippet,
+        ..CompletionItem::default()
+    };
+    let Some(resolved) = env.resolve(ty) else {
+        return Vec::new();
+    };
+    match resolved {
+        IDLType::PrimT(PrimType::Bool) => vec![item("true", None), item("false", None)],
+        IDLType::PrimT(PrimType::Null | PrimType::Reserved) => vec![item("null", None)],
+        IDLType::PrimT(PrimType::Text) => vec![item("\"\"", Some("\"$0\"".to_string()))],
+        IDLType::OptT(_) => vec![item("null", None), item("opt", Some("opt $0".to_string()))],
+        IDLType::VecT(inner) => {
+            let mut items = vec![item("vec {}", Some("vec { $0 }".to_string()))];
+            if matches!(
+                env.resolve(&inner.kind),
+                Some(IDLType::PrimT(PrimType::Nat8))
+            ) {
+                items.push(item("blob \"\"", Some("blob \"$0\"".to_string())));
+            }
+            items
+        }
+        IDLType::RecordT(_) => vec![item("record {}", Some("record { $0 }".to_string()))],
+        IDLType::VariantT(fields) => fields
+            .iter()
+            .map(|field| {
+                let tag = label_text(&field.label);
+                let unit = matches!(
+                    env.resolve(&field.typ.kind),
+                    Some(IDLType::PrimT(PrimType::Null))
+                );
+                let snippet = if unit {
+                    format!("variant {{ {tag} }}")
+                } else {
+                    format!("variant {{ {tag} = $0 }}")
+                };
+                item(&format!("variant {{ {tag} }}"), Some(snippet))
+            })
+            .collect(),
+        IDLType::PrincipalT => vec![item("principal", Some("principal \"$0\"".to_string()))],
+        IDLType::ServT(_) => vec![item("service", Some("service \"$0\"".to_string()))],
+        IDLType::FuncT(_) => vec![item("func", Some("func \"$1\".$0".to_string()))],
+        _ => Vec::new(),
+    }
+}
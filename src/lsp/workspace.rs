@@ -0,0 +1,154 @@
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tower_lsp_server::ls_types::Uri;
+
+/// Directory names that never contain hand-written Candid interfaces.
+const SKIPPED_DIRS: [&str; 3] = ["target", "node_modules", "dist"];
+
+/// Convert a `file://` URI into a local filesystem path.
+///
+/// `file:///C:/dir` becomes the drive path `C:/dir` and `file://server/share` the UNC path
+/// `//server/share`; a `localhost` authority is the local machine.
+pub fn uri_to_path(uri: &Uri) -> Option<PathBuf> {
+    let rest = uri.as_str().strip_prefix("file://")?;
+    let rest = rest.split(['?', '#']).next()?;
+    let (host, path) = rest.split_at(rest.find('/')?);
+    let decoded = percent_decode(path)?;
+    if !host.is_empty() && !host.eq_ignore_ascii_case("localhost") {
+        return Some(PathBuf::from(format!(
+            "//{}{decoded}",
+            percent_decode(host)?
+        )));
+    }
+    let bytes = decoded.as_bytes();
+    let is_drive_path =
+        bytes.len() >= 3 && bytes[0] == b'/' && bytes[1].is_ascii_alphabetic() && bytes[2] == b':';
+    if is_drive_path {
+        Some(PathBuf::from(&decoded[1..]))
+    } else {
+        Some(PathBuf::from(decoded))
+    }
+}
+
+/// Convert an absolute filesystem path into a `file://` URI.
+///
+/// Drive paths get an empty authority (`file:///C:/dir`) and UNC paths, including the verbatim
+/// `\\?\` forms canonicalization produces on Windows, keep their server as the authority.
+pub fn path_to_uri(path: &Path) -> Option<Uri> {
+    let normalized = path.to_str()?.replace('\\', "/");
+    let normalized = match normalized.strip_prefix("//?/") {
+        Some(verbatim) => match verbatim.strip_prefix("UNC/") {
+            Some(unc) => format!("//{unc}"),
+            None => verbatim.to_string(),
+        },
+        None => normalized,
+    };
+    let mut encoded = String::with_capacity(normalized.len() + 8);
+    encoded.push_str("file://");
+    let path = match normalized.strip_prefix("//") {
+        Some(unc) => {
+            let (server, share) = unc.split_at(unc.find('/')?);
+            percent_encode(&mut encoded, server);
+            share
+        }
+        None => {
+            if !normalized.starts_with('/') {
+                encoded.push('/');
+            }
+            normalized.as_str()
+        }
+    };
+    percent_encode(&mut encoded, path);
+    Uri::from_str(&encoded).ok()
+}
+
+/// Recursively collect every `.did` file below `root`, skipping hidden and build directories.
+pub fn collect_did_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if file_type.is_dir() {
+                if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_ref()) {
+                    pending.push(path);
+                }
+            } else if file_type.is_file() && is_candid_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Whether `path` names a Candid interface file; test suites (`.test.did`) are not interfaces.
+pub fn is_candid_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "did")
+        && !path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().ends_with(".test.did"))
+}
+
+/// How a document is analysed, decided by its file name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentKind {
+    /// A Candid interface (`.did`).
+    Interface,
+    /// A Candid value argument list (`.args`), checked against a method of an interface.
+    Values,
+    /// A Candid test suite (`.test.did`): type definitions followed by decoding assertions.
+    TestSuite,
+}
+
+impl DocumentKind {
+    pub fn of(uri: &Uri) -> Self {
+        let path = uri.as_str().split(['?', '#']).next().unwrap_or_default();
+        if path.ends_with(".args") {
+            DocumentKind::Values
+        } else if path.ends_with(".test.did") {
+            DocumentKind::TestSuite
+        } else {
+            DocumentKind::Interface
+        }
+    }
+}
+
+fn percent_encode(out: &mut String, text: &str) {
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'/' | b'-' | b'_' | b'.' | b'~' | b':') {
+            out.push(byte as char);
+        } else {
+            let _ = write!(out, "%{byte:02X}");
+        }
+    }
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            let hex = text.get(idx + 1..idx + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            idx += 3;
+        } else {
+            decoded.push(bytes[idx]);
+            idx += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
@@ -1,4 +1,7 @@
-use candid_language_server::CandidLanguageServer;
+use candid_language_server::{
+    CandidLanguageServer,
+    lsp::type_relation::{IS_SUBTYPE_METHOD, TYPE_EQUALS_METHOD},
+};
 use tower_lsp_server::{LspService, Server};
 #[cfg(feature = "tracing")]
 use tracing_subscriber::{EnvFilter, fmt};
@@ -10,7 +13,10 @@ async fn main() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let (service, socket) = LspService::build(CandidLanguageServer::new).finish();
+    let (service, socket) = LspService::build(CandidLanguageServer::new)
+        .custom_method(IS_SUBTYPE_METHOD, CandidLanguageServer::is_subtype)
+        .custom_method(TYPE_EQUALS_METHOD, CandidLanguageServer::type_equals)
+        .finish();
 
     Server::new(stdin, stdout, socket).serve(service).await;
 }
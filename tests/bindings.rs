@@ -0,0 +1,115 @@
+use candid_language_server::{
+    candid_lang::parse,
+    lsp::{
+        bindings::{
+            BindingLanguage, BindingTarget, binding_item, binding_item_lines, bindings_document,
+            bindings_uri,
+        },
+        code_lens::binding_lenses,
+        commands::SHOW_BINDINGS,
+        value_check::TypeEnv,
+    },
+};
+use ropey::Rope;
+use std::str::FromStr;
+use tower_lsp_server::ls_types::Uri;
+
+const SOURCE: &str = "type user_profile = record { name : text; age : nat8 };\nservice : {\n  get : (nat) -> (user_profile) query;\n}\n";
+
+fn uri() -> Uri {
+    Uri::from_str("file:///workspace/profile.did").expect("valid uri")
+}
+
+#[test]
+fn languages_parse_from_ids_and_extensions() {
+    for language in BindingLanguage::ALL {
+        assert_eq!(BindingLanguage::from_str(language.id()), Ok(language));
+    }
+    assert_eq!(
+        BindingLanguage::from_str("TS"),
+        Ok(BindingLanguage::TypeScript)
+    );
+    assert!(BindingLanguage::from_str("python").is_err());
+}
+
+#[test]
+fn virtual_uris_name_the_source_and_language() {
+    assert_eq!(
+        bindings_uri(&uri(), BindingLanguage::Motoko),
+        "candid-bindings:///workspace/profile.did.mo"
+    );
+}
+
+#[test]
+fn items_are_found_under_generated_names() {
+    let rust = "use candid::CandidType;\n\n#[derive(CandidType, Deserialize)]\npub struct UserProfile {\n  pub age: u8,\n  pub name: String,\n}\n\npub struct Service(pub Principal);\nimpl Service {\n  pub async fn get(&self) {}\n}\n";
+    let target = BindingTarget::Type("user_profile".to_string());
+    assert_eq!(
+        binding_item_lines(rust, BindingLanguage::Rust, &target),
+        Some(2..7)
+    );
+    let service =
+        binding_item(rust, BindingLanguage::Rust, &BindingTarget::Service).expect("service item");
+    assert!(service.starts_with("pub struct Service"));
+    assert!(service.ends_with('}'));
+
+    let typescript = "export interface UserProfile { 'age' : number, 'name' : string }\nexport interface _SERVICE { 'get' : ActorMethod<[bigint], UserProfile> }\n";
+    assert_eq!(
+        binding_item_lines(
+            typescript,
+            BindingLanguage::TypeScript,
+            &BindingTarget::Service
+        ),
+        Some(1..2)
+    );
+    assert_eq!(
+        binding_item_lines(typescript, BindingLanguage::TypeScript, &target),
+        Some(0..1)
+    );
+}
+
+#[test]
+fn generated_documents_select_the_requested_type() {
+    let ast = parse(SOURCE).ast.expect("parses");
+    let env = TypeEnv::new(&ast, None);
+    let document = bindings_document(
+        &uri(),
+        &ast,
+        &env,
+        BindingLanguage::Rust,
+        Some(&BindingTarget::Type("user_profile".to_string())),
+    )
+    .expect("bindings");
+    assert_eq!(document.language_id, "rust");
+    assert!(document.content.contains("UserProfile"));
+    let selection = document.selection.expect("selection");
+    let first = document
+        .content
+        .lines()
+        .nth(selection.start.line as usize)
+        .expect("line");
+    assert!(first.starts_with("#[") || first.contains("UserProfile"));
+}
+
+#[test]
+fn lenses_offer_every_language_for_types_and_the_service() {
+    let ast = parse(SOURCE).ast.expect("parses");
+    let lenses = binding_lenses(&uri(), &ast, &Rope::from_str(SOURCE));
+    assert_eq!(lenses.len(), 2 * BindingLanguage::ALL.len());
+    let commands: Vec<_> = lenses
+        .iter()
+        .filter_map(|lens| lens.command.as_ref())
+        .collect();
+    assert!(
+        commands
+            .iter()
+            .all(|command| command.command == SHOW_BINDINGS)
+    );
+    let type_argument = &commands[0].arguments.as_ref().expect("arguments")[0];
+    assert_eq!(type_argument["type"], "user_profile");
+    assert_eq!(type_argument["language"], "rust");
+    assert_eq!(lenses[0].range.start.line, 0);
+    let service_argument = &commands[4].arguments.as_ref().expect("arguments")[0];
+    assert_eq!(service_argument["service"], true);
+    assert_eq!(lenses[4].range.start.line, 1);
+}
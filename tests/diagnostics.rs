@@ -0,0 +1,150 @@
+use candid_language_server::lsp::{
+    config::{DEFAULT_DOCS_BASE_URL, DiagnosticsConfig, ServerConfig},
+    diagnostic_rules::{DiagnosticRule, RuleLevel},
+    diagnostics::{diagnostics_for_source, document_result_id, tag_result_id},
+};
+use serde_json::json;
+use std::str::FromStr;
+use tower_lsp_server::ls_types::{DiagnosticSeverity, NumberOrString, Uri};
+
+fn uri() -> Uri {
+    Uri::from_str("file:///workspace/service.did").expect("valid uri")
+}
+
+#[test]
+fn diagnostics_for_valid_source_are_empty() {
+    let text = "type User = record { name : text };\nservice : { get : () -> (User) query };";
+    assert!(diagnostics_for_source(&uri(), text, &DiagnosticsConfig::default()).is_empty());
+}
+
+#[test]
+fn diagnostics_for_source_reports_undefined_types() {
+    let text = "service : { get : () -> (Missing) query };";
+    let diagnostics = diagnostics_for_source(&uri(), text, &DiagnosticsConfig::default());
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0].message.contains("Missing"));
+}
+
+#[test]
+fn document_result_id_tracks_version() {
+    assert_eq!(document_result_id(Some(3), &[]), "v3");
+    assert_ne!(
+        document_result_id(Some(3), &[]),
+        document_result_id(Some(4), &[])
+    );
+    assert_eq!(document_result_id(None, &[]), document_result_id(None, &[]));
+}
+
+#[test]
+fn diagnostics_carry_codes_and_docs_links() {
+    let text = "type A = record { a : nat }\ntype B = Missing;";
+    let linked = diagnostics_for_source(&uri(), text, &DiagnosticsConfig::default());
+    assert!(!linked.is_empty());
+    assert!(linked.iter().all(|diagnostic| {
+        diagnostic
+            .code_description
+            .as_ref()
+            .is_some_and(|link| link.href.as_str().starts_with(DEFAULT_DOCS_BASE_URL))
+    }));
+
+    let unlinked_config = DiagnosticsConfig {
+        docs_base_url: None,
+        ..Default::default()
+    };
+    let unlinked = diagnostics_for_source(&uri(), text, &unlinked_config);
+    assert!(
+        unlinked
+            .iter()
+            .all(|diagnostic| diagnostic.code_description.is_none())
+    );
+
+    let config = DiagnosticsConfig {
+        docs_base_url: Some("https://example.com/candid-ls/docs/diagnostics/".to_string()),
+        ..Default::default()
+    };
+    let diagnostics = diagnostics_for_source(&uri(), text, &config);
+    assert!(!diagnostics.is_empty());
+    for diagnostic in &diagnostics {
+        let Some(NumberOrString::String(code)) = &diagnostic.code else {
+            panic!("missing code on {diagnostic:?}");
+        };
+        assert!(code.starts_with("candid::"));
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::ERROR));
+        let href = &diagnostic
+            .code_description
+            .as_ref()
+            .expect("code description")
+            .href;
+        let page = code.trim_start_matches("candid::").replace("::", "-");
+        assert_eq!(
+            href.as_str(),
+            format!("https://example.com/candid-ls/docs/diagnostics/{page}.md")
+        );
+    }
+}
+
+#[test]
+fn semantic_diagnostics_have_source_and_severity() {
+    let text = "service : { get : () -> (Missing) query };";
+    let diagnostics = diagnostics_for_source(&uri(), text, &DiagnosticsConfig::default());
+    assert_eq!(diagnostics[0].source.as_deref(), Some("semantic"));
+    assert_eq!(
+        diagnostics[0].code,
+        Some(NumberOrString::String("candid::undefined-type".to_string()))
+    );
+}
+
+#[test]
+fn rule_levels_override_severity_or_drop_diagnostics() {
+    let text = "service : { get : () -> (Missing) query };";
+    let mut config = DiagnosticsConfig::default();
+    config
+        .rules
+        .set(DiagnosticRule::UndefinedType, RuleLevel::Warning);
+    let diagnostics = diagnostics_for_source(&uri(), text, &config);
+    assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+
+    config
+        .rules
+        .set(DiagnosticRule::UndefinedType, RuleLevel::Off);
+    assert!(diagnostics_for_source(&uri(), text, &config).is_empty());
+}
+
+#[test]
+fn rules_are_read_from_settings() {
+    let mut config = ServerConfig::default();
+    config.apply_settings(json!({
+        "candidLanguageServer": {
+            "diagnostics": {
+                "rules": {
+                    "candid::undefined-type": "hint",
+                    "parse::missing-semicolon": "off",
+                    "unknown-rule": "error",
+                    "candid::type-error": "bogus"
+                }
+            }
+        }
+    }));
+    let rules = &config.diagnostics().rules;
+    assert_eq!(rules.level(DiagnosticRule::UndefinedType), RuleLevel::Hint);
+    assert_eq!(
+        rules.level(DiagnosticRule::MissingSemicolon),
+        RuleLevel::Off
+    );
+    assert_eq!(rules.level(DiagnosticRule::TypeError), RuleLevel::Error);
+}
+
+#[test]
+fn rule_overrides_change_result_ids() {
+    let mut config = DiagnosticsConfig::default();
+    assert_eq!(tag_result_id("v3".to_string(), &config), "v3");
+    config
+        .rules
+        .set(DiagnosticRule::UndefinedType, RuleLevel::Off);
+    let tagged = tag_result_id("v3".to_string(), &config);
+    assert!(tagged.starts_with("v3-"));
+    config
+        .rules
+        .set(DiagnosticRule::UndefinedType, RuleLevel::Hint);
+    assert_ne!(tag_result_id("v3".to_string(), &config), tagged);
+}
@@ -0,0 +1,140 @@
+use candid_language_server::{
+    candid_lang::{CandidError, parse},
+    lsp::error_span::{
+        ErrorReport, SpanLabel, report_candid_error, report_parser_error, report_to_diagnostic,
+    },
+};
+use candid_parser::candid::{Error as CandidCoreError, IDLArgs};
+use ropey::Rope;
+use std::str::FromStr;
+use tower_lsp_server::ls_types::{Position, Range, Uri};
+
+fn uri() -> Uri {
+    Uri::from_str("file:///workspace/service.did").expect("valid uri")
+}
+
+#[test]
+fn parse_errors_keep_token_spans() {
+    let text = "type A = record { a : nat }\ntype B = text;";
+    let errors = parse(text).parse_errors;
+    let err = errors
+        .iter()
+        .find_map(|err| match err {
+            CandidError::Parser(err @ candid_parser::Error::Parse(_)) => Some(err),
+            _ => None,
+        })
+        .expect("parse error");
+
+    let report = report_parser_error(err, None);
+    let primary = report.primary.expect("primary label");
+    assert_eq!(&text[primary.span.clone()], "type");
+    assert_eq!(primary.span.start, text.find("\ntype B").unwrap() + 1);
+}
+
+#[test]
+fn custom_parser_errors_are_pinned_to_the_anchor() {
+    let err = candid_parser::Error::msg("boom");
+    let report = report_parser_error(&err, Some(&(5..8)));
+    assert!(report.message.contains("boom"));
+    assert_eq!(report.primary.map(|label| label.span), Some(5..8));
+}
+
+#[test]
+fn subtype_errors_are_pinned_to_the_anchor() {
+    let err = CandidCoreError::Subtype("nat is not a subtype of text".to_string());
+    let report = report_candid_error(&err, Some(&(2..4)));
+    assert!(report.message.contains("nat is not a subtype of text"));
+    assert_eq!(report.primary.map(|label| label.span), Some(2..4));
+}
+
+#[test]
+fn custom_candid_errors_without_anchor_have_no_span() {
+    let err = CandidCoreError::msg("custom failure");
+    let report = report_candid_error(&err, None);
+    assert!(report.message.contains("custom failure"));
+    assert!(report.primary.is_none());
+}
+
+#[test]
+fn decoding_errors_never_use_binary_offsets_as_text_positions() {
+    let err = IDLArgs::from_bytes(b"DIDL\x01\x6c").expect_err("truncated message");
+    let report = report_candid_error(&err, Some(&(10..20)));
+    assert_eq!(report.primary.map(|label| label.span), Some(10..20));
+
+    let report = report_candid_error(&err, None);
+    let diagnostic = report_to_diagnostic(report, "candid", None, &Rope::from_str("text"));
+    assert_eq!(diagnostic.range, Range::default());
+}
+
+/// Byte offsets named by the message and notes of a decoding report.
+fn byte_offsets(report: &ErrorReport) -> Vec<usize> {
+    std::iter::once(&report.message)
+        .chain(&report.notes)
+        .filter_map(|text| text.rsplit_once("at byte offset ")?.1.parse().ok())
+        .collect()
+}
+
+#[test]
+fn decoding_frames_become_notes_with_byte_offsets() {
+    let bytes = b"DIDL\x01\x6c";
+    let err = IDLArgs::from_bytes(bytes).expect_err("truncated message");
+    assert!(matches!(err, CandidCoreError::Binread(_)));
+    let report = report_candid_error(&err, Some(&(0..4)));
+    assert_eq!(
+        report.primary.as_ref().map(|label| &label.span),
+        Some(&(0..4))
+    );
+    assert!(report.secondary.is_empty(), "frames are not document spans");
+    let offsets = byte_offsets(&report);
+    assert!(!offsets.is_empty());
+    assert!(offsets.iter().all(|offset| *offset <= bytes.len()));
+
+    let diagnostic = report_to_diagnostic(report, "candid", Some(&uri()), &Rope::from_str("DIDL"));
+    assert!(diagnostic.related_information.is_none());
+    assert!(diagnostic.message.contains("at byte offset"));
+}
+
+#[test]
+fn decoding_a_truncated_reserved_argument_list_names_offsets_in_the_message() {
+    // No type table, then two `reserved` arguments of which only one type is present.
+    let bytes = b"DIDL\x00\x02\x70";
+    let err = IDLArgs::from_bytes(bytes).expect_err("missing argument type");
+    assert!(matches!(err, CandidCoreError::Binread(_)));
+    let report = report_candid_error(&err, None);
+    assert!(report.primary.is_none());
+    assert!(report.secondary.is_empty());
+    let offsets = byte_offsets(&report);
+    assert!(!offsets.is_empty());
+    assert!(offsets.iter().all(|offset| *offset <= bytes.len()));
+    assert!(
+        offsets.iter().any(|offset| *offset >= 4),
+        "offsets {offsets:?} should reach past the magic number"
+    );
+}
+
+#[test]
+fn secondary_labels_become_related_information() {
+    let text = "type A = text;\ntype B = nat;\n";
+    let rope = Rope::from_str(text);
+    let report = ErrorReport {
+        message: "conflict".to_string(),
+        primary: Some(SpanLabel {
+            span: 5..6,
+            message: "first".to_string(),
+        }),
+        secondary: vec![SpanLabel {
+            span: 20..21,
+            message: "second".to_string(),
+        }],
+        notes: vec!["note".to_string()],
+    };
+
+    let diagnostic = report_to_diagnostic(report, "candid", Some(&uri()), &rope);
+    assert_eq!(diagnostic.range.start, Position::new(0, 5));
+    assert!(diagnostic.message.contains("first"));
+    assert!(diagnostic.message.contains("note"));
+    let related = diagnostic.related_information.expect("related information");
+    assert_eq!(related.len(), 1);
+    assert_eq!(related[0].location.range.start, Position::new(1, 5));
+    assert_eq!(related[0].message, "second");
+}
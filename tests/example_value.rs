@@ -0,0 +1,173 @@
+use candid_language_server::{
+    candid_lang::parse,
+    lsp::{
+        example_value::{
+            ExampleOptions, ExampleTarget, example_actions, example_for, example_target_at,
+        },
+        value::parse_value_args,
+        value_check::{TypeEnv, check_args},
+    },
+};
+use std::str::FromStr;
+use tower_lsp_server::ls_types::{CodeActionOrCommand, Uri};
+
+const SOURCE: &str = r#"type Role = variant { Admin; Member : nat8 };
+type User = record { name : text; age : nat8; role : Role; nickname : opt text; tags : vec text; avatar : blob };
+type List = opt record { head : int; tail : List };
+type Tree = variant { node : record { Tree; Tree }; leaf : principal };
+service : {
+  create_user : (User, opt nat) -> (nat);
+  ping : () -> () query;
+}
+"#;
+
+fn env() -> TypeEnv {
+    TypeEnv::new(&parse(SOURCE).ast.expect("parses"), None)
+}
+
+/// Parse `args` back and check them against `method`'s parameters.
+fn assert_well_typed(env: &TypeEnv, types_of: &str, text: &str) {
+    let parsed = parse_value_args(text);
+    assert!(parsed.errors.is_empty(), "{text}: {:?}", parsed.errors);
+    let args = parsed.args.expect("arguments");
+    let types = match env.method(types_of) {
+        Some(func) => func.args.clone(),
+        None => vec![env.get(types_of).expect("type").clone()],
+    };
+    let errors = check_args(&args, &types, env);
+    assert!(errors.is_empty(), "{text}: {errors:?}");
+}
+
+#[test]
+fn examples_type_check_for_every_seed() {
+    let env = env();
+    for seed in 0..32 {
+        let options = ExampleOptions {
+            seed,
+            ..ExampleOptions::default()
+        };
+        for name in ["Role", "User", "List", "Tree"] {
+            let value = example_for(&ExampleTarget::Type(name.to_string()), &env, options)
+                .expect("example");
+            assert_well_typed(&env, name, &format!("({value})"));
+        }
+        let args = example_for(
+            &ExampleTarget::Method("create_user".to_string()),
+            &env,
+            options,
+        )
+        .expect("example");
+        assert_well_typed(&env, "create_user", &args);
+    }
+}
+
+#[test]
+fn records_have_every_field_and_variants_take_the_first_case() {
+    let env = env();
+    let user = example_for(
+        &ExampleTarget::Type("User".to_string()),
+        &env,
+        ExampleOptions::default(),
+    )
+    .expect("example");
+    for field in [
+        "name =",
+        "age =",
+        "role =",
+        "nickname =",
+        "tags =",
+        "avatar =",
+    ] {
+        assert!(user.contains(field), "{user}");
+    }
+    assert!(user.contains("role = variant { Admin }"), "{user}");
+}
+
+#[test]
+fn recursive_types_stop_at_the_depth_limit() {
+    let env = env();
+    let options = ExampleOptions {
+        max_depth: 0,
+        ..ExampleOptions::default()
+    };
+    let list =
+        example_for(&ExampleTarget::Type("List".to_string()), &env, options).expect("example");
+    assert_eq!(list, "null");
+    let tree =
+        example_for(&ExampleTarget::Type("Tree".to_string()), &env, options).expect("example");
+    assert!(tree.starts_with("variant { leaf = principal "), "{tree}");
+}
+
+#[test]
+fn the_same_seed_gives_the_same_value() {
+    let env = env();
+    let target = ExampleTarget::Method("create_user".to_string());
+    let options = ExampleOptions {
+        seed: 7,
+        ..ExampleOptions::default()
+    };
+    assert_eq!(
+        example_for(&target, &env, options),
+        example_for(&target, &env, options)
+    );
+    assert_eq!(
+        example_for(&ExampleTarget::Method("ping".to_string()), &env, options).as_deref(),
+        Ok("()")
+    );
+}
+
+#[test]
+fn unresolved_types_are_reported_instead_of_generated() {
+    let broken = TypeEnv::new(
+        &parse("type Broken = record { inner : Missing };")
+            .ast
+            .expect("parses"),
+        None,
+    );
+    let example = example_for(
+        &ExampleTarget::Type("Broken".to_string()),
+        &broken,
+        ExampleOptions::default(),
+    );
+    assert!(
+        example.as_ref().is_err_and(|err| err.contains("Missing")),
+        "{example:?}"
+    );
+    assert_eq!(
+        example_for(
+            &ExampleTarget::Type("Other".to_string()),
+            &env(),
+            ExampleOptions::default()
+        ),
+        Err("no type `Other`".to_string())
+    );
+}
+
+#[test]
+fn actions_target_the_declaration_under_the_cursor() {
+    let ast = parse(SOURCE).ast.expect("parses");
+    let env = TypeEnv::new(&ast, None);
+    let at = |needle: &str| SOURCE.find(needle).expect("needle") + 1;
+    assert_eq!(
+        example_target_at(&ast, &env, at("record { name")),
+        Some(ExampleTarget::Type("User".to_string()))
+    );
+    assert_eq!(
+        example_target_at(&ast, &env, at("create_user")),
+        Some(ExampleTarget::Method("create_user".to_string()))
+    );
+    assert_eq!(example_target_at(&ast, &env, at("service")), None);
+
+    let uri = Uri::from_str("file:///workspace/users.did").expect("valid uri");
+    let actions = example_actions(&uri, &ast, &env, at("ping"));
+    let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+        panic!("expected one action: {actions:?}");
+    };
+    assert_eq!(action.title, "Generate example arguments for `ping`");
+    let command = action.command.as_ref().expect("command");
+    assert_eq!(command.command, "candid.exampleValue");
+    assert_eq!(
+        command.arguments.as_ref().expect("arguments")[0]["method"],
+        "ping"
+    );
+}
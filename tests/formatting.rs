@@ -1,31 +1,52 @@
 use candid_language_server::{
     candid_lang::{ParserResult, parse},
-    lsp::format::format_program,
+    lsp::{
+        config::ServerConfig,
+        format::{
+            FormatOptions, TrailingSeparator, format_program, format_range_with_options,
+            formatted_text, on_type_edits,
+        },
+        position::position_to_offset,
+        trivia::Comment,
+    },
 };
+use candid_parser::syntax::IDLMergedProg;
 use ropey::Rope;
+use serde_json::json;
+use tower_lsp_server::ls_types::{FormattingOptions, Position, Range, TextEdit};
+
+fn parsed(text: &str) -> (IDLMergedProg, Vec<Comment>) {
+    let ParserResult { ast, comments, .. } = parse(text);
+    (ast.expect("parsed AST"), comments)
+}
+
+fn apply_edits(text: &str, edits: &[TextEdit]) -> String {
+    let mut rope = Rope::from_str(text);
+    let mut edits = edits.to_vec();
+    edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+    for edit in edits.iter().rev() {
+        let start = position_to_offset(edit.range.start, &rope).expect("start");
+        let end = position_to_offset(edit.range.end, &rope).expect("end");
+        rope.remove(start..end);
+        rope.insert(start, &edit.new_text);
+    }
+    rope.to_string()
+}
 
 #[test]
 fn formatting_returns_text_edits() {
     let text = "service : { method : (text) -> (text) query; }";
     let rope = Rope::from_str(text);
-    let ParserResult { ast, .. } = parse(text);
-    let ast = ast.expect("parsed AST");
-
-    let edits = format_program(&ast, &rope).expect("edits");
-    assert_eq!(edits.len(), 1);
-    let edit = &edits[0];
-
-    // Check if new text is formatted.
-    // pretty_print typically formats nicely.
-    // "service : { method : (text) -> (text) query }"
-    // might become:
-    // service : {
-    //   method : (text) -> (text) query;
-    // }
-    // or similar.
-    assert!(edit.new_text.contains("service : {"));
+    let (ast, comments) = parsed(text);
+
+    let edits = format_program(&ast, &comments, &rope).expect("edits");
+    assert!(!edits.is_empty());
+    let formatted = apply_edits(text, &edits);
+
+    // The service block is broken over lines, one method per line.
+    assert!(formatted.contains("service : {"));
     assert_ne!(
-        edit.new_text, text,
+        formatted, text,
         "formatted text should differ from ugly input"
     );
 }
@@ -34,20 +55,17 @@ fn formatting_returns_text_edits() {
 fn formatting_preserves_imports_and_service_name() {
     let text = "import \"./shared.did\";\n\nservice Api : { method : (text) -> (); }";
     let rope = Rope::from_str(text);
-    let ParserResult { ast, .. } = parse(text);
-    let ast = ast.expect("parsed AST");
+    let (ast, comments) = parsed(text);
 
-    let edits = format_program(&ast, &rope).expect("edits");
-    let edit = &edits[0];
+    let edits = format_program(&ast, &comments, &rope).expect("edits");
+    let formatted = apply_edits(text, &edits);
 
-    assert!(edit.new_text.contains("import \"./shared.did\";"));
-    assert!(edit.new_text.contains("service Api :"));
-    let import_pos = edit
-        .new_text
+    assert!(formatted.contains("import \"./shared.did\";"));
+    assert!(formatted.contains("service Api :"));
+    let import_pos = formatted
         .find("import \"./shared.did\";")
         .expect("import should exist");
-    let service_pos = edit
-        .new_text
+    let service_pos = formatted
         .find("service Api :")
         .expect("service name should exist");
     assert!(
@@ -60,21 +78,19 @@ fn formatting_preserves_imports_and_service_name() {
 fn formatting_preserves_orphan_comment_lines() {
     let text = "// orphan comment\n\nservice : { method : () -> (); }";
     let rope = Rope::from_str(text);
-    let ParserResult { ast, .. } = parse(text);
-    let ast = ast.expect("parsed AST");
+    let (ast, comments) = parsed(text);
 
-    let edits = format_program(&ast, &rope).expect("edits");
-    let edit = &edits[0];
+    let edits = format_program(&ast, &comments, &rope).expect("edits");
+    let formatted = apply_edits(text, &edits);
 
     assert!(
-        edit.new_text.contains("// orphan comment"),
+        formatted.contains("// orphan comment"),
         "orphan comment should be preserved"
     );
-    let comment_pos = edit
-        .new_text
+    let comment_pos = formatted
         .find("// orphan comment")
         .expect("comment should exist");
-    let service_pos = edit.new_text.find("service").expect("service should exist");
+    let service_pos = formatted.find("service").expect("service should exist");
     assert!(
         comment_pos < service_pos,
         "comment should remain before service"
@@ -85,22 +101,19 @@ fn formatting_preserves_orphan_comment_lines() {
 fn formatting_preserves_comment_before_import_without_blank_line() {
     let text = "// import comment\nimport \"./shared.did\";\n\nservice : { method : () -> (); }";
     let rope = Rope::from_str(text);
-    let ParserResult { ast, .. } = parse(text);
-    let ast = ast.expect("parsed AST");
+    let (ast, comments) = parsed(text);
 
-    let edits = format_program(&ast, &rope).expect("edits");
-    let edit = &edits[0];
+    let edits = format_program(&ast, &comments, &rope).expect("edits");
+    let formatted = apply_edits(text, &edits);
 
     assert!(
-        edit.new_text.contains("// import comment"),
+        formatted.contains("// import comment"),
         "comment should be preserved"
     );
-    let comment_pos = edit
-        .new_text
+    let comment_pos = formatted
         .find("// import comment")
         .expect("comment should exist");
-    let import_pos = edit
-        .new_text
+    let import_pos = formatted
         .find("import \"./shared.did\";")
         .expect("import should exist");
     assert!(
@@ -108,3 +121,256 @@ fn formatting_preserves_comment_before_import_without_blank_line() {
         "comment should remain before import"
     );
 }
+
+#[test]
+fn range_formatting_only_touches_the_selected_declarations() {
+    let text = "type A = record {a:nat;b:text};\ntype B = record {c:nat};\n";
+    let rope = Rope::from_str(text);
+    let (ast, comments) = parsed(text);
+
+    let range = Range::new(Position::new(0, 5), Position::new(0, 6));
+    let edits = format_range_with_options(&ast, &comments, &rope, range, &Default::default())
+        .expect("edits");
+    assert!(!edits.is_empty());
+    assert!(edits.iter().all(|edit| edit.range.end.line <= 1));
+    let formatted = apply_edits(text, &edits);
+    assert!(!formatted.contains("a:nat"), "{formatted}");
+    assert!(
+        formatted.contains("type B = record {c:nat};"),
+        "{formatted}"
+    );
+}
+
+#[test]
+fn range_formatting_follows_declarations_across_collapsed_blank_lines() {
+    let text = "type A = record {a:nat};\n\n\n\n// type B\ntype B = record {c:nat};\n";
+    let rope = Rope::from_str(text);
+    let (ast, comments) = parsed(text);
+    let options = FormatOptions {
+        blank_lines: Some(1),
+        ..Default::default()
+    };
+
+    let range = Range::new(Position::new(5, 5), Position::new(5, 6));
+    let edits = format_range_with_options(&ast, &comments, &rope, range, &options).expect("edits");
+    assert!(!edits.is_empty());
+    assert!(edits.iter().all(|edit| edit.range.start.line == 5));
+    let formatted = apply_edits(text, &edits);
+    assert!(formatted.starts_with("type A = record {a:nat};\n\n\n\n// type B\n"));
+    assert!(!formatted.contains("c:nat"), "{formatted}");
+}
+
+#[test]
+fn on_type_braces_in_strings_are_not_blocks() {
+    let text = "type A = record {\n\"{\" : nat;\n    id : nat;\n}";
+    let rope = Rope::from_str(text);
+    let edits = on_type_edits(&rope, Position::new(3, 1), "}", "  ").expect("edits");
+    assert_eq!(
+        apply_edits(text, &edits),
+        "type A = record {\n  \"{\" : nat;\n  id : nat;\n}"
+    );
+}
+
+#[test]
+fn on_type_formatting_reindents_the_closed_block() {
+    let text = "type A = record {\nid : nat;\n    name : text;\n}";
+    let rope = Rope::from_str(text);
+    let edits = on_type_edits(&rope, Position::new(3, 1), "}", "  ").expect("edits");
+    assert_eq!(
+        edits,
+        vec![
+            TextEdit {
+                range: Range::new(Position::new(1, 0), Position::new(1, 0)),
+                new_text: "  ".to_string(),
+            },
+            TextEdit {
+                range: Range::new(Position::new(2, 0), Position::new(2, 4)),
+                new_text: "  ".to_string(),
+            },
+        ]
+    );
+    assert_eq!(
+        apply_edits(text, &edits),
+        "type A = record {\n  id : nat;\n  name : text;\n}"
+    );
+}
+
+#[test]
+fn on_type_formatting_after_a_semicolon_reindents_the_statement() {
+    let text = "service : {\n  get : (nat)\n-> (text);\n  // { not a brace\n}";
+    let rope = Rope::from_str(text);
+    let edits = on_type_edits(&rope, Position::new(2, 10), ";", "    ").expect("edits");
+    assert_eq!(
+        apply_edits(text, &edits),
+        "service : {\n    get : (nat)\n    -> (text);\n  // { not a brace\n}"
+    );
+    assert_eq!(
+        on_type_edits(&rope, Position::new(2, 10), "}", "    "),
+        None
+    );
+}
+
+#[test]
+fn formatting_emits_minimal_edits() {
+    let text = "service : { method : (text) -> (text) query; }";
+    let (ast, comments) = parsed(text);
+    let edits = format_program(&ast, &comments, &Rope::from_str(text)).expect("edits");
+    let formatted = apply_edits(text, &edits);
+
+    let (formatted_ast, formatted_comments) = parsed(&formatted);
+    let rope = Rope::from_str(&formatted);
+    assert_eq!(
+        format_program(&formatted_ast, &formatted_comments, &rope),
+        Some(vec![])
+    );
+
+    let spaced = formatted.replacen(" : ", " :  ", 1);
+    let (spaced_ast, spaced_comments) = parsed(&spaced);
+    let edits =
+        format_program(&spaced_ast, &spaced_comments, &Rope::from_str(&spaced)).expect("edits");
+    let [edit] = edits.as_slice() else {
+        panic!("expected one edit: {edits:?}");
+    };
+    assert_eq!(edit.new_text, "");
+    assert_eq!(edit.range.start.line, edit.range.end.line);
+    assert_eq!(edit.range.end.character - edit.range.start.character, 1);
+    assert_eq!(apply_edits(&spaced, &edits), formatted);
+}
+
+#[test]
+fn repeated_comment_text_keeps_each_comment_in_place() {
+    let text = "// note\ntype A = nat;\n// note\ntype B = text;\n";
+    let (ast, comments) = parsed(text);
+    let spans: Vec<_> = comments
+        .iter()
+        .map(|comment| comment.span.clone())
+        .collect();
+    assert_eq!(spans, vec![0..7, 22..29]);
+    assert_eq!(
+        formatted_text(&ast, &comments, &Rope::from_str(text), &Default::default()),
+        text
+    );
+}
+
+#[test]
+fn blob_spelling_service_name_and_parameter_names_are_kept() {
+    let text = "type A = record { data : blob; bytes : vec nat8 };\n\
+service Api : { put : (\"raw bytes\" : blob, n : nat) -> (ok : bool) };\n";
+    let (ast, comments) = parsed(text);
+    let formatted = formatted_text(&ast, &comments, &Rope::from_str(text), &Default::default());
+    assert!(formatted.contains("data : blob"), "{formatted}");
+    assert!(formatted.contains("bytes : vec nat8"), "{formatted}");
+    assert!(formatted.contains("service Api : {"), "{formatted}");
+    assert!(
+        formatted.contains("put : (\"raw bytes\" : blob, n : nat) -> (ok : bool)"),
+        "{formatted}"
+    );
+}
+
+#[test]
+fn formatting_keeps_every_comment_in_place() {
+    let text = r#"// header
+
+/// A user.
+type User = record {
+// the id
+    id : nat;   // trailing
+  /* block */ name : text;
+  tags : vec text; /* tail */
+};
+service Api : {
+  get:(id:nat)->(opt User) query; // lookup
+  // last
+}
+"#;
+    let (ast, comments) = parsed(text);
+    let edits = format_program(&ast, &comments, &Rope::from_str(text)).expect("edits");
+    let formatted = apply_edits(text, &edits);
+
+    for snippet in [
+        "// header\n\n/// A user.\ntype User = record {\n",
+        "  // the id\n  id : nat; // trailing\n",
+        "  /* block */\n  name : text;\n",
+        "  tags : vec text; /* tail */\n};\n",
+        "service Api : {\n  get : (id : nat) -> (opt User) query; // lookup\n  // last\n}\n",
+    ] {
+        assert!(formatted.contains(snippet), "{snippet:?} in {formatted}");
+    }
+
+    let (ast, comments) = parsed(&formatted);
+    assert_eq!(
+        format_program(&ast, &comments, &Rope::from_str(&formatted)),
+        Some(vec![])
+    );
+}
+
+#[test]
+fn formatting_prints_a_service_constructor_result_as_a_method_block() {
+    let text = "service : (nat) -> { get : () -> (nat) query }\n";
+    let (ast, comments) = parsed(text);
+    assert_eq!(
+        formatted_text(
+            &ast,
+            &comments,
+            &Rope::from_str(text),
+            &FormatOptions::default()
+        ),
+        "service : (nat) -> {\n  get : () -> (nat) query;\n}\n"
+    );
+}
+
+#[test]
+fn formatting_aligns_colons_with_tabs_and_no_trailing_separator() {
+    let text = "type User = record { id : nat; display_name : text };\nservice : { get : (nat) -> (User) query; list_all : () -> (vec User) query }\n";
+    let (ast, comments) = parsed(text);
+    let options = FormatOptions {
+        use_tabs: true,
+        align_colons: true,
+        trailing_separator: TrailingSeparator::Never,
+        ..Default::default()
+    };
+    assert_eq!(
+        formatted_text(&ast, &comments, &Rope::from_str(text), &options),
+        "type User = record {\n\tid           : nat;\n\tdisplay_name : text\n};\nservice : {\n\tget      : (nat) -> (User) query;\n\tlist_all : () -> (vec User) query;\n}\n"
+    );
+}
+
+#[test]
+fn formatting_keeps_short_records_on_one_line_and_sorts_declarations() {
+    let text = "// bee\ntype B = record { x : nat; y : nat };\ntype A = variant { ok : nat; err : text; long_name_here : record { a : nat; b : text } };\nservice : { zeta : () -> (); alpha : (A) -> (B) }\n";
+    let (ast, comments) = parsed(text);
+    let options = FormatOptions {
+        max_width: Some(50),
+        sort_methods: true,
+        sort_types: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        formatted_text(&ast, &comments, &Rope::from_str(text), &options),
+        "type A = variant {\n  ok : nat;\n  err : text;\n  long_name_here : record { a : nat; b : text };\n};\n// bee\ntype B = record { x : nat; y : nat };\nservice : {\n  alpha : (A) -> (B);\n  zeta : () -> ();\n}\n"
+    );
+}
+
+#[test]
+fn format_settings_fall_back_to_the_editor_options() {
+    let editor = FormattingOptions {
+        tab_size: 4,
+        insert_spaces: false,
+        ..Default::default()
+    };
+    let mut config = ServerConfig::default();
+    config.apply_settings(json!({
+        "format": { "alignColons": true, "trailingSeparator": "never", "maxWidth": 80, "sortTypes": true }
+    }));
+    let options = config.format_options(&editor);
+    assert_eq!(options.indent_width, Some(4));
+    assert!(options.use_tabs && options.align_colons && options.sort_types);
+    assert!(!options.sort_methods);
+    assert_eq!(options.trailing_separator, TrailingSeparator::Never);
+    assert_eq!(options.max_width, Some(80));
+
+    config.apply_settings(json!({ "format": { "indentWidth": 2, "useTabs": false } }));
+    let options = config.format_options(&editor);
+    assert_eq!(options.indent_width, Some(2));
+    assert_eq!(options.indent_unit(), "  ");
+}
@@ -0,0 +1,211 @@
+use candid_language_server::{
+    candid_lang::{ImCompleteSemanticToken, parse},
+    lsp::{
+        incremental::{EditLog, PreviousProgram, ProgramChunks, TextChange, splice},
+        semantic_analyze::{Semantic, analyze_program},
+    },
+};
+use ropey::Rope;
+
+fn apply(text: &str, start: usize, end: usize, insert: &str) -> (String, TextChange) {
+    let mut updated = String::with_capacity(text.len() + insert.len());
+    updated.push_str(&text[..start]);
+    updated.push_str(insert);
+    updated.push_str(&text[end..]);
+    let change = TextChange {
+        start,
+        old_end: end,
+        new_end: start + insert.len(),
+    };
+    (updated, change)
+}
+
+fn token_spans(tokens: &[ImCompleteSemanticToken]) -> Vec<(usize, usize, usize)> {
+    tokens
+        .iter()
+        .map(|token| (token.start, token.length, token.token_type))
+        .collect()
+}
+
+/// Everything analysis records, with unordered lists sorted.
+fn semantic_summary(semantic: &Semantic) -> Vec<String> {
+    let mut intervals = semantic
+        .ident_range
+        .iter()
+        .map(|interval| format!("{}..{} {:?}", interval.start, interval.stop, interval.val))
+        .collect::<Vec<_>>();
+    intervals.sort();
+    let mut keywords = semantic
+        .keyword_spans
+        .iter()
+        .map(|keyword| format!("{keyword:?}"))
+        .collect::<Vec<_>>();
+    keywords.sort();
+    let references = semantic
+        .table
+        .reference_id_to_reference
+        .iter()
+        .map(|reference| format!("{reference:?}"))
+        .collect::<Vec<_>>();
+    vec![
+        intervals.join("\n"),
+        keywords.join("\n"),
+        references.join("\n"),
+        format!("{:?}", semantic.table.symbol_id_to_span),
+        format!("{:?}", semantic.fields),
+        format!("{:?}", semantic.service_methods),
+        format!("{:?}", semantic.params),
+        format!("{:?}", semantic.locals),
+        format!("{:?}", semantic.symbol_ident_spans),
+        format!("{:?}", semantic.type_docs),
+        format!("{:?}", semantic.primitive_spans),
+        format!("{:?}", semantic.actor),
+    ]
+}
+
+/// Splice `needle` → `insert` into the analysis of `text` and compare with a full analysis.
+fn assert_matches_full_analysis(text: &str, needle: &str, insert: &str) {
+    let parsed = parse(text);
+    let chunks = ProgramChunks::new(&parsed, text).expect("error-free document is chunked");
+    let ast = parsed.ast.as_ref().expect("parsed AST");
+    let semantic = analyze_program(ast, &Rope::from_str(text)).expect("semantic");
+    let start = text.find(needle).expect("needle present");
+    let (updated, change) = apply(text, start, start + needle.len(), insert);
+    let rope = Rope::from_str(&updated);
+
+    let previous = PreviousProgram {
+        ast,
+        semantic: Some(&semantic),
+        comments: &parsed.comments,
+        chunks: &chunks,
+    };
+    let spliced = splice(previous, &updated, &rope, change).expect("edit is spliced");
+    let full = parse(&updated);
+    assert_eq!(
+        format!("{:?}", spliced.parsed.ast),
+        format!("{:?}", full.ast),
+        "spliced AST should match a full parse"
+    );
+    assert_eq!(
+        token_spans(&spliced.parsed.semantic_tokens),
+        token_spans(&full.semantic_tokens)
+    );
+    assert_eq!(spliced.parsed.comments, full.comments);
+    assert_eq!(spliced.parsed.declaration_ends, full.declaration_ends);
+    assert!(
+        ProgramChunks::new(&spliced.parsed, &updated).is_some(),
+        "the spliced program can be spliced again"
+    );
+
+    let spliced_semantic = spliced
+        .semantic
+        .expect("semantic analysis is spliced")
+        .expect("spliced semantic");
+    let full_semantic =
+        analyze_program(full.ast.as_ref().expect("full AST"), &rope).expect("full semantic");
+    assert_eq!(
+        semantic_summary(&spliced_semantic),
+        semantic_summary(&full_semantic)
+    );
+}
+
+const SOURCE: &str = "import \"other.did\";\ntype A = record { a : nat };\n/// Docs for B\ntype B = vec A; // trailing\ntype C = variant { c; d : B };\nservice : {\n  get : (id : A) -> (B) query;\n}\n";
+
+#[test]
+fn editing_a_middle_declaration_matches_full_analysis() {
+    assert_matches_full_analysis(SOURCE, "vec A", "opt record { x : A; y : text }");
+}
+
+#[test]
+fn growing_the_first_declaration_shifts_the_rest() {
+    assert_matches_full_analysis(SOURCE, "a : nat", "a : nat; b : opt A");
+}
+
+#[test]
+fn editing_the_actor_matches_full_analysis() {
+    assert_matches_full_analysis(SOURCE, "query", "composite_query");
+}
+
+#[test]
+fn editing_comments_matches_full_analysis() {
+    assert_matches_full_analysis(SOURCE, "// trailing", "/* block */");
+}
+
+#[test]
+fn new_declarations_are_parsed_but_analysed_from_scratch() {
+    let parsed = parse(SOURCE);
+    let chunks = ProgramChunks::new(&parsed, SOURCE).expect("chunks");
+    let ast = parsed.ast.as_ref().expect("AST");
+    let semantic = analyze_program(ast, &Rope::from_str(SOURCE)).expect("semantic");
+    let start = SOURCE.find("\ntype C").expect("needle");
+    let (updated, change) = apply(SOURCE, start, start, "\ntype D = text;");
+
+    let previous = PreviousProgram {
+        ast,
+        semantic: Some(&semantic),
+        comments: &parsed.comments,
+        chunks: &chunks,
+    };
+    let spliced =
+        splice(previous, &updated, &Rope::from_str(&updated), change).expect("edit is spliced");
+    assert_eq!(
+        format!("{:?}", spliced.parsed.ast),
+        format!("{:?}", parse(&updated).ast)
+    );
+    assert!(spliced.semantic.is_none(), "a new name changes resolution");
+}
+
+#[test]
+fn commenting_out_a_separator_falls_back_to_a_full_parse() {
+    let parsed = parse(SOURCE);
+    let chunks = ProgramChunks::new(&parsed, SOURCE).expect("chunks");
+    let start = SOURCE.find("type A").expect("needle");
+    let (updated, change) = apply(SOURCE, start, start, "// ");
+
+    let previous = PreviousProgram {
+        ast: parsed.ast.as_ref().expect("AST"),
+        semantic: None,
+        comments: &parsed.comments,
+        chunks: &chunks,
+    };
+    assert!(splice(previous, &updated, &Rope::from_str(&updated), change).is_none());
+}
+
+#[test]
+fn edits_introducing_errors_are_not_spliced() {
+    let parsed = parse(SOURCE);
+    let chunks = ProgramChunks::new(&parsed, SOURCE).expect("chunks");
+    let start = SOURCE.find("vec A;").expect("needle") + "vec A".len();
+    let (updated, change) = apply(SOURCE, start, start + 1, "");
+
+    let previous = PreviousProgram {
+        ast: parsed.ast.as_ref().expect("AST"),
+        semantic: None,
+        comments: &parsed.comments,
+        chunks: &chunks,
+    };
+    assert!(splice(previous, &updated, &Rope::from_str(&updated), change).is_none());
+}
+
+#[test]
+fn edit_log_combines_the_edits_since_a_version() {
+    let mut log = EditLog::new(Some(1));
+    let (first, first_change) = apply("type A = nat;", 9, 12, "text");
+    log.record(Some(2), first_change);
+    let (second, second_change) = apply(&first, 0, 0, "type B = A;\n");
+    log.record(Some(3), second_change);
+
+    let combined = log.since(Some(1)).expect("edits since version 1");
+    let (replayed, _) = apply(
+        "type A = nat;",
+        combined.start,
+        combined.old_end,
+        &second[combined.start..combined.new_end],
+    );
+    assert_eq!(replayed, second);
+    assert_eq!(log.since(Some(2)), Some(second_change));
+
+    log.prune(Some(2));
+    assert_eq!(log.since(Some(1)), None, "pruned edits are gone");
+    assert_eq!(log.since(Some(2)), Some(second_change));
+}
@@ -0,0 +1,188 @@
+use candid_language_server::{
+    candid_lang::parse,
+    lsp::{
+        message_codec::{
+            MessageFormat, MethodTypes, decode_method_message, encode_method_message,
+            format_message_bytes, parse_message_bytes,
+        },
+        normal_value::{NormalValue, decode_args, render_args, render_args_pretty},
+        semantic_analyze::{ParamRole, analyze_program},
+        value_check::TypeEnv,
+    },
+};
+use candid_parser::candid::types::Label;
+use ropey::Rope;
+use std::str::FromStr;
+use tower_lsp_server::ls_types::Uri;
+
+const SOURCE: &str = r#"type Role = variant { Admin; Member : nat8 };
+type User = record { name : text; age : nat8; role : Role; tags : vec text };
+type List = opt record { head : int; tail : List };
+service : {
+  create_user : (User, opt nat) -> (nat);
+  sum : (List) -> (int) query;
+  rename : (record { id : nat; name : text }) -> ();
+}
+"#;
+
+fn uri() -> Uri {
+    Uri::from_str("file:///workspace/users.did").expect("valid uri")
+}
+
+fn env() -> TypeEnv {
+    TypeEnv::new(&parse(SOURCE).ast.expect("parses"), None)
+}
+
+fn types(method: &str, role: ParamRole) -> MethodTypes {
+    let rope = Rope::from_str(SOURCE);
+    let ast = parse(SOURCE).ast.expect("parses");
+    let semantic = analyze_program(&ast, &rope).expect("analyzes");
+    MethodTypes::lookup(&semantic, &rope, &env(), method, role).expect("method")
+}
+
+fn encode(method: &str, role: ParamRole, value: &str) -> String {
+    encode_method_message(
+        &uri(),
+        &env(),
+        &types(method, role),
+        value,
+        MessageFormat::Hex,
+    )
+    .expect("encodes")
+    .content
+}
+
+#[test]
+fn hex_and_base64_round_trip() {
+    let bytes = b"DIDL\x00\x01\x7b\x2a".to_vec();
+    for format in [MessageFormat::Hex, MessageFormat::Base64] {
+        let text = format_message_bytes(&bytes, format);
+        assert_eq!(parse_message_bytes(&text, format), Ok(bytes.clone()));
+    }
+    assert_eq!(
+        format_message_bytes(&bytes, MessageFormat::Hex),
+        "4449444c00017b2a"
+    );
+    assert_eq!(
+        format_message_bytes(&bytes, MessageFormat::Base64),
+        "RElETAABeyo="
+    );
+    assert_eq!(
+        parse_message_bytes("0x4449 444c", MessageFormat::Hex),
+        Ok(b"DIDL".to_vec())
+    );
+    assert!(parse_message_bytes("abc", MessageFormat::Hex).is_err());
+    assert!(parse_message_bytes("a$", MessageFormat::Base64).is_err());
+    assert_eq!(
+        parse_message_bytes("RElETA", MessageFormat::Base64),
+        Ok(b"DIDL".to_vec())
+    );
+}
+
+#[test]
+fn encoding_matches_the_wire_format() {
+    assert_eq!(
+        encode("create_user", ParamRole::Result, "(300)"),
+        "4449444c00017dac02"
+    );
+    assert_eq!(
+        encode("sum", ParamRole::Result, "(-65)"),
+        "4449444c00017cbf7f"
+    );
+    assert_eq!(
+        encode("sum", ParamRole::Result, "(-64)"),
+        "4449444c00017c40"
+    );
+    assert_eq!(
+        encode("sum", ParamRole::Result, "(64)"),
+        "4449444c00017cc000"
+    );
+}
+
+#[test]
+fn encoded_values_decode_to_the_same_value() {
+    let env = env();
+    let value = r#"(record { name = "alice"; age = 30; role = variant { Member = 2 }; tags = vec { "a"; "b" } }, opt 12345678901234567890)"#;
+    let hex = encode("create_user", ParamRole::Argument, value);
+    let bytes = parse_message_bytes(&hex, MessageFormat::Hex).expect("hex");
+    let create_user = types("create_user", ParamRole::Argument);
+    let decoded = decode_args(&bytes, &create_user.types, &env).expect("decodes");
+    assert_eq!(
+        encode("create_user", ParamRole::Argument, &render_args(&decoded)),
+        hex
+    );
+
+    let list = encode(
+        "sum",
+        ParamRole::Argument,
+        "(opt record { head = -1; tail = opt record { head = 2; tail = null } })",
+    );
+    let bytes = parse_message_bytes(&list, MessageFormat::Hex).expect("hex");
+    let sum = types("sum", ParamRole::Argument);
+    let decoded = decode_args(&bytes, &sum.types, &env).expect("decodes");
+    assert_eq!(
+        decoded[0].to_string(),
+        "opt record { head = -1; tail = opt record { head = 2; tail = null; }; }"
+    );
+}
+
+#[test]
+fn decoded_arguments_open_as_a_value_document() {
+    let hex = encode(
+        "create_user",
+        ParamRole::Argument,
+        r#"(record { name = "alice"; age = 30; role = variant { Admin }; tags = vec {} }, null)"#,
+    );
+    let bytes = parse_message_bytes(&hex, MessageFormat::Hex).expect("hex");
+    let create_user = types("create_user", ParamRole::Argument);
+    let document = decode_method_message(&uri(), &env(), &create_user, &bytes).expect("decodes");
+    assert_eq!(
+        document.uri,
+        "candid-message:///workspace/users.did.create_user.args"
+    );
+    let mut lines = document.content.lines();
+    assert_eq!(lines.next(), Some("// candid-ls-did: /workspace/users.did"));
+    assert_eq!(lines.next(), Some("// candid-ls-method: create_user"));
+    assert!(document.content.contains("role = variant { Admin };"));
+}
+
+#[test]
+fn decoding_errors_point_at_the_method_type() {
+    // `record { id = 1; name = 2 }`, with `name` sent as `nat` instead of `text`.
+    let bytes =
+        b"DIDL\x01\x6c\x02\xdb\xb7\x01\x7d\xcb\xe4\xfd\xc7\x04\x7d\x01\x00\x01\x02".to_vec();
+    let rename = types("rename", ParamRole::Argument);
+    let err = decode_method_message(&uri(), &env(), &rename, &bytes).expect_err("fails");
+    assert!(
+        err.message.starts_with("cannot decode arguments: "),
+        "{}",
+        err.message
+    );
+    let span = err.span.expect("span");
+    assert_eq!(&SOURCE[span], "(record { id : nat; name : text }) -> ()");
+
+    let rope = Rope::from_str(SOURCE);
+    let semantic = analyze_program(&parse(SOURCE).ast.expect("parses"), &rope).expect("analyzes");
+    let err = MethodTypes::lookup(&semantic, &rope, &env(), "missing", ParamRole::Argument)
+        .expect_err("fails");
+    assert_eq!(err.message, "no service method `missing`");
+    assert_eq!(err.span, None);
+}
+
+#[test]
+fn long_values_are_pretty_printed() {
+    let values = vec![NormalValue::Record(
+        (0..6)
+            .map(|idx| {
+                (
+                    Label::Named(format!("field_{idx}")),
+                    NormalValue::Text("some longer text".to_string()),
+                )
+            })
+            .collect(),
+    )];
+    let pretty = render_args_pretty(&values, 80);
+    assert!(pretty.starts_with("(\n  record {\n    field_0 = \"some longer text\";\n"));
+    assert!(pretty.ends_with("  }\n)"));
+    assert_eq!(render_args_pretty(&values[..0], 80), "()");
+}
@@ -0,0 +1,99 @@
+use candid_language_server::{
+    candid_lang::{ParserResult, parse},
+    lsp::{
+        config::DiagnosticsConfig,
+        diagnostic_rules::{DiagnosticRule, RuleLevel},
+        diagnostics::diagnostics_for_source,
+        missing_docs::{DocTarget, missing_doc_actions, missing_doc_diagnostics, missing_docs},
+        semantic_analyze::{Semantic, analyze_program},
+    },
+};
+use ropey::Rope;
+use std::str::FromStr;
+use tower_lsp_server::ls_types::{CodeActionOrCommand, Position, Uri};
+
+const SOURCE: &str = "/// A user.\n\
+type User = record { name : text; status : Status };\n\
+type Status = variant {\n  /// Can sign in.\n  Active;\n  Banned;\n};\n\
+type Unused = variant { Orphan };\n\
+service : {\n  /// Look a user up.\n  get : (nat) -> (User) query;\n  ping : () -> ();\n};";
+
+fn uri() -> Uri {
+    Uri::from_str("file:///workspace/service.did").expect("valid uri")
+}
+
+fn analyze(text: &str) -> (Semantic, Rope) {
+    let rope = Rope::from_str(text);
+    let ParserResult { ast, .. } = parse(text);
+    let ast = ast.expect("parsed AST");
+    let semantic = analyze_program(&ast, &rope).expect("semantic");
+    (semantic, rope)
+}
+
+#[test]
+fn flags_undocumented_public_declarations_only() {
+    let (semantic, rope) = analyze(SOURCE);
+    let found: Vec<_> = missing_docs(&semantic, &rope)
+        .into_iter()
+        .map(|item| (item.target, item.name))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (DocTarget::Type, "Status".to_string()),
+            (DocTarget::VariantCase, "Banned".to_string()),
+            (DocTarget::Method, "ping".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn lint_is_off_unless_enabled() {
+    assert!(diagnostics_for_source(&uri(), SOURCE, &DiagnosticsConfig::default()).is_empty());
+
+    let mut config = DiagnosticsConfig::default();
+    config
+        .rules
+        .set(DiagnosticRule::MissingDocs, RuleLevel::Warning);
+    let diagnostics = diagnostics_for_source(&uri(), SOURCE, &config);
+    assert_eq!(diagnostics.len(), 3);
+    assert!(
+        diagnostics
+            .iter()
+            .all(|diagnostic| DiagnosticRule::of(diagnostic) == Some(DiagnosticRule::MissingDocs))
+    );
+    assert_eq!(
+        diagnostics[2].message,
+        "service method `ping` has no doc comment"
+    );
+}
+
+#[test]
+fn code_action_inserts_indented_stub() {
+    let (semantic, rope) = analyze(SOURCE);
+    let diagnostics = missing_doc_diagnostics(&semantic, &rope);
+    let ping = &diagnostics[2];
+
+    let actions = missing_doc_actions(&uri(), ping, &semantic, &rope);
+    let Some(CodeActionOrCommand::CodeAction(action)) = actions.into_iter().next() else {
+        panic!("expected code action");
+    };
+    assert_eq!(action.title, "Add doc comment for `ping`");
+    let mut changes = action.edit.and_then(|edit| edit.changes).expect("changes");
+    let edit = changes.remove(&uri()).expect("edits for uri").remove(0);
+    assert_eq!(edit.range.start, Position::new(ping.range.start.line, 0));
+    assert_eq!(edit.new_text, "  /// TODO: document `ping`.\n");
+}
+
+#[test]
+fn no_stub_when_declaration_shares_a_line() {
+    let text =
+        "type Status = variant { Active };\nservice : { /// Status.\n status : () -> (Status) };";
+    let (semantic, rope) = analyze(text);
+    let diagnostics = missing_doc_diagnostics(&semantic, &rope);
+    let active = diagnostics
+        .iter()
+        .find(|diagnostic| diagnostic.message.contains("Active"))
+        .expect("variant case diagnostic");
+    assert!(missing_doc_actions(&uri(), active, &semantic, &rope).is_empty());
+}
@@ -0,0 +1,189 @@
+use candid_language_server::{
+    candid_lang::{ParserResult, parse},
+    lsp::{
+        config::{DiagnosticsConfig, ServerConfig},
+        diagnostic_rules::{DiagnosticRule, RuleLevel},
+        diagnostics::diagnostics_for_source,
+        naming::{
+            CaseStyle, NamingConventions, naming_actions, naming_diagnostics, naming_violations,
+        },
+        semantic_analyze::{Semantic, analyze_program},
+    },
+};
+use ropey::Rope;
+use serde_json::json;
+use std::str::FromStr;
+use tower_lsp_server::ls_types::{
+    CodeAction, CodeActionOrCommand, Diagnostic, DiagnosticSeverity, Uri,
+};
+
+const SOURCE: &str = "type user_profile = record { userName : text; 0 : nat };\n\
+type Status = variant { active; Inactive };\n\
+service : { getUser : (nat) -> (user_profile) query };";
+
+fn uri() -> Uri {
+    Uri::from_str("file:///workspace/service.did").expect("valid uri")
+}
+
+fn analyze(text: &str) -> (Semantic, Rope) {
+    let rope = Rope::from_str(text);
+    let ParserResult { ast, .. } = parse(text);
+    let ast = ast.expect("parsed AST");
+    let semantic = analyze_program(&ast, &rope).expect("semantic");
+    (semantic, rope)
+}
+
+fn only_action(actions: Vec<CodeActionOrCommand>) -> CodeAction {
+    assert_eq!(actions.len(), 1);
+    match actions.into_iter().next() {
+        Some(CodeActionOrCommand::CodeAction(action)) => action,
+        _ => panic!("expected code action"),
+    }
+}
+
+fn diagnostic_for(diagnostics: &[Diagnostic], rule: DiagnosticRule) -> &Diagnostic {
+    diagnostics
+        .iter()
+        .find(|diagnostic| DiagnosticRule::of(diagnostic) == Some(rule))
+        .expect("diagnostic for rule")
+}
+
+#[test]
+fn case_styles_match_and_convert() {
+    assert!(CaseStyle::PascalCase.matches("HTTPRequest"));
+    assert!(!CaseStyle::PascalCase.matches("canister_id"));
+    assert!(CaseStyle::SnakeCase.matches("get_user2"));
+    assert!(!CaseStyle::SnakeCase.matches("getUser"));
+
+    assert_eq!(CaseStyle::PascalCase.convert("canister_id"), "CanisterId");
+    assert_eq!(CaseStyle::SnakeCase.convert("getUser"), "get_user");
+    assert_eq!(CaseStyle::SnakeCase.convert("HTTPRequest"), "http_request");
+    assert_eq!(CaseStyle::CamelCase.convert("user-name"), "userName");
+    assert_eq!(
+        CaseStyle::ScreamingSnakeCase.convert("maxValue"),
+        "MAX_VALUE"
+    );
+    assert_eq!(CaseStyle::from_str("snake_case"), Ok(CaseStyle::SnakeCase));
+    assert_eq!(CaseStyle::from_str("camelCase"), Ok(CaseStyle::CamelCase));
+    assert!(CaseStyle::from_str("kebab").is_err());
+}
+
+#[test]
+fn violations_cover_types_fields_tags_and_methods() {
+    let (semantic, rope) = analyze(SOURCE);
+    let violations = naming_violations(&semantic, &rope, &NamingConventions::default());
+    let found: Vec<_> = violations
+        .iter()
+        .map(|violation| (violation.rule, violation.name.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (DiagnosticRule::NamingTypeName, "user_profile"),
+            (DiagnosticRule::NamingFieldLabel, "userName"),
+            (DiagnosticRule::NamingVariantTag, "active"),
+            (DiagnosticRule::NamingMethodName, "getUser"),
+        ]
+    );
+    assert_eq!(violations[0].suggestion, "UserProfile");
+    assert_eq!(violations[0].references.len(), 1);
+}
+
+#[test]
+fn naming_diagnostics_are_opt_in_warnings_with_codes() {
+    assert!(diagnostics_for_source(&uri(), SOURCE, &DiagnosticsConfig::default()).is_empty());
+
+    let mut config = DiagnosticsConfig::default();
+    for rule in DiagnosticRule::NAMING {
+        config.rules.set(rule, RuleLevel::Warning);
+    }
+    let diagnostics = diagnostics_for_source(&uri(), SOURCE, &config);
+    assert_eq!(diagnostics.len(), 4);
+    let field = diagnostic_for(&diagnostics, DiagnosticRule::NamingFieldLabel);
+    assert_eq!(field.severity, Some(DiagnosticSeverity::WARNING));
+    assert_eq!(field.source.as_deref(), Some("naming"));
+    assert_eq!(field.message, "field label `userName` should be snake_case");
+}
+
+#[test]
+fn type_rename_updates_references() {
+    let (semantic, rope) = analyze(SOURCE);
+    let conventions = NamingConventions::default();
+    let diagnostics = naming_diagnostics(&semantic, &rope, &conventions);
+    let diagnostic = diagnostic_for(&diagnostics, DiagnosticRule::NamingTypeName);
+
+    let action = only_action(naming_actions(
+        &uri(),
+        diagnostic,
+        &semantic,
+        &rope,
+        &conventions,
+    ));
+    assert_eq!(action.title, "Rename `user_profile` to `UserProfile`");
+    assert_eq!(action.is_preferred, Some(true));
+    let mut changes = action.edit.and_then(|edit| edit.changes).expect("changes");
+    let edits = changes.remove(&uri()).expect("edits for uri");
+    assert_eq!(edits.len(), 2);
+    assert!(edits.iter().all(|edit| edit.new_text == "UserProfile"));
+    assert_eq!(edits[1].range.start.line, 2);
+}
+
+#[test]
+fn wire_format_renames_are_flagged_and_not_preferred() {
+    let (semantic, rope) = analyze(SOURCE);
+    let conventions = NamingConventions::default();
+    let diagnostics = naming_diagnostics(&semantic, &rope, &conventions);
+    let diagnostic = diagnostic_for(&diagnostics, DiagnosticRule::NamingMethodName);
+
+    let action = only_action(naming_actions(
+        &uri(),
+        diagnostic,
+        &semantic,
+        &rope,
+        &conventions,
+    ));
+    assert_eq!(
+        action.title,
+        "Rename `getUser` to `get_user` (changes the wire format)"
+    );
+    assert_eq!(action.is_preferred, None);
+}
+
+#[test]
+fn conflicting_type_renames_are_not_offered() {
+    let text = "type user = nat;\ntype User = text;";
+    let (semantic, rope) = analyze(text);
+    let conventions = NamingConventions::default();
+    let diagnostics = naming_diagnostics(&semantic, &rope, &conventions);
+    assert_eq!(diagnostics.len(), 1);
+    assert!(naming_actions(&uri(), &diagnostics[0], &semantic, &rope, &conventions).is_empty());
+}
+
+#[test]
+fn naming_styles_are_read_from_settings() {
+    let mut config = ServerConfig::default();
+    config.apply_settings(json!({
+        "candidLanguageServer": {
+            "diagnostics": {
+                "rules": { "naming::field-label": "warning" },
+                "naming": {
+                    "fieldLabels": "camelCase",
+                    "methodNames": "bogus"
+                }
+            }
+        }
+    }));
+    let naming = config.diagnostics().naming;
+    assert_eq!(naming.field_labels, CaseStyle::CamelCase);
+    assert_eq!(naming.method_names, CaseStyle::SnakeCase);
+    assert!(config.diagnostics().tag().is_some());
+    assert!(DiagnosticsConfig::default().tag().is_none());
+
+    let diagnostics = diagnostics_for_source(&uri(), SOURCE, config.diagnostics());
+    assert!(
+        diagnostics
+            .iter()
+            .all(|diagnostic| DiagnosticRule::of(diagnostic)
+                != Some(DiagnosticRule::NamingFieldLabel))
+    );
+}
@@ -0,0 +1,110 @@
+use candid_language_server::{
+    candid_lang::{CandidError, parse},
+    lsp::parse_diagnostics::{describe_terminal, parse_error_to_diagnostic},
+};
+use ropey::Rope;
+use std::str::FromStr;
+use tower_lsp_server::ls_types::{Diagnostic, Position, Range, Uri};
+
+fn uri() -> Uri {
+    Uri::from_str("file:///workspace/service.did").expect("valid uri")
+}
+
+fn first_parse_diagnostic(text: &str, uri: Option<&Uri>) -> Diagnostic {
+    let rope = Rope::from_str(text);
+    let err = parse(text)
+        .parse_errors
+        .into_iter()
+        .find_map(|err| match err {
+            CandidError::Parser(candid_parser::Error::Parse(err)) => Some(err),
+            _ => None,
+        })
+        .expect("parse error");
+    parse_error_to_diagnostic(err, uri, &rope)
+}
+
+#[test]
+fn terminals_are_described_in_prose() {
+    assert_eq!(describe_terminal("\"id\""), "an identifier");
+    assert_eq!(describe_terminal("\"text\""), "a text literal");
+    assert_eq!(describe_terminal("\"sign\""), "`+` or `-`");
+    assert_eq!(describe_terminal("\"record\""), "`record`");
+    assert_eq!(describe_terminal("\"->\""), "`->`");
+}
+
+#[test]
+fn missing_semicolon_between_declarations() {
+    let text = "type A = record { a : nat }\ntype B = text;";
+    let diagnostic = first_parse_diagnostic(text, Some(&uri()));
+    assert_eq!(diagnostic.message, "missing `;` after type definition");
+    assert_eq!(
+        diagnostic.range,
+        Range::new(Position::new(0, 26), Position::new(0, 27))
+    );
+}
+
+#[test]
+fn missing_semicolon_at_end_of_file() {
+    let text = "import \"other.did\";\ntype A = nat // trailing\n";
+    let diagnostic = first_parse_diagnostic(text, Some(&uri()));
+    assert_eq!(diagnostic.message, "missing `;` after type definition");
+    assert_eq!(
+        diagnostic.range,
+        Range::new(Position::new(1, 11), Position::new(1, 12))
+    );
+}
+
+#[test]
+fn unclosed_brace_points_back_to_the_opener() {
+    let text = "type A = record { a : nat;\ntype B = text;";
+    let diagnostic = first_parse_diagnostic(text, Some(&uri()));
+    assert_eq!(
+        diagnostic.message,
+        "unclosed `{`: expected `}` before `type`"
+    );
+    assert_eq!(diagnostic.range.start, Position::new(1, 0));
+
+    let related = diagnostic.related_information.expect("related information");
+    assert_eq!(related.len(), 1);
+    assert_eq!(related[0].message, "`{` opened here");
+    assert_eq!(related[0].location.uri, uri());
+    assert_eq!(
+        related[0].location.range,
+        Range::new(Position::new(0, 16), Position::new(0, 17))
+    );
+}
+
+#[test]
+fn unclosed_brace_without_uri_is_folded_into_the_message() {
+    let text = "type A = record { a : nat;\ntype B = text;";
+    let diagnostic = first_parse_diagnostic(text, None);
+    assert!(diagnostic.related_information.is_none());
+    assert!(diagnostic.message.contains("`{` opened here at line 1"));
+}
+
+#[test]
+fn unexpected_tokens_name_the_token_and_expectations() {
+    let text = "type A = record { a : };";
+    let diagnostic = first_parse_diagnostic(text, Some(&uri()));
+    assert!(
+        diagnostic.message.starts_with("unexpected `}`, expected"),
+        "{}",
+        diagnostic.message
+    );
+    assert!(diagnostic.message.contains("an identifier"));
+    assert!(!diagnostic.message.contains('"'));
+}
+
+#[test]
+fn delimiters_in_comments_and_text_are_not_openers() {
+    let text = "/* { ( */\nimport \"{(.did\";\ntype A = record { a : nat; // }\n";
+    let diagnostic = first_parse_diagnostic(text, Some(&uri()));
+    assert_eq!(
+        diagnostic.message,
+        "unclosed `{`: expected `}` before the end of the file"
+    );
+    assert_eq!(
+        diagnostic.range,
+        Range::new(Position::new(2, 16), Position::new(2, 17))
+    );
+}
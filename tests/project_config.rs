@@ -0,0 +1,108 @@
+use candid_language_server::lsp::{
+    diagnostic_rules::{DiagnosticRule, RuleLevel},
+    format::TrailingSeparator,
+    project_config::{ProjectConfigs, config_for_path, discover},
+};
+use serde_json::json;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tower_lsp_server::ls_types::FormattingOptions;
+
+/// A fresh directory tree for one test.
+fn project(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("candid-ls-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("api/nested")).expect("create project");
+    root
+}
+
+fn write(path: &Path, text: &str) {
+    fs::write(path, text).expect("write file");
+}
+
+#[test]
+fn discovery_walks_up_to_the_nearest_configuration() {
+    let root = project("discover");
+    let document = root.join("api/nested/service.did");
+    assert_eq!(discover(&document), None);
+
+    write(&root.join("api/Cargo.toml"), "[package]\nname = \"api\"\n");
+    write(
+        &root.join("candid-ls.toml"),
+        "[format]\nalign-colons = true\n",
+    );
+    assert_eq!(discover(&document), Some(root.join("candid-ls.toml")));
+
+    write(
+        &root.join("api/Cargo.toml"),
+        "[package]\nname = \"api\"\n\n[package.metadata.candid-ls.format]\nmax-width = 60\n",
+    );
+    assert_eq!(discover(&document), Some(root.join("api/Cargo.toml")));
+    let config = config_for_path(&document).expect("config");
+    let options = config.format_options(&FormattingOptions::default());
+    assert_eq!(options.max_width, Some(60));
+    assert!(!options.align_colons);
+
+    write(&root.join("api/nested/candid-ls.toml"), "[format\n");
+    assert!(config_for_path(&document).is_err());
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn editor_settings_override_the_project_file() {
+    let root = project("merge");
+    let config_path = root.join("candid-ls.toml");
+    write(
+        &config_path,
+        "[format]\nindent-width = 4\ntrailing-separator = \"never\"\n\n[diagnostics.rules]\n\"missing-docs\" = \"error\"\n",
+    );
+    let uri = "file:///workspace/api/service.did";
+
+    let mut configs = ProjectConfigs::default();
+    configs.apply_editor_settings(json!({ "format": { "sortTypes": true } }));
+    configs.load(&config_path).expect("load");
+    configs.attach(uri, Some(config_path.clone()));
+    configs.apply_editor_settings(json!({ "format": { "indentWidth": 2 } }));
+
+    let config = configs.config_for(uri).expect("project config");
+    let options = config.format_options(&FormattingOptions::default());
+    assert_eq!(options.indent_width, Some(2));
+    assert_eq!(options.trailing_separator, TrailingSeparator::Never);
+    assert!(options.sort_types);
+    assert!(config.diagnostics().rules.tag().is_some());
+
+    // Editor rules join the project's instead of replacing them, whatever the key spelling.
+    configs.apply_editor_settings(json!({
+        "candidLanguageServer": {
+            "diagnostics": { "rules": { "candid::undefined-type": "warning" } },
+            "format": { "indent_width": 3 }
+        }
+    }));
+    let config = configs.config_for(uri).expect("project config");
+    let rules = &config.diagnostics().rules;
+    assert_eq!(rules.level(DiagnosticRule::MissingDocs), RuleLevel::Error);
+    assert_eq!(
+        rules.level(DiagnosticRule::UndefinedType),
+        RuleLevel::Warning
+    );
+    let options = config.format_options(&FormattingOptions::default());
+    assert_eq!(options.indent_width, Some(3));
+    assert_eq!(options.trailing_separator, TrailingSeparator::Never);
+    configs.apply_editor_settings(json!({ "format": { "indentWidth": 2 } }));
+
+    // Reloading replays the merged editor settings, earlier keys included.
+    configs.clear_files();
+    configs.load(&config_path).expect("reload");
+    let options = configs
+        .config_for(uri)
+        .expect("project config")
+        .format_options(&FormattingOptions::default());
+    assert_eq!(options.indent_width, Some(2));
+    assert!(options.sort_types);
+
+    configs.attach(uri, None);
+    assert!(configs.config_for(uri).is_none());
+    let _ = fs::remove_dir_all(&root);
+}
@@ -0,0 +1,110 @@
+use candid_language_server::{
+    candid_lang::{ParserResult, parse},
+    lsp::{
+        semantic_analyze::analyze_program,
+        semantic_token::{
+            LEGEND_TYPES, MODIFIER_DECLARATION, MODIFIER_DEPRECATED, MODIFIER_DOCUMENTATION,
+            MODIFIER_READONLY, apply_semantic_tokens, semantic_tokens_edits,
+        },
+    },
+};
+use ropey::Rope;
+use tower_lsp_server::ls_types::{SemanticToken, SemanticTokenType};
+
+fn token_at(text: &str, needle: &str) -> (SemanticTokenType, u32) {
+    let rope = Rope::from_str(text);
+    let ParserResult {
+        ast,
+        mut semantic_tokens,
+        ..
+    } = parse(text);
+    let semantic = analyze_program(&ast.expect("parsed AST"), &rope).expect("semantic");
+    apply_semantic_tokens(&mut semantic_tokens, &semantic);
+    let offset = text.find(needle).expect("needle present");
+    let token = semantic_tokens
+        .iter()
+        .find(|token| token.start == offset)
+        .expect("token at needle");
+    (
+        LEGEND_TYPES[token.token_type].clone(),
+        token.token_modifiers,
+    )
+}
+
+const SOURCE: &str = "/// A user.\n/// @deprecated use Account\ntype User = record { name : text };\nservice : {\n  get : (id : nat) -> (User) query;\n  set : (User) -> ();\n}";
+
+#[test]
+fn binding_names_are_type_declarations() {
+    let (kind, modifiers) = token_at(SOURCE, "User =");
+    assert_eq!(kind, SemanticTokenType::TYPE);
+    assert_ne!(modifiers & MODIFIER_DECLARATION, 0);
+    assert_ne!(modifiers & MODIFIER_DEPRECATED, 0);
+}
+
+#[test]
+fn references_are_types_without_declaration() {
+    let (kind, modifiers) = token_at(SOURCE, "User) query");
+    assert_eq!(kind, SemanticTokenType::TYPE);
+    assert_eq!(modifiers & MODIFIER_DECLARATION, 0);
+}
+
+#[test]
+fn fields_methods_and_params_are_classified() {
+    assert_eq!(token_at(SOURCE, "name").0, SemanticTokenType::PROPERTY);
+    assert_eq!(token_at(SOURCE, "id :").0, SemanticTokenType::PARAMETER);
+
+    let (kind, modifiers) = token_at(SOURCE, "get");
+    assert_eq!(kind, SemanticTokenType::METHOD);
+    assert_ne!(modifiers & MODIFIER_READONLY, 0);
+
+    let (_, modifiers) = token_at(SOURCE, "set");
+    assert_eq!(modifiers & MODIFIER_READONLY, 0);
+}
+
+#[test]
+fn doc_comments_carry_documentation_modifier() {
+    let (kind, modifiers) = token_at(SOURCE, "/// A user.");
+    assert_eq!(kind, SemanticTokenType::COMMENT);
+    assert_ne!(modifiers & MODIFIER_DOCUMENTATION, 0);
+}
+
+fn encoded(lines: &[u32]) -> Vec<SemanticToken> {
+    lines
+        .iter()
+        .map(|&delta_line| SemanticToken {
+            delta_line,
+            delta_start: 0,
+            length: 1,
+            token_type: 0,
+            token_modifiers_bitset: 0,
+        })
+        .collect()
+}
+
+#[test]
+fn delta_of_identical_tokens_is_empty() {
+    let tokens = encoded(&[0, 1, 2]);
+    assert!(semantic_tokens_edits(&tokens, &tokens).is_empty());
+}
+
+#[test]
+fn delta_replaces_only_the_changed_middle() {
+    let previous = encoded(&[0, 1, 2, 3]);
+    let current = encoded(&[0, 1, 5, 6, 3]);
+    let edits = semantic_tokens_edits(&previous, &current);
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0].start, 10);
+    assert_eq!(edits[0].delete_count, 5);
+    assert_eq!(edits[0].data.as_deref(), Some(&current[2..4]));
+}
+
+#[test]
+fn delta_handles_pure_deletion() {
+    let previous = encoded(&[0, 1, 2]);
+    let current = encoded(&[0, 2]);
+    let edits = semantic_tokens_edits(&previous, &current);
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0].start, 5);
+    assert_eq!(edits[0].delete_count, 5);
+    assert!(edits[0].data.is_none());
+}
@@ -0,0 +1,124 @@
+use candid_language_server::lsp::{
+    config::DiagnosticsConfig,
+    diagnostic_rules::{DiagnosticRule, RuleLevel},
+    diagnostics::diagnostics_for_source,
+    suppression::suppression_actions,
+};
+use ropey::Rope;
+use std::str::FromStr;
+use tower_lsp_server::ls_types::{
+    CodeActionOrCommand, Diagnostic, DiagnosticSeverity, DiagnosticTag, NumberOrString, Uri,
+};
+
+fn uri() -> Uri {
+    Uri::from_str("file:///workspace/service.did").expect("valid uri")
+}
+
+fn diagnostics(text: &str) -> Vec<Diagnostic> {
+    diagnostics_for_source(&uri(), text, &DiagnosticsConfig::default())
+}
+
+fn code(diagnostic: &Diagnostic) -> &str {
+    match &diagnostic.code {
+        Some(NumberOrString::String(code)) => code,
+        _ => "",
+    }
+}
+
+#[test]
+fn line_suppression_silences_the_next_line() {
+    let text = "// candid-ls-ignore undefined-type\nservice : { get : () -> (Missing) query };";
+    assert!(diagnostics(text).is_empty());
+}
+
+#[test]
+fn stacked_and_trailing_suppressions_apply() {
+    let stacked = "// candid-ls-ignore undefined-type -- legacy name\n// candid-ls-ignore\nservice : { get : () -> (Missing) query };";
+    assert!(diagnostics(stacked).is_empty());
+
+    let trailing =
+        "service : { get : () -> (Missing) query }; // candid-ls-ignore candid::undefined-type";
+    assert!(diagnostics(trailing).is_empty());
+}
+
+#[test]
+fn suppression_for_another_rule_is_reported_as_unused() {
+    let text =
+        "// candid-ls-ignore inconsistent-vec-type\nservice : { get : () -> (Missing) query };";
+    let result = diagnostics(text);
+    assert_eq!(result.len(), 2);
+    assert_eq!(code(&result[0]), "candid::undefined-type");
+
+    let hint = &result[1];
+    assert_eq!(code(hint), "candid::unused-suppression");
+    assert_eq!(hint.severity, Some(DiagnosticSeverity::HINT));
+    assert_eq!(hint.tags, Some(vec![DiagnosticTag::UNNECESSARY]));
+    assert_eq!(hint.range.start.line, 0);
+}
+
+#[test]
+fn file_suppression_applies_only_at_the_top() {
+    let text = "// candid-ls-ignore-file undefined-type\ntype A = nat;\nservice : { get : () -> (Missing) query };";
+    assert!(diagnostics(text).is_empty());
+
+    let misplaced = "type A = nat;\n// candid-ls-ignore-file undefined-type\nservice : { get : () -> (Missing) query };";
+    let result = diagnostics(misplaced);
+    assert_eq!(result.len(), 2);
+    assert_eq!(code(&result[0]), "candid::undefined-type");
+    assert!(result[1].message.contains("before the first declaration"));
+}
+
+#[test]
+fn unknown_rules_are_reported() {
+    let text = "// candid-ls-ignore no-such-rule\ntype A = nat;";
+    let result = diagnostics(text);
+    assert_eq!(result.len(), 1);
+    assert!(result[0].message.contains("no-such-rule"));
+}
+
+#[test]
+fn unused_suppression_hints_follow_rule_config() {
+    let text = "// candid-ls-ignore undefined-type\ntype A = nat;";
+    let mut config = DiagnosticsConfig::default();
+    config
+        .rules
+        .set(DiagnosticRule::UnusedSuppression, RuleLevel::Off);
+    assert!(diagnostics_for_source(&uri(), text, &config).is_empty());
+}
+
+#[test]
+fn suppressions_for_disabled_rules_are_not_reported_as_unused() {
+    let text = "// candid-ls-ignore undefined-type\ntype A = nat;";
+    let mut config = DiagnosticsConfig::default();
+    config
+        .rules
+        .set(DiagnosticRule::UndefinedType, RuleLevel::Off);
+    assert!(diagnostics_for_source(&uri(), text, &config).is_empty());
+}
+
+#[test]
+fn code_actions_insert_line_and_file_suppressions() {
+    let text = "type A = record {\n  a : Missing;\n};";
+    let diagnostic = diagnostics(text).remove(0);
+    assert_eq!(diagnostic.range.start.line, 1);
+
+    let actions = suppression_actions(&uri(), &diagnostic, &Rope::from_str(text));
+    let edits: Vec<_> = actions
+        .into_iter()
+        .map(|action| match action {
+            CodeActionOrCommand::CodeAction(action) => {
+                let mut changes = action.edit.and_then(|edit| edit.changes).expect("changes");
+                changes.remove(&uri()).expect("edits for uri").remove(0)
+            }
+            CodeActionOrCommand::Command(_) => panic!("expected code action"),
+        })
+        .collect();
+    assert_eq!(edits.len(), 2);
+    assert_eq!(edits[0].range.start.line, 1);
+    assert_eq!(edits[0].new_text, "  // candid-ls-ignore undefined-type\n");
+    assert_eq!(edits[1].range.start.line, 0);
+    assert_eq!(
+        edits[1].new_text,
+        "// candid-ls-ignore-file undefined-type\n"
+    );
+}
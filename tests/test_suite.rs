@@ -0,0 +1,164 @@
+use candid_language_server::lsp::{
+    config::DiagnosticsConfig,
+    diagnostic_rules::DiagnosticRule,
+    diagnostics::diagnostics_for_source,
+    normal_value::render_args,
+    test_suite::{
+        AssertionKind, TestInput, assertion_hover_markdown, parse_test_suite, run_assertion,
+        test_suite_semantic_tokens,
+    },
+    value_check::TypeEnv,
+    workspace::{DocumentKind, is_candid_file},
+};
+use std::{path::Path, str::FromStr};
+use tower_lsp_server::ls_types::{Diagnostic, Uri};
+
+const SUITE: &str = r#"type Nat8 = nat8;
+assert blob "DIDL\00\01\7b\2a" : (Nat8) "nat8 decodes";
+assert blob "DIDL\00\01\7d\80\01" : (Nat8) "nat is not nat8";
+assert blob "DIDL\00\01\7d\80\01" !: (Nat8);
+assert "(42)" == blob "DIDL\00\01\7b\2a" : (nat8);
+assert "(41)" == blob "DIDL\00\01\7b\2a" : (nat8);
+"#;
+
+fn suite_uri() -> Uri {
+    Uri::from_str("file:///workspace/nat.test.did").expect("valid uri")
+}
+
+fn diagnostics(text: &str) -> Vec<Diagnostic> {
+    diagnostics_for_source(&suite_uri(), text, &DiagnosticsConfig::default())
+}
+
+fn suite_env(text: &str) -> TypeEnv {
+    let suite = parse_test_suite(text);
+    let defs = candid_language_server::candid_lang::parse(&text[..suite.defs_end]);
+    TypeEnv::new(&defs.ast.expect("definitions parse"), None)
+}
+
+#[test]
+fn test_suites_are_detected_by_extension() {
+    assert_eq!(DocumentKind::of(&suite_uri()), DocumentKind::TestSuite);
+    assert!(!is_candid_file(Path::new("/workspace/nat.test.did")));
+    assert!(is_candid_file(Path::new("/workspace/nat.did")));
+}
+
+#[test]
+fn assertions_follow_the_definitions() {
+    let suite = parse_test_suite(SUITE);
+    assert!(suite.errors.is_empty());
+    assert_eq!(&SUITE[suite.defs_end..suite.defs_end + 6], "assert");
+    let kinds: Vec<_> = suite.assertions.iter().map(|a| a.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            AssertionKind::Decodes,
+            AssertionKind::Decodes,
+            AssertionKind::Rejects,
+            AssertionKind::Equal,
+            AssertionKind::Equal,
+        ]
+    );
+    let first = &suite.assertions[0];
+    assert_eq!(first.description.as_deref(), Some("nat8 decodes"));
+    assert_eq!(
+        first.left.input,
+        TestInput::Blob(b"DIDL\x00\x01\x7b\x2a".to_vec())
+    );
+    assert_eq!(first.types.len(), 1);
+}
+
+#[test]
+fn failing_assertions_are_reported_inline() {
+    let diagnostics = diagnostics(SUITE);
+    assert_eq!(diagnostics.len(), 2, "{diagnostics:?}");
+    assert!(diagnostics.iter().all(|diagnostic| {
+        DiagnosticRule::of(diagnostic) == Some(DiagnosticRule::TestAssertionFailed)
+    }));
+    assert_eq!(diagnostics[0].range.start.line, 2);
+    assert!(
+        diagnostics[0]
+            .message
+            .starts_with("nat is not nat8: blob does not decode as `(Nat8)`"),
+        "{}",
+        diagnostics[0].message
+    );
+    assert_eq!(diagnostics[1].range.start.line, 5);
+    assert_eq!(diagnostics[1].message, "values differ: `(41)` and `(42)`");
+}
+
+#[test]
+fn decoded_values_are_normalized_for_comparison() {
+    let text = r#"type R = record { a : nat; b : opt text };
+assert blob "DIDL\01\6c\01\61\7d\01\00\05" == "(record { a = 5 })" : (R);
+assert "(record { a = 0x05; b = null })" == "(record { a = 5 })" : (R);
+"#;
+    let suite = parse_test_suite(text);
+    let env = suite_env(text);
+    for assertion in &suite.assertions {
+        let outcome = run_assertion(assertion, &env);
+        assert!(outcome.passed, "{}", outcome.message);
+    }
+    let outcome = run_assertion(&suite.assertions[0], &env);
+    assert_eq!(
+        render_args(&outcome.left.expect("decodes")),
+        "(record { a = 5; b = null; })"
+    );
+}
+
+#[test]
+fn malformed_assertions_recover_at_semicolons() {
+    let text = "assert blob \"DIDL\\00\\00\" (nat);\nassert \"()\" : ();\nassert 42 : ();\n";
+    let suite = parse_test_suite(text);
+    assert_eq!(suite.defs_end, 0);
+    assert_eq!(suite.assertions.len(), 1);
+    let diagnostics = diagnostics(text);
+    assert_eq!(diagnostics.len(), 2);
+    assert!(
+        diagnostics
+            .iter()
+            .all(|diagnostic| DiagnosticRule::of(diagnostic) == Some(DiagnosticRule::TestSyntax))
+    );
+    assert_eq!(diagnostics[0].range.start.line, 0);
+    assert_eq!(diagnostics[1].range.start.line, 2);
+}
+
+#[test]
+fn undefined_types_are_reported_on_the_types() {
+    let diagnostics = diagnostics("assert \"(1)\" : (Missing);\n");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        DiagnosticRule::of(&diagnostics[0]),
+        Some(DiagnosticRule::UndefinedType)
+    );
+    assert_eq!(diagnostics[0].message, "type `Missing` is not defined");
+}
+
+#[test]
+fn assertion_identifiers_get_semantic_roles() {
+    let text = "type T = nat;\nassert \"(record { a = 1 })\" : (record { a : T });\n";
+    let suite = parse_test_suite(text);
+    let tokens = test_suite_semantic_tokens(text, &suite);
+    let token_at = |needle: &str| {
+        let start = text.rfind(needle).expect("needle");
+        tokens
+            .iter()
+            .find(|token| token.start == start)
+            .expect("token")
+            .token_type
+    };
+    assert_ne!(token_at("assert"), token_at("a :"));
+    assert_ne!(token_at("a :"), token_at("T }"));
+    assert!(tokens.iter().all(|token| token.start >= suite.defs_end));
+}
+
+#[test]
+fn hover_shows_the_outcome_and_decoded_values() {
+    let suite = parse_test_suite(SUITE);
+    let env = suite_env(SUITE);
+    let passing = assertion_hover_markdown(&suite.assertions[0], &env).expect("markdown");
+    assert!(passing.contains("**nat8 decodes** — assertion passes"));
+    assert!(passing.contains("(42)"));
+    let failing = assertion_hover_markdown(&suite.assertions[4], &env).expect("markdown");
+    assert!(failing.contains("Assertion fails"));
+    assert!(failing.contains("values differ"));
+}
@@ -0,0 +1,115 @@
+use candid_language_server::{
+    candid_lang::parse,
+    lsp::{
+        type_relation::{Relation, TypeRelation, check_relation, parse_type_text},
+        value_check::TypeEnv,
+    },
+};
+
+const SOURCE: &str = r#"type Small = record { id : nat; value : nat16 };
+type Large = record { id : nat; value : nat32; extra : text };
+type Renamed = record { value : nat16; id : nat };
+type Status = variant { ok; err : text };
+type List = opt record { head : nat; tail : List };
+type IntList = opt record { head : int; tail : IntList };
+type Api = service { get : (nat) -> (Small) query };
+"#;
+
+fn relate(a: &str, b: &str, relation: Relation) -> TypeRelation {
+    let env = TypeEnv::new(&parse(SOURCE).ast.expect("parses"), None);
+    let a = parse_type_text(a, &env).expect("type a");
+    let b = parse_type_text(b, &env).expect("type b");
+    check_relation(&a, &b, &env, relation).expect("types check")
+}
+
+fn explanation(a: &str, b: &str, relation: Relation) -> String {
+    let relation = relate(a, b, relation);
+    assert!(!relation.holds);
+    relation.explanation.expect("explanation")
+}
+
+#[test]
+fn mismatches_explain_the_path_to_the_difference() {
+    let relation = relate("Large", "Small", Relation::Subtype);
+    assert!(!relation.holds);
+    let explanation = relation.explanation.expect("explanation");
+    assert!(explanation.contains("nat32"), "{explanation}");
+    assert!(explanation.contains("nat16"), "{explanation}");
+    assert!(!relation.path.is_empty());
+    assert!(explanation.starts_with(&relation.path.join(": ")));
+}
+
+#[test]
+fn subtyping_follows_the_candid_rules() {
+    for (a, b) in [
+        ("nat", "int"),
+        ("record { id : nat; value : nat16; extra : text }", "Small"),
+        (
+            "Small",
+            "record { id : nat; value : nat16; note : opt text }",
+        ),
+        ("variant { ok }", "Status"),
+        ("List", "IntList"),
+        ("nat", "opt nat"),
+        ("empty", "text"),
+        ("Api", "service {}"),
+        ("func (int) -> (nat)", "func (nat) -> (int)"),
+    ] {
+        let relation = relate(a, b, Relation::Subtype);
+        assert!(relation.holds, "{a} <: {b}: {:?}", relation.explanation);
+    }
+    assert!(explanation("Status", "variant { ok }", Relation::Subtype).contains("err"));
+    assert!(
+        explanation(
+            "Small",
+            "record { id : nat; name : text }",
+            Relation::Subtype
+        )
+        .contains("name")
+    );
+    explanation(
+        "func (nat) -> ()",
+        "func (nat) -> () query",
+        Relation::Subtype,
+    );
+}
+
+#[test]
+fn the_special_opt_rule_holds_with_a_warning() {
+    let relation = relate(
+        "record { value : opt text }",
+        "record { value : opt nat }",
+        Relation::Subtype,
+    );
+    assert!(relation.holds);
+    assert_eq!(relation.warnings.len(), 1, "{:?}", relation.warnings);
+    assert!(relate("nat", "int", Relation::Subtype).warnings.is_empty());
+}
+
+#[test]
+fn equality_is_structural() {
+    assert!(relate("Small", "Renamed", Relation::Equal).holds);
+    assert!(
+        relate(
+            "List",
+            "opt record { head : nat; tail : List }",
+            Relation::Equal
+        )
+        .holds
+    );
+    assert!(explanation("List", "IntList", Relation::Equal).contains("int"));
+    explanation("Small", "Large", Relation::Equal);
+}
+
+#[test]
+fn type_texts_must_be_types_of_the_document() {
+    let env = TypeEnv::new(&parse(SOURCE).ast.expect("parses"), None);
+    assert_eq!(
+        parse_type_text("vec Missing", &env),
+        Err("type `Missing` is not defined".to_string())
+    );
+    assert_eq!(
+        parse_type_text("record {", &env),
+        Err("`record {` is not a type".to_string())
+    );
+}
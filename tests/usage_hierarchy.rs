@@ -0,0 +1,129 @@
+use candid_language_server::{
+    candid_lang::parse,
+    lsp::{
+        code_lens::usage_lenses,
+        semantic_analyze::{Semantic, analyze_program},
+        span::Span,
+        usage_hierarchy::{UsageIndex, UsageNode, node_at, node_item, node_of_item, used_by, uses},
+    },
+};
+use ropey::Rope;
+use std::str::FromStr;
+use tower_lsp_server::ls_types::Uri;
+
+const SOURCE: &str = r#"type Foo = record { id : nat };
+type FooList = vec Foo;
+type Page = record { items : FooList; next : opt Page; first : opt Foo };
+type Unused = text;
+service : {
+  list : () -> (FooList) query;
+  page : (Page) -> (Page);
+  get : (nat) -> (opt Foo);
+}
+"#;
+
+fn analyze() -> (Semantic, Rope) {
+    let rope = Rope::from_str(SOURCE);
+    let ast = parse(SOURCE).ast.expect("parses");
+    let semantic = analyze_program(&ast, &rope).expect("semantic");
+    (semantic, rope)
+}
+
+fn at(semantic: &Semantic, needle: &str) -> UsageNode {
+    let offset = SOURCE.find(needle).expect("needle");
+    node_at(semantic, offset).expect("node")
+}
+
+fn users(semantic: &Semantic, node: UsageNode) -> Vec<(UsageNode, Vec<Span>)> {
+    used_by(semantic, &UsageIndex::build(semantic), node)
+}
+
+fn names(semantic: &Semantic, rope: &Rope, nodes: &[(UsageNode, Vec<Span>)]) -> Vec<String> {
+    let uri = Uri::from_str("file:///workspace/api.did").expect("valid uri");
+    nodes
+        .iter()
+        .map(|(node, _)| node_item(&uri, semantic, rope, *node).expect("item").name)
+        .collect()
+}
+
+#[test]
+fn types_list_the_declarations_that_use_them() {
+    let (semantic, rope) = analyze();
+    let foo = at(&semantic, "Foo =");
+    let users = users(&semantic, foo);
+    assert_eq!(names(&semantic, &rope, &users), ["FooList", "Page", "get"]);
+
+    let list = at(&semantic, "FooList =");
+    assert_eq!(
+        names(&semantic, &rope, &users(&semantic, list)),
+        ["Page", "list"]
+    );
+
+    let page = at(&semantic, "Page =");
+    let users = users(&semantic, page);
+    assert_eq!(names(&semantic, &rope, &users), ["page"]);
+    assert_eq!(users[0].1.len(), 2);
+
+    assert!(users(&semantic, at(&semantic, "Unused")).is_empty());
+}
+
+#[test]
+fn declarations_list_the_types_they_use() {
+    let (semantic, rope) = analyze();
+    let page = at(&semantic, "Page =");
+    assert_eq!(
+        names(&semantic, &rope, &uses(&semantic, page)),
+        ["FooList", "Foo"]
+    );
+
+    let get = at(&semantic, "get");
+    assert!(matches!(get, UsageNode::Method(_)));
+    assert_eq!(names(&semantic, &rope, &uses(&semantic, get)), ["Foo"]);
+    assert!(users(&semantic, get).is_empty());
+}
+
+#[test]
+fn items_find_their_declaration_again() {
+    let (semantic, rope) = analyze();
+    let uri = Uri::from_str("file:///workspace/api.did").expect("valid uri");
+    for needle in ["Foo =", "FooList; next", "page :"] {
+        let node = at(&semantic, needle);
+        let item = node_item(&uri, &semantic, &rope, node).expect("item");
+        assert_eq!(
+            node_of_item(&semantic, &rope, &item),
+            Some(node),
+            "{needle}"
+        );
+    }
+    let list = node_item(&uri, &semantic, &rope, at(&semantic, "list")).expect("item");
+    assert_eq!(list.data.expect("data")["method"], "list");
+}
+
+#[test]
+fn lenses_count_references_and_name_dependent_methods() {
+    let (semantic, rope) = analyze();
+    let uri = Uri::from_str("file:///workspace/api.did").expect("valid uri");
+    let lenses = usage_lenses(&uri, &semantic, &UsageIndex::build(&semantic), &rope);
+    let titles: Vec<_> = lenses
+        .iter()
+        .filter_map(|lens| lens.command.as_ref())
+        .map(|command| command.title.as_str())
+        .collect();
+    assert_eq!(
+        titles,
+        [
+            "3 references · used by list, page, get",
+            "2 references · used by list, page",
+            "3 references · used by page",
+            "0 references",
+            "query",
+            "update",
+            "update",
+        ]
+    );
+    let command = lenses[0].command.as_ref().expect("command");
+    assert_eq!(command.command, "editor.action.showReferences");
+    let arguments = command.arguments.as_ref().expect("arguments");
+    assert_eq!(arguments[0], "file:///workspace/api.did");
+    assert_eq!(arguments[2].as_array().map(Vec::len), Some(3));
+}
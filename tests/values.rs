@@ -0,0 +1,188 @@
+use candid_language_server::lsp::{
+    diagnostic_rules::DiagnosticRule,
+    value::{ValueKind, parse_value_args},
+    value_document::{analyze_value_document, resolve_target, value_completion},
+    workspace::{DocumentKind, path_to_uri},
+};
+use ropey::Rope;
+use std::{path::Path, str::FromStr};
+use tower_lsp_server::ls_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity, Uri,
+};
+
+fn fixture_uri(name: &str) -> Uri {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data/values")
+        .join(name);
+    path_to_uri(&path).expect("file uri")
+}
+
+fn diagnostics(uri: &Uri, text: &str) -> Vec<Diagnostic> {
+    analyze_value_document(uri, text, &Rope::from_str(text)).diagnostics
+}
+
+fn messages(diagnostics: &[Diagnostic]) -> Vec<&str> {
+    diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.message.as_str())
+        .collect()
+}
+
+fn complete_at(uri: &Uri, text: &str, marker: &str) -> Vec<CompletionItem> {
+    let offset = text.find(marker).expect("marker") + marker.len();
+    let target = resolve_target(uri, text)
+        .expect("resolved target")
+        .expect("target");
+    value_completion(&target, text, offset)
+}
+
+#[test]
+fn args_documents_are_detected_by_extension() {
+    let args = Uri::from_str("file:///workspace/call.args").expect("valid uri");
+    let did = Uri::from_str("file:///workspace/service.did").expect("valid uri");
+    assert_eq!(DocumentKind::of(&args), DocumentKind::Values);
+    assert_eq!(DocumentKind::of(&did), DocumentKind::Interface);
+}
+
+#[test]
+fn parser_recovers_and_reports_every_error() {
+    let parsed = parse_value_args("(record { name = \"alice\"; age = }, 1");
+    let args = parsed.args.expect("arguments");
+    assert_eq!(args.values.len(), 2);
+    assert!(matches!(args.values[0].kind, ValueKind::Record { .. }));
+    assert_eq!(parsed.errors.len(), 2);
+    assert_eq!(parsed.errors[1].message, "unclosed `(`: expected `)`");
+}
+
+#[test]
+fn values_are_checked_against_the_method_named_like_the_file() {
+    let uri = fixture_uri("create_user.args");
+    let text = "(record { name = \"alice\"; age = 300; role = variant { Guest }; extra = true })";
+    let diagnostics = diagnostics(&uri, text);
+    assert_eq!(
+        messages(&diagnostics),
+        vec![
+            "`300` is out of range for `nat8`",
+            "variant has no case `Guest`",
+            "record has no field `extra`",
+        ]
+    );
+    assert!(diagnostics.iter().all(|diagnostic| {
+        DiagnosticRule::of(diagnostic) == Some(DiagnosticRule::ValueTypeMismatch)
+            && diagnostic.severity == Some(DiagnosticSeverity::ERROR)
+    }));
+}
+
+#[test]
+fn missing_fields_and_variant_payloads_are_reported() {
+    let uri = fixture_uri("create_user.args");
+    let text = "(record { name = \"alice\"; role = variant { Member } }, opt 1)";
+    assert_eq!(
+        messages(&diagnostics(&uri, text)),
+        vec![
+            "case `Member` needs a value of type `nat8`",
+            "missing field `age`"
+        ]
+    );
+}
+
+#[test]
+fn type_errors_wait_for_a_clean_parse() {
+    let uri = fixture_uri("create_user.args");
+    let diagnostics = diagnostics(&uri, "(record { age = 300 ");
+    assert!(!diagnostics.is_empty());
+    assert!(
+        diagnostics
+            .iter()
+            .all(|diagnostic| DiagnosticRule::of(diagnostic) == Some(DiagnosticRule::ValueSyntax))
+    );
+}
+
+#[test]
+fn types_from_imported_files_are_checked() {
+    let uri = fixture_uri("imports/greet.args");
+    assert!(diagnostics(&uri, "(record { name = \"alice\"; age = 30 })").is_empty());
+    assert_eq!(
+        messages(&diagnostics(
+            &uri,
+            "(record { name = \"alice\"; age = 300 })"
+        )),
+        vec!["`300` is out of range for `nat8`"]
+    );
+}
+
+#[test]
+fn directives_choose_the_method() {
+    let uri = fixture_uri("smoke.args");
+    let target = resolve_target(&uri, "// candid-ls-method: ping\n()")
+        .expect("resolved")
+        .expect("target");
+    assert_eq!(target.method, "ping");
+    assert!(target.arg_types.is_empty());
+
+    let text = "// candid-ls-did: service.did\n// candid-ls-method: ping\n(1)";
+    assert_eq!(
+        messages(&diagnostics(&uri, text)),
+        vec!["unexpected argument: the method takes 0 arguments"]
+    );
+}
+
+#[test]
+fn unresolved_directives_warn_on_the_comment() {
+    let uri = fixture_uri("smoke.args");
+    let diagnostics = diagnostics(&uri, "// candid-ls-method: missing\n()");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        DiagnosticRule::of(&diagnostics[0]),
+        Some(DiagnosticRule::ValueTarget)
+    );
+    assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+    assert!(diagnostics[0].message.ends_with("has no method `missing`"));
+    assert_eq!(diagnostics[0].range.start.line, 0);
+}
+
+#[test]
+fn documents_without_a_target_are_only_parsed() {
+    let uri = Uri::from_str("file:///nonexistent/values/call.args").expect("valid uri");
+    assert!(diagnostics(&uri, "(1, 2, 3)").is_empty());
+    assert!(matches!(resolve_target(&uri, "(record { })"), Ok(None)));
+}
+
+#[test]
+fn completion_offers_missing_record_fields() {
+    let uri = fixture_uri("create_user.args");
+    let items = complete_at(&uri, "(record { name = \"a\"; })", "; ");
+    let mut labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+    labels.sort();
+    assert_eq!(labels, vec!["age", "nickname", "role"]);
+    let age = items.iter().find(|item| item.label == "age").expect("age");
+    assert_eq!(age.kind, Some(CompletionItemKind::FIELD));
+    assert_eq!(age.insert_text.as_deref(), Some("age = "));
+    assert_eq!(age.detail.as_deref(), Some("nat8"));
+}
+
+#[test]
+fn completion_offers_variant_tags_and_values() {
+    let uri = fixture_uri("create_user.args");
+    let tags = complete_at(&uri, "(record { role = variant { } })", "variant { ");
+    let mut tags: Vec<_> = tags
+        .iter()
+        .map(|item| (item.label.as_str(), item.insert_text.as_deref()))
+        .collect();
+    tags.sort();
+    assert_eq!(
+        tags,
+        vec![("Admin", Some("Admin")), ("Member", Some("Member = "))]
+    );
+
+    let values = complete_at(&uri, "(record { name = \"a\"; role = })", "role = ");
+    let mut snippets: Vec<_> = values
+        .iter()
+        .map(|item| item.insert_text.as_deref())
+        .collect();
+    snippets.sort();
+    assert_eq!(
+        snippets,
+        vec![Some("variant { Admin }"), Some("variant { Member = $0 }")]
+    );
+}
@@ -0,0 +1,71 @@
+use candid_language_server::lsp::workspace::{path_to_uri, uri_to_path};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tower_lsp_server::ls_types::Uri;
+
+fn to_path(uri: &str) -> Option<PathBuf> {
+    uri_to_path(&Uri::from_str(uri).expect("valid uri"))
+}
+
+fn to_uri(path: &str) -> String {
+    path_to_uri(Path::new(path))
+        .expect("file uri")
+        .as_str()
+        .to_string()
+}
+
+#[test]
+fn unix_paths_round_trip_with_percent_encoding() {
+    let uri = to_uri("/tmp/100% ü#?.did");
+    assert_eq!(uri, "file:///tmp/100%25%20%C3%BC%23%3F.did");
+    assert_eq!(to_path(&uri), Some(PathBuf::from("/tmp/100% ü#?.did")));
+    assert_eq!(
+        to_path("file://localhost/etc/service.did"),
+        Some(PathBuf::from("/etc/service.did"))
+    );
+    assert_eq!(
+        to_path("file:///tmp/service.did?query#fragment"),
+        Some(PathBuf::from("/tmp/service.did"))
+    );
+    assert_eq!(to_path("file:///tmp/%zz.did"), None);
+    assert_eq!(to_path("untitled:Untitled-1"), None);
+}
+
+#[test]
+fn windows_drive_letters_drop_the_leading_slash() {
+    assert_eq!(
+        to_path("file:///C:/Users/dev/service.did"),
+        Some(PathBuf::from("C:/Users/dev/service.did"))
+    );
+    // Editors may encode the drive colon.
+    assert_eq!(
+        to_path("file:///c%3A/Users/dev/service.did"),
+        Some(PathBuf::from("c:/Users/dev/service.did"))
+    );
+    assert_eq!(
+        to_uri(r"C:\Users\dev\my service.did"),
+        "file:///C:/Users/dev/my%20service.did"
+    );
+    assert_eq!(
+        to_uri(r"\\?\C:\Users\dev\service.did"),
+        "file:///C:/Users/dev/service.did"
+    );
+}
+
+#[test]
+fn unc_paths_keep_the_server_as_authority() {
+    assert_eq!(
+        to_path("file://server/share/dir/service.did"),
+        Some(PathBuf::from("//server/share/dir/service.did"))
+    );
+    assert_eq!(
+        to_uri(r"\\server\share\dir\service.did"),
+        "file://server/share/dir/service.did"
+    );
+    assert_eq!(
+        to_uri(r"\\?\UNC\server\share\service.did"),
+        "file://server/share/service.did"
+    );
+}